@@ -0,0 +1,23 @@
+use std::fmt;
+
+/// The credentials of a factorio.com account, required to download mods and to list multiplayer games.
+///
+/// The token can be found on the [profile page](https://factorio.com/profile) or in the `player-data.json` file of a
+/// game installation. It is redacted when formatted with [`Debug`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// The factorio.com username.
+    pub username: String,
+
+    /// The factorio.com authentication token.
+    pub token: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
@@ -174,7 +174,7 @@ pub fn download_to<P: AsRef<std::path::Path>>(
     let filename = response
         .url()
         .path_segments()
-        .and_then(|segments| segments.last())
+        .and_then(|mut segments| segments.next_back())
         .and_then(|name| if name.is_empty() { None } else { Some(name) })
         .unwrap_or("factorio.tar.xz");
 
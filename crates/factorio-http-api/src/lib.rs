@@ -1,3 +1,8 @@
 //! The *api* crate provides a Rust interface for [Factorio's HTTP APIs](https://wiki.factorio.com/Factorio_HTTP_API_usage_guidelines).
 
+mod credentials;
 pub mod download;
+pub mod matchmaking;
+pub mod mod_portal;
+
+pub use credentials::Credentials;
@@ -0,0 +1,224 @@
+//! The *matchmaking* module mirrors the [Factorio Matchmaking API](https://wiki.factorio.com/Matchmaking_API).
+//!
+//! The Matchmaking API lists the multiplayer games that are published to the public game list, which includes servers
+//! with `visibility.public` set in their server settings.
+//!
+//! # Example
+//! ```no_run
+//! use factorio_http_api::{matchmaking, Credentials};
+//! let credentials = Credentials {
+//!     username: "username".to_owned(),
+//!     token: "token".to_owned(),
+//! };
+//!
+//! let games = matchmaking::get_games(&credentials);
+//! println!("games: {:?}", games);
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::mod_portal::ModVersion;
+use crate::Credentials;
+
+/// The URL of the `/get-games` endpoint.
+pub const GET_GAMES_URL: &str = "https://multiplayer.factorio.com/get-games";
+
+/// A game in the public game list.
+///
+/// The `/get-games` endpoint returns a summary of each game, and `/get-game-details` adds the game's players and mods.
+/// Fields that are missing from a response take their default values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Game {
+    /// The ID of the game, used to fetch its details.
+    pub game_id: u64,
+
+    /// The name of the game, from the server settings.
+    pub name: String,
+
+    /// The description of the game, from the server settings.
+    #[serde(default)]
+    pub description: String,
+
+    /// The maximum number of players. 0 means unlimited.
+    #[serde(default)]
+    pub max_players: u32,
+
+    /// The version of the game the server is running.
+    pub application_version: ApplicationVersion,
+
+    /// The time the game has been running, in minutes.
+    #[serde(default)]
+    pub game_time_elapsed: u64,
+
+    /// Whether a password is required to join.
+    #[serde(default)]
+    pub has_password: bool,
+
+    /// An identifier of the server that is stable across games.
+    #[serde(default)]
+    pub server_id: Option<String>,
+
+    /// The tags of the game, from the server settings.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The address players connect to, as seen by the matchmaking server, like `203.0.113.1:34197`.
+    #[serde(default)]
+    pub host_address: Option<String>,
+
+    /// The names of the players currently in the game.
+    #[serde(default)]
+    pub players: Vec<String>,
+
+    /// The number of mods the game uses.
+    #[serde(default)]
+    pub mod_count: Option<u32>,
+
+    /// The mods the game uses. Only returned by `/get-game-details`.
+    #[serde(default)]
+    pub mods: Option<Vec<GameMod>>,
+}
+
+/// The version of the game a server is running.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApplicationVersion {
+    /// The game version, like `1.1.110`.
+    pub game_version: semver::Version,
+
+    /// The build number.
+    pub build_version: u32,
+
+    /// The build mode, like `headless`.
+    pub build_mode: String,
+
+    /// The platform, like `linux64`.
+    pub platform: String,
+}
+
+/// A mod used by a game.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GameMod {
+    /// The name of the mod.
+    pub name: String,
+
+    /// The version of the mod.
+    pub version: ModVersion,
+}
+
+/// Get the URL of the `/get-game-details` endpoint for a game.
+///
+/// # Example
+/// ```
+/// use factorio_http_api::matchmaking;
+/// assert_eq!(
+///     matchmaking::game_details_url(1234),
+///     "https://multiplayer.factorio.com/get-game-details/1234"
+/// );
+/// ```
+pub fn game_details_url(game_id: u64) -> String {
+    format!("https://multiplayer.factorio.com/get-game-details/{}", game_id)
+}
+
+/// Fetch the games in the public game list.
+#[tracing::instrument]
+pub fn get_games(credentials: &Credentials) -> Result<Vec<Game>, Box<dyn std::error::Error>> {
+    Ok(reqwest::blocking::Client::new()
+        .get(GET_GAMES_URL)
+        .query(&[("username", &credentials.username), ("token", &credentials.token)])
+        .send()?
+        .error_for_status()?
+        .json::<Vec<Game>>()?)
+}
+
+/// Fetch the details of a game, including its players and mods.
+#[tracing::instrument]
+pub fn get_game_details(game_id: u64) -> Result<Game, Box<dyn std::error::Error>> {
+    Ok(reqwest::blocking::get(game_details_url(game_id))?
+        .error_for_status()?
+        .json::<Game>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_games() {
+        let json = r#"[
+            {
+                "application_version": {
+                    "build_mode": "headless",
+                    "build_version": 60000,
+                    "game_version": "1.1.110",
+                    "platform": "linux64"
+                },
+                "description": "a factoriod server",
+                "game_id": 1234,
+                "game_time_elapsed": 61,
+                "has_password": false,
+                "host_address": "203.0.113.1:34197",
+                "max_players": 0,
+                "mod_count": 1,
+                "name": "factoriod",
+                "server_id": "abcdef",
+                "tags": ["vanilla"]
+            },
+            {
+                "application_version": {
+                    "build_mode": "headless",
+                    "build_version": 79590,
+                    "game_version": "2.0.28",
+                    "platform": "linux64"
+                },
+                "game_id": 5678,
+                "name": "another",
+                "players": ["foo", "bar"]
+            }
+        ]"#;
+
+        let games: Vec<Game> = serde_json::from_str(json).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].game_id, 1234);
+        assert_eq!(games[0].application_version.game_version, semver::Version::new(1, 1, 110));
+        assert_eq!(games[0].host_address.as_deref(), Some("203.0.113.1:34197"));
+        assert_eq!(games[0].tags, vec!["vanilla"]);
+        assert!(games[0].players.is_empty());
+        assert_eq!(games[1].description, "");
+        assert_eq!(games[1].players, vec!["foo", "bar"]);
+        assert_eq!(games[1].mods, None);
+    }
+
+    #[test]
+    fn test_deserialize_game_details() {
+        let json = r#"{
+            "application_version": {
+                "build_mode": "headless",
+                "build_version": 60000,
+                "game_version": "1.1.110",
+                "platform": "linux64"
+            },
+            "game_id": 1234,
+            "name": "factoriod",
+            "mods": [
+                {"name": "base", "version": "1.1.110"},
+                {"name": "even-distribution", "version": "1.0.10"}
+            ],
+            "players": ["foo"]
+        }"#;
+
+        let game: Game = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            game.mods,
+            Some(vec![
+                GameMod {
+                    name: "base".to_owned(),
+                    version: ModVersion::new(1, 1, 110),
+                },
+                GameMod {
+                    name: "even-distribution".to_owned(),
+                    version: ModVersion::new(1, 0, 10),
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! Mod dependencies, as declared in the `dependencies` array of a mod's `info.json`.
+//!
+//! Each dependency is a string made of an optional prefix, the name of the mod, and an optional version requirement:
+//! ```text
+//! [prefix] mod-name [operator version]
+//! ```
+//!
+//! The prefix is one of:
+//! - `!`: the mods are incompatible.
+//! - `?`: an optional dependency.
+//! - `(?)`: a hidden optional dependency, which is not shown in the game's mod GUI.
+//! - `~`: a required dependency that does not affect the load order.
+//! - no prefix: a required dependency.
+//!
+//! The operator is one of `<`, `<=`, `=`, `>=`, or `>`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::ModVersion;
+
+/// The kind of a [`Dependency`], given by its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    /// No prefix. The dependency must be present and is loaded before the dependent mod.
+    Required,
+
+    /// The `?` prefix. The dependency is loaded before the dependent mod if it is present.
+    Optional,
+
+    /// The `(?)` prefix. Like [`Self::Optional`], but not shown in the game's mod GUI.
+    HiddenOptional,
+
+    /// The `!` prefix. The mods cannot be enabled at the same time.
+    Incompatible,
+
+    /// The `~` prefix. The dependency must be present, but does not affect the load order.
+    NoLoadOrder,
+}
+
+impl DependencyKind {
+    /// The prefix of this kind of dependency, without trailing whitespace.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            DependencyKind::Required => "",
+            DependencyKind::Optional => "?",
+            DependencyKind::HiddenOptional => "(?)",
+            DependencyKind::Incompatible => "!",
+            DependencyKind::NoLoadOrder => "~",
+        }
+    }
+
+    /// Whether the dependency must be present for the dependent mod to load.
+    pub fn is_required(&self) -> bool {
+        matches!(self, DependencyKind::Required | DependencyKind::NoLoadOrder)
+    }
+}
+
+/// A comparison operator in a [`VersionRequirement`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VersionOperator {
+    /// `<`
+    Less,
+
+    /// `<=`
+    LessOrEqual,
+
+    /// `=`
+    Equal,
+
+    /// `>=`
+    GreaterOrEqual,
+
+    /// `>`
+    Greater,
+}
+
+impl VersionOperator {
+    /// The textual form of the operator.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VersionOperator::Less => "<",
+            VersionOperator::LessOrEqual => "<=",
+            VersionOperator::Equal => "=",
+            VersionOperator::GreaterOrEqual => ">=",
+            VersionOperator::Greater => ">",
+        }
+    }
+}
+
+impl fmt::Display for VersionOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A requirement on the version of a dependency, such as `>= 0.6.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VersionRequirement {
+    pub operator: VersionOperator,
+    pub version: ModVersion,
+}
+
+impl VersionRequirement {
+    /// Whether `version` satisfies this requirement.
+    ///
+    /// # Examples
+    /// ```
+    /// use factorio_http_api::mod_portal::{ModVersion, VersionOperator, VersionRequirement};
+    /// let requirement = VersionRequirement {
+    ///     operator: VersionOperator::GreaterOrEqual,
+    ///     version: ModVersion::new(0, 6, 0),
+    /// };
+    ///
+    /// assert!(requirement.matches(&ModVersion::new(0, 6, 3)));
+    /// assert!(!requirement.matches(&ModVersion::new(0, 5, 9)));
+    /// ```
+    pub fn matches(&self, version: &ModVersion) -> bool {
+        match self.operator {
+            VersionOperator::Less => version < &self.version,
+            VersionOperator::LessOrEqual => version <= &self.version,
+            VersionOperator::Equal => version == &self.version,
+            VersionOperator::GreaterOrEqual => version >= &self.version,
+            VersionOperator::Greater => version > &self.version,
+        }
+    }
+}
+
+impl fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.operator, self.version)
+    }
+}
+
+/// A dependency of a mod on another mod.
+///
+/// # Examples
+/// ```
+/// use factorio_http_api::mod_portal::{Dependency, DependencyKind, ModVersion, VersionOperator};
+/// let dependency: Dependency = "? space-exploration >= 0.6.0".parse().unwrap();
+/// assert_eq!(dependency.kind, DependencyKind::Optional);
+/// assert_eq!(dependency.name, "space-exploration");
+///
+/// let requirement = dependency.requirement.unwrap();
+/// assert_eq!(requirement.operator, VersionOperator::GreaterOrEqual);
+/// assert_eq!(requirement.version, ModVersion::new(0, 6, 0));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dependency {
+    /// The kind of dependency.
+    pub kind: DependencyKind,
+
+    /// The name of the mod that is depended on.
+    pub name: String,
+
+    /// The version requirement of the dependency, if any.
+    pub requirement: Option<VersionRequirement>,
+}
+
+impl Dependency {
+    /// Create a required dependency on any version of `name`.
+    pub fn required<S: Into<String>>(name: S) -> Self {
+        Self {
+            kind: DependencyKind::Required,
+            name: name.into(),
+            requirement: None,
+        }
+    }
+
+    /// Whether `version` of the depended-on mod satisfies this dependency's version requirement. A dependency without
+    /// a requirement is satisfied by any version.
+    pub fn matches(&self, version: &ModVersion) -> bool {
+        self.requirement
+            .map(|requirement| requirement.matches(version))
+            .unwrap_or(true)
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = self.kind.prefix();
+        if !prefix.is_empty() {
+            write!(f, "{} ", prefix)?;
+        }
+
+        f.write_str(&self.name)?;
+        if let Some(requirement) = &self.requirement {
+            write!(f, " {}", requirement)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error returned when parsing a [`Dependency`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseDependencyError {
+    /// The dependency string does not contain a mod name.
+    MissingName(String),
+
+    /// An operator was given without a version, or a version without an operator.
+    MissingVersion(String),
+
+    /// The operator is not one of `<`, `<=`, `=`, `>=`, or `>`.
+    InvalidOperator(String),
+
+    /// The version could not be parsed.
+    InvalidVersion(super::ParseModVersionError),
+}
+
+impl fmt::Display for ParseDependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseDependencyError::MissingName(s) => write!(f, "dependency {:?} has no mod name", s),
+            ParseDependencyError::MissingVersion(s) => {
+                write!(f, "dependency {:?} has an operator without a version", s)
+            },
+            ParseDependencyError::InvalidOperator(op) => write!(f, "invalid version operator {:?}", op),
+            ParseDependencyError::InvalidVersion(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseDependencyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseDependencyError::InvalidVersion(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for VersionOperator {
+    type Err = ParseDependencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "<" => Ok(VersionOperator::Less),
+            "<=" => Ok(VersionOperator::LessOrEqual),
+            "=" => Ok(VersionOperator::Equal),
+            ">=" => Ok(VersionOperator::GreaterOrEqual),
+            ">" => Ok(VersionOperator::Greater),
+            _ => Err(ParseDependencyError::InvalidOperator(s.to_owned())),
+        }
+    }
+}
+
+impl FromStr for Dependency {
+    type Err = ParseDependencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+
+        let (kind, rest) = [
+            DependencyKind::HiddenOptional,
+            DependencyKind::Optional,
+            DependencyKind::Incompatible,
+            DependencyKind::NoLoadOrder,
+        ]
+        .into_iter()
+        .find_map(|kind| trimmed.strip_prefix(kind.prefix()).map(|rest| (kind, rest)))
+        .unwrap_or((DependencyKind::Required, trimmed));
+
+        // mod names may contain spaces, but never the characters used by operators
+        let (name, requirement) = match rest.find(['<', '=', '>']) {
+            Some(index) => (&rest[..index], Some(&rest[index..])),
+            None => (rest, None),
+        };
+
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ParseDependencyError::MissingName(s.to_owned()));
+        }
+
+        let requirement = requirement
+            .map(|requirement| {
+                let operator_len = requirement
+                    .find(|c| !matches!(c, '<' | '=' | '>'))
+                    .unwrap_or(requirement.len());
+
+                let (operator, version) = requirement.split_at(operator_len);
+                let operator = operator.parse()?;
+                let version = version.trim();
+                if version.is_empty() {
+                    return Err(ParseDependencyError::MissingVersion(s.to_owned()));
+                }
+
+                let version = version.parse().map_err(ParseDependencyError::InvalidVersion)?;
+                Ok(VersionRequirement { operator, version })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            kind,
+            name: name.to_owned(),
+            requirement,
+        })
+    }
+}
+
+impl Serialize for Dependency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Dependency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirement(operator: VersionOperator, major: u16, minor: u16, patch: u16) -> Option<VersionRequirement> {
+        Some(VersionRequirement {
+            operator,
+            version: ModVersion::new(major, minor, patch),
+        })
+    }
+
+    #[test]
+    fn test_parse_kinds() {
+        let cases = [
+            ("bar", DependencyKind::Required),
+            ("? bar", DependencyKind::Optional),
+            ("(?) bar", DependencyKind::HiddenOptional),
+            ("! bar", DependencyKind::Incompatible),
+            ("~ bar", DependencyKind::NoLoadOrder),
+        ];
+
+        for (s, kind) in cases {
+            let dependency: Dependency = s.parse().unwrap();
+            assert_eq!(dependency.kind, kind, "{}", s);
+            assert_eq!(dependency.name, "bar", "{}", s);
+            assert_eq!(dependency.requirement, None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_prefix_without_space() {
+        assert_eq!("?bar".parse::<Dependency>().unwrap().kind, DependencyKind::Optional);
+        assert_eq!("(?)bar".parse::<Dependency>().unwrap().kind, DependencyKind::HiddenOptional);
+        assert_eq!("!bar".parse::<Dependency>().unwrap().kind, DependencyKind::Incompatible);
+        assert_eq!("~bar".parse::<Dependency>().unwrap().kind, DependencyKind::NoLoadOrder);
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let cases = [
+            ("base < 1.1.0", VersionOperator::Less),
+            ("base <= 1.1.0", VersionOperator::LessOrEqual),
+            ("base = 1.1.0", VersionOperator::Equal),
+            ("base >= 1.1.0", VersionOperator::GreaterOrEqual),
+            ("base > 1.1.0", VersionOperator::Greater),
+        ];
+
+        for (s, operator) in cases {
+            let dependency: Dependency = s.parse().unwrap();
+            assert_eq!(dependency.name, "base", "{}", s);
+            assert_eq!(dependency.requirement, requirement(operator, 1, 1, 0), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_full() {
+        let dependency: Dependency = "? space-exploration >= 0.6.0".parse().unwrap();
+        assert_eq!(
+            dependency,
+            Dependency {
+                kind: DependencyKind::Optional,
+                name: "space-exploration".to_owned(),
+                requirement: requirement(VersionOperator::GreaterOrEqual, 0, 6, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_whitespace() {
+        let dependency: Dependency = "  !   bobs-mods   ".parse().unwrap();
+        assert_eq!(dependency.kind, DependencyKind::Incompatible);
+        assert_eq!(dependency.name, "bobs-mods");
+
+        let dependency: Dependency = "base>=1.1".parse().unwrap();
+        assert_eq!(dependency.name, "base");
+        assert_eq!(dependency.requirement, requirement(VersionOperator::GreaterOrEqual, 1, 1, 0));
+    }
+
+    #[test]
+    fn test_parse_name_with_spaces() {
+        let dependency: Dependency = "? Squeak Through >= 1.8.2".parse().unwrap();
+        assert_eq!(dependency.name, "Squeak Through");
+        assert_eq!(dependency.requirement, requirement(VersionOperator::GreaterOrEqual, 1, 8, 2));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!("".parse::<Dependency>(), Err(ParseDependencyError::MissingName(_))));
+        assert!(matches!("? ".parse::<Dependency>(), Err(ParseDependencyError::MissingName(_))));
+        assert!(matches!(">= 1.0.0".parse::<Dependency>(), Err(ParseDependencyError::MissingName(_))));
+        assert!(matches!("base >=".parse::<Dependency>(), Err(ParseDependencyError::MissingVersion(_))));
+        assert!(matches!("base => 1.0".parse::<Dependency>(), Err(ParseDependencyError::InvalidOperator(_))));
+        assert!(matches!("base == 1.0".parse::<Dependency>(), Err(ParseDependencyError::InvalidOperator(_))));
+        assert!(matches!("base >= one".parse::<Dependency>(), Err(ParseDependencyError::InvalidVersion(_))));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let cases = [
+            "base",
+            "base >= 1.1.0",
+            "? space-exploration >= 0.6.0",
+            "(?) foo",
+            "! bobs-mods",
+            "~ bar < 2.0.0",
+        ];
+
+        for s in cases {
+            let dependency: Dependency = s.parse().unwrap();
+            assert_eq!(dependency.to_string(), s);
+            assert_eq!(dependency.to_string().parse::<Dependency>().unwrap(), dependency);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let dependency: Dependency = "foo > 1.0.0".parse().unwrap();
+        assert!(!dependency.matches(&ModVersion::new(1, 0, 0)));
+        assert!(dependency.matches(&ModVersion::new(1, 0, 1)));
+
+        let dependency: Dependency = "foo < 1.0.0".parse().unwrap();
+        assert!(dependency.matches(&ModVersion::new(0, 9, 0)));
+        assert!(!dependency.matches(&ModVersion::new(1, 0, 0)));
+
+        let dependency: Dependency = "foo <= 1.0.0".parse().unwrap();
+        assert!(dependency.matches(&ModVersion::new(1, 0, 0)));
+        assert!(!dependency.matches(&ModVersion::new(1, 0, 1)));
+
+        let dependency: Dependency = "foo = 1.0.0".parse().unwrap();
+        assert!(dependency.matches(&ModVersion::new(1, 0, 0)));
+        assert!(!dependency.matches(&ModVersion::new(1, 0, 1)));
+
+        let dependency: Dependency = "foo".parse().unwrap();
+        assert!(dependency.matches(&ModVersion::new(0, 0, 1)));
+    }
+
+    #[test]
+    fn test_is_required() {
+        assert!(DependencyKind::Required.is_required());
+        assert!(DependencyKind::NoLoadOrder.is_required());
+        assert!(!DependencyKind::Optional.is_required());
+        assert!(!DependencyKind::HiddenOptional.is_required());
+        assert!(!DependencyKind::Incompatible.is_required());
+    }
+
+    #[test]
+    fn test_serde() {
+        let dependencies: Vec<Dependency> =
+            serde_json::from_str(r#"["base >= 1.1", "? foo", "! bar"]"#).unwrap();
+        assert_eq!(dependencies.len(), 3);
+        assert_eq!(
+            serde_json::to_string(&dependencies).unwrap(),
+            r#"["base >= 1.1.0","? foo","! bar"]"#
+        );
+
+        assert!(serde_json::from_str::<Dependency>(r#""base >= x""#).is_err());
+    }
+}
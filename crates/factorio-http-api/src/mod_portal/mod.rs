@@ -0,0 +1,311 @@
+//! The *mod_portal* module mirrors the [Factorio Mod Portal API](https://wiki.factorio.com/Mod_portal_API).
+//!
+//! The Mod Portal API provides information about mods and their releases, including each release's dependencies.
+//! The [`resolve`] module uses this information to pick a consistent set of mod versions for a game version.
+//!
+//! # Example
+//! ```no_run
+//! use factorio_http_api::mod_portal;
+//! let full_mod = mod_portal::full_mod("space-exploration");
+//! println!("space-exploration: {:?}", full_mod);
+//! ```
+
+mod dependency;
+pub mod resolve;
+mod version;
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+pub use crate::Credentials;
+pub use dependency::*;
+pub use version::*;
+
+/// A mod on the mod portal, as returned by the `/api/mods/{name}/full` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Mod {
+    /// The mod's machine-readable ID string.
+    pub name: String,
+
+    /// The mod's human-readable name.
+    pub title: String,
+
+    /// The Factorio username of the mod's author.
+    pub owner: String,
+
+    /// A shorter mod description.
+    #[serde(default)]
+    pub summary: String,
+
+    /// Number of downloads.
+    #[serde(default)]
+    pub downloads_count: u64,
+
+    /// A list of the releases of the mod, in the order returned by the portal.
+    #[serde(default)]
+    pub releases: Vec<Release>,
+}
+
+impl Mod {
+    /// The newest release of the mod that can be loaded by the `game` version, if any.
+    pub fn latest_release_for(&self, game: &ModVersion) -> Option<&Release> {
+        self.releases
+            .iter()
+            .filter(|release| release.info_json.factorio_version.is_loadable_by(game))
+            .max_by_key(|release| release.version)
+    }
+
+    /// The release with exactly the given `version`, if any.
+    pub fn release(&self, version: &ModVersion) -> Option<&Release> {
+        self.releases.iter().find(|release| &release.version == version)
+    }
+}
+
+/// A release of a mod.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Release {
+    /// Path to download the release's zip file. Downloading requires a username and token.
+    pub download_url: String,
+
+    /// The file name of the release, typically `{name}_{version}.zip`.
+    pub file_name: String,
+
+    /// A copy of the release's `info.json`.
+    pub info_json: InfoJson,
+
+    /// ISO 8601 timestamp for when the release was created.
+    pub released_at: String,
+
+    /// The version of the release.
+    pub version: ModVersion,
+
+    /// The SHA1 hash of the release's zip file.
+    pub sha1: String,
+}
+
+/// The parts of a release's `info.json` returned by the mod portal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InfoJson {
+    /// The game version the release targets.
+    pub factorio_version: ModVersion,
+
+    /// The release's dependencies. Only populated by the `/full` endpoint.
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+}
+
+/// Get the URL of the `/full` endpoint for the mod `name`.
+///
+/// # Example
+/// ```
+/// use factorio_http_api::mod_portal;
+/// assert_eq!(mod_portal::full_mod_url("foo"), "https://mods.factorio.com/api/mods/foo/full");
+/// ```
+pub fn full_mod_url(name: &str) -> String {
+    format!("https://mods.factorio.com/api/mods/{}/full", name)
+}
+
+/// Fetch the full information for a mod, including the dependencies of every release.
+///
+/// # Example
+/// ```no_run
+/// use factorio_http_api::mod_portal;
+/// let full_mod = mod_portal::full_mod("space-exploration");
+/// println!("space-exploration: {:?}", full_mod);
+/// ```
+#[tracing::instrument]
+pub fn full_mod(name: &str) -> Result<Mod, Box<dyn std::error::Error>> {
+    Ok(reqwest::blocking::get(full_mod_url(name))?
+        .error_for_status()?
+        .json::<Mod>()?)
+}
+
+/// Get the URL to download a release, without credentials.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use factorio_http_api::mod_portal::{self, Release};
+/// let release: Release = serde_json::from_str(r#"{
+///     "download_url": "/download/foo/5a5f1ae6adcc441024d72b5f",
+///     "file_name": "foo_1.0.0.zip",
+///     "info_json": {"factorio_version": "1.1"},
+///     "released_at": "2023-01-01T00:00:00.000000Z",
+///     "version": "1.0.0",
+///     "sha1": "abc"
+/// }"#)?;
+///
+/// assert_eq!(
+///     mod_portal::release_download_url(&release),
+///     "https://mods.factorio.com/download/foo/5a5f1ae6adcc441024d72b5f"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn release_download_url(release: &Release) -> String {
+    format!("https://mods.factorio.com{}", release.download_url)
+}
+
+/// Compute the hex-encoded SHA1 hash of a file.
+pub fn sha1_file<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.digest().to_string())
+}
+
+/// Downloads a release to a directory and verifies its SHA1 hash. Returns the path to the downloaded file, which is
+/// named after [`Release::file_name`].
+///
+/// The release is first downloaded to a `.part` file that is renamed once verified, so an interrupted download never
+/// leaves a partial zip behind.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use factorio_http_api::mod_portal::{self, Credentials, ModVersion};
+/// let full_mod = mod_portal::full_mod("even-distribution")?;
+/// let release = full_mod.latest_release_for(&ModVersion::new(1, 1, 110)).ok_or("no release")?;
+/// let credentials = Credentials {
+///     username: "username".to_owned(),
+///     token: "token".to_owned(),
+/// };
+///
+/// let zip = mod_portal::download_release_to(release, &credentials, "/tmp/mods")?;
+/// println!("downloaded to: {}", zip.display());
+/// # Ok(())
+/// # }
+/// ```
+#[tracing::instrument(skip(release), fields(file_name = %release.file_name))]
+pub fn download_release_to<P: AsRef<Path> + fmt::Debug>(
+    release: &Release,
+    credentials: &Credentials,
+    directory: P,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let directory = directory.as_ref();
+    if directory.is_file() {
+        return Err("directory is a file".into());
+    }
+
+    if release.file_name.contains(['/', '\\']) {
+        return Err(format!("invalid release file name: {}", release.file_name).into());
+    }
+
+    std::fs::create_dir_all(directory)?;
+
+    let mut response = reqwest::blocking::Client::new()
+        .get(release_download_url(release))
+        .query(&[("username", &credentials.username), ("token", &credentials.token)])
+        .send()?
+        .error_for_status()?;
+
+    let destination = directory.join(&release.file_name);
+    let partial = directory.join(format!("{}.part", release.file_name));
+    debug!("downloading to: {}", partial.display());
+    let mut file = std::fs::File::create(&partial)?;
+    response.copy_to(&mut file)?;
+    drop(file);
+
+    let sha1 = sha1_file(&partial)?;
+    if !sha1.eq_ignore_ascii_case(&release.sha1) {
+        std::fs::remove_file(&partial)?;
+        return Err(format!(
+            "SHA1 mismatch for {}: expected {}, got {}",
+            release.file_name, release.sha1, sha1
+        )
+        .into());
+    }
+
+    std::fs::rename(&partial, &destination)?;
+    Ok(destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL_MOD: &str = r#"{
+        "category": "content",
+        "downloads_count": 1000,
+        "name": "foo",
+        "owner": "someone",
+        "releases": [
+            {
+                "download_url": "/download/foo/1",
+                "file_name": "foo_1.0.0.zip",
+                "info_json": {"factorio_version": "1.1", "dependencies": ["base >= 1.1", "? bar"]},
+                "released_at": "2023-01-01T00:00:00.000000Z",
+                "version": "1.0.0",
+                "sha1": "abc"
+            },
+            {
+                "download_url": "/download/foo/2",
+                "file_name": "foo_2.0.0.zip",
+                "info_json": {"factorio_version": "2.0"},
+                "released_at": "2024-10-21T00:00:00.000000Z",
+                "version": "2.0.0",
+                "sha1": "def"
+            }
+        ],
+        "summary": "A mod",
+        "title": "Foo"
+    }"#;
+
+    #[test]
+    fn test_deserialize_full_mod() {
+        let full_mod: Mod = serde_json::from_str(FULL_MOD).unwrap();
+        assert_eq!(full_mod.name, "foo");
+        assert_eq!(full_mod.releases.len(), 2);
+        assert_eq!(full_mod.releases[0].info_json.dependencies.len(), 2);
+        assert!(full_mod.releases[1].info_json.dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_latest_release_for() {
+        let full_mod: Mod = serde_json::from_str(FULL_MOD).unwrap();
+        let latest = full_mod.latest_release_for(&ModVersion::new(1, 1, 110)).unwrap();
+        assert_eq!(latest.version, ModVersion::new(1, 0, 0));
+        let latest = full_mod.latest_release_for(&ModVersion::new(2, 0, 7)).unwrap();
+        assert_eq!(latest.version, ModVersion::new(2, 0, 0));
+        assert!(full_mod.latest_release_for(&ModVersion::new(0, 17, 0)).is_none());
+    }
+
+    #[test]
+    fn test_credentials_debug_redacts_token() {
+        let credentials = Credentials {
+            username: "user".to_owned(),
+            token: "secret".to_owned(),
+        };
+
+        let debug = format!("{:?}", credentials);
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[test]
+    fn test_sha1_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), b"abc").unwrap();
+        assert_eq!(sha1_file(file.path()).unwrap(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn test_release() {
+        let full_mod: Mod = serde_json::from_str(FULL_MOD).unwrap();
+        assert!(full_mod.release(&ModVersion::new(1, 0, 0)).is_some());
+        assert!(full_mod.release(&ModVersion::new(1, 0, 1)).is_none());
+    }
+}
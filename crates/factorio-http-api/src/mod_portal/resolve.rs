@@ -0,0 +1,809 @@
+//! Resolution of mod dependencies into a consistent set of mod releases.
+//!
+//! Given the releases of every mod that may be involved, the [`Resolver`] picks one release per mod such that:
+//! 1. every release can be loaded by the target game version;
+//! 1. every required dependency is present and satisfies its version requirement;
+//! 1. every optional dependency that happens to be present satisfies its version requirement; and
+//! 1. no two releases are incompatible with each other.
+//!
+//! Newer releases are preferred. When the newest release of a mod leads to a conflict, older releases are tried before
+//! giving up. The resolver is pure: fetching mods from the portal is left to the caller.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use factorio_http_api::mod_portal::{Dependency, Mod, ModVersion};
+//! use factorio_http_api::mod_portal::resolve::Resolver;
+//!
+//! let mods: Vec<Mod> = serde_json::from_str(r#"[{
+//!     "name": "foo",
+//!     "title": "Foo",
+//!     "owner": "someone",
+//!     "releases": [{
+//!         "download_url": "/download/foo/1",
+//!         "file_name": "foo_1.0.0.zip",
+//!         "info_json": {"factorio_version": "1.1", "dependencies": ["base >= 1.1"]},
+//!         "released_at": "2023-01-01T00:00:00.000000Z",
+//!         "version": "1.0.0",
+//!         "sha1": "abc"
+//!     }]
+//! }]"#)?;
+//!
+//! let resolver = Resolver::new(&mods, ModVersion::new(1, 1, 110));
+//! let resolution = resolver.resolve(&[Dependency::required("foo")])?;
+//! assert_eq!(resolution.mods["foo"].version, ModVersion::new(1, 0, 0));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use super::{Dependency, DependencyKind, Mod, ModVersion, Release};
+
+/// Mods that ship with the game and are never downloaded from the portal. Their version is the game version.
+pub const BUILTIN_MODS: &[&str] = &["base", "core", "elevated-rails", "quality", "space-age"];
+
+/// Whether `name` is one of the [`BUILTIN_MODS`].
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN_MODS.contains(&name)
+}
+
+/// Who declared a [`Requirement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirer {
+    /// The set of mods passed to [`Resolver::resolve`].
+    Requested,
+
+    /// A release of a mod.
+    Mod { name: String, version: ModVersion },
+}
+
+impl fmt::Display for Requirer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirer::Requested => write!(f, "the requested mods"),
+            Requirer::Mod { name, version } => write!(f, "{} {}", name, version),
+        }
+    }
+}
+
+/// A [`Dependency`] along with who declared it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub required_by: Requirer,
+    pub dependency: Dependency,
+}
+
+impl Requirement {
+    /// Whether `version` of the depended-on mod may be present alongside the requirer.
+    fn allows(&self, version: &ModVersion) -> bool {
+        allows(&self.dependency, version)
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} declares \"{}\"", self.required_by, self.dependency)
+    }
+}
+
+/// Whether `dependency` allows `version` of its mod to be present. Incompatible dependencies forbid the versions that
+/// match them, every other kind of dependency forbids the versions that do not.
+fn allows(dependency: &Dependency, version: &ModVersion) -> bool {
+    match dependency.kind {
+        DependencyKind::Incompatible => !dependency.matches(version),
+        _ => dependency.matches(version),
+    }
+}
+
+/// Why a release was not picked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// The release targets a game version that cannot load it.
+    GameVersion(ModVersion),
+
+    /// A requirement on the mod excludes the release.
+    Requirement(Requirement),
+
+    /// One of the release's own dependencies excludes the already picked `version` of another mod.
+    Dependency {
+        dependency: Dependency,
+        version: ModVersion,
+    },
+
+    /// The release's dependencies could not be resolved.
+    Unresolvable(Box<ResolveError>),
+}
+
+/// A release that was not picked, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRelease {
+    pub version: ModVersion,
+    pub reason: Rejection,
+}
+
+/// An error explaining why no consistent set of mods exists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// A required mod is neither built in nor known to the resolver.
+    UnknownMod {
+        name: String,
+        required_by: Vec<Requirer>,
+    },
+
+    /// No release of a required mod satisfies every constraint.
+    Unsatisfiable {
+        name: String,
+        requirements: Vec<Requirement>,
+        rejected: Vec<RejectedRelease>,
+    },
+}
+
+impl ResolveError {
+    /// Write the error, indenting nested errors by `depth` levels.
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            ResolveError::UnknownMod { name, required_by } => {
+                let required_by = required_by.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+                write!(f, "{}mod {} is required by {} but is unknown", indent, name, required_by.join(", "))
+            },
+            ResolveError::Unsatisfiable { name, requirements, rejected } => {
+                write!(f, "{}no release of {} satisfies every constraint", indent, name)?;
+                for requirement in requirements {
+                    write!(f, "\n{}  - {}", indent, requirement)?;
+                }
+
+                for RejectedRelease { version, reason } in rejected {
+                    write!(f, "\n{}  {} {} was rejected: ", indent, name, version)?;
+                    match reason {
+                        Rejection::GameVersion(factorio_version) => write!(
+                            f,
+                            "it targets factorio {}.{}",
+                            factorio_version.major, factorio_version.minor
+                        )?,
+                        Rejection::Requirement(requirement) => write!(f, "{}", requirement)?,
+                        Rejection::Dependency { dependency, version } => write!(
+                            f,
+                            "it declares \"{}\" but {} {} was picked",
+                            dependency, dependency.name, version
+                        )?,
+                        Rejection::Unresolvable(error) => {
+                            writeln!(f, "its dependencies could not be resolved")?;
+                            error.fmt_indented(f, depth + 2)?;
+                        },
+                    }
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl Error for ResolveError {}
+
+/// A consistent set of mod releases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution {
+    /// The picked release of every mod, keyed by mod name. Builtin mods are not included.
+    pub mods: BTreeMap<String, Release>,
+
+    /// The builtin mods that were required.
+    pub builtins: BTreeSet<String>,
+}
+
+/// The partial solution explored by the resolver.
+#[derive(Debug, Clone)]
+struct State {
+    /// The picked release of each mod.
+    picked: BTreeMap<String, Release>,
+
+    /// The builtin mods that are present. `base` is always present.
+    builtins: BTreeSet<String>,
+
+    /// Every requirement declared by the requested mods and the picked releases.
+    requirements: Vec<Requirement>,
+
+    /// Mods that are required but not yet picked, in the order they were first required.
+    pending: Vec<String>,
+}
+
+impl State {
+    /// The version of `name` if it is present.
+    fn version_of(&self, name: &str, game_version: &ModVersion) -> Option<ModVersion> {
+        if self.builtins.contains(name) {
+            Some(*game_version)
+        } else {
+            self.picked.get(name).map(|release| release.version)
+        }
+    }
+
+    /// Add the requirements declared by `required_by`, queueing the required mods that are not yet present.
+    fn require<'a, I: IntoIterator<Item = &'a Dependency>>(&mut self, required_by: Requirer, dependencies: I) {
+        for dependency in dependencies {
+            let name = &dependency.name;
+            if dependency.kind.is_required()
+                && !self.builtins.contains(name)
+                && !self.picked.contains_key(name)
+                && !self.pending.contains(name)
+            {
+                self.pending.push(name.clone());
+            }
+
+            self.requirements.push(Requirement {
+                required_by: required_by.clone(),
+                dependency: dependency.clone(),
+            });
+        }
+    }
+
+    /// The requirements on the mod `name`.
+    fn requirements_on(&self, name: &str) -> Vec<Requirement> {
+        self.requirements
+            .iter()
+            .filter(|requirement| requirement.dependency.name == name)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Resolves dependencies against a known set of mods for a particular game version.
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    mods: HashMap<&'a str, &'a Mod>,
+    game_version: ModVersion,
+}
+
+impl<'a> Resolver<'a> {
+    /// Create a resolver over `mods` for the `game_version`. Mods that are not in `mods` are considered unknown, unless
+    /// they are [`BUILTIN_MODS`].
+    pub fn new<I: IntoIterator<Item = &'a Mod>>(mods: I, game_version: ModVersion) -> Self {
+        Self {
+            mods: mods.into_iter().map(|m| (m.name.as_str(), m)).collect(),
+            game_version,
+        }
+    }
+
+    /// Pick a release of every mod required, directly or transitively, by `requested`.
+    ///
+    /// Requested dependencies of any kind are honored: `"foo >= 2.0"` requires foo, `"? foo < 3.0"` constrains foo if
+    /// something else requires it, and `"! foo"` forbids it.
+    ///
+    /// # Errors
+    /// Returns a [`ResolveError`] explaining the conflict if no consistent set of releases exists.
+    pub fn resolve(&self, requested: &[Dependency]) -> Result<Resolution, ResolveError> {
+        let mut state = State {
+            picked: BTreeMap::new(),
+            builtins: BTreeSet::from(["base".to_owned()]),
+            requirements: Vec::new(),
+            pending: Vec::new(),
+        };
+
+        state.require(Requirer::Requested, requested);
+        if let Some(error) = self.check_builtin(&state, "base") {
+            return Err(error);
+        }
+
+        let state = self.solve(state)?;
+        Ok(Resolution {
+            mods: state.picked,
+            builtins: state.builtins,
+        })
+    }
+
+    /// Check every requirement on the builtin mod `name` against the game version.
+    fn check_builtin(&self, state: &State, name: &str) -> Option<ResolveError> {
+        let requirements = state.requirements_on(name);
+        let rejected = requirements
+            .iter()
+            .filter(|requirement| !requirement.allows(&self.game_version))
+            .map(|requirement| RejectedRelease {
+                version: self.game_version,
+                reason: Rejection::Requirement(requirement.clone()),
+            })
+            .collect::<Vec<_>>();
+
+        if rejected.is_empty() {
+            None
+        } else {
+            Some(ResolveError::Unsatisfiable {
+                name: name.to_owned(),
+                requirements,
+                rejected,
+            })
+        }
+    }
+
+    /// Pick a release for the first pending mod and recurse, backtracking on failure.
+    fn solve(&self, mut state: State) -> Result<State, ResolveError> {
+        if state.pending.is_empty() {
+            return Ok(state);
+        }
+
+        let name = state.pending.remove(0);
+        if is_builtin(&name) {
+            state.builtins.insert(name.clone());
+            return match self.check_builtin(&state, &name) {
+                Some(error) => Err(error),
+                None => self.solve(state),
+            };
+        }
+
+        let requirements = state.requirements_on(&name);
+        let Some(candidate_mod) = self.mods.get(name.as_str()) else {
+            return Err(ResolveError::UnknownMod {
+                name,
+                required_by: requirements
+                    .into_iter()
+                    .filter(|requirement| requirement.dependency.kind.is_required())
+                    .map(|requirement| requirement.required_by)
+                    .collect(),
+            });
+        };
+
+        let mut releases = candidate_mod.releases.iter().collect::<Vec<_>>();
+        releases.sort_by_key(|release| std::cmp::Reverse(release.version));
+
+        let mut rejected = Vec::new();
+        for release in releases {
+            match self.try_release(&state, &name, release, &requirements) {
+                Ok(solved) => return Ok(solved),
+                Err(reason) => rejected.push(RejectedRelease {
+                    version: release.version,
+                    reason,
+                }),
+            }
+        }
+
+        // when every release fails for the same reason further down, the choice of release is irrelevant to the
+        // conflict, so report the conflict itself
+        if let Some(RejectedRelease { reason: Rejection::Unresolvable(first), .. }) = rejected.first() {
+            let all_same = rejected.iter().all(|rejection| match &rejection.reason {
+                Rejection::Unresolvable(error) => error == first,
+                _ => false,
+            });
+
+            if all_same {
+                return Err(*first.clone());
+            }
+        }
+
+        Err(ResolveError::Unsatisfiable {
+            name,
+            requirements,
+            rejected,
+        })
+    }
+
+    /// Try to pick `release` of the mod `name` and solve the remaining pending mods.
+    fn try_release(
+        &self,
+        state: &State,
+        name: &str,
+        release: &Release,
+        requirements: &[Requirement],
+    ) -> Result<State, Rejection> {
+        if !release.info_json.factorio_version.is_loadable_by(&self.game_version) {
+            return Err(Rejection::GameVersion(release.info_json.factorio_version));
+        }
+
+        if let Some(requirement) = requirements.iter().find(|r| !r.allows(&release.version)) {
+            return Err(Rejection::Requirement(requirement.clone()));
+        }
+
+        for dependency in &release.info_json.dependencies {
+            if let Some(version) = state.version_of(&dependency.name, &self.game_version) {
+                if !allows(dependency, &version) {
+                    return Err(Rejection::Dependency {
+                        dependency: dependency.clone(),
+                        version,
+                    });
+                }
+            }
+        }
+
+        let mut next = state.clone();
+        next.picked.insert(name.to_owned(), release.clone());
+        next.require(
+            Requirer::Mod {
+                name: name.to_owned(),
+                version: release.version,
+            },
+            &release.info_json.dependencies,
+        );
+
+        self.solve(next).map_err(|error| Rejection::Unresolvable(Box::new(error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::InfoJson;
+    use super::*;
+
+    const GAME: ModVersion = ModVersion::new(1, 1, 110);
+
+    /// Create a release of `version` for factorio 1.1 with the given dependencies.
+    fn release(version: &str, dependencies: &[&str]) -> Release {
+        release_for("1.1", version, dependencies)
+    }
+
+    /// Create a release of `version` for the `factorio_version` with the given dependencies.
+    fn release_for(factorio_version: &str, version: &str, dependencies: &[&str]) -> Release {
+        Release {
+            download_url: format!("/download/{}", version),
+            file_name: format!("mod_{}.zip", version),
+            info_json: InfoJson {
+                factorio_version: factorio_version.parse().unwrap(),
+                dependencies: dependencies.iter().map(|d| d.parse().unwrap()).collect(),
+            },
+            released_at: "2024-01-01T00:00:00.000000Z".to_owned(),
+            version: version.parse().unwrap(),
+            sha1: "0".repeat(40),
+        }
+    }
+
+    fn portal_mod(name: &str, releases: Vec<Release>) -> Mod {
+        Mod {
+            name: name.to_owned(),
+            title: name.to_owned(),
+            owner: "someone".to_owned(),
+            summary: String::new(),
+            downloads_count: 0,
+            releases,
+        }
+    }
+
+    fn deps(dependencies: &[&str]) -> Vec<Dependency> {
+        dependencies.iter().map(|d| d.parse().unwrap()).collect()
+    }
+
+    fn versions(resolution: &Resolution) -> Vec<(&str, String)> {
+        resolution
+            .mods
+            .iter()
+            .map(|(name, release)| (name.as_str(), release.version.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_empty() {
+        let resolution = Resolver::new(&[], GAME).resolve(&[]).unwrap();
+        assert!(resolution.mods.is_empty());
+        assert_eq!(resolution.builtins, BTreeSet::from(["base".to_owned()]));
+    }
+
+    #[test]
+    fn test_picks_newest() {
+        let mods = [portal_mod(
+            "foo",
+            vec![release("1.0.0", &[]), release("1.2.0", &[]), release("1.1.0", &[])],
+        )];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["foo"])).unwrap();
+        assert_eq!(versions(&resolution), vec![("foo", "1.2.0".to_owned())]);
+    }
+
+    #[test]
+    fn test_skips_other_game_versions() {
+        let mods = [portal_mod(
+            "foo",
+            vec![
+                release_for("1.0", "0.9.0", &[]),
+                release_for("1.1", "1.0.0", &[]),
+                release_for("2.0", "2.0.0", &[]),
+            ],
+        )];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["foo"])).unwrap();
+        assert_eq!(versions(&resolution), vec![("foo", "1.0.0".to_owned())]);
+    }
+
+    #[test]
+    fn test_requested_version_requirement() {
+        let mods = [portal_mod(
+            "foo",
+            vec![release("1.0.0", &[]), release("1.1.0", &[]), release("1.2.0", &[])],
+        )];
+
+        let resolver = Resolver::new(&mods, GAME);
+        let resolution = resolver.resolve(&deps(&["foo < 1.2.0"])).unwrap();
+        assert_eq!(versions(&resolution), vec![("foo", "1.1.0".to_owned())]);
+
+        let resolution = resolver.resolve(&deps(&["foo = 1.0.0"])).unwrap();
+        assert_eq!(versions(&resolution), vec![("foo", "1.0.0".to_owned())]);
+    }
+
+    #[test]
+    fn test_transitive() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["base >= 1.1", "b >= 2.0"])]),
+            portal_mod("b", vec![release("1.0.0", &[]), release("2.1.0", &["~ c"])]),
+            portal_mod("c", vec![release("0.1.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("a", "1.0.0".to_owned()),
+                ("b", "2.1.0".to_owned()),
+                ("c", "0.1.0".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_optional_not_pulled_in() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["? b", "(?) c"])]),
+            portal_mod("b", vec![release("1.0.0", &[])]),
+            portal_mod("c", vec![release("1.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a"])).unwrap();
+        assert_eq!(versions(&resolution), vec![("a", "1.0.0".to_owned())]);
+    }
+
+    #[test]
+    fn test_optional_constrains_when_present() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["? b < 2.0"])]),
+            portal_mod("b", vec![release("1.0.0", &[]), release("2.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a", "b"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "1.0.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_optional_constraint_checked_against_picked() {
+        // b is picked before a, so a's optional requirement is checked against the picked release
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &[]), release("2.0.0", &["? b >= 2.0"])]),
+            portal_mod("b", vec![release("1.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["b", "a"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "1.0.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_incompatible_backtracks() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &[]), release("2.0.0", &["! b"])]),
+            portal_mod("b", vec![release("1.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["b", "a"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "1.0.0".to_owned())]
+        );
+
+        // the same result regardless of the order the mods are requested in
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a", "b"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "1.0.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_incompatible_with_version() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["! b < 2.0", "b"])]),
+            portal_mod("b", vec![release("1.0.0", &[]), release("2.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a", "b < 3.0"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "2.0.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_backtracks_through_dependencies() {
+        // a 2.0 needs c >= 2, but b needs c < 2, so a 1.0 must be picked
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["c"]), release("2.0.0", &["c >= 2.0"])]),
+            portal_mod("b", vec![release("1.0.0", &["c < 2.0"])]),
+            portal_mod("c", vec![release("1.0.0", &[]), release("2.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a", "b"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("a", "1.0.0".to_owned()),
+                ("b", "1.0.0".to_owned()),
+                ("c", "1.0.0".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_requested_incompatible() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["b"])]),
+            portal_mod("b", vec![release("1.0.0", &[])]),
+        ];
+
+        let error = Resolver::new(&mods, GAME).resolve(&deps(&["a", "! b"])).unwrap_err();
+        match error {
+            ResolveError::Unsatisfiable { name, requirements, rejected } => {
+                assert_eq!(name, "b");
+                assert_eq!(requirements.len(), 2);
+                assert_eq!(rejected.len(), 1);
+                assert!(matches!(
+                    &rejected[0].reason,
+                    Rejection::Requirement(Requirement { required_by: Requirer::Requested, .. })
+                ));
+            },
+            _ => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_unknown_mod() {
+        let mods = [portal_mod("a", vec![release("1.0.0", &["missing"])])];
+        let error = Resolver::new(&mods, GAME).resolve(&deps(&["a"])).unwrap_err();
+        assert_eq!(
+            error,
+            ResolveError::UnknownMod {
+                name: "missing".to_owned(),
+                required_by: vec![Requirer::Mod {
+                    name: "a".to_owned(),
+                    version: ModVersion::new(1, 0, 0),
+                }],
+            }
+        );
+
+        assert_eq!(error.to_string(), "mod missing is required by a 1.0.0 but is unknown");
+    }
+
+    #[test]
+    fn test_unknown_optional_is_ignored() {
+        let mods = [portal_mod("a", vec![release("1.0.0", &["? missing", "! other-missing"])])];
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a"])).unwrap();
+        assert_eq!(versions(&resolution), vec![("a", "1.0.0".to_owned())]);
+    }
+
+    #[test]
+    fn test_no_release_for_game_version() {
+        let mods = [portal_mod("a", vec![release_for("2.0", "2.0.0", &[])])];
+        let error = Resolver::new(&mods, GAME).resolve(&deps(&["a"])).unwrap_err();
+        assert_eq!(
+            error,
+            ResolveError::Unsatisfiable {
+                name: "a".to_owned(),
+                requirements: vec![Requirement {
+                    required_by: Requirer::Requested,
+                    dependency: Dependency::required("a"),
+                }],
+                rejected: vec![RejectedRelease {
+                    version: ModVersion::new(2, 0, 0),
+                    reason: Rejection::GameVersion(ModVersion::new(2, 0, 0)),
+                }],
+            }
+        );
+
+        assert_eq!(
+            error.to_string(),
+            "no release of a satisfies every constraint\n  - the requested mods declares \"a\"\n  a 2.0.0 was \
+             rejected: it targets factorio 2.0"
+        );
+    }
+
+    #[test]
+    fn test_builtin_requirements() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["base >= 1.1.0"])]),
+            portal_mod("b", vec![release("1.0.0", &["base >= 2.0.0"])]),
+            portal_mod("c", vec![release("1.0.0", &["quality"])]),
+        ];
+
+        let resolver = Resolver::new(&mods, GAME);
+        assert!(resolver.resolve(&deps(&["a"])).is_ok());
+
+        let error = resolver.resolve(&deps(&["b"])).unwrap_err();
+        assert!(matches!(error, ResolveError::Unsatisfiable { ref name, .. } if name == "b"));
+
+        let resolution = resolver.resolve(&deps(&["c"])).unwrap();
+        assert!(resolution.builtins.contains("quality"));
+        assert!(!resolution.mods.contains_key("quality"));
+
+        let error = resolver.resolve(&deps(&["base < 1.0"])).unwrap_err();
+        assert!(matches!(error, ResolveError::Unsatisfiable { ref name, .. } if name == "base"));
+    }
+
+    #[test]
+    fn test_conflict_explanation_collapses_irrelevant_choices() {
+        // every release of a fails because of c, which does not depend on the choice of a
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &[]), release("2.0.0", &[])]),
+            portal_mod("c", vec![release_for("2.0", "1.0.0", &[])]),
+        ];
+
+        let error = Resolver::new(&mods, GAME).resolve(&deps(&["a", "c"])).unwrap_err();
+        assert!(matches!(error, ResolveError::Unsatisfiable { ref name, .. } if name == "c"));
+    }
+
+    #[test]
+    fn test_conflict_explanation_nested() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["c < 1.0"]), release("2.0.0", &["c >= 2.0"])]),
+            portal_mod("b", vec![release("1.0.0", &["c = 1.5.0"])]),
+            portal_mod("c", vec![release("0.5.0", &[]), release("1.5.0", &[]), release("2.0.0", &[])]),
+        ];
+
+        let error = Resolver::new(&mods, GAME).resolve(&deps(&["a", "b"])).unwrap_err();
+        let ResolveError::Unsatisfiable { name, rejected, .. } = &error else {
+            panic!("unexpected error: {:?}", error);
+        };
+
+        assert_eq!(name, "a");
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected
+            .iter()
+            .all(|rejection| matches!(rejection.reason, Rejection::Unresolvable(_))));
+
+        let message = error.to_string();
+        assert!(message.contains("a 2.0.0 was rejected: its dependencies could not be resolved"));
+        assert!(message.contains("b 1.0.0 declares \"c = 1.5.0\""));
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["b"])]),
+            portal_mod("b", vec![release("1.0.0", &["a >= 1.0"])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["a"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "1.0.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_dependency_rejects_picked_version() {
+        // b 1.0 is picked first; a 2.0 requires b >= 2 so a 1.0 is used instead
+        let mods = [
+            portal_mod("a", vec![release("1.0.0", &["b"]), release("2.0.0", &["b >= 2.0"])]),
+            portal_mod("b", vec![release("1.0.0", &[])]),
+        ];
+
+        let resolution = Resolver::new(&mods, GAME).resolve(&deps(&["b", "a"])).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("a", "1.0.0".to_owned()), ("b", "1.0.0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_factorio_1_0_loads_0_18_mods() {
+        let mods = [portal_mod("a", vec![release_for("0.18", "0.1.0", &[])])];
+        let resolution = Resolver::new(&mods, ModVersion::new(1, 0, 0))
+            .resolve(&deps(&["a"]))
+            .unwrap();
+        assert_eq!(versions(&resolution), vec![("a", "0.1.0".to_owned())]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Versions of mods and of the game, as used by the mod portal and `info.json` files.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The version of a mod, or of the game itself. Factorio versions are made of three `u16` parts, `major.minor.patch`.
+///
+/// The game version a release targets (`factorio_version` in `info.json`) only has two parts, so the patch component
+/// may be omitted when parsing and defaults to `0`.
+///
+/// # Examples
+/// ```
+/// use factorio_http_api::mod_portal::ModVersion;
+/// let version: ModVersion = "1.1.110".parse().unwrap();
+/// assert_eq!(version, ModVersion::new(1, 1, 110));
+/// assert_eq!("1.1".parse::<ModVersion>().unwrap(), ModVersion::new(1, 1, 0));
+/// assert_eq!(version.to_string(), "1.1.110");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl ModVersion {
+    /// Create a new version from its parts.
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Whether a mod released for the game version `self` can be loaded by the game version `game`.
+    ///
+    /// Only the major and minor parts are compared. As a special case, Factorio 1.0 loads mods released for 0.18.
+    ///
+    /// # Examples
+    /// ```
+    /// use factorio_http_api::mod_portal::ModVersion;
+    /// assert!(ModVersion::new(1, 1, 0).is_loadable_by(&ModVersion::new(1, 1, 110)));
+    /// assert!(ModVersion::new(0, 18, 0).is_loadable_by(&ModVersion::new(1, 0, 0)));
+    /// assert!(!ModVersion::new(1, 1, 0).is_loadable_by(&ModVersion::new(2, 0, 7)));
+    /// ```
+    pub fn is_loadable_by(&self, game: &ModVersion) -> bool {
+        (self.major, self.minor) == (game.major, game.minor)
+            || ((self.major, self.minor) == (0, 18) && (game.major, game.minor) == (1, 0))
+    }
+}
+
+impl From<&semver::Version> for ModVersion {
+    /// Convert a game version from the download API. Parts that do not fit in a `u16` saturate.
+    fn from(version: &semver::Version) -> Self {
+        let part = |p: u64| u16::try_from(p).unwrap_or(u16::MAX);
+        Self::new(part(version.major), part(version.minor), part(version.patch))
+    }
+}
+
+impl fmt::Display for ModVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// An error returned when parsing a [`ModVersion`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseModVersionError(String);
+
+impl fmt::Display for ParseModVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid version {:?}, expected major.minor or major.minor.patch", self.0)
+    }
+}
+
+impl std::error::Error for ParseModVersionError {}
+
+impl FromStr for ModVersion {
+    type Err = ParseModVersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseModVersionError(s.to_owned());
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u16>().map_err(|_| error()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match parts[..] {
+            [major, minor] => Ok(Self::new(major, minor, 0)),
+            [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl Serialize for ModVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ModVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("0.6.0".parse(), Ok(ModVersion::new(0, 6, 0)));
+        assert_eq!("1.1".parse(), Ok(ModVersion::new(1, 1, 0)));
+        assert_eq!(" 2.0.7 ".parse(), Ok(ModVersion::new(2, 0, 7)));
+        assert!("1".parse::<ModVersion>().is_err());
+        assert!("1.2.3.4".parse::<ModVersion>().is_err());
+        assert!("1.a.3".parse::<ModVersion>().is_err());
+        assert!("1.2.-3".parse::<ModVersion>().is_err());
+        assert!("1.2.70000".parse::<ModVersion>().is_err());
+        assert!("".parse::<ModVersion>().is_err());
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(ModVersion::new(0, 10, 0) > ModVersion::new(0, 9, 99));
+        assert!(ModVersion::new(1, 0, 0) > ModVersion::new(0, 18, 47));
+        assert!(ModVersion::new(1, 1, 2) < ModVersion::new(1, 1, 10));
+    }
+
+    #[test]
+    fn test_is_loadable_by() {
+        let game = ModVersion::new(1, 1, 110);
+        assert!(ModVersion::new(1, 1, 0).is_loadable_by(&game));
+        assert!(!ModVersion::new(1, 0, 0).is_loadable_by(&game));
+        assert!(!ModVersion::new(2, 0, 0).is_loadable_by(&game));
+        assert!(ModVersion::new(0, 18, 0).is_loadable_by(&ModVersion::new(1, 0, 0)));
+        assert!(!ModVersion::new(0, 17, 0).is_loadable_by(&ModVersion::new(1, 0, 0)));
+    }
+
+    #[test]
+    fn test_from_semver() {
+        let version = semver::Version::new(2, 0, 28);
+        assert_eq!(ModVersion::from(&version), ModVersion::new(2, 0, 28));
+    }
+
+    #[test]
+    fn test_serde() {
+        let version: ModVersion = serde_json::from_str(r#""1.1""#).unwrap();
+        assert_eq!(version, ModVersion::new(1, 1, 0));
+        assert_eq!(serde_json::to_string(&version).unwrap(), r#""1.1.0""#);
+        assert!(serde_json::from_str::<ModVersion>(r#""one""#).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Semantic differences between two settings documents, like a server's settings and the defaults.
+//!
+//! Documents are compared field by field through their JSON values, so a difference has the same path as in
+//! [`Layered::explain`](crate::Layered::explain). Numbers are compared by value, so `1` equals `1.0`, and each type can
+//! treat other spellings of a value as equal, like the [`MapGenSize`] names `big` and `high`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{MapAndDifficultySettings, MapGenSettings, MapGenSize, ServerSettings, ServerSettingsWithSecrets};
+
+/// A field whose value differs between two documents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    /// The path to the field, a `.`-separated list of object keys.
+    pub path: String,
+
+    /// The value in the old document, or [`None`] if the field was added.
+    pub old: Option<Value>,
+
+    /// The value in the new document, or [`None`] if the field was removed.
+    pub new: Option<Value>,
+
+    /// What changed, like `changed from "normal" (1) to "very-high" (2)`.
+    pub description: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+impl Difference {
+    /// The difference of the field at `path` from `old` to `new`, with values described by `describe`.
+    pub fn new(path: String, old: Option<Value>, new: Option<Value>, describe: impl Fn(&Value) -> String) -> Self {
+        let description = match (&old, &new) {
+            (Some(old), Some(new)) => format!("changed from {} to {}", describe(old), describe(new)),
+            (Some(old), None) => format!("removed, was {}", describe(old)),
+            (None, Some(new)) => format!("added as {}", describe(new)),
+            (None, None) => "unchanged".to_owned(),
+        };
+
+        Self {
+            path,
+            old,
+            new,
+            description,
+        }
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Settings that can be compared field by field.
+pub trait Diff: Serialize {
+    /// Whether `old` and `new`, which differ as JSON, mean the same at `path`.
+    fn equivalent(_path: &str, _old: &Value, _new: &Value) -> bool {
+        false
+    }
+
+    /// Describe `value` at `path` for a human.
+    fn describe(_path: &str, value: &Value) -> String {
+        value.to_string()
+    }
+
+    /// Every field that differs from `self` to `new`, in order of their paths.
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod_config::{Diff, MapGenSettings, MapGenSize};
+    /// let old = MapGenSettings::default();
+    /// let mut new = MapGenSettings::default();
+    /// new.autoplace_controls.get_mut("coal").unwrap().size = MapGenSize::VeryBig;
+    /// new.autoplace_controls.get_mut("stone").unwrap().size = MapGenSize::Normal;
+    ///
+    /// let differences = old.diff(&new);
+    /// assert_eq!(differences.len(), 1);
+    /// assert_eq!(differences[0].to_string(), "autoplace_controls.coal.size: changed from 1.0 to \"very-big\" (2)");
+    /// ```
+    fn diff(&self, new: &Self) -> Vec<Difference> {
+        let old = serde_json::to_value(self).expect("settings serialize to JSON");
+        let new = serde_json::to_value(new).expect("settings serialize to JSON");
+        let mut differences = Vec::new();
+        diff_values::<Self>("", Some(&old), Some(&new), &mut differences);
+        differences
+    }
+}
+
+fn diff_values<T: Diff + ?Sized>(
+    path: &str,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    differences: &mut Vec<Difference>,
+) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut keys: Vec<_> = old.keys().chain(new.keys().filter(|key| !old.contains_key(*key))).collect();
+            keys.sort();
+            for key in keys {
+                diff_values::<T>(&join_path(path, key), old.get(key), new.get(key), differences);
+            }
+        },
+        (Some(Value::Number(old)), Some(Value::Number(new))) if old.as_f64() == new.as_f64() => {},
+        (Some(old), Some(new)) if old == new || T::equivalent(path, old, new) => {},
+        (None, None) => {},
+        (old, new) => differences.push(Difference::new(path.to_owned(), old.cloned(), new.cloned(), |value| {
+            T::describe(path, value)
+        })),
+    }
+}
+
+/// The value of the [`MapGenSize`] at `path`, if `path` is the frequency, size or richness of an autoplace control.
+fn map_gen_size(path: &str, value: &Value) -> Option<f32> {
+    let (parent, field) = path.rsplit_once('.')?;
+    let autoplace = parent.starts_with("autoplace_controls.") || parent.starts_with("autoplace_settings.");
+    if !autoplace || !["frequency", "size", "richness"].contains(&field) {
+        return None;
+    }
+
+    MapGenSize::deserialize(value).ok().map(f32::from)
+}
+
+impl Diff for MapGenSettings {
+    fn equivalent(path: &str, old: &Value, new: &Value) -> bool {
+        matches!((map_gen_size(path, old), map_gen_size(path, new)), (Some(old), Some(new)) if old == new)
+    }
+
+    fn describe(path: &str, value: &Value) -> String {
+        match map_gen_size(path, value) {
+            Some(size) if value.is_string() => format!("{} ({})", value, size),
+            _ => value.to_string(),
+        }
+    }
+}
+
+impl Diff for MapAndDifficultySettings {}
+
+impl Diff for ServerSettings {}
+
+impl Diff for ServerSettingsWithSecrets {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_map_gen_sizes_by_value() {
+        let mut old = MapGenSettings::default();
+        let mut new = MapGenSettings::default();
+        old.autoplace_controls.get_mut("coal").unwrap().frequency = MapGenSize::Big;
+        new.autoplace_controls.get_mut("coal").unwrap().frequency = MapGenSize::High;
+        assert!(old.diff(&new).is_empty());
+
+        new.autoplace_controls.get_mut("coal").unwrap().frequency = MapGenSize::VeryHigh;
+        let differences = old.diff(&new);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].old, Some(json!("big")));
+        assert_eq!(differences[0].new, Some(json!("very-high")));
+        assert_eq!(differences[0].description, "changed from \"big\" (1.4142135) to \"very-high\" (2)");
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        let old = MapGenSettings::default();
+        let mut new = MapGenSettings::default();
+        new.autoplace_controls.remove("trees");
+        new.property_expression_names.insert("elevation".to_owned(), "0_17-island".to_owned());
+        new.seed = Some(1234);
+
+        let descriptions: Vec<_> = old.diff(&new).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            descriptions,
+            [
+                "autoplace_controls.trees: removed, was {\"frequency\":1.0,\"richness\":1.0,\"size\":1.0}",
+                "property_expression_names.elevation: added as \"0_17-island\"",
+                "seed: changed from null to 1234",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_settings() {
+        let old = MapAndDifficultySettings::default();
+        let mut new = MapAndDifficultySettings::default();
+        assert!(old.diff(&new).is_empty());
+
+        new.enemy_expansion.enabled = false;
+        new.difficulty_settings.technology_price_multiplier = 4.0;
+        let paths: Vec<_> = old.diff(&new).into_iter().map(|difference| difference.path).collect();
+        assert_eq!(paths, ["difficulty_settings.technology_price_multiplier", "enemy_expansion.enabled"]);
+    }
+
+    #[test]
+    fn test_server_settings() {
+        let old = ServerSettings::default();
+        let new = ServerSettings {
+            max_players: 8,
+            ..Default::default()
+        };
+
+        let differences = old.diff(&new);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].to_string(), "max_players: changed from 0 to 8");
+    }
+
+    #[test]
+    fn test_numbers_by_value() {
+        let mut differences = Vec::new();
+        diff_values::<ServerSettings>("", Some(&json!({"a": 1})), Some(&json!({"a": 1.0})), &mut differences);
+        assert!(differences.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! The formats config files can be written in. The game only reads JSON, so files in other formats are converted.
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use serde_json::Value;
+
+/// The format of a config file, selected by its extension.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::ConfigFormat;
+/// assert_eq!(ConfigFormat::from_path("server-settings.toml"), Some(ConfigFormat::Toml));
+/// assert_eq!(ConfigFormat::from_path("server-settings.yml"), Some(ConfigFormat::Yaml));
+/// assert_eq!(ConfigFormat::from_path("server-settings.txt"), None);
+///
+/// let value = ConfigFormat::Toml.parse(b"name = \"my server\"\n[visibility]\npublic = false\n").unwrap();
+/// assert_eq!(value, serde_json::json!({"name": "my server", "visibility": {"public": false}}));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Every supported format, in the order their files are looked for.
+    pub const ALL: [ConfigFormat; 3] = [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml];
+
+    /// The file extensions of the format.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ConfigFormat::Json => &["json"],
+            ConfigFormat::Toml => &["toml"],
+            ConfigFormat::Yaml => &["yaml", "yml"],
+        }
+    }
+
+    /// The format of the file at `path`, from its extension. Returns [`None`] for unsupported extensions.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension))
+    }
+
+    /// Parse `contents` in this format into a JSON value.
+    pub fn parse(self, contents: &[u8]) -> Result<Value, FormatError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_slice(contents).map_err(FormatError::Json),
+            ConfigFormat::Toml => {
+                let contents = std::str::from_utf8(contents).map_err(|_| FormatError::InvalidUtf8)?;
+                toml::from_str(contents).map_err(FormatError::Toml)
+            },
+            ConfigFormat::Yaml => serde_norway::from_slice(contents).map_err(FormatError::Yaml),
+        }
+    }
+
+    /// Write `value` in this format. Comments and formatting of a file that was parsed are not kept.
+    pub fn to_vec(self, value: &Value) -> Result<Vec<u8>, FormatError> {
+        match self {
+            ConfigFormat::Json => {
+                let mut contents = serde_json::to_vec_pretty(value)?;
+                contents.push(b'\n');
+                Ok(contents)
+            },
+            ConfigFormat::Toml => toml::to_string_pretty(value)
+                .map(String::into_bytes)
+                .map_err(FormatError::TomlWrite),
+            ConfigFormat::Yaml => serde_norway::to_string(value).map(String::into_bytes).map_err(FormatError::Yaml),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Toml => write!(f, "TOML"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// An error returned when a config file cannot be parsed, or its contents do not match the config's type.
+#[derive(Debug)]
+pub enum FormatError {
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+
+    /// The value cannot be written as TOML, like a `null`, which TOML cannot represent.
+    TomlWrite(toml::ser::Error),
+    Yaml(serde_norway::Error),
+
+    /// TOML files must be UTF-8.
+    InvalidUtf8,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Json(e) => write!(f, "{}", e),
+            FormatError::Toml(e) => write!(f, "{}", e),
+            FormatError::TomlWrite(e) => write!(f, "{}", e),
+            FormatError::Yaml(e) => write!(f, "{}", e),
+            FormatError::InvalidUtf8 => write!(f, "invalid UTF-8"),
+        }
+    }
+}
+
+impl Error for FormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FormatError::Json(e) => Some(e),
+            FormatError::Toml(e) => Some(e),
+            FormatError::TomlWrite(e) => Some(e),
+            FormatError::Yaml(e) => Some(e),
+            FormatError::InvalidUtf8 => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for FormatError {
+    fn from(e: serde_json::Error) -> Self {
+        FormatError::Json(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_formats_agree() {
+        let expected = json!({"name": "foo", "tags": ["a", "b"], "max_players": 4, "visibility": {"lan": true}});
+        let json = br#"{"name": "foo", "tags": ["a", "b"], "max_players": 4, "visibility": {"lan": true}}"#;
+        let toml = b"name = \"foo\"\ntags = [\"a\", \"b\"]\nmax_players = 4\n\n[visibility]\nlan = true\n";
+        let yaml = b"name: foo\ntags: [a, b]\nmax_players: 4\nvisibility:\n  lan: true\n";
+        assert_eq!(ConfigFormat::Json.parse(json).unwrap(), expected);
+        assert_eq!(ConfigFormat::Toml.parse(toml).unwrap(), expected);
+        assert_eq!(ConfigFormat::Yaml.parse(yaml).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let value = json!({"name": "foo", "tags": ["a", "b"], "visibility": {"lan": true}});
+        for format in ConfigFormat::ALL {
+            let written = format.to_vec(&value).unwrap();
+            assert_eq!(format.parse(&written).unwrap(), value, "{}", format);
+        }
+
+        assert!(matches!(ConfigFormat::Toml.to_vec(&json!({"a": null})), Err(FormatError::TomlWrite(_))));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(ConfigFormat::Toml.parse(b"name = "), Err(FormatError::Toml(_))));
+        assert!(matches!(ConfigFormat::Toml.parse(&[0xff]), Err(FormatError::InvalidUtf8)));
+        assert!(matches!(ConfigFormat::Yaml.parse(b"a: [1"), Err(FormatError::Yaml(_))));
+    }
+}
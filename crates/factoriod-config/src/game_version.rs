@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A version of the game, as recorded in the headers of saves and other binary files written by the game.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::GameVersion;
+/// let version = GameVersion::new(1, 1, 110, 60000);
+/// assert_eq!(version.to_string(), "1.1.110 (build 60000)");
+/// assert!(version < GameVersion::new(2, 0, 0, 0));
+/// assert_eq!("2.0".parse::<GameVersion>().unwrap(), GameVersion::new(2, 0, 0, 0));
+/// assert_eq!("1.1.110".parse::<GameVersion>().unwrap(), GameVersion::new(1, 1, 110, 0));
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GameVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    pub build: u16,
+}
+
+impl GameVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16, build: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            build,
+        }
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{} (build {})", self.major, self.minor, self.patch, self.build)
+    }
+}
+
+/// An error returned when parsing a [`GameVersion`] that is not two to four `.`-separated numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGameVersionError(pub String);
+
+impl fmt::Display for ParseGameVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid game version {}, expected a version like 2.0 or 1.1.110", self.0)
+    }
+}
+
+impl Error for ParseGameVersionError {}
+
+impl FromStr for GameVersion {
+    type Err = ParseGameVersionError;
+
+    /// Parse a version like `2.0`, `1.1.110` or `1.1.110.60000`. Missing parts are 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseGameVersionError(s.to_owned());
+        let parts = s
+            .split('.')
+            .map(|part| part.parse::<u16>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error())?;
+
+        match parts[..] {
+            [major, minor] => Ok(Self::new(major, minor, 0, 0)),
+            [major, minor, patch] => Ok(Self::new(major, minor, patch, 0)),
+            [major, minor, patch, build] => Ok(Self::new(major, minor, patch, build)),
+            _ => Err(error()),
+        }
+    }
+}
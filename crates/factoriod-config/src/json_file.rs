@@ -0,0 +1,112 @@
+//! Loading and saving config files as JSON.
+
+use std::io::Write;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Write `contents` to `path` atomically: the contents are written to a temporary file in the same directory, which then
+/// replaces `path`. Readers see either the old or the new contents, never a partial file. A replaced file keeps its
+/// permissions and, where the writer may give it away, its owner.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"))?;
+
+    let mut temp_name = file_name.to_owned();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = std::fs::File::create(&temp_path)?;
+    match std::fs::metadata(path) {
+        Ok(metadata) => copy_owner_and_permissions(&file, &metadata)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e),
+    }
+
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temp_path, path)
+}
+
+/// Give `file` the permissions and owner in `metadata`. Changing the owner fails unless the writer is privileged, in
+/// which case the writer keeps it.
+fn copy_owner_and_permissions(file: &std::fs::File, metadata: &std::fs::Metadata) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid())) {
+            Err(e) if e.kind() != std::io::ErrorKind::PermissionDenied => return Err(e),
+            _ => {},
+        }
+    }
+
+    file.set_permissions(metadata.permissions())
+}
+
+/// Load a JSON config file, or the default value if it does not exist.
+pub fn load_json<T: DeserializeOwned + Default, P: AsRef<Path>>(path: P) -> std::io::Result<T> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Save a config file as pretty-printed JSON with [`write_atomic`].
+pub fn save_json<T: Serialize, P: AsRef<Path>>(path: P, value: &T) -> std::io::Result<()> {
+    let mut contents = serde_json::to_vec_pretty(value)?;
+    contents.push(b'\n');
+    write_atomic(path, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.json");
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"two");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.json");
+        write_atomic(&path, b"one").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(&path, b"two").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_load_json_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let value: Vec<String> = load_json(dir.path().join("missing.json")).unwrap();
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.json");
+        save_json(&path, &vec!["a", "b"]).unwrap();
+        assert_eq!(load_json::<Vec<String>, _>(&path).unwrap(), vec!["a", "b"]);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(
+            load_json::<Vec<String>, _>(&path).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,372 @@
+//! Layered configuration, where partial documents are deep-merged over built-in defaults.
+//!
+//! A config named `server-settings` in a configuration directory is made of these layers, from lowest to highest
+//! precedence:
+//! 1. the built-in defaults, from the type's [`Default`] implementation;
+//! 2. `server-settings.json`;
+//! 3. the drop-ins `server-settings.d/*.json`, in lexical order of their file names.
+//!
+//! Any of these files may be written in another [`ConfigFormat`] instead, like `server-settings.toml`.
+//!
+//! Objects are merged key by key. Any other value, including an array, replaces the value beneath it. The source of
+//! every value in the effective document is tracked so it can be explained.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::ConfigFormat;
+
+/// The source of the values from the built-in defaults.
+pub const DEFAULTS_SOURCE: &str = "defaults";
+
+/// Deep-merge `overlay` into `base`. Objects are merged recursively, and any other value in `overlay` replaces the
+/// value in `base`.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::merge;
+/// use serde_json::json;
+/// let mut base = json!({"a": {"b": 1, "c": 2}, "d": [1, 2]});
+/// merge(&mut base, json!({"a": {"b": 3}, "d": [3]}));
+/// assert_eq!(base, json!({"a": {"b": 3, "c": 2}, "d": [3]}));
+/// ```
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// The parts of `overlay` that differ from `base`, or [`None`] if merging `overlay` into `base` would change nothing.
+fn changes(base: &Value, overlay: Value) -> Option<Value> {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) if !overlay.is_empty() => {
+            let changed: Map<String, Value> = overlay
+                .into_iter()
+                .filter_map(|(key, value)| match base.get(&key) {
+                    Some(existing) => changes(existing, value).map(|value| (key, value)),
+                    None => Some((key, value)),
+                })
+                .collect();
+
+            Some(Value::Object(changed)).filter(|changed| !changed.as_object().is_some_and(Map::is_empty))
+        },
+        (base, overlay) => Some(overlay).filter(|overlay| overlay != base),
+    }
+}
+
+/// Visit every leaf of `value`: values that are not objects, or are empty objects.
+fn visit_leaves<'a>(prefix: &str, value: &'a Value, visit: &mut impl FnMut(String, &'a Value)) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                visit_leaves(&join_path(prefix, key), value, visit);
+            }
+        },
+        _ => visit(prefix.to_owned(), value),
+    }
+}
+
+/// A document built from layers, with the source of each of its values.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{Layered, ServerSettings};
+/// use serde_json::json;
+/// let mut layered = Layered::new(&ServerSettings::default()).unwrap();
+/// layered.push("server-settings.json", json!({"name": "my server", "visibility": {"lan": false}}));
+///
+/// let settings: ServerSettings = layered.deserialize().unwrap();
+/// assert_eq!(settings.name, "my server");
+/// assert!(settings.visibility.public);
+/// assert_eq!(layered.source("visibility.lan"), Some("server-settings.json"));
+/// assert_eq!(layered.source("visibility.public"), Some("defaults"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layered {
+    value: Value,
+    sources: BTreeMap<String, String>,
+}
+
+impl Layered {
+    /// Start from the built-in defaults.
+    pub fn new<T: Serialize>(defaults: &T) -> serde_json::Result<Self> {
+        let mut layered = Self {
+            value: Value::Object(Map::new()),
+            sources: BTreeMap::new(),
+        };
+
+        layered.push(DEFAULTS_SOURCE, serde_json::to_value(defaults)?);
+        Ok(layered)
+    }
+
+    /// Merge a layer over the current document, attributing its values to `source`.
+    pub fn push<S: Into<String>>(&mut self, source: S, layer: Value) {
+        let source = source.into();
+        let mut leaves = Vec::new();
+        visit_leaves("", &layer, &mut |path, _| leaves.push(path));
+        for path in leaves {
+            // values beneath or above a replaced leaf no longer exist
+            let nested = format!("{}.", path);
+            self.sources
+                .retain(|existing, _| !existing.starts_with(&nested) && !path.starts_with(&format!("{}.", existing)));
+            self.sources.insert(path, source.clone());
+        }
+
+        merge(&mut self.value, layer);
+    }
+
+    /// Like [`push`](Self::push), but only the values of `layer` that differ from the current document are
+    /// attributed to `source`. Useful for a layer that is a complete document, like a preset.
+    pub fn push_changes<S: Into<String>>(&mut self, source: S, layer: Value) {
+        if let Some(changed) = changes(&self.value, layer) {
+            self.push(source, changed);
+        }
+    }
+
+    /// The effective document.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Deserialize the effective document.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        T::deserialize(&self.value)
+    }
+
+    /// The source of the value at `path`, a `.`-separated list of object keys.
+    pub fn source(&self, path: &str) -> Option<&str> {
+        self.sources.get(path).map(String::as_str)
+    }
+
+    /// Every value in the effective document with its path and source, in document order.
+    pub fn explain(&self) -> Vec<(String, &Value, &str)> {
+        let mut explained = Vec::new();
+        visit_leaves("", &self.value, &mut |path, value| {
+            let source = self.sources.get(&path).map(String::as_str).unwrap_or(DEFAULTS_SOURCE);
+            explained.push((path, value, source));
+        });
+
+        explained
+    }
+}
+
+/// An error returned when the files of a config cannot be listed.
+#[derive(Debug)]
+pub enum LayerPathsError {
+    /// The directory could not be read.
+    Io { path: PathBuf, source: std::io::Error },
+
+    /// More than one file provides the same layer, in different formats.
+    Ambiguous(Vec<PathBuf>),
+}
+
+impl fmt::Display for LayerPathsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayerPathsError::Io { path, source } => write!(f, "failed to read {}: {}", path.display(), source),
+            LayerPathsError::Ambiguous(paths) => write!(
+                f,
+                "{} are the same config in different formats, only one may exist",
+                paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" and ")
+            ),
+        }
+    }
+}
+
+impl Error for LayerPathsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LayerPathsError::Io { source, .. } => Some(source),
+            LayerPathsError::Ambiguous(_) => None,
+        }
+    }
+}
+
+/// Group the files in `paths` that have a supported format by their stem, rejecting stems with more than one format.
+fn unique_by_stem(paths: impl IntoIterator<Item = PathBuf>) -> Result<Vec<PathBuf>, LayerPathsError> {
+    let mut by_stem: BTreeMap<OsString, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        if let (Some(stem), Some(_)) = (path.file_stem(), ConfigFormat::from_path(&path)) {
+            by_stem.entry(stem.to_owned()).or_default().push(path);
+        }
+    }
+
+    by_stem
+        .into_values()
+        .map(|mut paths| match paths.len() {
+            1 => Ok(paths.remove(0)),
+            _ => {
+                paths.sort();
+                Err(LayerPathsError::Ambiguous(paths))
+            },
+        })
+        .collect()
+}
+
+/// The files that make up the config `name` in `config_dir`, from lowest to highest precedence: `{name}.json`, then
+/// `{name}.d/*.json` sorted by file name. Files that do not exist are skipped.
+///
+/// Each file may instead be written in any [`ConfigFormat`], like `{name}.toml`, but a layer that exists in more than
+/// one format is an error.
+pub fn layer_paths<P: AsRef<Path>>(config_dir: P, name: &str) -> Result<Vec<PathBuf>, LayerPathsError> {
+    let config_dir = config_dir.as_ref();
+    let bases = ConfigFormat::ALL
+        .iter()
+        .flat_map(|format| format.extensions())
+        .map(|extension| config_dir.join(format!("{}.{}", name, extension)))
+        .filter(|path| path.is_file());
+
+    let mut paths = unique_by_stem(bases)?;
+    let drop_in_dir = config_dir.join(format!("{}.d", name));
+    if drop_in_dir.is_dir() {
+        let io_error = |source| LayerPathsError::Io {
+            path: drop_in_dir.clone(),
+            source,
+        };
+
+        let drop_ins = drop_in_dir
+            .read_dir()
+            .map_err(io_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(io_error)?;
+
+        paths.extend(unique_by_stem(drop_ins.into_iter().filter(|path| path.is_file()))?);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_replaces_non_objects() {
+        let mut base = json!({"a": {"b": 1}, "c": 1});
+        merge(&mut base, json!({"a": 2, "c": {"d": 3}}));
+        assert_eq!(base, json!({"a": 2, "c": {"d": 3}}));
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut layered = Layered::new(&json!({"a": {"b": 1, "c": 2}, "d": 3, "e": [1]})).unwrap();
+        layered.push("one", json!({"a": {"b": 10}, "e": [2, 3]}));
+        layered.push("two", json!({"a": {"c": 20}}));
+        assert_eq!(layered.value(), &json!({"a": {"b": 10, "c": 20}, "d": 3, "e": [2, 3]}));
+        assert_eq!(
+            layered
+                .explain()
+                .into_iter()
+                .map(|(path, _, source)| (path, source.to_owned()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a.b".to_owned(), "one".to_owned()),
+                ("a.c".to_owned(), "two".to_owned()),
+                ("d".to_owned(), "defaults".to_owned()),
+                ("e".to_owned(), "one".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_push_changes() {
+        let mut layered = Layered::new(&json!({"a": {"b": 1, "c": 2}, "d": [1]})).unwrap();
+        layered.push_changes("preset", json!({"a": {"b": 1, "c": 3}, "d": [1], "e": {}}));
+        assert_eq!(layered.value(), &json!({"a": {"b": 1, "c": 3}, "d": [1], "e": {}}));
+        assert_eq!(layered.source("a.b"), Some(DEFAULTS_SOURCE));
+        assert_eq!(layered.source("a.c"), Some("preset"));
+        assert_eq!(layered.source("d"), Some(DEFAULTS_SOURCE));
+        assert_eq!(layered.source("e"), Some("preset"));
+
+        let unchanged = layered.clone();
+        layered.push_changes("again", unchanged.value().clone());
+        assert_eq!(layered, unchanged);
+    }
+
+    #[test]
+    fn test_sources_replaced_objects() {
+        let mut layered = Layered::new(&json!({"a": {"b": 1}, "c": 1})).unwrap();
+        layered.push("one", json!({"a": 2, "c": {"d": 3}}));
+        assert_eq!(layered.source("a"), Some("one"));
+        assert_eq!(layered.source("a.b"), None);
+        assert_eq!(layered.source("c"), None);
+        assert_eq!(layered.source("c.d"), Some("one"));
+    }
+
+    #[test]
+    fn test_layer_paths_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("foo.toml"), "").unwrap();
+        std::fs::create_dir(dir.path().join("foo.d")).unwrap();
+        std::fs::write(dir.path().join("foo.d/10-a.yml"), "").unwrap();
+        std::fs::write(dir.path().join("foo.d/20-b.json"), "{}").unwrap();
+        assert_eq!(
+            layer_paths(dir.path(), "foo").unwrap(),
+            vec![
+                dir.path().join("foo.toml"),
+                dir.path().join("foo.d/10-a.yml"),
+                dir.path().join("foo.d/20-b.json"),
+            ]
+        );
+
+        std::fs::write(dir.path().join("foo.d/20-b.yaml"), "").unwrap();
+        match layer_paths(dir.path(), "foo") {
+            Err(LayerPathsError::Ambiguous(paths)) => assert_eq!(
+                paths,
+                vec![dir.path().join("foo.d/20-b.json"), dir.path().join("foo.d/20-b.yaml")]
+            ),
+            other => panic!("expected an ambiguous layer, got {:?}", other),
+        }
+
+        std::fs::write(dir.path().join("foo.json"), "{}").unwrap();
+        assert!(matches!(layer_paths(dir.path(), "foo"), Err(LayerPathsError::Ambiguous(_))));
+    }
+
+    #[test]
+    fn test_layer_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(layer_paths(dir.path(), "foo").unwrap().is_empty());
+
+        std::fs::write(dir.path().join("foo.json"), "{}").unwrap();
+        std::fs::create_dir(dir.path().join("foo.d")).unwrap();
+        std::fs::write(dir.path().join("foo.d/20-b.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("foo.d/10-a.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("foo.d/README"), "").unwrap();
+        assert_eq!(
+            layer_paths(dir.path(), "foo").unwrap(),
+            vec![
+                dir.path().join("foo.json"),
+                dir.path().join("foo.d/10-a.json"),
+                dir.path().join("foo.d/20-b.json"),
+            ]
+        );
+    }
+}
@@ -1,10 +1,40 @@
 //! The *config* crate helps configure a Factorio server by creating and updating various config JSON files.
 //! It provides a Rust interface for various [Factorio Lua Concepts](https://lua-api.factorio.com/latest/concepts.html).
 
+mod diff;
+mod format;
+mod game_version;
+mod json_file;
+mod layered;
+mod map_exchange_string;
 mod map_gen_settings;
 mod map_settings;
+mod migration;
+mod mod_settings;
+mod mods;
+mod player_lists;
+mod presets;
+mod property_tree;
+mod schema;
 mod server_settings;
+mod validation;
+mod version_check;
 
+pub use diff::*;
+pub use format::*;
+pub use game_version::*;
+pub use json_file::*;
+pub use layered::*;
+pub use map_exchange_string::*;
 pub use map_gen_settings::*;
 pub use map_settings::*;
+pub use migration::*;
+pub use mod_settings::*;
+pub use mods::*;
+pub use player_lists::*;
+pub use presets::*;
+pub use property_tree::*;
+pub use schema::*;
 pub use server_settings::*;
+pub use validation::*;
+pub use version_check::*;
@@ -0,0 +1,907 @@
+//! Map exchange strings, the `>>>eNp...<<<` strings players use to share map settings.
+//!
+//! An exchange string is the base64 encoding of a zlib-deflated payload, between `>>>` and `<<<`. The game wraps it
+//! in whitespace, which is ignored. The payload is followed by the CRC32 checksum of the payload, and all integers are
+//! little-endian:
+//! ```text
+//! u16 x4   game version (major, minor, patch, build)
+//! u8       unused, always 0
+//! ...      map gen settings
+//! ...      map settings, each value preceded by a u8 that is 1 when the value is present
+//! ...      difficulty settings
+//! u32      CRC32 of everything above
+//! ```
+//!
+//! Counts and string lengths are space optimized: a `u8`, or `0xFF` followed by a `u32`. Positions are fixed-point
+//! `i32`s in 1/256ths of a tile.
+//!
+//! The layout follows Factorio 1.1. Values the game stores that the config types do not model, like
+//! `area_to_generate_at_start`, are skipped when decoding and written as zeros when encoding.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::{
+    AutoplaceControl, AutoplaceSettings, CliffPlacementSettings, DifficultySettings, EnemyEvolutionMapSettings,
+    EnemyExpansionMapSettings, GameVersion, MapAndDifficultySettings, MapGenSettings, MapGenSize, MapGenSizeFloat,
+    MapPosition, PathFinderMapSettings, PollutionMapSettings, RecipeDifficulty, ResearchQueueSetting,
+    SteeringMapSetting, SteeringMapSettings, TechnologyDifficulty, UnitGroupMapSettings,
+};
+
+const PREFIX: &str = ">>>";
+const SUFFIX: &str = "<<<";
+
+/// The major and minor version of the game whose layout is decoded and encoded.
+const SUPPORTED_VERSION: (u16, u16) = (1, 1);
+
+/// An error returned when a map exchange string cannot be decoded.
+#[derive(Debug)]
+pub enum MapExchangeStringError {
+    /// The string is not surrounded by `>>>` and `<<<`.
+    MissingDelimiters,
+
+    /// The string is not valid base64.
+    Base64(base64::DecodeError),
+
+    /// The payload could not be inflated.
+    Inflate(std::io::Error),
+
+    /// The payload does not match its checksum.
+    ChecksumMismatch { expected: u32, actual: u32 },
+
+    /// The payload ended before all settings were read.
+    Truncated,
+
+    /// The payload has bytes after the settings.
+    TrailingData(usize),
+
+    /// A string in the payload is not valid UTF-8.
+    InvalidUtf8,
+
+    /// A value in the payload is out of range for its setting.
+    InvalidValue(String),
+
+    /// The string was created by a version of the game whose layout is not supported.
+    UnsupportedVersion(GameVersion),
+}
+
+impl fmt::Display for MapExchangeStringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapExchangeStringError::MissingDelimiters => {
+                write!(f, "map exchange strings start with {} and end with {}", PREFIX, SUFFIX)
+            },
+            MapExchangeStringError::Base64(e) => write!(f, "map exchange string is not valid base64: {}", e),
+            MapExchangeStringError::Inflate(e) => write!(f, "failed to inflate map exchange string: {}", e),
+            MapExchangeStringError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "map exchange string checksum is {:08x}, expected {:08x}",
+                actual, expected
+            ),
+            MapExchangeStringError::Truncated => write!(f, "map exchange string is truncated"),
+            MapExchangeStringError::TrailingData(len) => {
+                write!(f, "map exchange string has {} unexpected bytes after the settings", len)
+            },
+            MapExchangeStringError::InvalidUtf8 => write!(f, "map exchange string contains invalid UTF-8"),
+            MapExchangeStringError::InvalidValue(message) => {
+                write!(f, "invalid value in map exchange string: {}", message)
+            },
+            MapExchangeStringError::UnsupportedVersion(version) => write!(
+                f,
+                "map exchange string is from factorio {}, only strings from {}.{} are supported",
+                version, SUPPORTED_VERSION.0, SUPPORTED_VERSION.1
+            ),
+        }
+    }
+}
+
+impl Error for MapExchangeStringError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MapExchangeStringError::Base64(e) => Some(e),
+            MapExchangeStringError::Inflate(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, MapExchangeStringError>;
+
+/// A value in the binary payload of a map exchange string.
+trait Binary: Sized {
+    fn write(&self, writer: &mut Vec<u8>);
+    fn read(reader: &mut &[u8]) -> Result<Self>;
+}
+
+fn read_array<const N: usize>(reader: &mut &[u8]) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes).map_err(|_| MapExchangeStringError::Truncated)?;
+    Ok(bytes)
+}
+
+macro_rules! binary_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Binary for $ty {
+                fn write(&self, writer: &mut Vec<u8>) {
+                    writer.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(reader: &mut &[u8]) -> Result<Self> {
+                    Ok(<$ty>::from_le_bytes(read_array(reader)?))
+                }
+            }
+        )*
+    };
+}
+
+binary_number!(u8, u16, u32, i32, f32, f64);
+
+impl Binary for bool {
+    fn write(&self, writer: &mut Vec<u8>) {
+        u8::from(*self).write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(u8::read(reader)? != 0)
+    }
+}
+
+fn write_count(writer: &mut Vec<u8>, count: usize) {
+    match u8::try_from(count) {
+        Ok(count) if count < 0xFF => count.write(writer),
+        _ => {
+            0xFFu8.write(writer);
+            (count as u32).write(writer);
+        },
+    }
+}
+
+fn read_count(reader: &mut &[u8]) -> Result<usize> {
+    let count = match u8::read(reader)? {
+        0xFF => u32::read(reader)?,
+        count => count.into(),
+    };
+
+    // every item is at least a byte, so larger counts can only come from a corrupt payload
+    let count = count as usize;
+    if count > reader.len() {
+        return Err(MapExchangeStringError::Truncated);
+    }
+
+    Ok(count)
+}
+
+impl Binary for String {
+    fn write(&self, writer: &mut Vec<u8>) {
+        write_count(writer, self.len());
+        writer.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        let len = read_count(reader)?;
+        let mut bytes = vec![0; len];
+        reader.read_exact(&mut bytes).map_err(|_| MapExchangeStringError::Truncated)?;
+        String::from_utf8(bytes).map_err(|_| MapExchangeStringError::InvalidUtf8)
+    }
+}
+
+impl<T: Binary> Binary for Vec<T> {
+    fn write(&self, writer: &mut Vec<u8>) {
+        write_count(writer, self.len());
+        for item in self {
+            item.write(writer);
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        (0..read_count(reader)?).map(|_| T::read(reader)).collect()
+    }
+}
+
+/// Maps are written sorted by key, so encoding is deterministic.
+impl<T: Binary> Binary for HashMap<String, T> {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let mut entries = self.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        write_count(writer, entries.len());
+        for (key, value) in entries {
+            key.write(writer);
+            value.write(writer);
+        }
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        (0..read_count(reader)?)
+            .map(|_| Ok((String::read(reader)?, T::read(reader)?)))
+            .collect()
+    }
+}
+
+impl Binary for MapGenSize {
+    fn write(&self, writer: &mut Vec<u8>) {
+        f32::from(self.clone()).write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        let value = f32::read(reader)?;
+        MapGenSizeFloat::try_from(value)
+            .map(MapGenSize::Float)
+            .map_err(|e| MapExchangeStringError::InvalidValue(format!("map gen size {}: {:?}", value, e)))
+    }
+}
+
+impl Binary for AutoplaceControl {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.frequency.write(writer);
+        self.size.write(writer);
+        self.richness.write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            frequency: MapGenSize::read(reader)?,
+            size: MapGenSize::read(reader)?,
+            richness: MapGenSize::read(reader)?,
+        })
+    }
+}
+
+impl Binary for AutoplaceSettings {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.treat_missing_as_default.write(writer);
+        self.settings.write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            treat_missing_as_default: bool::read(reader)?,
+            settings: HashMap::read(reader)?,
+        })
+    }
+}
+
+impl Binary for CliffPlacementSettings {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.name.write(writer);
+        self.cliff_elevation_0.write(writer);
+        self.cliff_elevation_interval.write(writer);
+        self.richness.write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            name: String::read(reader)?,
+            cliff_elevation_0: f32::read(reader)?,
+            cliff_elevation_interval: f32::read(reader)?,
+            richness: f32::read(reader)?,
+        })
+    }
+}
+
+impl Binary for MapPosition {
+    fn write(&self, writer: &mut Vec<u8>) {
+        ((self.x * 256.0).round() as i32).write(writer);
+        ((self.y * 256.0).round() as i32).write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            x: f64::from(i32::read(reader)?) / 256.0,
+            y: f64::from(i32::read(reader)?) / 256.0,
+        })
+    }
+}
+
+impl Binary for MapGenSettings {
+    fn write(&self, writer: &mut Vec<u8>) {
+        // the 1.1 layout requires the water settings, which 2.0 moved to the `water` autoplace control
+        self.terrain_segmentation.unwrap_or(1.0).write(writer);
+        self.water.unwrap_or(1.0).write(writer);
+        self.autoplace_controls.write(writer);
+        self.autoplace_settings.write(writer);
+
+        // default_enable_all_autoplace_controls
+        true.write(writer);
+        self.seed.unwrap_or(0).write(writer);
+        self.width.write(writer);
+        self.height.write(writer);
+
+        // area_to_generate_at_start, as left top and right bottom positions
+        for _ in 0..4 {
+            0i32.write(writer);
+        }
+
+        self.starting_area.write(writer);
+        self.peaceful_mode.write(writer);
+        self.starting_points.write(writer);
+        self.property_expression_names.write(writer);
+        self.cliff_settings.write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        let terrain_segmentation = f32::read(reader)?;
+        let water = f32::read(reader)?;
+        let autoplace_controls = HashMap::read(reader)?;
+        let autoplace_settings = HashMap::read(reader)?;
+        let _default_enable_all_autoplace_controls = bool::read(reader)?;
+        let seed = Some(u32::read(reader)?);
+        let width = u32::read(reader)?;
+        let height = u32::read(reader)?;
+        for _ in 0..4 {
+            i32::read(reader)?;
+        }
+
+        Ok(Self {
+            terrain_segmentation: Some(terrain_segmentation),
+            water: Some(water),
+            autoplace_controls,
+            autoplace_settings,
+            seed,
+            width,
+            height,
+            starting_area: f32::read(reader)?,
+            peaceful_mode: bool::read(reader)?,
+            no_enemies_mode: None,
+            territory_settings: None,
+            starting_points: Vec::read(reader)?,
+            property_expression_names: HashMap::read(reader)?,
+            cliff_settings: CliffPlacementSettings::read(reader)?,
+        })
+    }
+}
+
+/// Map settings whose values are each preceded by a flag that is set when the value is present. Missing values keep
+/// their defaults.
+trait OptionalFields {
+    fn write_fields(&self, writer: &mut Vec<u8>);
+    fn read_fields(&mut self, reader: &mut &[u8]) -> Result<()>;
+}
+
+fn write_optional<T: Binary>(writer: &mut Vec<u8>, value: &T) {
+    true.write(writer);
+    value.write(writer);
+}
+
+fn read_optional<T: Binary>(reader: &mut &[u8], value: &mut T) -> Result<()> {
+    if bool::read(reader)? {
+        *value = T::read(reader)?;
+    }
+
+    Ok(())
+}
+
+macro_rules! optional_fields {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl OptionalFields for $ty {
+            fn write_fields(&self, writer: &mut Vec<u8>) {
+                $(write_optional(writer, &self.$field);)*
+            }
+
+            fn read_fields(&mut self, reader: &mut &[u8]) -> Result<()> {
+                $(read_optional(reader, &mut self.$field)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+optional_fields!(PollutionMapSettings {
+    enabled,
+    diffusion_ratio,
+    min_to_diffuse,
+    ageing,
+    expected_max_per_chunk,
+    min_to_show_per_chunk,
+    min_pollution_to_damage_trees,
+    pollution_with_max_forest_damage,
+    pollution_per_tree_damage,
+    pollution_restored_per_tree_damage,
+    max_pollution_to_restore_trees,
+    enemy_attack_pollution_consumption_modifier,
+});
+
+optional_fields!(SteeringMapSetting {
+    radius,
+    separation_factor,
+    separation_force,
+    force_unit_fuzzy_goto_behavior,
+});
+
+optional_fields!(EnemyEvolutionMapSettings {
+    enabled,
+    time_factor,
+    destroy_factor,
+    pollution_factor,
+});
+
+optional_fields!(EnemyExpansionMapSettings {
+    enabled,
+    max_expansion_distance,
+    friendly_base_influence_radius,
+    enemy_building_influence_radius,
+    building_coefficient,
+    other_base_coefficient,
+    neighbouring_chunk_coefficient,
+    neighbouring_base_chunk_coefficient,
+    max_colliding_tiles_coefficient,
+    settler_group_min_size,
+    settler_group_max_size,
+    min_expansion_cooldown,
+    max_expansion_cooldown,
+});
+
+optional_fields!(UnitGroupMapSettings {
+    min_group_gathering_time,
+    max_group_gathering_time,
+    max_wait_time_for_late_members,
+    min_group_radius,
+    max_group_radius,
+    max_member_speedup_when_behind,
+    max_member_slowdown_when_ahead,
+    max_group_slowdown_factor,
+    max_group_member_fallback_factor,
+    member_disown_distance,
+    tick_tolerance_when_member_arrives,
+    max_gathering_unit_groups,
+    max_unit_group_size,
+});
+
+optional_fields!(PathFinderMapSettings {
+    fwd_2_bwd_ratio,
+    goal_pressure_ratio,
+    max_steps_worked_per_tick,
+    max_work_done_per_tick,
+    use_path_cache,
+    short_cache_size,
+    long_cache_size,
+    short_cache_min_cacheable_distance,
+    short_cache_min_algo_steps_to_cache,
+    long_cache_min_cacheable_distance,
+    cache_max_connect_to_cache_steps_multiplier,
+    cache_accept_path_start_distance_ratio,
+    cache_accept_path_end_distance_ratio,
+    negative_cache_accept_path_start_distance_ratio,
+    negative_cache_accept_path_end_distance_ratio,
+    cache_path_start_distance_rating_multiplier,
+    cache_path_end_distance_rating_multiplier,
+    stale_enemy_with_same_destination_collision_penalty,
+    ignore_moving_enemy_collision_distance,
+    enemy_with_different_destination_collision_penalty,
+    general_entity_collision_penalty,
+    general_entity_subsequent_collision_penalty,
+    extended_collision_penalty,
+    max_clients_to_accept_any_new_request,
+    max_clients_to_accept_short_new_request,
+    direct_distance_to_consider_short_request,
+    short_request_max_steps,
+    short_request_ratio,
+    min_steps_to_check_path_find_termination,
+    start_to_goal_cost_multiplier_to_terminate_path_find,
+    overload_levels,
+    overload_multipliers,
+    negative_path_cache_delay_interval,
+});
+
+impl Binary for RecipeDifficulty {
+    fn write(&self, writer: &mut Vec<u8>) {
+        match self {
+            RecipeDifficulty::Normal => 0u8,
+            RecipeDifficulty::Expensive => 1,
+        }
+        .write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        match u8::read(reader)? {
+            0 => Ok(RecipeDifficulty::Normal),
+            1 => Ok(RecipeDifficulty::Expensive),
+            value => Err(MapExchangeStringError::InvalidValue(format!("recipe difficulty {}", value))),
+        }
+    }
+}
+
+impl Binary for TechnologyDifficulty {
+    fn write(&self, writer: &mut Vec<u8>) {
+        match self {
+            TechnologyDifficulty::Normal => 0u8,
+            TechnologyDifficulty::Expensive => 1,
+        }
+        .write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        match u8::read(reader)? {
+            0 => Ok(TechnologyDifficulty::Normal),
+            1 => Ok(TechnologyDifficulty::Expensive),
+            value => Err(MapExchangeStringError::InvalidValue(format!("technology difficulty {}", value))),
+        }
+    }
+}
+
+impl Binary for ResearchQueueSetting {
+    fn write(&self, writer: &mut Vec<u8>) {
+        match self {
+            ResearchQueueSetting::AfterVictory => 0u8,
+            ResearchQueueSetting::Always => 1,
+            ResearchQueueSetting::Never => 2,
+        }
+        .write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        match u8::read(reader)? {
+            0 => Ok(ResearchQueueSetting::AfterVictory),
+            1 => Ok(ResearchQueueSetting::Always),
+            2 => Ok(ResearchQueueSetting::Never),
+            value => Err(MapExchangeStringError::InvalidValue(format!("research queue setting {}", value))),
+        }
+    }
+}
+
+impl Binary for DifficultySettings {
+    fn write(&self, writer: &mut Vec<u8>) {
+        // the 1.1 layout has no room for the fields added in 2.0, and requires those removed in 2.0
+        self.recipe_difficulty.unwrap_or(RecipeDifficulty::Normal).write(writer);
+        self.technology_difficulty.unwrap_or(TechnologyDifficulty::Normal).write(writer);
+        self.technology_price_multiplier.write(writer);
+        self.research_queue_setting.unwrap_or(ResearchQueueSetting::AfterVictory).write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            recipe_difficulty: Some(RecipeDifficulty::read(reader)?),
+            technology_difficulty: Some(TechnologyDifficulty::read(reader)?),
+            technology_price_multiplier: f64::read(reader)?,
+            research_queue_setting: Some(ResearchQueueSetting::read(reader)?),
+            spoil_time_modifier: None,
+        })
+    }
+}
+
+impl Binary for MapAndDifficultySettings {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.pollution.write_fields(writer);
+        self.steering.default.write_fields(writer);
+        self.steering.moving.write_fields(writer);
+        self.enemy_evolution.write_fields(writer);
+        self.enemy_expansion.write_fields(writer);
+        self.unit_group.write_fields(writer);
+        self.path_finder.write_fields(writer);
+        write_optional(writer, &self.max_failed_behavior_count);
+        self.difficulty_settings.write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        let mut settings = Self::default();
+        settings.pollution.read_fields(reader)?;
+        let SteeringMapSettings { default, moving } = &mut settings.steering;
+        default.read_fields(reader)?;
+        moving.read_fields(reader)?;
+        settings.enemy_evolution.read_fields(reader)?;
+        settings.enemy_expansion.read_fields(reader)?;
+        settings.unit_group.read_fields(reader)?;
+        settings.path_finder.read_fields(reader)?;
+        read_optional(reader, &mut settings.max_failed_behavior_count)?;
+        settings.difficulty_settings = DifficultySettings::read(reader)?;
+        Ok(settings)
+    }
+}
+
+impl Binary for GameVersion {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.major.write(writer);
+        self.minor.write(writer);
+        self.patch.write(writer);
+        self.build.write(writer);
+    }
+
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            major: u16::read(reader)?,
+            minor: u16::read(reader)?,
+            patch: u16::read(reader)?,
+            build: u16::read(reader)?,
+        })
+    }
+}
+
+/// The settings shared by a map exchange string.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{GameVersion, MapAndDifficultySettings, MapExchangeString, MapGenSettings};
+/// let exchange = MapExchangeString {
+///     version: GameVersion::new(1, 1, 110, 0),
+///     map_gen_settings: MapGenSettings { seed: Some(1234), ..Default::default() },
+///     map_settings: MapAndDifficultySettings::default(),
+/// };
+///
+/// let encoded = exchange.to_string();
+/// assert!(encoded.starts_with(">>>") && encoded.ends_with("<<<"));
+///
+/// let decoded: MapExchangeString = encoded.parse().unwrap();
+/// assert_eq!(decoded.version, exchange.version);
+/// assert_eq!(decoded.map_gen_settings.seed, Some(1234));
+/// ```
+#[derive(Debug)]
+pub struct MapExchangeString {
+    /// The version of the game that created the string.
+    pub version: GameVersion,
+
+    /// The map generation settings, as in `map-gen-settings.json`.
+    pub map_gen_settings: MapGenSettings,
+
+    /// The map and difficulty settings, as in `map-settings.json`.
+    pub map_settings: MapAndDifficultySettings,
+}
+
+impl MapExchangeString {
+    /// Decode a map exchange string. Whitespace, including the line breaks the game adds, is ignored. Strings from
+    /// versions of the game other than 1.1 are rejected with [`MapExchangeStringError::UnsupportedVersion`], since
+    /// their layout differs.
+    pub fn decode(exchange_string: &str) -> Result<Self> {
+        let encoded = exchange_string
+            .trim()
+            .strip_prefix(PREFIX)
+            .and_then(|s| s.strip_suffix(SUFFIX))
+            .ok_or(MapExchangeStringError::MissingDelimiters)?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+
+        let compressed = STANDARD.decode(encoded).map_err(MapExchangeStringError::Base64)?;
+        let mut data = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut data)
+            .map_err(MapExchangeStringError::Inflate)?;
+
+        if data.len() < 4 {
+            return Err(MapExchangeStringError::Truncated);
+        }
+
+        let (payload, checksum) = data.split_at(data.len() - 4);
+        let expected = u32::from_le_bytes(checksum.try_into().expect("checksum is 4 bytes"));
+        let actual = crc32fast::hash(payload);
+        if expected != actual {
+            return Err(MapExchangeStringError::ChecksumMismatch { expected, actual });
+        }
+
+        let reader = &mut &payload[..];
+        let version = GameVersion::read(reader)?;
+        if (version.major, version.minor) != SUPPORTED_VERSION {
+            return Err(MapExchangeStringError::UnsupportedVersion(version));
+        }
+
+        let _unused = u8::read(reader)?;
+        let map_gen_settings = MapGenSettings::read(reader)?;
+        let map_settings = MapAndDifficultySettings::read(reader)?;
+        if !reader.is_empty() {
+            return Err(MapExchangeStringError::TrailingData(reader.len()));
+        }
+
+        Ok(Self {
+            version,
+            map_gen_settings,
+            map_settings,
+        })
+    }
+
+    /// Encode the settings as a map exchange string, without line breaks. The layout is always that of 1.1, whatever
+    /// the `version`.
+    pub fn encode(&self) -> String {
+        let mut payload = Vec::new();
+        self.version.write(&mut payload);
+        0u8.write(&mut payload);
+        self.map_gen_settings.write(&mut payload);
+        self.map_settings.write(&mut payload);
+        let checksum = crc32fast::hash(&payload);
+        checksum.write(&mut payload);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        let compressed = encoder
+            .write_all(&payload)
+            .and_then(|_| encoder.finish())
+            .expect("writing to a Vec does not fail");
+
+        format!("{}{}{}", PREFIX, STANDARD.encode(compressed), SUFFIX)
+    }
+}
+
+impl FromStr for MapExchangeString {
+    type Err = MapExchangeStringError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::decode(s)
+    }
+}
+
+impl fmt::Display for MapExchangeString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange() -> MapExchangeString {
+        let mut map_gen_settings = MapGenSettings {
+            seed: Some(123456789),
+            width: 2000,
+            height: 1000,
+            peaceful_mode: true,
+            starting_points: vec![MapPosition { x: 10.5, y: -3.0 }],
+            ..Default::default()
+        };
+
+        map_gen_settings.autoplace_controls.insert(
+            "iron-ore".to_owned(),
+            AutoplaceControl {
+                frequency: MapGenSize::Float(MapGenSizeFloat::try_from(2.0).unwrap()),
+                size: MapGenSize::Float(MapGenSizeFloat::try_from(0.5).unwrap()),
+                richness: MapGenSize::Float(MapGenSizeFloat::try_from(6.0).unwrap()),
+            },
+        );
+
+        map_gen_settings.autoplace_settings.insert(
+            "tile".to_owned(),
+            AutoplaceSettings {
+                treat_missing_as_default: true,
+                settings: HashMap::from([("water".to_owned(), AutoplaceControl::default())]),
+            },
+        );
+
+        let mut map_settings = MapAndDifficultySettings::default();
+        map_settings.pollution.enabled = false;
+        map_settings.enemy_expansion.max_expansion_distance = 3;
+        map_settings.path_finder.overload_levels = vec![0, 200];
+        map_settings.difficulty_settings.research_queue_setting = Some(ResearchQueueSetting::Never);
+        map_settings.difficulty_settings.technology_price_multiplier = 4.0;
+
+        MapExchangeString {
+            version: GameVersion::new(1, 1, 110, 0),
+            map_gen_settings,
+            map_settings,
+        }
+    }
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let exchange = exchange();
+        let encoded = exchange.encode();
+        let decoded = MapExchangeString::decode(&encoded).unwrap();
+        assert_eq!(decoded.version, exchange.version);
+        assert_eq!(json(&decoded.map_gen_settings), json(&exchange.map_gen_settings));
+        assert_eq!(json(&decoded.map_settings), json(&exchange.map_settings));
+        assert_eq!(decoded.encode(), encoded);
+    }
+
+    /// Strings copied from the game, which must be encoded back to the same payload, unlike those built by the tests.
+    #[test]
+    #[ignore = "no strings from the game are checked in yet, see fixtures/exchange-strings/README.md"]
+    fn test_game_strings() {
+        fn payload(exchange_string: &str) -> Vec<u8> {
+            let encoded: String = exchange_string.trim().chars().filter(|c| !c.is_whitespace()).collect();
+            let encoded = &encoded[PREFIX.len()..encoded.len() - SUFFIX.len()];
+            let mut data = Vec::new();
+            ZlibDecoder::new(STANDARD.decode(encoded).unwrap().as_slice())
+                .read_to_end(&mut data)
+                .unwrap();
+            data
+        }
+
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/exchange-strings");
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+            .collect();
+
+        paths.sort();
+        assert!(!paths.is_empty(), "no exchange strings in {}", dir.display());
+        for path in paths {
+            let exchange_string = std::fs::read_to_string(&path).unwrap();
+            let decoded = MapExchangeString::decode(&exchange_string)
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+            assert_eq!((decoded.version.major, decoded.version.minor), SUPPORTED_VERSION, "{}", path.display());
+            assert!(
+                payload(&decoded.encode()) == payload(&exchange_string),
+                "{} is not encoded back as decoded",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_ignores_whitespace() {
+        let encoded = exchange().encode();
+        let body = &encoded[PREFIX.len()..encoded.len() - SUFFIX.len()];
+        let wrapped = body
+            .as_bytes()
+            .chunks(40)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let decoded = MapExchangeString::decode(&format!("  >>>{}<<<\n", wrapped)).unwrap();
+        assert_eq!(decoded.map_gen_settings.seed, Some(123456789));
+    }
+
+    #[test]
+    fn test_decode_missing_values_keep_defaults() {
+        let mut payload = Vec::new();
+        GameVersion::new(1, 1, 110, 0).write(&mut payload);
+        0u8.write(&mut payload);
+        MapGenSettings::default().write(&mut payload);
+
+        // every map setting is missing except the difficulty settings, which are always present
+        let optional_fields = 12 + 4 + 4 + 4 + 13 + 13 + 33 + 1;
+        payload.resize(payload.len() + optional_fields, 0);
+        DifficultySettings::default().write(&mut payload);
+        let checksum = crc32fast::hash(&payload);
+        checksum.write(&mut payload);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&payload).unwrap();
+        let encoded = format!(">>>{}<<<", STANDARD.encode(encoder.finish().unwrap()));
+        let decoded = MapExchangeString::decode(&encoded).unwrap();
+        assert_eq!(json(&decoded.map_settings), json(&MapAndDifficultySettings::default()));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(matches!(
+            MapExchangeString::decode("eNp"),
+            Err(MapExchangeStringError::MissingDelimiters)
+        ));
+
+        assert!(matches!(
+            MapExchangeString::decode(">>>not base64!<<<"),
+            Err(MapExchangeStringError::Base64(_))
+        ));
+
+        // a payload whose last four bytes are not its checksum
+        let mut payload = Vec::new();
+        GameVersion::new(1, 1, 110, 0).write(&mut payload);
+        0u32.write(&mut payload);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&payload).unwrap();
+        let encoded = format!(">>>{}<<<", STANDARD.encode(encoder.finish().unwrap()));
+        assert!(matches!(
+            MapExchangeString::decode(&encoded),
+            Err(MapExchangeStringError::ChecksumMismatch { .. })
+        ));
+
+        // the version is checked before the settings, which are laid out differently in other versions
+        let mut payload = Vec::new();
+        GameVersion::new(2, 0, 28, 0).write(&mut payload);
+        0u8.write(&mut payload);
+        let checksum = crc32fast::hash(&payload);
+        checksum.write(&mut payload);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&payload).unwrap();
+        let encoded = format!(">>>{}<<<", STANDARD.encode(encoder.finish().unwrap()));
+        match MapExchangeString::decode(&encoded) {
+            Err(MapExchangeStringError::UnsupportedVersion(version)) => {
+                assert_eq!(version, GameVersion::new(2, 0, 28, 0))
+            },
+            result => panic!("unexpected result: {:?}", result.map(|exchange| exchange.version)),
+        }
+    }
+}
@@ -1,11 +1,12 @@
 //! Settings for the placement of a resource.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{MapGenSize, MapGenSizeFloat};
 
 /// Settings for the placement of a resource.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct AutoplaceControl {
     /// The frequency of the resource.
     pub frequency: MapGenSize,
@@ -1,6 +1,9 @@
 //! Types for map generation size settings.
 
 use nutype::nutype;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Metadata, NumberValidation, Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// A newtype for a floating point value representing a map generation size. This value must be in the range `[0, 6]`.
@@ -53,6 +56,26 @@ impl From<MapGenSizeFloat> for f32 {
     }
 }
 
+// nutype's derive does not carry the validation over to the schema
+impl JsonSchema for MapGenSizeFloat {
+    fn schema_name() -> String {
+        "MapGenSizeFloat".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::Number.into()),
+            number: Some(Box::new(NumberValidation {
+                minimum: Some(0.0),
+                maximum: Some(6.0),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 /// All possible values for a map generation size. This models the
 /// [`MapGenSize`](https://lua-api.factorio.com/latest/concepts.html#MapGenSize) type from the Factorio Lua API.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -125,6 +148,66 @@ pub enum MapGenSize {
     Float(MapGenSizeFloat),
 }
 
+impl MapGenSize {
+    /// The named sizes, in order.
+    const NAMED: [MapGenSize; 16] = [
+        MapGenSize::None,
+        MapGenSize::VeryLow,
+        MapGenSize::VerySmall,
+        MapGenSize::VeryPoor,
+        MapGenSize::Low,
+        MapGenSize::Small,
+        MapGenSize::Poor,
+        MapGenSize::Normal,
+        MapGenSize::Medium,
+        MapGenSize::Regular,
+        MapGenSize::High,
+        MapGenSize::Big,
+        MapGenSize::Good,
+        MapGenSize::VeryHigh,
+        MapGenSize::VeryBig,
+        MapGenSize::VeryGood,
+    ];
+}
+
+// schemars ignores `#[serde(untagged)]` on a variant and would describe the float as `{"float": <number>}`
+impl JsonSchema for MapGenSize {
+    fn schema_name() -> String {
+        "MapGenSize".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let names = MapGenSize::NAMED
+            .iter()
+            .map(|size| serde_json::to_value(size).expect("named sizes serialize to strings"))
+            .collect();
+
+        let named = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(names),
+            ..Default::default()
+        };
+
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "All possible values for a map generation size. This models the \
+                     [`MapGenSize`](https://lua-api.factorio.com/latest/concepts.html#MapGenSize) type from the \
+                     Factorio Lua API."
+                        .to_owned(),
+                ),
+                ..Default::default()
+            })),
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(vec![named.into(), gen.subschema_for::<MapGenSizeFloat>()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl Default for MapGenSize {
     fn default() -> Self {
         MapGenSize::Float(MapGenSizeFloat::try_from(1.0).expect("valid map gen size"))
@@ -262,7 +345,7 @@ mod tests {
             for (size, json) in sizes.iter() {
                 assert_eq!(serde_json::to_string(&size).expect("valid json"), *json);
                 assert_eq!(
-                    serde_json::from_str::<MapGenSize>(*json).expect("valid json"),
+                    serde_json::from_str::<MapGenSize>(json).expect("valid json"),
                     *size
                 );
             }
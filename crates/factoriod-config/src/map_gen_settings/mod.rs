@@ -2,25 +2,61 @@
 
 mod autoplace_control;
 mod map_gen_size;
+mod names;
 
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub use autoplace_control::*;
 pub use map_gen_size::*;
+pub use names::*;
+
+use crate::{added_in, check_range, removed_in, GameVersion, Validate, ValidationError, VersionCheck, VERSION_2_0};
+
+/// The planets of the Space Age expansion, which requires 2.0, and their autoplace controls. The settings of every
+/// planet are in the same [`MapGenSettings::autoplace_controls`], and the controls of Nauvis, the planet of the base
+/// game, are those not listed here.
+pub const SPACE_AGE_AUTOPLACE_CONTROLS: [(&str, &[&str]); 4] = [
+    (
+        "vulcanus",
+        &["calcite", "sulfuric_acid_geyser", "tungsten_ore", "vulcanus_coal", "vulcanus_volcanism"],
+    ),
+    ("gleba", &["gleba_cliff", "gleba_enemy_base", "gleba_plants", "gleba_stone", "gleba_water"]),
+    ("fulgora", &["fulgora_cliff", "fulgora_islands", "scrap"]),
+    ("aquilo", &["aquilo_crude_oil", "fluorine_vent", "lithium_brine"]),
+];
+
+/// The planet of the autoplace control `name`: one of [`SPACE_AGE_AUTOPLACE_CONTROLS`], or `nauvis`.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::planet_of_autoplace_control;
+/// assert_eq!(planet_of_autoplace_control("scrap"), "fulgora");
+/// assert_eq!(planet_of_autoplace_control("iron-ore"), "nauvis");
+/// ```
+pub fn planet_of_autoplace_control(name: &str) -> &'static str {
+    SPACE_AGE_AUTOPLACE_CONTROLS
+        .iter()
+        .find(|(_, controls)| controls.contains(&name))
+        .map_or("nauvis", |(planet, _)| planet)
+}
 
 /// Settings for the map generator. These settings are used to create a new map and are saved in the
 /// `map-gen-settings.json` file. This struct models the [`MapGenSettings`](https://lua-api.factorio.com/latest/concepts.html#MapGenSettings)
 ///  type in the Factorio Lua API.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct MapGenSettings {
-    /// The inverse of "water scale" in the map generator GUI.
-    pub terrain_segmentation: f32,
+    /// The inverse of "water scale" in the map generator GUI. Removed in 2.0, where it is the frequency of the `water`
+    /// autoplace control.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terrain_segmentation: Option<f32>,
 
     /// The equivalent to "water coverage" in the map generator GUI. Higher coverage means more water in larger oceans.
-    /// Water level = `10 * log2(this value)`
-    pub water: f32,
+    /// Water level = `10 * log2(this value)`. Removed in 2.0, where it is the size of the `water` autoplace control.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub water: Option<f32>,
     pub autoplace_controls: HashMap<String, AutoplaceControl>,
     pub autoplace_settings: HashMap<String, AutoplaceSettings>,
     pub cliff_settings: CliffPlacementSettings,
@@ -39,6 +75,14 @@ pub struct MapGenSettings {
     pub starting_points: Vec<MapPosition>,
     pub peaceful_mode: bool,
 
+    /// Whether no enemies are placed at all, unlike `peaceful_mode`. Added in 2.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_enemies_mode: Option<bool>,
+
+    /// Where the units of the territories of Gleba are placed. Added in 2.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub territory_settings: Option<TerritorySettings>,
+
     /// Overrides for property value generators (map type).
     ///
     /// Leave "elevation" blank to get "normal" terrain.
@@ -56,8 +100,8 @@ pub struct MapGenSettings {
 impl Default for MapGenSettings {
     fn default() -> Self {
         Self {
-            terrain_segmentation: 1.0,
-            water: 1.0,
+            terrain_segmentation: Some(1.0),
+            water: Some(1.0),
             autoplace_controls: HashMap::from(
                 [
                     ("coal", AutoplaceControl::default()),
@@ -80,6 +124,8 @@ impl Default for MapGenSettings {
             starting_area: 1.0,
             starting_points: vec![MapPosition { x: 0.0, y: 0.0 }],
             peaceful_mode: false,
+            no_enemies_mode: None,
+            territory_settings: None,
             property_expression_names: {
                 let mut names = HashMap::new();
                 names.insert(
@@ -98,13 +144,118 @@ impl Default for MapGenSettings {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl MapGenSettings {
+    /// The default settings of the game `version`. [`Default`] is the shape of 1.1, and 2.0 sets the water of the map
+    /// with the `water` autoplace control instead of `terrain_segmentation` and `water`.
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod_config::{GameVersion, MapGenSettings, VersionCheck};
+    /// let version = GameVersion::new(2, 0, 28, 0);
+    /// let settings = MapGenSettings::for_version(&version);
+    /// assert!(settings.water.is_none());
+    /// assert!(settings.autoplace_controls.contains_key("water"));
+    /// assert!(settings.check_version(&version).is_ok());
+    /// assert!(MapGenSettings::default().check_version(&version).is_err());
+    /// ```
+    pub fn for_version(version: &GameVersion) -> Self {
+        let mut settings = Self::default();
+        if *version >= VERSION_2_0 {
+            settings.terrain_segmentation = None;
+            settings.water = None;
+            settings
+                .autoplace_controls
+                .insert("water".to_owned(), AutoplaceControl::default());
+        }
+
+        settings
+    }
+
+    /// The autoplace controls of `planet`, like `vulcanus`, sorted by name. See [`SPACE_AGE_AUTOPLACE_CONTROLS`].
+    pub fn planet_autoplace_controls<'a>(&'a self, planet: &'a str) -> Vec<(&'a str, &'a AutoplaceControl)> {
+        let mut controls: Vec<_> = self
+            .autoplace_controls
+            .iter()
+            .filter(|(name, _)| planet_of_autoplace_control(name) == planet)
+            .map(|(name, control)| (name.as_str(), control))
+            .collect();
+
+        controls.sort_by_key(|(name, _)| *name);
+        controls
+    }
+}
+
+impl VersionCheck for MapGenSettings {
+    fn check_version_into(&self, version: &GameVersion, prefix: &str, errors: &mut Vec<ValidationError>) {
+        let mut names: Vec<_> = self.autoplace_controls.keys().collect();
+        names.sort();
+        for name in names {
+            let space_age = Some(name).filter(|name| planet_of_autoplace_control(name) != "nauvis");
+            added_in(errors, version, VERSION_2_0, format!("{}autoplace_controls.{}", prefix, name), &space_age);
+        }
+
+        let path = |field: &str| format!("{}{}", prefix, field);
+        removed_in(errors, version, VERSION_2_0, path("terrain_segmentation"), &self.terrain_segmentation);
+        removed_in(errors, version, VERSION_2_0, path("water"), &self.water);
+        added_in(errors, version, VERSION_2_0, path("no_enemies_mode"), &self.no_enemies_mode);
+        added_in(errors, version, VERSION_2_0, path("territory_settings"), &self.territory_settings);
+    }
+}
+
+/// The largest width or height of a map, in tiles.
+pub const MAX_MAP_SIZE: u32 = 2_000_000;
+
+/// The smallest and largest scales of the map generator GUI, 17% and 600%.
+const GUI_SCALE: (f32, f32) = (1.0 / 6.0, 6.0);
+
+/// Check that `coordinate` of a starting point is finite, and within a map that is `size` tiles across, centered on the
+/// origin, unless the map is infinite.
+fn check_starting_point(errors: &mut Vec<ValidationError>, path: String, coordinate: f64, size: u32, field: &str) {
+    let half = f64::from(size) / 2.0;
+    if !coordinate.is_finite() {
+        errors.push(ValidationError::new(path, format!("must be a finite number, got {}", coordinate)));
+    } else if size > 0 && coordinate.abs() > half {
+        errors.push(ValidationError::new(
+            path,
+            format!("must be between -{} and {} for a {} of {}, got {}", half, half, field, size, coordinate),
+        ));
+    }
+}
+
+impl Validate for MapGenSettings {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<ValidationError>) {
+        let (min_scale, max_scale) = GUI_SCALE;
+        let path = |field: &str| format!("{}{}", prefix, field);
+        if let Some(terrain_segmentation) = self.terrain_segmentation {
+            check_range(errors, path("terrain_segmentation"), terrain_segmentation, min_scale, max_scale);
+        }
+
+        // 0 is "none" in the map generator GUI
+        if let Some(water) = self.water {
+            check_range(errors, path("water"), water, 0.0, max_scale);
+        }
+
+        check_range(errors, path("starting_area"), self.starting_area, 0.0, max_scale);
+        check_range(errors, path("width"), self.width, 0, MAX_MAP_SIZE);
+        check_range(errors, path("height"), self.height, 0, MAX_MAP_SIZE);
+
+        for (i, point) in self.starting_points.iter().enumerate() {
+            let path = |axis: &str| format!("{}starting_points.{}.{}", prefix, i, axis);
+            check_starting_point(errors, path("x"), point.x, self.width, "width");
+            check_starting_point(errors, path("y"), point.y, self.height, "height");
+        }
+
+        self.cliff_settings.validate_into(&path("cliff_settings."), errors);
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct AutoplaceSettings {
     pub treat_missing_as_default: bool,
     pub settings: HashMap<String, AutoplaceControl>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct CliffPlacementSettings {
     /// Name of the cliff prototype.
     pub name: String,
@@ -132,8 +283,46 @@ impl Default for CliffPlacementSettings {
     }
 }
 
+impl Validate for CliffPlacementSettings {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<ValidationError>) {
+        if !self.cliff_elevation_0.is_finite() || self.cliff_elevation_0 < 0.0 {
+            errors.push(ValidationError::new(
+                format!("{}cliff_elevation_0", prefix),
+                format!("must be a finite number that is not negative, got {}", self.cliff_elevation_0),
+            ));
+        }
+
+        if !self.cliff_elevation_interval.is_finite() || self.cliff_elevation_interval <= 0.0 {
+            errors.push(ValidationError::new(
+                format!("{}cliff_elevation_interval", prefix),
+                format!("must be a finite number greater than 0, got {}", self.cliff_elevation_interval),
+            ));
+        }
+
+        check_range(errors, format!("{}richness", prefix), self.richness, 0.0, 10.0);
+    }
+}
+
+/// Settings for the territories of Gleba, which are guarded by its units. This struct models the
+/// [`TerritorySettings`](https://lua-api.factorio.com/latest/concepts/TerritorySettings.html) type in the Factorio Lua
+/// API.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct TerritorySettings {
+    /// Names of the unit spawner prototypes placed in the territories.
+    pub units: Vec<String>,
+
+    /// Name of the noise expression that gives the index of the territory at a position.
+    pub territory_index_expression: String,
+
+    /// Name of the noise expression that varies the borders of territories.
+    pub territory_variation_expression: String,
+
+    /// The smallest territory, in chunks.
+    pub minimum_territory_size: u32,
+}
+
 /// A position on the map.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct MapPosition {
     /// The x coordinate.
     pub x: f64,
@@ -141,3 +330,90 @@ pub struct MapPosition {
     /// The y coordinate.
     pub y: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(settings: &MapGenSettings) -> Vec<String> {
+        match settings.validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.0.into_iter().map(|error| error.path).collect(),
+        }
+    }
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(MapGenSettings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut settings = MapGenSettings {
+            terrain_segmentation: Some(0.0),
+            water: Some(0.0),
+            starting_area: -1.0,
+            ..Default::default()
+        };
+
+        settings.cliff_settings.richness = 11.0;
+        settings.cliff_settings.cliff_elevation_interval = 0.0;
+        assert_eq!(
+            paths(&settings),
+            [
+                "terrain_segmentation",
+                "starting_area",
+                "cliff_settings.cliff_elevation_interval",
+                "cliff_settings.richness",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_starting_points() {
+        let mut settings = MapGenSettings {
+            width: 100,
+            starting_points: vec![MapPosition { x: 50.0, y: 1000.0 }, MapPosition { x: -60.0, y: 0.0 }],
+            ..Default::default()
+        };
+
+        let errors = settings.validate().unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(
+            errors.0[0].to_string(),
+            "starting_points.1.x: must be between -50 and 50 for a width of 100, got -60"
+        );
+
+        settings.height = 1000;
+        assert_eq!(paths(&settings), ["starting_points.0.y", "starting_points.1.x"]);
+    }
+
+    #[test]
+    fn test_not_finite() {
+        let mut settings = MapGenSettings {
+            starting_points: vec![MapPosition {
+                x: f64::NAN,
+                y: f64::INFINITY,
+            }],
+            ..Default::default()
+        };
+
+        settings.cliff_settings.cliff_elevation_0 = f32::NAN;
+        settings.cliff_settings.cliff_elevation_interval = f32::INFINITY;
+        settings.cliff_settings.richness = f32::NAN;
+        assert_eq!(
+            paths(&settings),
+            [
+                "starting_points.0.x",
+                "starting_points.0.y",
+                "cliff_settings.cliff_elevation_0",
+                "cliff_settings.cliff_elevation_interval",
+                "cliff_settings.richness",
+            ]
+        );
+
+        settings.width = 100;
+        let errors = settings.validate().unwrap_err();
+        assert_eq!(errors.0[0].to_string(), "starting_points.0.x: must be a finite number, got NaN");
+    }
+}
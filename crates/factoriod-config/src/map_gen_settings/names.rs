@@ -0,0 +1,464 @@
+//! Typed names for the keys of [`MapGenSettings::autoplace_controls`] and
+//! [`MapGenSettings::property_expression_names`]. The maps keep their string keys, so names defined by mods can still
+//! be used.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{AutoplaceControl, MapGenSettings, SPACE_AGE_AUTOPLACE_CONTROLS};
+use crate::ValidationError;
+
+/// The name of an autoplace control: one of the base game, or [`Other`](Self::Other) for those added by mods or the
+/// Space Age expansion.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::AutoplaceControlName;
+/// assert_eq!("iron-ore".parse(), Ok(AutoplaceControlName::IronOre));
+/// assert_eq!(AutoplaceControlName::EnemyBase.to_string(), "enemy-base");
+/// assert_eq!(AutoplaceControlName::from("angels-ore1"), AutoplaceControlName::Other("angels-ore1".to_owned()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AutoplaceControlName {
+    Coal,
+    CopperOre,
+    CrudeOil,
+    EnemyBase,
+    IronOre,
+    Stone,
+    Trees,
+    UraniumOre,
+    Other(String),
+}
+
+impl AutoplaceControlName {
+    /// The autoplace controls of the base game.
+    pub const VANILLA: [AutoplaceControlName; 8] = [
+        AutoplaceControlName::Coal,
+        AutoplaceControlName::CopperOre,
+        AutoplaceControlName::CrudeOil,
+        AutoplaceControlName::EnemyBase,
+        AutoplaceControlName::IronOre,
+        AutoplaceControlName::Stone,
+        AutoplaceControlName::Trees,
+        AutoplaceControlName::UraniumOre,
+    ];
+
+    /// The key of the control in [`MapGenSettings::autoplace_controls`].
+    pub fn as_str(&self) -> &str {
+        match self {
+            AutoplaceControlName::Coal => "coal",
+            AutoplaceControlName::CopperOre => "copper-ore",
+            AutoplaceControlName::CrudeOil => "crude-oil",
+            AutoplaceControlName::EnemyBase => "enemy-base",
+            AutoplaceControlName::IronOre => "iron-ore",
+            AutoplaceControlName::Stone => "stone",
+            AutoplaceControlName::Trees => "trees",
+            AutoplaceControlName::UraniumOre => "uranium-ore",
+            AutoplaceControlName::Other(name) => name,
+        }
+    }
+
+    /// Whether the control is defined by the game, including the `water` of 2.0 and the planets of Space Age, rather
+    /// than by a mod.
+    pub fn is_known(&self) -> bool {
+        match self {
+            AutoplaceControlName::Other(name) => {
+                name == "water"
+                    || SPACE_AGE_AUTOPLACE_CONTROLS
+                        .iter()
+                        .any(|(_, controls)| controls.contains(&name.as_str()))
+            },
+            _ => true,
+        }
+    }
+}
+
+impl From<&str> for AutoplaceControlName {
+    fn from(name: &str) -> Self {
+        Self::VANILLA
+            .into_iter()
+            .find(|known| known.as_str() == name)
+            .unwrap_or_else(|| AutoplaceControlName::Other(name.to_owned()))
+    }
+}
+
+impl FromStr for AutoplaceControlName {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl fmt::Display for AutoplaceControlName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A key of [`MapGenSettings::property_expression_names`], which overrides a noise expression of the map generator.
+/// Factorio 2.0 renamed the keys of the map generator's controls, and both spellings are recognized.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::PropertyExpression;
+/// assert_eq!("control-setting:moisture:bias".parse(), Ok(PropertyExpression::MoistureBias));
+/// assert_eq!("control:moisture:bias".parse(), Ok(PropertyExpression::MoistureBias));
+/// assert_eq!(PropertyExpression::MoistureBias.to_string(), "control-setting:moisture:bias");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PropertyExpression {
+    /// The inverse of "moisture scale" in the map generator GUI.
+    MoistureFrequency,
+
+    /// "Moisture bias" in the map generator GUI.
+    MoistureBias,
+
+    /// The inverse of "terrain type scale" in the map generator GUI.
+    AuxFrequency,
+
+    /// "Terrain type bias" in the map generator GUI.
+    AuxBias,
+
+    /// The map type, see [`Elevation`].
+    Elevation,
+
+    /// An expression that factoriod does not know, like one defined by a mod.
+    Other(String),
+}
+
+impl PropertyExpression {
+    /// The expressions known to factoriod.
+    pub const KNOWN: [PropertyExpression; 5] = [
+        PropertyExpression::MoistureFrequency,
+        PropertyExpression::MoistureBias,
+        PropertyExpression::AuxFrequency,
+        PropertyExpression::AuxBias,
+        PropertyExpression::Elevation,
+    ];
+
+    /// The keys of the expression, as `(1.1, 2.0)`.
+    fn keys(&self) -> (&str, &str) {
+        match self {
+            PropertyExpression::MoistureFrequency => {
+                ("control-setting:moisture:frequency:multiplier", "control:moisture:frequency")
+            },
+            PropertyExpression::MoistureBias => ("control-setting:moisture:bias", "control:moisture:bias"),
+            PropertyExpression::AuxFrequency => ("control-setting:aux:frequency:multiplier", "control:aux:frequency"),
+            PropertyExpression::AuxBias => ("control-setting:aux:bias", "control:aux:bias"),
+            PropertyExpression::Elevation => ("elevation", "elevation"),
+            PropertyExpression::Other(key) => (key, key),
+        }
+    }
+
+    /// The key of the expression in Factorio 1.1, the shape of [`MapGenSettings::default`].
+    pub fn as_str(&self) -> &str {
+        self.keys().0
+    }
+}
+
+impl From<&str> for PropertyExpression {
+    fn from(key: &str) -> Self {
+        Self::KNOWN
+            .into_iter()
+            .find(|known| {
+                let (v1_1, v2_0) = known.keys();
+                key == v1_1 || key == v2_0
+            })
+            .unwrap_or_else(|| PropertyExpression::Other(key.to_owned()))
+    }
+}
+
+impl FromStr for PropertyExpression {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl fmt::Display for PropertyExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The map type, selected by the `elevation` property expression.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{Elevation, MapGenSettings};
+/// let mut settings = MapGenSettings::default();
+/// assert_eq!(settings.elevation(), Elevation::Normal);
+/// settings.set_elevation(Elevation::Island);
+/// assert_eq!(settings.property_expression_names["elevation"], "0_17-island");
+/// assert_eq!(settings.elevation(), Elevation::Island);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Elevation {
+    /// The default terrain, when `elevation` is not set.
+    Normal,
+
+    /// A single island surrounded by water, `0_17-island` in 1.1 and `elevation_island` in 2.0.
+    Island,
+
+    /// The terrain of 0.16, `0_16-elevation`.
+    Legacy0_16,
+
+    /// An elevation expression that factoriod does not know, like one defined by a mod.
+    Other(String),
+}
+
+impl Elevation {
+    /// The value of the `elevation` expression, or [`None`] for [`Normal`](Self::Normal).
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Elevation::Normal => None,
+            Elevation::Island => Some("0_17-island"),
+            Elevation::Legacy0_16 => Some("0_16-elevation"),
+            Elevation::Other(name) => Some(name),
+        }
+    }
+}
+
+impl From<&str> for Elevation {
+    fn from(name: &str) -> Self {
+        match name {
+            "" => Elevation::Normal,
+            "0_17-island" | "elevation_island" => Elevation::Island,
+            "0_16-elevation" => Elevation::Legacy0_16,
+            name => Elevation::Other(name.to_owned()),
+        }
+    }
+}
+
+impl MapGenSettings {
+    /// The autoplace control `name`, if set.
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod_config::{AutoplaceControlName, MapGenSettings, MapGenSize};
+    /// let mut settings = MapGenSettings::default();
+    /// settings.autoplace_control_mut(AutoplaceControlName::Coal).richness = MapGenSize::VeryGood;
+    /// assert_eq!(settings.autoplace_control(AutoplaceControlName::Coal).unwrap().richness, MapGenSize::VeryGood);
+    /// assert!(settings.autoplace_control("angels-ore1").is_none());
+    /// ```
+    pub fn autoplace_control<N: Into<AutoplaceControlName>>(&self, name: N) -> Option<&AutoplaceControl> {
+        self.autoplace_controls.get(name.into().as_str())
+    }
+
+    /// The autoplace control `name`, inserted with its defaults if not set.
+    pub fn autoplace_control_mut<N: Into<AutoplaceControlName>>(&mut self, name: N) -> &mut AutoplaceControl {
+        self.autoplace_controls
+            .entry(name.into().as_str().to_owned())
+            .or_default()
+    }
+
+    /// The key of `expression` in [`property_expression_names`](Self::property_expression_names), in either
+    /// spelling, if set.
+    fn property_expression_key(&self, expression: &PropertyExpression) -> Option<&str> {
+        let (v1_1, v2_0) = expression.keys();
+        [v1_1, v2_0]
+            .into_iter()
+            .find_map(|key| self.property_expression_names.get_key_value(key))
+            .map(|(key, _)| key.as_str())
+    }
+
+    /// The value of `expression`, whichever spelling of its key is set.
+    pub fn property_expression<E: Into<PropertyExpression>>(&self, expression: E) -> Option<&str> {
+        let expression = expression.into();
+        let key = self.property_expression_key(&expression)?;
+        self.property_expression_names.get(key).map(String::as_str)
+    }
+
+    /// Set `expression` to `value`, keeping the spelling of its key that is already set, or the spelling of 1.1.
+    pub fn set_property_expression<E: Into<PropertyExpression>>(&mut self, expression: E, value: String) {
+        let expression = expression.into();
+        let key = self
+            .property_expression_key(&expression)
+            .unwrap_or(expression.as_str())
+            .to_owned();
+
+        self.property_expression_names.insert(key, value);
+    }
+
+    /// The value of `expression` as a number, if set and numeric.
+    fn property_expression_number(&self, expression: PropertyExpression) -> Option<f32> {
+        self.property_expression(expression)?.parse().ok()
+    }
+
+    /// "Water scale" in the map generator GUI, the inverse of [`terrain_segmentation`](Self::terrain_segmentation),
+    /// if set.
+    pub fn water_scale(&self) -> Option<f32> {
+        self.terrain_segmentation.map(|segmentation| 1.0 / segmentation)
+    }
+
+    pub fn set_water_scale(&mut self, scale: f32) {
+        self.terrain_segmentation = Some(1.0 / scale);
+    }
+
+    /// "Moisture scale" in the map generator GUI, the inverse of [`PropertyExpression::MoistureFrequency`].
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod_config::{MapGenSettings, PropertyExpression};
+    /// let mut settings = MapGenSettings::default();
+    /// settings.set_moisture_scale(2.0);
+    /// assert_eq!(settings.property_expression(PropertyExpression::MoistureFrequency), Some("0.5"));
+    /// assert_eq!(settings.moisture_scale(), Some(2.0));
+    /// ```
+    pub fn moisture_scale(&self) -> Option<f32> {
+        self.property_expression_number(PropertyExpression::MoistureFrequency)
+            .map(|frequency| 1.0 / frequency)
+    }
+
+    pub fn set_moisture_scale(&mut self, scale: f32) {
+        self.set_property_expression(PropertyExpression::MoistureFrequency, (1.0 / scale).to_string());
+    }
+
+    /// "Moisture bias" in the map generator GUI.
+    pub fn moisture_bias(&self) -> Option<f32> {
+        self.property_expression_number(PropertyExpression::MoistureBias)
+    }
+
+    pub fn set_moisture_bias(&mut self, bias: f32) {
+        self.set_property_expression(PropertyExpression::MoistureBias, bias.to_string());
+    }
+
+    /// "Terrain type scale" in the map generator GUI, the inverse of [`PropertyExpression::AuxFrequency`].
+    pub fn terrain_type_scale(&self) -> Option<f32> {
+        self.property_expression_number(PropertyExpression::AuxFrequency)
+            .map(|frequency| 1.0 / frequency)
+    }
+
+    pub fn set_terrain_type_scale(&mut self, scale: f32) {
+        self.set_property_expression(PropertyExpression::AuxFrequency, (1.0 / scale).to_string());
+    }
+
+    /// "Terrain type bias" in the map generator GUI.
+    pub fn terrain_type_bias(&self) -> Option<f32> {
+        self.property_expression_number(PropertyExpression::AuxBias)
+    }
+
+    pub fn set_terrain_type_bias(&mut self, bias: f32) {
+        self.set_property_expression(PropertyExpression::AuxBias, bias.to_string());
+    }
+
+    /// The map type, from the `elevation` expression.
+    pub fn elevation(&self) -> Elevation {
+        self.property_expression(PropertyExpression::Elevation)
+            .map_or(Elevation::Normal, Elevation::from)
+    }
+
+    pub fn set_elevation(&mut self, elevation: Elevation) {
+        match elevation.as_str() {
+            Some(value) => self.set_property_expression(PropertyExpression::Elevation, value.to_owned()),
+            None => {
+                self.property_expression_names.remove("elevation");
+            },
+        }
+    }
+
+    /// Autoplace controls and property expressions that factoriod does not know. They are allowed, since mods define
+    /// their own, but may be typos.
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod_config::MapGenSettings;
+    /// let mut settings = MapGenSettings::default();
+    /// assert!(settings.warnings().is_empty());
+    /// settings.autoplace_control_mut("iron_ore");
+    /// assert_eq!(settings.warnings()[0].to_string(), "autoplace_controls.iron_ore: unknown autoplace control");
+    /// ```
+    pub fn warnings(&self) -> Vec<ValidationError> {
+        let mut controls: Vec<_> = self
+            .autoplace_controls
+            .keys()
+            .filter(|name| !AutoplaceControlName::from(name.as_str()).is_known())
+            .map(|name| ValidationError::new(format!("autoplace_controls.{}", name), "unknown autoplace control"))
+            .collect();
+
+        let mut expressions: Vec<_> = self
+            .property_expression_names
+            .keys()
+            .filter(|key| matches!(PropertyExpression::from(key.as_str()), PropertyExpression::Other(_)))
+            .map(|key| {
+                ValidationError::new(format!("property_expression_names.{}", key), "unknown property expression")
+            })
+            .collect();
+
+        controls.sort_by(|a, b| a.path.cmp(&b.path));
+        expressions.sort_by(|a, b| a.path.cmp(&b.path));
+        controls.extend(expressions);
+        controls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        for name in AutoplaceControlName::VANILLA {
+            assert_eq!(AutoplaceControlName::from(name.as_str()), name);
+            assert!(MapGenSettings::default().autoplace_control(name).is_some());
+        }
+
+        for expression in PropertyExpression::KNOWN {
+            assert_eq!(PropertyExpression::from(expression.as_str()), expression);
+        }
+    }
+
+    #[test]
+    fn test_default_has_no_warnings() {
+        assert_eq!(MapGenSettings::default().warnings(), []);
+    }
+
+    #[test]
+    fn test_property_expressions_in_either_spelling() {
+        let mut settings = MapGenSettings::default();
+        settings.property_expression_names.clear();
+        settings
+            .property_expression_names
+            .insert("control:aux:bias".to_owned(), "0.25".to_owned());
+
+        assert_eq!(settings.terrain_type_bias(), Some(0.25));
+        settings.set_terrain_type_bias(-0.5);
+        assert_eq!(settings.property_expression_names.len(), 1);
+        assert_eq!(settings.property_expression_names["control:aux:bias"], "-0.5");
+        assert!(settings.warnings().is_empty());
+
+        settings.set_elevation(Elevation::Island);
+        settings.set_elevation(Elevation::Normal);
+        assert!(!settings.property_expression_names.contains_key("elevation"));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut settings = MapGenSettings::default();
+        settings.autoplace_control_mut("scrap");
+        settings.autoplace_control_mut("angels-ore1");
+        settings
+            .property_expression_names
+            .insert("control-setting:moisture:biass".to_owned(), "0".to_owned());
+
+        let warnings: Vec<_> = settings.warnings().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "autoplace_controls.angels-ore1: unknown autoplace control",
+                "property_expression_names.control-setting:moisture:biass: unknown property expression",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_water_scale() {
+        let mut settings = MapGenSettings::default();
+        settings.set_water_scale(2.0);
+        assert_eq!(settings.terrain_segmentation, Some(0.5));
+        assert_eq!(settings.water_scale(), Some(2.0));
+    }
+}
@@ -1,12 +1,18 @@
 //! Map settings for the game, used during map generation.
+//!
+//! The settings model both Factorio 1.1 and 2.0. Fields that exist in only one of them are optional and omitted from
+//! the file when unset, and [`VersionCheck`] reports the fields the installed game does not accept.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::{added_in, removed_in, GameVersion, ValidationError, VersionCheck, VERSION_2_0};
+
 /// Settings for the map. These settings are used to configure the map generation and the game difficulty and are saved
 /// in the `map-settings.json` file. This struct models the
 /// [`MapAndDifficultySettings`](https://lua-api.factorio.com/latest/concepts.html#MapAndDifficultySettings) type in
 /// the Factorio Lua API.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct MapAndDifficultySettings {
     pub difficulty_settings: DifficultySettings,
     pub pollution: PollutionMapSettings,
@@ -16,6 +22,50 @@ pub struct MapAndDifficultySettings {
     pub steering: SteeringMapSettings,
     pub path_finder: PathFinderMapSettings,
     pub max_failed_behavior_count: u32,
+
+    /// Settings for asteroids in space. Added in 2.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asteroids: Option<AsteroidsMapSettings>,
+}
+
+impl MapAndDifficultySettings {
+    /// The default settings of the game `version`. [`Default`] is the shape of 1.1.
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod_config::{GameVersion, MapAndDifficultySettings, VersionCheck};
+    /// let version = GameVersion::new(2, 0, 28, 0);
+    /// let settings = MapAndDifficultySettings::for_version(&version);
+    /// assert!(settings.difficulty_settings.recipe_difficulty.is_none());
+    /// assert!(settings.check_version(&version).is_ok());
+    /// assert!(MapAndDifficultySettings::default().check_version(&version).is_err());
+    /// ```
+    pub fn for_version(version: &GameVersion) -> Self {
+        if *version < VERSION_2_0 {
+            return Self::default();
+        }
+
+        Self {
+            difficulty_settings: DifficultySettings {
+                recipe_difficulty: None,
+                technology_difficulty: None,
+                technology_price_multiplier: 1.0,
+                research_queue_setting: None,
+                spoil_time_modifier: Some(1.0),
+            },
+            asteroids: Some(AsteroidsMapSettings::default()),
+            ..Self::default()
+        }
+    }
+}
+
+impl VersionCheck for MapAndDifficultySettings {
+    fn check_version_into(&self, version: &GameVersion, prefix: &str, errors: &mut Vec<ValidationError>) {
+        self.difficulty_settings
+            .check_version_into(version, &format!("{}difficulty_settings.", prefix), errors);
+
+        added_in(errors, version, VERSION_2_0, format!("{}asteroids", prefix), &self.asteroids);
+    }
 }
 
 impl Default for MapAndDifficultySettings {
@@ -29,34 +79,75 @@ impl Default for MapAndDifficultySettings {
             steering: SteeringMapSettings::default(),
             path_finder: PathFinderMapSettings::default(),
             max_failed_behavior_count: 3,
+            asteroids: None,
         }
     }
 }
 
 /// Settings for the difficulty of the game.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct DifficultySettings {
-    pub recipe_difficulty: RecipeDifficulty,
-    pub technology_difficulty: TechnologyDifficulty,
+    /// Removed in 2.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipe_difficulty: Option<RecipeDifficulty>,
+
+    /// Removed in 2.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub technology_difficulty: Option<TechnologyDifficulty>,
+
     pub technology_price_multiplier: f64,
-    pub research_queue_setting: ResearchQueueSetting,
+
+    /// Removed in 2.0, where the research queue is always enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub research_queue_setting: Option<ResearchQueueSetting>,
+
+    /// The multiplier of the time it takes items to spoil. Added in 2.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spoil_time_modifier: Option<f64>,
 }
 
-impl DifficultySettings {}
+impl VersionCheck for DifficultySettings {
+    fn check_version_into(&self, version: &GameVersion, prefix: &str, errors: &mut Vec<ValidationError>) {
+        let path = |field: &str| format!("{}{}", prefix, field);
+        removed_in(errors, version, VERSION_2_0, path("recipe_difficulty"), &self.recipe_difficulty);
+        removed_in(errors, version, VERSION_2_0, path("technology_difficulty"), &self.technology_difficulty);
+        removed_in(errors, version, VERSION_2_0, path("research_queue_setting"), &self.research_queue_setting);
+        added_in(errors, version, VERSION_2_0, path("spoil_time_modifier"), &self.spoil_time_modifier);
+    }
+}
 
 impl Default for DifficultySettings {
     fn default() -> Self {
         Self {
-            recipe_difficulty: RecipeDifficulty::Normal,
-            technology_difficulty: TechnologyDifficulty::Normal,
+            recipe_difficulty: Some(RecipeDifficulty::Normal),
+            technology_difficulty: Some(TechnologyDifficulty::Normal),
             technology_price_multiplier: 1.0,
-            research_queue_setting: ResearchQueueSetting::AfterVictory,
+            research_queue_setting: Some(ResearchQueueSetting::AfterVictory),
+            spoil_time_modifier: None,
+        }
+    }
+}
+
+/// Settings for asteroids in space. Added in 2.0.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct AsteroidsMapSettings {
+    /// The multiplier of the rate asteroids spawn at.
+    pub spawning_rate: f64,
+
+    pub max_ray_portals_expanded_per_tick: u32,
+}
+
+impl Default for AsteroidsMapSettings {
+    fn default() -> Self {
+        Self {
+            spawning_rate: 1.0,
+            max_ray_portals_expanded_per_tick: 100,
         }
     }
 }
 
 /// Settings for enemy evolution.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct EnemyEvolutionMapSettings {
     /// Whether enemy evolution is enabled.
     pub enabled: bool,
@@ -82,7 +173,7 @@ impl Default for EnemyEvolutionMapSettings {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct EnemyExpansionMapSettings {
     pub enabled: bool,
     pub max_expansion_distance: u32,
@@ -120,7 +211,7 @@ impl Default for EnemyExpansionMapSettings {
 }
 
 /// Settings for unit path finding.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct PathFinderMapSettings {
     pub fwd_2_bwd_ratio: u32,
     pub goal_pressure_ratio: f64,
@@ -198,7 +289,7 @@ impl Default for PathFinderMapSettings {
 }
 
 /// Pollution settings for the map.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct PollutionMapSettings {
     /// Whether pollution is enabled.
     pub enabled: bool,
@@ -237,7 +328,7 @@ impl Default for PollutionMapSettings {
 }
 
 /// Difficulty of the recipes.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum RecipeDifficulty {
     /// Recipes have normal difficulty.
@@ -248,7 +339,7 @@ pub enum RecipeDifficulty {
 }
 
 /// Whether the research queue should be enabled.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ResearchQueueSetting {
     /// The research queue is enabled after victory.
@@ -261,7 +352,7 @@ pub enum ResearchQueueSetting {
     Never,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct SteeringMapSettings {
     pub default: SteeringMapSetting,
     pub moving: SteeringMapSetting,
@@ -286,7 +377,7 @@ impl Default for SteeringMapSettings {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct SteeringMapSetting {
     pub radius: f64,
     pub separation_factor: f64,
@@ -294,14 +385,14 @@ pub struct SteeringMapSetting {
     pub force_unit_fuzzy_goto_behavior: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum TechnologyDifficulty {
     Normal,
     Expensive,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct UnitGroupMapSettings {
     pub min_group_gathering_time: u32,
     pub max_group_gathering_time: u32,
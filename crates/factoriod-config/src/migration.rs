@@ -0,0 +1,441 @@
+//! Migration of config files between versions of the game.
+//!
+//! Factorio 2.0 removed some map settings of 1.1, renamed the property expressions of the map generator, made water an
+//! autoplace control, and added settings for the Space Age expansion. Migrations work on the JSON value of a single
+//! file, which may be a partial layer (see [`Layered`](crate::Layered)), so only the fields present in the file are
+//! changed.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use crate::{planet_of_autoplace_control, GameVersion, MapGenSize, VERSION_2_0};
+
+/// The property expression names that were renamed in 2.0, as `(1.1, 2.0)`.
+const RENAMED_PROPERTY_EXPRESSIONS: [(&str, &str); 4] = [
+    ("control-setting:moisture:frequency:multiplier", "control:moisture:frequency"),
+    ("control-setting:moisture:bias", "control:moisture:bias"),
+    ("control-setting:aux:frequency:multiplier", "control:aux:frequency"),
+    ("control-setting:aux:bias", "control:aux:bias"),
+];
+
+/// The elevation expressions that were renamed in 2.0, as `(1.1, 2.0)`.
+const RENAMED_ELEVATIONS: [(&str, &str); 1] = [("0_17-island", "elevation_island")];
+
+/// A change made by a migration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationChange {
+    /// A field the target version does not accept was removed, with what that means for the map.
+    Removed { path: String, value: Value, note: String },
+
+    /// A field was moved to the name the target version uses.
+    Renamed { from: String, to: String },
+
+    /// A value was replaced with its equivalent in the target version.
+    Replaced { path: String, from: Value, to: Value },
+
+    /// A field was moved to a different place in the target version, with what that means for the map.
+    Moved { from: String, to: String, note: String },
+}
+
+impl fmt::Display for MigrationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationChange::Removed { path, value, note } => write!(f, "removed {} = {}: {}", path, value, note),
+            MigrationChange::Renamed { from, to } => write!(f, "renamed {} to {}", from, to),
+            MigrationChange::Replaced { path, from, to } => write!(f, "replaced {} = {} with {}", path, from, to),
+            MigrationChange::Moved { from, to, note } => write!(f, "moved {} to {}: {}", from, to, note),
+        }
+    }
+}
+
+/// Remove the field at `path` from `value`, recording it in `changes` with `note`, which is given the removed value.
+fn remove(
+    value: &mut Value,
+    path: &[&str],
+    changes: &mut Vec<MigrationChange>,
+    note: impl FnOnce(&Value) -> String,
+) {
+    let Some((field, parents)) = path.split_last() else {
+        return;
+    };
+
+    let parent = parents.iter().try_fold(value, |value, key| value.get_mut(key));
+    if let Some(removed) = parent.and_then(Value::as_object_mut).and_then(|parent| parent.remove(*field)) {
+        changes.push(MigrationChange::Removed {
+            path: path.join("."),
+            note: note(&removed),
+            value: removed,
+        });
+    }
+}
+
+/// Move the field at `from` in `value` to `to`, creating the parents of `to` as needed, and converting the value with
+/// `convert`. A field whose destination is already set is removed instead.
+fn move_field(
+    value: &mut Value,
+    from: &[&str],
+    to: &[&str],
+    changes: &mut Vec<MigrationChange>,
+    convert: impl FnOnce(Value) -> Value,
+    note: &str,
+) {
+    let (Some((field, parents)), Some((to_field, to_parents))) = (from.split_last(), to.split_last()) else {
+        return;
+    };
+
+    let parent = parents.iter().try_fold(&mut *value, |value, key| value.get_mut(key));
+    let Some(moved) = parent.and_then(Value::as_object_mut).and_then(|parent| parent.remove(*field)) else {
+        return;
+    };
+
+    let (from, to) = (from.join("."), to.join("."));
+    let destination = to_parents.iter().try_fold(value, |value, key| {
+        let object = value.as_object_mut()?;
+        Some(object.entry(*key).or_insert_with(|| Value::Object(Map::new())))
+    });
+
+    match destination.and_then(Value::as_object_mut) {
+        Some(destination) if !destination.contains_key(*to_field) => {
+            destination.insert((*to_field).to_owned(), convert(moved));
+            changes.push(MigrationChange::Moved {
+                from,
+                to,
+                note: note.to_owned(),
+            });
+        },
+        _ => changes.push(MigrationChange::Removed {
+            path: from,
+            value: moved,
+            note: format!("{} is already set", to),
+        }),
+    }
+}
+
+/// The number of a [`MapGenSize`], which may be named like `high`, for a field of 1.1 that only accepts numbers.
+fn map_gen_size_number(value: Value) -> Value {
+    match serde_json::from_value::<MapGenSize>(value.clone()) {
+        Ok(size) if value.is_string() => serde_json::to_value(f32::from(size)).unwrap_or(value),
+        _ => value,
+    }
+}
+
+/// Rename the keys of `object` from the first to the second of each pair in `renames`. A key whose new name is already
+/// set is removed instead.
+fn rename_keys(
+    object: &mut Map<String, Value>,
+    prefix: &str,
+    renames: impl IntoIterator<Item = (&'static str, &'static str)>,
+    changes: &mut Vec<MigrationChange>,
+) {
+    for (from, to) in renames {
+        let Some(value) = object.remove(from) else {
+            continue;
+        };
+
+        let (from_path, to_path) = (format!("{}{}", prefix, from), format!("{}{}", prefix, to));
+        if object.contains_key(to) {
+            changes.push(MigrationChange::Removed {
+                path: from_path,
+                value,
+                note: format!("{} is already set", to_path),
+            });
+        } else {
+            object.insert(to.to_owned(), value);
+            changes.push(MigrationChange::Renamed {
+                from: from_path,
+                to: to_path,
+            });
+        }
+    }
+}
+
+/// The pairs of `renames` in the direction of migrating to `to`.
+fn directed(renames: &[(&'static str, &'static str)], to: &GameVersion) -> Vec<(&'static str, &'static str)> {
+    renames
+        .iter()
+        .map(|&(old, new)| if *to >= VERSION_2_0 { (old, new) } else { (new, old) })
+        .collect()
+}
+
+/// Migrate the fields of `map-gen-settings.json` in `value` to the game version `to`, returning the changes made.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{migrate_map_gen_settings, GameVersion, MigrationChange};
+/// use serde_json::json;
+/// let mut value = json!({"property_expression_names": {"control-setting:moisture:bias": "0.1"}});
+/// let changes = migrate_map_gen_settings(&mut value, &GameVersion::new(2, 0, 0, 0));
+/// assert_eq!(value, json!({"property_expression_names": {"control:moisture:bias": "0.1"}}));
+/// assert!(matches!(&changes[..], [MigrationChange::Renamed { .. }]));
+/// ```
+pub fn migrate_map_gen_settings(value: &mut Value, to: &GameVersion) -> Vec<MigrationChange> {
+    let mut changes = Vec::new();
+    if let Some(names) = value.get_mut("property_expression_names").and_then(Value::as_object_mut) {
+        let prefix = "property_expression_names.";
+        rename_keys(names, prefix, directed(&RENAMED_PROPERTY_EXPRESSIONS, to), &mut changes);
+
+        if let Some(elevation) = names.get_mut("elevation") {
+            let renamed = directed(&RENAMED_ELEVATIONS, to)
+                .into_iter()
+                .find(|(from, _)| elevation.as_str() == Some(from));
+
+            if let Some((_, renamed)) = renamed {
+                changes.push(MigrationChange::Replaced {
+                    path: format!("{}elevation", prefix),
+                    from: elevation.clone(),
+                    to: renamed.into(),
+                });
+
+                *elevation = renamed.into();
+            }
+        }
+    }
+
+    // "water scale" and "water coverage" of 1.1 are the frequency and size of the water autoplace control of 2.0
+    let water = [("terrain_segmentation", "frequency"), ("water", "size")];
+    if *to >= VERSION_2_0 {
+        for (field, control_field) in water {
+            let note = "water is an autoplace control in 2.0";
+            move_field(value, &[field], &["autoplace_controls", "water", control_field], &mut changes, |v| v, note);
+        }
+
+        // the game does not use the richness of water, but the control requires it
+        let control = value
+            .pointer_mut("/autoplace_controls/water")
+            .and_then(Value::as_object_mut)
+            .filter(|control| control.contains_key("frequency") && control.contains_key("size"));
+
+        if let Some(control) = control {
+            control.entry("richness").or_insert(1.0.into());
+        }
+    } else {
+        for (field, control_field) in water {
+            let note = "water is not an autoplace control before 2.0";
+            let from = ["autoplace_controls", "water", control_field];
+            move_field(value, &from, &[field], &mut changes, map_gen_size_number, note);
+        }
+
+        remove(value, &["autoplace_controls", "water"], &mut changes, |_| {
+            "the water autoplace control was added in 2.0".to_owned()
+        });
+
+        if let Some(controls) = value.get_mut("autoplace_controls").and_then(Value::as_object_mut) {
+            let space_age: Vec<_> = controls
+                .keys()
+                .filter(|name| planet_of_autoplace_control(name) != "nauvis")
+                .cloned()
+                .collect();
+
+            for name in space_age {
+                let planet = planet_of_autoplace_control(&name);
+                remove(value, &["autoplace_controls", &name], &mut changes, |_| {
+                    format!("{} is a planet of Space Age, which requires 2.0", planet)
+                });
+            }
+        }
+
+        remove(value, &["no_enemies_mode"], &mut changes, |removed| match removed.as_bool() {
+            Some(true) => "enemies are placed before 2.0, consider peaceful_mode".to_owned(),
+            _ => "no enemies mode was added in 2.0".to_owned(),
+        });
+
+        remove(value, &["territory_settings"], &mut changes, |_| {
+            "territories are part of Gleba, a planet of Space Age, which requires 2.0".to_owned()
+        });
+    }
+
+    changes
+}
+
+/// Migrate the fields of `map-settings.json` in `value` to the game version `to`, returning the changes made.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{migrate_map_settings, GameVersion};
+/// use serde_json::json;
+/// let difficulty = json!({"recipe_difficulty": "expensive", "technology_difficulty": "normal"});
+/// let mut value = json!({"difficulty_settings": difficulty});
+/// let changes = migrate_map_settings(&mut value, &GameVersion::new(2, 0, 0, 0));
+/// assert_eq!(value, json!({"difficulty_settings": {}}));
+/// assert_eq!(changes.len(), 2);
+/// ```
+pub fn migrate_map_settings(value: &mut Value, to: &GameVersion) -> Vec<MigrationChange> {
+    let mut changes = Vec::new();
+    let difficulty = |field: &'static str| ["difficulty_settings", field];
+    if *to >= VERSION_2_0 {
+        remove(value, &difficulty("recipe_difficulty"), &mut changes, |removed| {
+            match removed.as_str() {
+                Some("expensive") => "expensive recipes were removed in 2.0, recipes are normal".to_owned(),
+                _ => "recipe difficulty was removed in 2.0".to_owned(),
+            }
+        });
+
+        remove(value, &difficulty("technology_difficulty"), &mut changes, |removed| {
+            match removed.as_str() {
+                Some("expensive") => {
+                    "expensive technologies were removed in 2.0, technology_price_multiplier still raises their cost"
+                        .to_owned()
+                },
+                _ => "technology difficulty was removed in 2.0".to_owned(),
+            }
+        });
+
+        remove(value, &difficulty("research_queue_setting"), &mut changes, |_| {
+            "the research queue is always enabled in 2.0".to_owned()
+        });
+    } else {
+        remove(value, &difficulty("spoil_time_modifier"), &mut changes, |_| {
+            "items do not spoil before 2.0".to_owned()
+        });
+
+        remove(value, &["asteroids"], &mut changes, |_| "there are no asteroids before 2.0".to_owned());
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{MapAndDifficultySettings, MapGenSettings, VersionCheck};
+
+    const VERSION_1_1: GameVersion = GameVersion::new(1, 1, 110, 0);
+
+    #[test]
+    fn test_map_settings_round_trip() {
+        let mut value = serde_json::to_value(MapAndDifficultySettings::default()).unwrap();
+        let changes = migrate_map_settings(&mut value, &VERSION_2_0);
+        assert_eq!(changes.len(), 3);
+        let settings: MapAndDifficultySettings = serde_json::from_value(value.clone()).unwrap();
+        assert!(settings.check_version(&VERSION_2_0).is_ok());
+
+        // migrating again changes nothing
+        assert!(migrate_map_settings(&mut value, &VERSION_2_0).is_empty());
+
+        let mut value = serde_json::to_value(MapAndDifficultySettings::for_version(&VERSION_2_0)).unwrap();
+        let changes = migrate_map_settings(&mut value, &VERSION_1_1);
+        let paths: Vec<_> = changes
+            .iter()
+            .map(|change| match change {
+                MigrationChange::Removed { path, .. } => path.as_str(),
+                change => panic!("unexpected {:?}", change),
+            })
+            .collect();
+
+        assert_eq!(paths, ["difficulty_settings.spoil_time_modifier", "asteroids"]);
+    }
+
+    #[test]
+    fn test_map_gen_settings_round_trip() {
+        let mut value = serde_json::to_value(MapGenSettings::default()).unwrap();
+        value["property_expression_names"]["elevation"] = json!("0_17-island");
+        let original = value.clone();
+
+        let changes = migrate_map_gen_settings(&mut value, &VERSION_2_0);
+        assert_eq!(changes.len(), 7);
+        assert_eq!(value["property_expression_names"]["control:aux:bias"], json!("0"));
+        assert_eq!(value["property_expression_names"]["elevation"], json!("elevation_island"));
+        let settings: MapGenSettings = serde_json::from_value(value.clone()).unwrap();
+        assert!(settings.check_version(&VERSION_2_0).is_ok());
+
+        migrate_map_gen_settings(&mut value, &VERSION_1_1);
+        assert_eq!(value, original);
+    }
+
+    #[test]
+    fn test_water_to_autoplace_control() {
+        let mut value = json!({"terrain_segmentation": 0.5, "water": 2.0});
+        let changes = migrate_map_gen_settings(&mut value, &VERSION_2_0);
+        assert_eq!(
+            value,
+            json!({"autoplace_controls": {"water": {"frequency": 0.5, "size": 2.0, "richness": 1.0}}})
+        );
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "moved terrain_segmentation to autoplace_controls.water.frequency: water is an autoplace control in \
+                 2.0",
+                "moved water to autoplace_controls.water.size: water is an autoplace control in 2.0",
+            ]
+        );
+
+        // a layer may set only one of them
+        let mut value = json!({"water": 0.25, "autoplace_controls": {"coal": {}}});
+        migrate_map_gen_settings(&mut value, &VERSION_2_0);
+        assert_eq!(value, json!({"autoplace_controls": {"coal": {}, "water": {"size": 0.25}}}));
+
+        let mut value = json!({"water": 0.25, "autoplace_controls": {"water": {"size": 4}}});
+        let changes = migrate_map_gen_settings(&mut value, &VERSION_2_0);
+        assert_eq!(value, json!({"autoplace_controls": {"water": {"size": 4}}}));
+        assert_eq!(
+            changes[0].to_string(),
+            "removed water = 0.25: autoplace_controls.water.size is already set"
+        );
+    }
+
+    #[test]
+    fn test_water_from_autoplace_control() {
+        let mut value = json!({"autoplace_controls": {
+            "coal": {},
+            "water": {"frequency": 0.5, "size": "very-big", "richness": 1},
+        }});
+
+        let changes = migrate_map_gen_settings(&mut value, &VERSION_1_1);
+        assert_eq!(value, json!({"terrain_segmentation": 0.5, "water": 2.0, "autoplace_controls": {"coal": {}}}));
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "moved autoplace_controls.water.frequency to terrain_segmentation: water is not an autoplace control \
+                 before 2.0",
+                "moved autoplace_controls.water.size to water: water is not an autoplace control before 2.0",
+                "removed autoplace_controls.water = {\"richness\":1}: the water autoplace control was added in 2.0",
+            ]
+        );
+
+        let mut value = serde_json::to_value(MapGenSettings::for_version(&VERSION_2_0)).unwrap();
+        migrate_map_gen_settings(&mut value, &VERSION_1_1);
+        let settings: MapGenSettings = serde_json::from_value(value).unwrap();
+        assert!(settings.check_version(&VERSION_1_1).is_ok());
+        assert_eq!((settings.terrain_segmentation, settings.water), (Some(1.0), Some(1.0)));
+    }
+
+    #[test]
+    fn test_rename_conflict() {
+        let mut value = json!({"property_expression_names": {
+            "control-setting:aux:bias": "0.5",
+            "control:aux:bias": "0.25",
+        }});
+
+        let changes = migrate_map_gen_settings(&mut value, &VERSION_2_0);
+        assert_eq!(value, json!({"property_expression_names": {"control:aux:bias": "0.25"}}));
+        assert_eq!(
+            changes[0].to_string(),
+            "removed property_expression_names.control-setting:aux:bias = \"0.5\": \
+             property_expression_names.control:aux:bias is already set"
+        );
+    }
+
+    #[test]
+    fn test_space_age_removed() {
+        let mut value = json!({
+            "autoplace_controls": {"scrap": {}, "coal": {}},
+            "no_enemies_mode": true,
+            "territory_settings": {},
+        });
+
+        let changes = migrate_map_gen_settings(&mut value, &VERSION_1_1);
+        assert_eq!(value, json!({"autoplace_controls": {"coal": {}}}));
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "removed autoplace_controls.scrap = {}: fulgora is a planet of Space Age, which requires 2.0",
+                "removed no_enemies_mode = true: enemies are placed before 2.0, consider peaceful_mode",
+                "removed territory_settings = {}: territories are part of Gleba, a planet of Space Age, which requires \
+                 2.0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Mod settings for a Factorio server, stored by the game in `mod-settings.dat` in the mods directory.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{GameVersion, PropertyTree, PropertyTreeError};
+
+/// The values of mod settings, by setting name, in each of the game's setting types. Settings that are not present keep
+/// the default value declared by their mod.
+///
+/// In `mod-settings.dat`, each section is a dictionary of setting names to dictionaries holding the setting's `value`.
+/// In JSON the values are written directly.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{GameVersion, ModSettings, PropertyTree};
+/// let settings: ModSettings = serde_json::from_str(r#"{
+///     "startup": { "bobmods-logistics-beltoverhaul": true },
+///     "runtime-global": { "even-distribution-ticks": 60 }
+/// }"#).unwrap();
+///
+/// let mut bytes = Vec::new();
+/// settings.write_to(&mut bytes, &GameVersion::new(1, 1, 110, 0)).unwrap();
+/// let (_, read) = ModSettings::read_from(&mut bytes.as_slice()).unwrap();
+/// assert_eq!(read, settings);
+/// assert_eq!(read.runtime_global["even-distribution-ticks"], PropertyTree::Number(60.0));
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModSettings {
+    /// Settings that can only be changed before the game is started, as they affect prototypes.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub startup: BTreeMap<String, PropertyTree>,
+
+    /// Settings that apply to the whole map and can be changed while the game is running.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub runtime_global: BTreeMap<String, PropertyTree>,
+
+    /// Defaults for settings that each player can change for themselves.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub runtime_per_user: BTreeMap<String, PropertyTree>,
+}
+
+/// An error returned when reading mod settings fails.
+#[derive(Debug)]
+pub enum ModSettingsError {
+    /// The property tree could not be read.
+    PropertyTree(PropertyTreeError),
+
+    /// A node is not shaped like mod settings. The path to the node is given.
+    Malformed(String),
+}
+
+impl fmt::Display for ModSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModSettingsError::PropertyTree(e) => write!(f, "{}", e),
+            ModSettingsError::Malformed(path) => write!(f, "mod settings are malformed at {}", path),
+        }
+    }
+}
+
+impl Error for ModSettingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModSettingsError::PropertyTree(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PropertyTreeError> for ModSettingsError {
+    fn from(e: PropertyTreeError) -> Self {
+        ModSettingsError::PropertyTree(e)
+    }
+}
+
+const STARTUP: &str = "startup";
+const RUNTIME_GLOBAL: &str = "runtime-global";
+const RUNTIME_PER_USER: &str = "runtime-per-user";
+
+fn section_from_property_tree(
+    name: &str,
+    tree: Option<&PropertyTree>,
+) -> Result<BTreeMap<String, PropertyTree>, ModSettingsError> {
+    let entries = match tree {
+        None => return Ok(BTreeMap::new()),
+        Some(PropertyTree::Dictionary(entries)) => entries,
+        Some(_) => return Err(ModSettingsError::Malformed(name.to_owned())),
+    };
+
+    entries
+        .iter()
+        .map(|(setting, tree)| match tree.get("value") {
+            Some(value) => Ok((setting.clone(), value.clone())),
+            None => Err(ModSettingsError::Malformed(format!("{}.{}", name, setting))),
+        })
+        .collect()
+}
+
+fn section_to_property_tree(section: &BTreeMap<String, PropertyTree>) -> PropertyTree {
+    PropertyTree::Dictionary(
+        section
+            .iter()
+            .map(|(setting, value)| {
+                (
+                    setting.clone(),
+                    PropertyTree::Dictionary(vec![("value".to_owned(), value.clone())]),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Overwrite the settings in `section` with those in `other`. JSON has no integers, so a number replacing an integer
+/// keeps the integer's type, like the [`PropertyTree::SignedInteger`] of integer settings written by 2.0.
+fn merge_section(section: &mut BTreeMap<String, PropertyTree>, other: BTreeMap<String, PropertyTree>) {
+    for (setting, value) in other {
+        let value = match (section.get(&setting), value) {
+            (Some(PropertyTree::SignedInteger(_)), PropertyTree::Number(n))
+                if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 =>
+            {
+                PropertyTree::SignedInteger(n as i64)
+            },
+            (Some(PropertyTree::UnsignedInteger(_)), PropertyTree::Number(n))
+                if n.fract() == 0.0 && n >= 0.0 && n < u64::MAX as f64 =>
+            {
+                PropertyTree::UnsignedInteger(n as u64)
+            },
+            (_, value) => value,
+        };
+
+        section.insert(setting, value);
+    }
+}
+
+impl ModSettings {
+    /// Read mod settings from the root of a `mod-settings.dat` property tree. Unknown sections are ignored.
+    pub fn from_property_tree(tree: &PropertyTree) -> Result<Self, ModSettingsError> {
+        if !matches!(tree, PropertyTree::Dictionary(_)) {
+            return Err(ModSettingsError::Malformed("root".to_owned()));
+        }
+
+        Ok(Self {
+            startup: section_from_property_tree(STARTUP, tree.get(STARTUP))?,
+            runtime_global: section_from_property_tree(RUNTIME_GLOBAL, tree.get(RUNTIME_GLOBAL))?,
+            runtime_per_user: section_from_property_tree(RUNTIME_PER_USER, tree.get(RUNTIME_PER_USER))?,
+        })
+    }
+
+    /// Convert the mod settings to the property tree stored in `mod-settings.dat`.
+    pub fn to_property_tree(&self) -> PropertyTree {
+        PropertyTree::Dictionary(vec![
+            (STARTUP.to_owned(), section_to_property_tree(&self.startup)),
+            (RUNTIME_GLOBAL.to_owned(), section_to_property_tree(&self.runtime_global)),
+            (RUNTIME_PER_USER.to_owned(), section_to_property_tree(&self.runtime_per_user)),
+        ])
+    }
+
+    /// Read the contents of a `mod-settings.dat` file, returning the version of the game that wrote it and the settings.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<(GameVersion, Self), ModSettingsError> {
+        let (version, tree) = PropertyTree::read_file(reader)?;
+        Ok((version, Self::from_property_tree(&tree)?))
+    }
+
+    /// Write the contents of a `mod-settings.dat` file for the given version of the game.
+    pub fn write_to<W: Write>(&self, writer: &mut W, version: &GameVersion) -> std::io::Result<()> {
+        self.to_property_tree().write_file(writer, version)
+    }
+
+    /// Overwrite the settings in `self` with those in `other`. Settings only in `self` are kept, and integers in `self`
+    /// stay integers when `other` replaces them with a whole number.
+    pub fn merge(&mut self, other: ModSettings) {
+        merge_section(&mut self.startup, other.startup);
+        merge_section(&mut self.runtime_global, other.runtime_global);
+        merge_section(&mut self.runtime_per_user, other.runtime_per_user);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // built to the documented format rather than written by the game, so they only check the codec against itself
+    const FIXTURE_1_1: &[u8] = include_bytes!("../fixtures/mod-settings-1.1.dat");
+    const FIXTURE_2_0: &[u8] = include_bytes!("../fixtures/mod-settings-2.0.dat");
+
+    #[test]
+    fn test_fixture_round_trip() {
+        for fixture in [FIXTURE_1_1, FIXTURE_2_0] {
+            let (version, tree) = PropertyTree::read_file(&mut &fixture[..]).unwrap();
+            let mut bytes = Vec::new();
+            tree.write_file(&mut bytes, &version).unwrap();
+            assert_eq!(bytes, fixture);
+
+            let (_, settings) = ModSettings::read_from(&mut &fixture[..]).unwrap();
+            let mut bytes = Vec::new();
+            settings.write_to(&mut bytes, &version).unwrap();
+            assert_eq!(ModSettings::read_from(&mut bytes.as_slice()).unwrap(), (version, settings));
+        }
+    }
+
+    /// Files written by the game, which must be written back byte for byte, unlike the fixtures built for the tests.
+    #[test]
+    #[ignore = "no files written by the game are checked in yet, see fixtures/mod-settings/README.md"]
+    fn test_game_files() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/mod-settings");
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "dat"))
+            .collect();
+
+        paths.sort();
+        assert!(!paths.is_empty(), "no mod settings in {}", dir.display());
+        for path in paths {
+            let file = std::fs::read(&path).unwrap();
+            let (version, settings) = ModSettings::read_from(&mut &file[..])
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+            let expected: GameVersion = path.file_stem().unwrap().to_str().unwrap().parse().unwrap();
+            assert_eq!((version.major, version.minor), (expected.major, expected.minor), "{}", path.display());
+
+            let mut bytes = Vec::new();
+            settings.write_to(&mut bytes, &version).unwrap();
+            assert!(bytes == file, "{} is not written back as read", path.display());
+        }
+    }
+
+    #[test]
+    fn test_fixture_1_1() {
+        let (version, settings) = ModSettings::read_from(&mut &FIXTURE_1_1[..]).unwrap();
+        assert_eq!(version, GameVersion::new(1, 1, 110, 60000));
+        assert_eq!(settings.startup["bobmods-logistics-beltoverhaul"], PropertyTree::Bool(true));
+        assert_eq!(settings.startup["bobmods-logistics-inserteroverhaul"], PropertyTree::Bool(false));
+        assert_eq!(settings.runtime_global["even-distribution-ticks"], PropertyTree::Number(60.0));
+        assert_eq!(
+            settings.runtime_per_user["even-distribution-mode"],
+            PropertyTree::String("balanced".to_owned())
+        );
+
+        assert_eq!(
+            settings.runtime_per_user["squeak-through-color"],
+            PropertyTree::Dictionary(vec![
+                ("r".to_owned(), PropertyTree::Number(1.0)),
+                ("g".to_owned(), PropertyTree::Number(0.5)),
+                ("b".to_owned(), PropertyTree::Number(0.0)),
+                ("a".to_owned(), PropertyTree::Number(1.0)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_fixture_2_0() {
+        let (version, settings) = ModSettings::read_from(&mut &FIXTURE_2_0[..]).unwrap();
+        assert_eq!(version, GameVersion::new(2, 0, 28, 1));
+        assert_eq!(settings.startup["rocket-silo-stack-size"], PropertyTree::SignedInteger(10));
+        assert_eq!(settings.runtime_global["fill-chests-threshold"], PropertyTree::Number(0.25));
+        assert!(settings.runtime_per_user.is_empty());
+    }
+
+    #[test]
+    fn test_json() {
+        let (_, settings) = ModSettings::read_from(&mut &FIXTURE_1_1[..]).unwrap();
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(serde_json::from_str::<ModSettings>(&json).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let tree = PropertyTree::Dictionary(vec![(
+            STARTUP.to_owned(),
+            PropertyTree::Dictionary(vec![("foo".to_owned(), PropertyTree::Bool(true))]),
+        )]);
+
+        assert!(matches!(
+            ModSettings::from_property_tree(&tree),
+            Err(ModSettingsError::Malformed(path)) if path == "startup.foo"
+        ));
+
+        assert!(matches!(
+            ModSettings::from_property_tree(&PropertyTree::List(vec![])),
+            Err(ModSettingsError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut settings: ModSettings = serde_json::from_str(r#"{"startup": {"a": 1, "b": 2}}"#).unwrap();
+        settings.merge(serde_json::from_str(r#"{"startup": {"b": 3}, "runtime-global": {"c": true}}"#).unwrap());
+        assert_eq!(
+            settings,
+            serde_json::from_str(r#"{"startup": {"a": 1, "b": 3}, "runtime-global": {"c": true}}"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_integers() {
+        let (_, mut settings) = ModSettings::read_from(&mut &FIXTURE_2_0[..]).unwrap();
+        settings.runtime_per_user.insert("unsigned".to_owned(), PropertyTree::UnsignedInteger(1));
+        settings.merge(
+            serde_json::from_str(
+                r#"{
+                    "startup": {"rocket-silo-stack-size": 20},
+                    "runtime-global": {"fill-chests-threshold": 0.5},
+                    "runtime-per-user": {"unsigned": 2}
+                }"#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(settings.startup["rocket-silo-stack-size"], PropertyTree::SignedInteger(20));
+        assert_eq!(settings.runtime_global["fill-chests-threshold"], PropertyTree::Number(0.5));
+        assert_eq!(settings.runtime_per_user["unsigned"], PropertyTree::UnsignedInteger(2));
+
+        // a fraction cannot be an integer
+        settings.merge(serde_json::from_str(r#"{"startup": {"rocket-silo-stack-size": 2.5}}"#).unwrap());
+        assert_eq!(settings.startup["rocket-silo-stack-size"], PropertyTree::Number(2.5));
+    }
+}
@@ -0,0 +1,114 @@
+//! Mod configuration for a Factorio server.
+//!
+//! Two files are modeled here:
+//! - `mods.json`, in the configuration directory, declares the mods the server should run. It is read by factoriod.
+//! - `mod-list.json`, in the mods directory, lists the mods the game should enable. It is read by the game.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The mods the server should run. These are reflected in the `mods.json` file in the configuration directory.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::DeclaredMods;
+/// let declared: DeclaredMods = serde_json::from_str(r#"{
+///     "mods": [
+///         { "name": "space-exploration", "version": ">= 0.6.0" },
+///         { "name": "even-distribution", "enabled": false }
+///     ]
+/// }"#).unwrap();
+///
+/// assert_eq!(declared.mods.len(), 2);
+/// assert!(declared.mods[0].enabled);
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+pub struct DeclaredMods {
+    /// The declared mods. Dependencies of these mods are added automatically and do not need to be declared.
+    pub mods: Vec<DeclaredMod>,
+}
+
+/// A mod declared in `mods.json`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct DeclaredMod {
+    /// The name of the mod on the mod portal.
+    pub name: String,
+
+    /// An optional version requirement, such as `"= 1.2.3"` or `">= 0.6.0"`. The newest release satisfying the
+    /// requirement and the dependencies of the other mods is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Whether the mod is enabled. Disabled mods are still downloaded but are not loaded by the game. Defaults to
+    /// [`true`].
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// The mods the game should enable. These are reflected in the `mod-list.json` file in the mods directory.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+pub struct ModList {
+    pub mods: Vec<ModListEntry>,
+}
+
+/// A mod in `mod-list.json`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ModListEntry {
+    /// The name of the mod.
+    pub name: String,
+
+    /// Whether the game should load the mod.
+    pub enabled: bool,
+
+    /// The version of the mod to load when several versions are present in the mods directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl ModListEntry {
+    /// Create an entry for `name` without a version.
+    pub fn new<S: Into<String>>(name: S, enabled: bool) -> Self {
+        Self {
+            name: name.into(),
+            enabled,
+            version: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declared_mods_defaults() {
+        let declared: DeclaredMods = serde_json::from_str(r#"{"mods": [{"name": "foo"}]}"#).unwrap();
+        assert_eq!(
+            declared.mods,
+            vec![DeclaredMod {
+                name: "foo".to_owned(),
+                version: None,
+                enabled: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_mod_list_serde() {
+        let mod_list = ModList {
+            mods: vec![ModListEntry::new("base", true), ModListEntry::new("foo", false)],
+        };
+
+        let json = serde_json::to_string(&mod_list).unwrap();
+        assert_eq!(
+            json,
+            r#"{"mods":[{"name":"base","enabled":true},{"name":"foo","enabled":false}]}"#
+        );
+
+        assert_eq!(serde_json::from_str::<ModList>(&json).unwrap(), mod_list);
+    }
+}
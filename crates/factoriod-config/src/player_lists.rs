@@ -0,0 +1,253 @@
+//! Lists of players for a Factorio server: `server-whitelist.json`, `server-adminlist.json` and `server-banlist.json`.
+//!
+//! The game matches usernames in these lists case-insensitively, and so do the editing methods here.
+
+use std::path::Path;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{load_json, save_json};
+
+/// A list of player names, as in `server-whitelist.json` and `server-adminlist.json`.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::PlayerList;
+/// let mut whitelist: PlayerList = serde_json::from_str(r#"["foo", "bar"]"#).unwrap();
+/// assert!(!whitelist.add("Foo"));
+/// assert!(whitelist.add("baz"));
+/// assert!(whitelist.remove("BAR"));
+/// assert_eq!(serde_json::to_string(&whitelist).unwrap(), r#"["foo","baz"]"#);
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct PlayerList {
+    pub players: Vec<String>,
+}
+
+/// The players allowed to join a server, in `server-whitelist.json`. The whitelist is only enforced when the server is
+/// started with `--use-server-whitelist`.
+pub type Whitelist = PlayerList;
+
+/// The players that are admins of a server, in `server-adminlist.json`.
+pub type Adminlist = PlayerList;
+
+impl PlayerList {
+    /// Load a list from `path`. A missing file is an empty list.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        load_json(path)
+    }
+
+    /// Save the list to `path`, atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        save_json(path, self)
+    }
+
+    /// Whether `name` is in the list.
+    pub fn contains(&self, name: &str) -> bool {
+        self.players.iter().any(|player| player.eq_ignore_ascii_case(name))
+    }
+
+    /// Add `name` to the list. Returns `false` if it was already in the list.
+    pub fn add(&mut self, name: &str) -> bool {
+        if self.contains(name) {
+            return false;
+        }
+
+        self.players.push(name.to_owned());
+        true
+    }
+
+    /// Remove `name` from the list. Returns `false` if it was not in the list.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.players.len();
+        self.players.retain(|player| !player.eq_ignore_ascii_case(name));
+        self.players.len() != len
+    }
+}
+
+/// A banned player.
+///
+/// The game writes bans as objects with a reason and address, but also accepts plain usernames. Bans without a reason or
+/// address are written as plain usernames.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Ban {
+    Username(String),
+    Detailed {
+        /// The banned player's username.
+        username: String,
+
+        /// Why the player was banned.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+
+        /// The address the player was connected from when banned.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+    },
+}
+
+impl Ban {
+    /// Create a ban for `username`.
+    pub fn new<S: Into<String>>(username: S, reason: Option<String>, address: Option<String>) -> Self {
+        let username = username.into();
+        if reason.is_none() && address.is_none() {
+            Ban::Username(username)
+        } else {
+            Ban::Detailed {
+                username,
+                reason,
+                address,
+            }
+        }
+    }
+
+    /// The banned player's username.
+    pub fn username(&self) -> &str {
+        match self {
+            Ban::Username(username) => username,
+            Ban::Detailed { username, .. } => username,
+        }
+    }
+
+    /// Why the player was banned, if known.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Ban::Username(_) => None,
+            Ban::Detailed { reason, .. } => reason.as_deref(),
+        }
+    }
+
+    /// The address of the banned player, if known.
+    pub fn address(&self) -> Option<&str> {
+        match self {
+            Ban::Username(_) => None,
+            Ban::Detailed { address, .. } => address.as_deref(),
+        }
+    }
+}
+
+/// The players banned from a server, in `server-banlist.json`.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{Ban, Banlist};
+/// let mut banlist: Banlist = serde_json::from_str(r#"[
+///     "foo",
+///     { "username": "bar", "reason": "griefing", "address": "203.0.113.1" }
+/// ]"#).unwrap();
+///
+/// assert_eq!(banlist.get("BAR").and_then(Ban::reason), Some("griefing"));
+/// assert!(banlist.add(Ban::new("baz", None, None)));
+/// assert!(banlist.remove("foo"));
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Banlist {
+    pub bans: Vec<Ban>,
+}
+
+impl Banlist {
+    /// Load a banlist from `path`. A missing file is an empty banlist.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        load_json(path)
+    }
+
+    /// Save the banlist to `path`, atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        save_json(path, self)
+    }
+
+    /// Get the ban for `username`, if any.
+    pub fn get(&self, username: &str) -> Option<&Ban> {
+        self.bans.iter().find(|ban| ban.username().eq_ignore_ascii_case(username))
+    }
+
+    /// Add a ban. Returns `false` if the player was already banned, in which case the existing ban is replaced.
+    pub fn add(&mut self, ban: Ban) -> bool {
+        match self
+            .bans
+            .iter_mut()
+            .find(|existing| existing.username().eq_ignore_ascii_case(ban.username()))
+        {
+            Some(existing) => {
+                *existing = ban;
+                false
+            },
+            None => {
+                self.bans.push(ban);
+                true
+            },
+        }
+    }
+
+    /// Remove the ban for `username`. Returns `false` if the player was not banned.
+    pub fn remove(&mut self, username: &str) -> bool {
+        let len = self.bans.len();
+        self.bans.retain(|ban| !ban.username().eq_ignore_ascii_case(username));
+        self.bans.len() != len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_player_list() {
+        let mut list = PlayerList::default();
+        assert!(list.add("foo"));
+        assert!(!list.add("FOO"));
+        assert!(list.contains("Foo"));
+        assert!(!list.remove("bar"));
+        assert!(list.remove("foo"));
+        assert!(list.players.is_empty());
+    }
+
+    #[test]
+    fn test_banlist_serde() {
+        let json = r#"["foo",{"username":"bar","reason":"griefing"},{"username":"baz"}]"#;
+        let banlist: Banlist = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            banlist.bans,
+            vec![
+                Ban::Username("foo".to_owned()),
+                Ban::Detailed {
+                    username: "bar".to_owned(),
+                    reason: Some("griefing".to_owned()),
+                    address: None,
+                },
+                Ban::Detailed {
+                    username: "baz".to_owned(),
+                    reason: None,
+                    address: None,
+                },
+            ]
+        );
+
+        assert_eq!(serde_json::to_string(&banlist).unwrap(), json);
+    }
+
+    #[test]
+    fn test_banlist_add_replaces() {
+        let mut banlist = Banlist::default();
+        assert!(banlist.add(Ban::new("foo", None, None)));
+        assert!(!banlist.add(Ban::new("FOO", Some("spam".to_owned()), None)));
+        assert_eq!(banlist.bans.len(), 1);
+        assert_eq!(banlist.get("foo").and_then(Ban::reason), Some("spam"));
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server-banlist.json");
+        let mut banlist = Banlist::load(&path).unwrap();
+        assert!(banlist.bans.is_empty());
+
+        banlist.add(Ban::new("foo", Some("griefing".to_owned()), Some("203.0.113.1".to_owned())));
+        banlist.save(&path).unwrap();
+        assert_eq!(Banlist::load(&path).unwrap(), banlist);
+    }
+}
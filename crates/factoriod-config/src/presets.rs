@@ -0,0 +1,269 @@
+//! The map generation presets that ship with the game, from `data/base/prototypes/map-gen-presets.lua`.
+//!
+//! A preset changes a few values of the default settings, which are [`MapGenSettings::default`] and
+//! [`MapAndDifficultySettings::default`].
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    AutoplaceControl, AutoplaceControlName, Elevation, GameVersion, MapAndDifficultySettings, MapGenSettings,
+    MapGenSize, MapGenSizeFloat, RecipeDifficulty, TechnologyDifficulty,
+};
+
+/// The resources placed by the default autoplace controls.
+const RESOURCES: [AutoplaceControlName; 6] = [
+    AutoplaceControlName::Coal,
+    AutoplaceControlName::CopperOre,
+    AutoplaceControlName::CrudeOil,
+    AutoplaceControlName::IronOre,
+    AutoplaceControlName::Stone,
+    AutoplaceControlName::UraniumOre,
+];
+
+/// A map generation preset, as selectable in the game's map generator.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::{MapPreset, RecipeDifficulty};
+/// let preset: MapPreset = "marathon".parse().unwrap();
+/// assert_eq!(preset, MapPreset::Marathon);
+/// assert_eq!(preset.map_settings().difficulty_settings.recipe_difficulty, Some(RecipeDifficulty::Expensive));
+/// assert!("unknown".parse::<MapPreset>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapPreset {
+    #[default]
+    Default,
+    RichResources,
+    Marathon,
+    DeathWorld,
+    DeathWorldMarathon,
+    RailWorld,
+    RibbonWorld,
+    Island,
+}
+
+/// An error returned when parsing the name of an unknown preset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPresetError(pub String);
+
+impl fmt::Display for UnknownPresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = MapPreset::ALL.map(MapPreset::name);
+        write!(f, "unknown preset {}, expected one of: {}", self.0, names.join(", "))
+    }
+}
+
+impl Error for UnknownPresetError {}
+
+fn size(value: f32) -> MapGenSize {
+    MapGenSize::Float(MapGenSizeFloat::try_from(value).expect("valid map gen size"))
+}
+
+impl MapPreset {
+    /// Every preset, in the order the game lists them.
+    pub const ALL: [MapPreset; 8] = [
+        MapPreset::Default,
+        MapPreset::RichResources,
+        MapPreset::Marathon,
+        MapPreset::DeathWorld,
+        MapPreset::DeathWorldMarathon,
+        MapPreset::RailWorld,
+        MapPreset::RibbonWorld,
+        MapPreset::Island,
+    ];
+
+    /// The name of the preset in `map-gen-presets.lua`, like `rich-resources`.
+    pub fn name(self) -> &'static str {
+        match self {
+            MapPreset::Default => "default",
+            MapPreset::RichResources => "rich-resources",
+            MapPreset::Marathon => "marathon",
+            MapPreset::DeathWorld => "death-world",
+            MapPreset::DeathWorldMarathon => "death-world-marathon",
+            MapPreset::RailWorld => "rail-world",
+            MapPreset::RibbonWorld => "ribbon-world",
+            MapPreset::Island => "island",
+        }
+    }
+
+    /// The map generation settings of the preset.
+    pub fn map_gen_settings(self) -> MapGenSettings {
+        let mut settings = MapGenSettings::default();
+        let mut set_resources = |control: AutoplaceControl| {
+            for resource in RESOURCES {
+                *settings.autoplace_control_mut(resource) = control.clone();
+            }
+        };
+
+        match self {
+            MapPreset::Default | MapPreset::Marathon => {},
+            MapPreset::RichResources => set_resources(AutoplaceControl {
+                richness: MapGenSize::VeryGood,
+                ..Default::default()
+            }),
+            MapPreset::DeathWorld | MapPreset::DeathWorldMarathon => {
+                *settings.autoplace_control_mut(AutoplaceControlName::EnemyBase) = AutoplaceControl {
+                    frequency: MapGenSize::VeryHigh,
+                    size: MapGenSize::VeryBig,
+                    ..Default::default()
+                };
+
+                settings.starting_area = 0.75;
+            },
+            MapPreset::RailWorld => {
+                set_resources(AutoplaceControl {
+                    frequency: size(0.33333334),
+                    size: size(3.0),
+                    ..Default::default()
+                });
+
+                settings.water = Some(1.5);
+            },
+            MapPreset::RibbonWorld => {
+                set_resources(AutoplaceControl {
+                    frequency: size(3.0),
+                    size: size(0.5),
+                    richness: size(2.0),
+                });
+
+                settings.height = 128;
+                settings.starting_area = 3.0;
+                settings.water = Some(0.25);
+            },
+            MapPreset::Island => settings.set_elevation(Elevation::Island),
+        }
+
+        settings
+    }
+
+    /// The map and difficulty settings of the preset, in the shape of 1.1.
+    pub fn map_settings(self) -> MapAndDifficultySettings {
+        self.apply_map_settings(MapAndDifficultySettings::default())
+    }
+
+    /// The map and difficulty settings of the preset for the game `version`. Expensive recipes were removed in 2.0,
+    /// so the marathon presets only raise the price of technologies there.
+    pub fn map_settings_for(self, version: &GameVersion) -> MapAndDifficultySettings {
+        self.apply_map_settings(MapAndDifficultySettings::for_version(version))
+    }
+
+    fn apply_map_settings(self, mut settings: MapAndDifficultySettings) -> MapAndDifficultySettings {
+        let expensive = |settings: &mut MapAndDifficultySettings| {
+            let difficulty = &mut settings.difficulty_settings;
+            if difficulty.recipe_difficulty.is_some() {
+                difficulty.recipe_difficulty = Some(RecipeDifficulty::Expensive);
+            }
+
+            if difficulty.technology_difficulty.is_some() {
+                difficulty.technology_difficulty = Some(TechnologyDifficulty::Expensive);
+            }
+
+            difficulty.technology_price_multiplier = 4.0;
+        };
+
+        let aggressive_enemies = |settings: &mut MapAndDifficultySettings| {
+            settings.enemy_evolution.destroy_factor = 0.002;
+            settings.enemy_evolution.pollution_factor = 0.0000012;
+            settings.enemy_expansion.min_expansion_cooldown = 60 * 60;
+            settings.enemy_expansion.max_expansion_cooldown = 10 * 60 * 60;
+            settings.pollution.enemy_attack_pollution_consumption_modifier = 0.5;
+        };
+
+        match self {
+            MapPreset::Default | MapPreset::RichResources | MapPreset::RibbonWorld | MapPreset::Island => {},
+            MapPreset::Marathon => expensive(&mut settings),
+            MapPreset::DeathWorld => {
+                aggressive_enemies(&mut settings);
+                settings.enemy_evolution.time_factor = 0.00002;
+            },
+            MapPreset::DeathWorldMarathon => {
+                aggressive_enemies(&mut settings);
+                settings.enemy_evolution.time_factor = 0.000015;
+                expensive(&mut settings);
+            },
+            MapPreset::RailWorld => {
+                settings.enemy_evolution.time_factor = 0.000002;
+                settings.enemy_expansion.enabled = false;
+            },
+        }
+
+        settings
+    }
+}
+
+impl fmt::Display for MapPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for MapPreset {
+    type Err = UnknownPresetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MapPreset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == s)
+            .ok_or_else(|| UnknownPresetError(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for preset in MapPreset::ALL {
+            assert_eq!(preset.name().parse::<MapPreset>(), Ok(preset));
+        }
+    }
+
+    #[test]
+    fn test_default_preset_is_default() {
+        assert_eq!(json(&MapPreset::Default.map_gen_settings()), json(&MapGenSettings::default()));
+        assert_eq!(json(&MapPreset::Default.map_settings()), json(&MapAndDifficultySettings::default()));
+    }
+
+    #[test]
+    fn test_presets_differ_from_default() {
+        let default = (json(&MapGenSettings::default()), json(&MapAndDifficultySettings::default()));
+        for preset in MapPreset::ALL.into_iter().skip(1) {
+            let settings = (json(&preset.map_gen_settings()), json(&preset.map_settings()));
+            assert_ne!(settings, default, "{} is the same as the default preset", preset);
+        }
+    }
+
+    #[test]
+    fn test_map_settings_for_version() {
+        use crate::{VersionCheck, VERSION_2_0};
+        for preset in MapPreset::ALL {
+            assert!(preset.map_settings_for(&VERSION_2_0).check_version(&VERSION_2_0).is_ok(), "{}", preset);
+        }
+
+        let marathon = MapPreset::Marathon.map_settings_for(&VERSION_2_0);
+        assert_eq!(marathon.difficulty_settings.technology_price_multiplier, 4.0);
+    }
+
+    #[test]
+    fn test_presets_are_valid() {
+        use crate::Validate;
+        for preset in MapPreset::ALL {
+            assert_eq!(preset.map_gen_settings().validate(), Ok(()), "{}", preset);
+        }
+    }
+
+    #[test]
+    fn test_death_world() {
+        let settings = MapPreset::DeathWorld.map_gen_settings();
+        assert_eq!(f32::from(settings.autoplace_controls["enemy-base"].frequency.clone()), 2.0);
+        assert!(MapPreset::DeathWorldMarathon.map_settings().enemy_expansion.min_expansion_cooldown < 14000);
+    }
+}
@@ -0,0 +1,460 @@
+//! Factorio's binary [property tree](https://wiki.factorio.com/Property_tree) format, used by `mod-settings.dat`.
+//!
+//! A property tree file starts with the version of the game that wrote it, followed by a tree. All integers are
+//! little-endian:
+//! ```text
+//! u16 x4   game version (major, minor, patch, build)
+//! u8       unused, always 0, since 0.17
+//! tree     the root node
+//! ```
+//!
+//! Each node is a `u8` type, a `u8` "any type" flag, and the node's content:
+//! ```text
+//! 0  none          no content
+//! 1  bool          u8
+//! 2  number        f64
+//! 3  string        string
+//! 4  list          u32 count, then count x (string key, tree), keys are empty
+//! 5  dictionary    u32 count, then count x (string key, tree)
+//! 6  signed        i64, since 2.0
+//! 7  unsigned      u64, since 2.0
+//! ```
+//!
+//! A string is a `u8` that is 1 when the string is empty, otherwise followed by a space optimized length (a `u8`, or
+//! `0xFF` followed by a `u32`) and that many bytes of UTF-8.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::GameVersion;
+
+/// A node of a property tree.
+///
+/// Property trees convert to and from JSON naturally, with one caveat: JSON integers are read as
+/// [`PropertyTree::Number`], so [`PropertyTree::SignedInteger`] and [`PropertyTree::UnsignedInteger`], which the game
+/// uses for integer mod settings since 2.0, do not survive a round trip through JSON. See
+/// [`ModSettings::merge`](crate::ModSettings::merge) for how declared settings keep the type of the game's.
+///
+/// # Examples
+/// ```
+/// use factoriod_config::PropertyTree;
+/// let tree: PropertyTree = serde_json::from_str(r#"{"value": 5}"#).unwrap();
+/// assert_eq!(tree.get("value"), Some(&PropertyTree::Number(5.0)));
+///
+/// let mut bytes = Vec::new();
+/// tree.write_to(&mut bytes).unwrap();
+/// assert_eq!(PropertyTree::read_from(&mut bytes.as_slice()).unwrap(), tree);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyTree {
+    None,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<PropertyTree>),
+
+    /// A dictionary, in the order the game wrote it.
+    Dictionary(Vec<(String, PropertyTree)>),
+    SignedInteger(i64),
+    UnsignedInteger(u64),
+}
+
+/// An error returned when reading a property tree fails.
+#[derive(Debug)]
+pub enum PropertyTreeError {
+    /// The property tree could not be read or written.
+    Io(std::io::Error),
+
+    /// A node has a type that is not known.
+    UnknownType(u8),
+
+    /// A string is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl fmt::Display for PropertyTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyTreeError::Io(e) => write!(f, "failed to read property tree: {}", e),
+            PropertyTreeError::UnknownType(t) => write!(f, "unknown property tree type {}", t),
+            PropertyTreeError::InvalidUtf8 => write!(f, "property tree contains a string that is not valid UTF-8"),
+        }
+    }
+}
+
+impl Error for PropertyTreeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PropertyTreeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PropertyTreeError {
+    fn from(e: std::io::Error) -> Self {
+        PropertyTreeError::Io(e)
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], PropertyTreeError> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, PropertyTreeError> {
+    Ok(read_array::<R, 1>(reader)?[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, PropertyTreeError> {
+    Ok(u16::from_le_bytes(read_array(reader)?))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, PropertyTreeError> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, PropertyTreeError> {
+    if read_u8(reader)? != 0 {
+        return Ok(String::new());
+    }
+
+    let len = match read_u8(reader)? {
+        0xFF => read_u32(reader)?,
+        len => len.into(),
+    };
+
+    // the length comes from the file, so the bytes are only allocated as they are read
+    let mut bytes = Vec::new();
+    reader.take(len.into()).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+
+    String::from_utf8(bytes).map_err(|_| PropertyTreeError::InvalidUtf8)
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> std::io::Result<()> {
+    if value.is_empty() {
+        return writer.write_all(&[1]);
+    }
+
+    writer.write_all(&[0])?;
+    match u8::try_from(value.len()) {
+        Ok(len) if len < 0xFF => writer.write_all(&[len])?,
+        _ => {
+            writer.write_all(&[0xFF])?;
+            writer.write_all(&(value.len() as u32).to_le_bytes())?;
+        },
+    }
+
+    writer.write_all(value.as_bytes())
+}
+
+impl PropertyTree {
+    /// Get the value of `key`, if this is a dictionary containing it.
+    pub fn get(&self, key: &str) -> Option<&PropertyTree> {
+        match self {
+            PropertyTree::Dictionary(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn type_id(&self) -> u8 {
+        match self {
+            PropertyTree::None => 0,
+            PropertyTree::Bool(_) => 1,
+            PropertyTree::Number(_) => 2,
+            PropertyTree::String(_) => 3,
+            PropertyTree::List(_) => 4,
+            PropertyTree::Dictionary(_) => 5,
+            PropertyTree::SignedInteger(_) => 6,
+            PropertyTree::UnsignedInteger(_) => 7,
+        }
+    }
+
+    /// Read a tree, without a version header.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, PropertyTreeError> {
+        let type_id = read_u8(reader)?;
+
+        // the "any type" flag has no bearing on the content
+        read_u8(reader)?;
+
+        let tree = match type_id {
+            0 => PropertyTree::None,
+            1 => PropertyTree::Bool(read_u8(reader)? != 0),
+            2 => PropertyTree::Number(f64::from_le_bytes(read_array(reader)?)),
+            3 => PropertyTree::String(read_string(reader)?),
+            4 => {
+                let count = read_u32(reader)?;
+                let items = (0..count)
+                    .map(|_| {
+                        read_string(reader)?;
+                        PropertyTree::read_from(reader)
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                PropertyTree::List(items)
+            },
+            5 => {
+                let count = read_u32(reader)?;
+                let entries = (0..count)
+                    .map(|_| Ok((read_string(reader)?, PropertyTree::read_from(reader)?)))
+                    .collect::<Result<_, PropertyTreeError>>()?;
+
+                PropertyTree::Dictionary(entries)
+            },
+            6 => PropertyTree::SignedInteger(i64::from_le_bytes(read_array(reader)?)),
+            7 => PropertyTree::UnsignedInteger(u64::from_le_bytes(read_array(reader)?)),
+            t => return Err(PropertyTreeError::UnknownType(t)),
+        };
+
+        Ok(tree)
+    }
+
+    /// Write the tree, without a version header.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[self.type_id(), 0])?;
+        match self {
+            PropertyTree::None => Ok(()),
+            PropertyTree::Bool(value) => writer.write_all(&[u8::from(*value)]),
+            PropertyTree::Number(value) => writer.write_all(&value.to_le_bytes()),
+            PropertyTree::String(value) => write_string(writer, value),
+            PropertyTree::List(items) => {
+                writer.write_all(&(items.len() as u32).to_le_bytes())?;
+                for item in items {
+                    write_string(writer, "")?;
+                    item.write_to(writer)?;
+                }
+
+                Ok(())
+            },
+            PropertyTree::Dictionary(entries) => {
+                writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+                for (key, value) in entries {
+                    write_string(writer, key)?;
+                    value.write_to(writer)?;
+                }
+
+                Ok(())
+            },
+            PropertyTree::SignedInteger(value) => writer.write_all(&value.to_le_bytes()),
+            PropertyTree::UnsignedInteger(value) => writer.write_all(&value.to_le_bytes()),
+        }
+    }
+
+    /// Read a property tree file: a version header followed by a tree.
+    pub fn read_file<R: Read>(reader: &mut R) -> Result<(GameVersion, Self), PropertyTreeError> {
+        let version = GameVersion {
+            major: read_u16(reader)?,
+            minor: read_u16(reader)?,
+            patch: read_u16(reader)?,
+            build: read_u16(reader)?,
+        };
+
+        read_u8(reader)?;
+        Ok((version, PropertyTree::read_from(reader)?))
+    }
+
+    /// Write a property tree file: a version header followed by the tree.
+    pub fn write_file<W: Write>(&self, writer: &mut W, version: &GameVersion) -> std::io::Result<()> {
+        for part in [version.major, version.minor, version.patch, version.build] {
+            writer.write_all(&part.to_le_bytes())?;
+        }
+
+        writer.write_all(&[0])?;
+        self.write_to(writer)
+    }
+}
+
+// a property tree holds any value, as defined by the mod that reads it
+impl JsonSchema for PropertyTree {
+    fn schema_name() -> String {
+        "PropertyTree".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        Schema::Bool(true)
+    }
+}
+
+impl Serialize for PropertyTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PropertyTree::None => serializer.serialize_unit(),
+            PropertyTree::Bool(value) => serializer.serialize_bool(*value),
+            PropertyTree::Number(value) => serializer.serialize_f64(*value),
+            PropertyTree::String(value) => serializer.serialize_str(value),
+            PropertyTree::List(items) => serializer.collect_seq(items),
+            PropertyTree::Dictionary(entries) => serializer.collect_map(entries.iter().map(|(k, v)| (k, v))),
+            PropertyTree::SignedInteger(value) => serializer.serialize_i64(*value),
+            PropertyTree::UnsignedInteger(value) => serializer.serialize_u64(*value),
+        }
+    }
+}
+
+struct PropertyTreeVisitor;
+
+impl<'de> Visitor<'de> for PropertyTreeVisitor {
+    type Value = PropertyTree;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a property tree value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(PropertyTree::None)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(PropertyTree::None)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(PropertyTree::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(PropertyTree::Number(v as f64))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(PropertyTree::Number(v as f64))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(PropertyTree::Number(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(PropertyTree::String(v.to_owned()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+
+        Ok(PropertyTree::List(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+
+        Ok(PropertyTree::Dictionary(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for PropertyTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PropertyTreeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(tree: &PropertyTree) -> PropertyTree {
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes).unwrap();
+        PropertyTree::read_from(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tree = PropertyTree::Dictionary(vec![
+            ("none".to_owned(), PropertyTree::None),
+            ("bool".to_owned(), PropertyTree::Bool(true)),
+            ("number".to_owned(), PropertyTree::Number(-1.5)),
+            ("empty".to_owned(), PropertyTree::String(String::new())),
+            ("long".to_owned(), PropertyTree::String("x".repeat(300))),
+            (
+                "list".to_owned(),
+                PropertyTree::List(vec![PropertyTree::Number(1.0), PropertyTree::String("a".to_owned())]),
+            ),
+            ("signed".to_owned(), PropertyTree::SignedInteger(-7)),
+            ("unsigned".to_owned(), PropertyTree::UnsignedInteger(u64::MAX)),
+        ]);
+
+        assert_eq!(round_trip(&tree), tree);
+    }
+
+    #[test]
+    fn test_write_string() {
+        let mut bytes = Vec::new();
+        PropertyTree::String("abc".to_owned()).write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, [3, 0, 0, 3, b'a', b'b', b'c']);
+
+        let mut bytes = Vec::new();
+        PropertyTree::String(String::new()).write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, [3, 0, 1]);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(matches!(
+            PropertyTree::read_from(&mut [8u8, 0].as_slice()),
+            Err(PropertyTreeError::UnknownType(8))
+        ));
+
+        assert!(matches!(
+            PropertyTree::read_from(&mut [2u8, 0, 0, 0].as_slice()),
+            Err(PropertyTreeError::Io(_))
+        ));
+
+        assert!(matches!(
+            PropertyTree::read_from(&mut [3u8, 0, 0, 1, 0xFF].as_slice()),
+            Err(PropertyTreeError::InvalidUtf8)
+        ));
+
+        // a corrupt length is not allocated up front
+        assert!(matches!(
+            PropertyTree::read_from(&mut [3u8, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, b'a'].as_slice()),
+            Err(PropertyTreeError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let version = GameVersion::new(2, 0, 28, 1);
+        let tree = PropertyTree::Dictionary(vec![("startup".to_owned(), PropertyTree::Dictionary(vec![]))]);
+        let mut bytes = Vec::new();
+        tree.write_file(&mut bytes, &version).unwrap();
+        assert_eq!(&bytes[..9], [2, 0, 0, 0, 28, 0, 1, 0, 0]);
+        assert_eq!(PropertyTree::read_file(&mut bytes.as_slice()).unwrap(), (version, tree));
+    }
+
+    #[test]
+    fn test_json() {
+        let json = r#"{"b":true,"n":1.5,"s":"x","l":[null,2.0],"d":{}}"#;
+        let tree: PropertyTree = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            tree,
+            PropertyTree::Dictionary(vec![
+                ("b".to_owned(), PropertyTree::Bool(true)),
+                ("n".to_owned(), PropertyTree::Number(1.5)),
+                ("s".to_owned(), PropertyTree::String("x".to_owned())),
+                (
+                    "l".to_owned(),
+                    PropertyTree::List(vec![PropertyTree::None, PropertyTree::Number(2.0)])
+                ),
+                ("d".to_owned(), PropertyTree::Dictionary(vec![])),
+            ])
+        );
+
+        assert_eq!(serde_json::to_string(&tree).unwrap(), json);
+    }
+}
@@ -0,0 +1,187 @@
+//! [JSON Schemas](https://json-schema.org/) for the config files, for autocomplete and validation in editors.
+//!
+//! The schemas of the layered configs (see [`Layered`](crate::Layered)) do not require any property, since each layer
+//! may set only some of them. The schemas are checked in to the `schemas` directory of this crate.
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{RootSchema, SchemaObject};
+use schemars::visit::{visit_schema_object, Visitor};
+
+use crate::{
+    Adminlist, Banlist, DeclaredMods, MapAndDifficultySettings, MapGenSettings, ModSettings, ServerSettingsWithSecrets,
+    Whitelist,
+};
+
+/// The names of the config files with a schema, without their extension.
+pub const SCHEMA_NAMES: [&str; 8] = [
+    "server-settings",
+    "map-gen-settings",
+    "map-settings",
+    "server-whitelist",
+    "server-adminlist",
+    "server-banlist",
+    "mods",
+    "mod-settings",
+];
+
+/// Removes `required` from every object.
+#[derive(Debug, Clone)]
+struct OptionalProperties;
+
+impl Visitor for OptionalProperties {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        if let Some(object) = &mut schema.object {
+            object.required.clear();
+        }
+
+        visit_schema_object(self, schema);
+    }
+}
+
+/// Removes the examples from descriptions, which are taken from doc comments and are Rust code.
+#[derive(Debug, Clone)]
+struct WithoutExamples;
+
+impl Visitor for WithoutExamples {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        if let Some(metadata) = &mut schema.metadata {
+            if let Some(description) = &mut metadata.description {
+                if let Some(examples) = description.find("# Example") {
+                    description.truncate(examples);
+                    description.truncate(description.trim_end().len());
+                }
+
+                if description.is_empty() {
+                    metadata.description = None;
+                }
+            }
+        }
+
+        visit_schema_object(self, schema);
+    }
+}
+
+fn layered_generator() -> SchemaGenerator {
+    SchemaSettings::draft07()
+        .with_visitor(WithoutExamples)
+        .with_visitor(OptionalProperties)
+        .into_generator()
+}
+
+fn generator() -> SchemaGenerator {
+    SchemaSettings::draft07().with_visitor(WithoutExamples).into_generator()
+}
+
+/// The schema of the config file `name`, one of [`SCHEMA_NAMES`].
+///
+/// # Examples
+/// ```
+/// use factoriod_config::config_schema;
+/// let schema = config_schema("map-gen-settings").unwrap();
+/// assert_eq!(schema.schema.metadata.unwrap().title.as_deref(), Some("MapGenSettings"));
+/// assert!(config_schema("unknown").is_none());
+/// ```
+pub fn config_schema(name: &str) -> Option<RootSchema> {
+    let schema = match name {
+        "server-settings" => layered_generator().into_root_schema_for::<ServerSettingsWithSecrets>(),
+        "map-gen-settings" => layered_generator().into_root_schema_for::<MapGenSettings>(),
+        "map-settings" => layered_generator().into_root_schema_for::<MapAndDifficultySettings>(),
+        "server-whitelist" => generator().into_root_schema_for::<Whitelist>(),
+        "server-adminlist" => generator().into_root_schema_for::<Adminlist>(),
+        "server-banlist" => generator().into_root_schema_for::<Banlist>(),
+        "mods" => generator().into_root_schema_for::<DeclaredMods>(),
+        "mod-settings" => generator().into_root_schema_for::<ModSettings>(),
+        _ => return None,
+    };
+
+    Some(schema)
+}
+
+/// The schema of the config file `name` as pretty-printed JSON, as in the checked-in `{name}.schema.json`.
+pub fn config_schema_json(name: &str) -> Option<String> {
+    let schema = config_schema(name)?;
+    let mut json = serde_json::to_string_pretty(&schema).expect("schemas serialize to JSON");
+    json.push('\n');
+    Some(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Fails when a type changes without regenerating the checked-in schemas. Run the tests with `UPDATE_SCHEMAS=1` to
+    /// regenerate them.
+    #[test]
+    fn test_checked_in_schemas() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("schemas");
+        let update = std::env::var_os("UPDATE_SCHEMAS").is_some();
+        for name in SCHEMA_NAMES {
+            let path = dir.join(format!("{}.schema.json", name));
+            let expected = config_schema_json(name).unwrap();
+            if update {
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(&path, &expected).unwrap();
+            }
+
+            let checked_in = std::fs::read_to_string(&path).unwrap_or_default();
+            assert!(
+                checked_in == expected,
+                "{} is out of date, regenerate it with `UPDATE_SCHEMAS=1 cargo test -p factoriod-config`",
+                path.display()
+            );
+        }
+    }
+
+    /// The serialized defaults of each config, and of the map presets, are valid against the checked-in schemas.
+    #[test]
+    fn test_defaults_match_checked_in_schemas() {
+        fn check<T: serde::Serialize>(name: &str, value: &T) {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("schemas/{}.schema.json", name));
+            let schema: serde_json::Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+            let validator = jsonschema::draft7::new(&schema).unwrap();
+            let value = serde_json::to_value(value).unwrap();
+            let errors = validator.iter_errors(&value).map(|e| e.to_string()).collect::<Vec<_>>();
+            assert!(errors.is_empty(), "{} does not match its schema: {:?}\n{}", name, errors, value);
+        }
+
+        check("server-settings", &ServerSettingsWithSecrets::default());
+        check("map-gen-settings", &MapGenSettings::default());
+        check("map-settings", &MapAndDifficultySettings::default());
+        check("server-whitelist", &Whitelist::default());
+        check("server-adminlist", &Adminlist::default());
+        check("server-banlist", &Banlist::default());
+        check("mods", &DeclaredMods::default());
+        check("mod-settings", &ModSettings::default());
+        for preset in crate::MapPreset::ALL {
+            check("map-gen-settings", &preset.map_gen_settings());
+            check("map-settings", &preset.map_settings());
+        }
+    }
+
+    #[test]
+    fn test_layered_schemas_require_nothing() {
+        let schema = serde_json::to_value(config_schema("map-settings").unwrap()).unwrap();
+        assert!(!schema.to_string().contains("\"required\""));
+
+        let schema = serde_json::to_value(config_schema("server-banlist").unwrap()).unwrap();
+        assert!(schema.to_string().contains("\"required\""));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let schema = serde_json::to_value(config_schema("server-settings").unwrap()).unwrap();
+        let heartbeats = &schema["properties"]["max_heartbeats_per_second"];
+        assert_eq!(heartbeats["minimum"], 6.0);
+        assert_eq!(heartbeats["maximum"], 240.0);
+
+        let schema = serde_json::to_value(config_schema("map-gen-settings").unwrap()).unwrap();
+        let size = &schema["definitions"]["MapGenSizeFloat"];
+        assert_eq!(size["minimum"], 0.0);
+        assert_eq!(size["maximum"], 6.0);
+        let named = schema["definitions"]["MapGenSize"].to_string();
+        assert!(named.contains("\"very-high\""), "{}", named);
+        assert!(!named.contains("assert_eq!"), "{}", named);
+    }
+}
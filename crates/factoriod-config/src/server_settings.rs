@@ -1,10 +1,15 @@
 //! Server settings for a Factorio server.
 
+use std::fmt;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::validation::{check_range, Validate, ValidationError};
+
 /// The server settings. These are reflected in the `server-settings.json` file and can be modified after generating a
 /// map. The changes will take effect after the server is restarted.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct ServerSettings {
     /// Name of the game as it will appear in the game listing.
@@ -37,7 +42,9 @@ pub struct ServerSettings {
     /// Optional. One tick is 16ms in default speed. Default value is 0. 0 means no minimum.
     pub minimum_latency_in_ticks: u32,
 
-    /// Network tick rate. Maximum rate game updates packets are sent at before bundling them together. Minimum value is 6, maximum value is 240.
+    /// Network tick rate. Maximum rate game updates packets are sent at before bundling them together. Minimum value is
+    /// 6, maximum value is 240.
+    #[schemars(range(min = 6, max = 240))]
     pub max_heartbeats_per_second: u32,
 
     /// Players that played on this map already can join even when the max player limit was reached.
@@ -109,17 +116,24 @@ impl Default for ServerSettings {
     }
 }
 
+/// The server settings along with the secrets that are stored in the same `server-settings.json` file. The secrets are
+/// redacted when formatted with [`Debug`].
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ServerSettingsWithSecrets {
+    #[serde(flatten)]
     pub server_settings: ServerSettings,
 
     /// Your factorio.com login credentials. Required for games with [`Visibility::public`] set to [`true`].
+    #[serde(default)]
     pub password: String,
 
     /// Authentication token. May be used instead of [`Self::password`] for games with [`Visibility::public`] set to
     /// [`true`].
+    #[serde(default)]
     pub token: String,
 
     /// Password for joining the game. Empty string means no password.
+    #[serde(default)]
     pub game_password: String,
 }
 
@@ -134,7 +148,64 @@ impl Default for ServerSettingsWithSecrets {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl fmt::Debug for ServerSettingsWithSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redact = |secret: &str| if secret.is_empty() { "" } else { "<redacted>" };
+        f.debug_struct("ServerSettingsWithSecrets")
+            .field("server_settings", &self.server_settings)
+            .field("password", &redact(&self.password))
+            .field("token", &redact(&self.token))
+            .field("game_password", &redact(&self.game_password))
+            .finish()
+    }
+}
+
+impl Validate for ServerSettings {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<ValidationError>) {
+        if self.name.trim().is_empty() {
+            errors.push(ValidationError::new(format!("{}name", prefix), "must not be empty"));
+        }
+
+        if self.visibility.public && self.username.trim().is_empty() {
+            errors.push(ValidationError::new(
+                format!("{}username", prefix),
+                "must be set when visibility.public is true",
+            ));
+        }
+
+        check_range(
+            errors,
+            format!("{}max_heartbeats_per_second", prefix),
+            self.max_heartbeats_per_second,
+            6,
+            240,
+        );
+
+        if self.minimum_segment_size > self.maximum_segment_size {
+            errors.push(ValidationError::new(
+                format!("{}minimum_segment_size", prefix),
+                format!(
+                    "must not be greater than maximum_segment_size ({}), got {}",
+                    self.maximum_segment_size, self.minimum_segment_size
+                ),
+            ));
+        }
+    }
+}
+
+impl Validate for ServerSettingsWithSecrets {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<ValidationError>) {
+        self.server_settings.validate_into(prefix, errors);
+        if self.server_settings.visibility.public && self.password.is_empty() && self.token.is_empty() {
+            errors.push(ValidationError::new(
+                format!("{}token", prefix),
+                "a token or password must be set when visibility.public is true",
+            ));
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 /// What commands are allowed in the game.
 pub enum AllowCommands {
@@ -148,7 +219,7 @@ pub enum AllowCommands {
     AdminsOnly,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Visibility {
     /// Game will be published on the official Factorio matching server.
     pub public: bool,
@@ -165,3 +236,96 @@ impl Default for Visibility {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(errors: Vec<ValidationError>) -> Vec<String> {
+        errors.into_iter().map(|error| error.path).collect()
+    }
+
+    #[test]
+    fn test_validate_default() {
+        let settings = ServerSettings {
+            username: "foo".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let settings = ServerSettings {
+            name: " ".to_owned(),
+            max_heartbeats_per_second: 300,
+            minimum_segment_size: 200,
+            ..Default::default()
+        };
+
+        let errors = settings.validate().unwrap_err();
+        assert_eq!(
+            paths(errors.0),
+            vec!["name", "username", "max_heartbeats_per_second", "minimum_segment_size"]
+        );
+    }
+
+    #[test]
+    fn test_validate_private_without_username() {
+        let settings = ServerSettings {
+            visibility: Visibility {
+                public: false,
+                lan: true,
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_with_secrets() {
+        let mut settings = ServerSettingsWithSecrets::default();
+        settings.server_settings.username = "foo".to_owned();
+        assert_eq!(paths(settings.validate().unwrap_err().0), vec!["token"]);
+
+        settings.token = "secret".to_owned();
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_deserialize_with_secrets() {
+        let mut json = serde_json::to_value(ServerSettings::default()).unwrap();
+        json["token"] = "secret".into();
+        let settings: ServerSettingsWithSecrets = serde_json::from_value(json).unwrap();
+        assert_eq!(settings.token, "secret");
+        assert_eq!(settings.password, "");
+        assert_eq!(settings.server_settings.name, "factoriod");
+    }
+
+    #[test]
+    fn test_serialize_with_secrets() {
+        let settings = ServerSettingsWithSecrets {
+            token: "secret".to_owned(),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&settings).unwrap();
+        assert_eq!(json["token"], "secret");
+        assert_eq!(json["name"], "factoriod");
+        assert_eq!(json.get("server_settings"), None);
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let settings = ServerSettingsWithSecrets {
+            token: "secret".to_owned(),
+            ..Default::default()
+        };
+
+        let debug = format!("{:?}", settings);
+        assert!(!debug.contains("secret"), "{}", debug);
+        assert!(debug.contains(r#"token: "<redacted>""#), "{}", debug);
+    }
+}
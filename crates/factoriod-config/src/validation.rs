@@ -0,0 +1,119 @@
+//! Validation of config values beyond what their types enforce.
+
+use std::error::Error;
+use std::fmt;
+
+/// A violation of a constraint on a config value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The path to the offending field, like `visibility.public`.
+    pub path: String,
+
+    /// What is wrong with the field.
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new<P: Into<String>, M: Into<String>>(path: P, message: M) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// All of the violations found when validating a config value. Formats as one violation per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "- {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ValidationErrors {}
+
+/// A config value with constraints that its type does not enforce.
+pub trait Validate {
+    /// Push every violation in `self` to `errors`. Paths are prefixed with `prefix`, which is empty for the root value
+    /// and otherwise ends with a `.`.
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<ValidationError>);
+
+    /// Check every constraint, returning all violations.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = Vec::new();
+        self.validate_into("", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+}
+
+/// Check that `value` is within `min..=max`.
+pub(crate) fn check_range<T: PartialOrd + fmt::Display>(
+    errors: &mut Vec<ValidationError>,
+    path: String,
+    value: T,
+    min: T,
+    max: T,
+) {
+    // written so that values which compare false with everything, like NaN, are out of range too
+    if !(value >= min && value <= max) {
+        errors.push(ValidationError::new(
+            path,
+            format!("must be between {} and {}, got {}", min, max, value),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let errors = ValidationErrors(vec![
+            ValidationError::new("a", "is wrong"),
+            ValidationError::new("b.c", "is also wrong"),
+        ]);
+
+        assert_eq!(errors.to_string(), "- a: is wrong\n- b.c: is also wrong");
+    }
+
+    #[test]
+    fn test_check_range() {
+        let mut errors = Vec::new();
+        check_range(&mut errors, "x".to_owned(), 5, 6, 240);
+        check_range(&mut errors, "y".to_owned(), 6, 6, 240);
+        assert_eq!(errors, vec![ValidationError::new("x", "must be between 6 and 240, got 5")]);
+
+        let mut errors = Vec::new();
+        check_range(&mut errors, "x".to_owned(), f64::NAN, 0.0, 6.0);
+        check_range(&mut errors, "y".to_owned(), f64::INFINITY, 0.0, 6.0);
+        check_range(&mut errors, "z".to_owned(), 6.0, 0.0, 6.0);
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::new("x", "must be between 0 and 6, got NaN"),
+                ValidationError::new("y", "must be between 0 and 6, got inf"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Checks of config values against the version of the game that reads them.
+//!
+//! Factorio 2.0 removed some settings of 1.1 and added others. The config types model both shapes, with the fields of
+//! only one version optional, and [`VersionCheck`] reports the fields that are set but not accepted by a given version.
+
+use crate::{GameVersion, ValidationError, ValidationErrors};
+
+/// The first release of Factorio 2.0.
+pub const VERSION_2_0: GameVersion = GameVersion::new(2, 0, 0, 0);
+
+/// A config value with fields that only some versions of the game accept.
+pub trait VersionCheck {
+    /// Push a violation to `errors` for every field that is set but not accepted by `version`. Paths are prefixed with
+    /// `prefix`, which is empty for the root value and otherwise ends with a `.`.
+    fn check_version_into(&self, version: &GameVersion, prefix: &str, errors: &mut Vec<ValidationError>);
+
+    /// Check every field against `version`, returning all violations.
+    fn check_version(&self, version: &GameVersion) -> Result<(), ValidationErrors> {
+        let mut errors = Vec::new();
+        self.check_version_into(version, "", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+}
+
+/// Format a version without its build, like `2.0.28`, as players know it.
+fn short(version: &GameVersion) -> String {
+    format!("{}.{}.{}", version.major, version.minor, version.patch)
+}
+
+/// Check that `value` is unset if `version` is older than `since`, when the field was added.
+pub(crate) fn added_in<T>(
+    errors: &mut Vec<ValidationError>,
+    version: &GameVersion,
+    since: GameVersion,
+    path: String,
+    value: &Option<T>,
+) {
+    if value.is_some() && *version < since {
+        errors.push(ValidationError::new(
+            path,
+            format!("requires factorio {} or later, but the game is {}", short(&since), short(version)),
+        ));
+    }
+}
+
+/// Check that `value` is unset if `version` is `until` or newer, when the field was removed.
+pub(crate) fn removed_in<T>(
+    errors: &mut Vec<ValidationError>,
+    version: &GameVersion,
+    until: GameVersion,
+    path: String,
+    value: &Option<T>,
+) {
+    if value.is_some() && *version >= until {
+        errors.push(ValidationError::new(
+            path,
+            format!("was removed in factorio {}, but the game is {}", short(&until), short(version)),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MapAndDifficultySettings, MapGenSettings};
+
+    const VERSION_1_1: GameVersion = GameVersion::new(1, 1, 110, 0);
+
+    #[test]
+    fn test_added_and_removed() {
+        let mut errors = Vec::new();
+        added_in(&mut errors, &VERSION_1_1, VERSION_2_0, "a".to_owned(), &Some(1));
+        added_in(&mut errors, &VERSION_2_0, VERSION_2_0, "b".to_owned(), &Some(1));
+        removed_in(&mut errors, &VERSION_1_1, VERSION_2_0, "c".to_owned(), &Some(1));
+        removed_in(&mut errors, &VERSION_2_0, VERSION_2_0, "d".to_owned(), &None::<u8>);
+        removed_in(&mut errors, &VERSION_2_0, VERSION_2_0, "e".to_owned(), &Some(1));
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::new("a", "requires factorio 2.0.0 or later, but the game is 1.1.110"),
+                ValidationError::new("e", "was removed in factorio 2.0.0, but the game is 2.0.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_settings() {
+        let settings = MapAndDifficultySettings::default();
+        assert!(settings.check_version(&VERSION_1_1).is_ok());
+        let errors = settings.check_version(&VERSION_2_0).unwrap_err();
+        let paths: Vec<_> = errors.0.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "difficulty_settings.recipe_difficulty",
+                "difficulty_settings.technology_difficulty",
+                "difficulty_settings.research_queue_setting",
+            ]
+        );
+
+        let settings = MapAndDifficultySettings::for_version(&VERSION_2_0);
+        assert!(settings.check_version(&VERSION_2_0).is_ok());
+        let errors = settings.check_version(&VERSION_1_1).unwrap_err();
+        let paths: Vec<_> = errors.0.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(paths, ["difficulty_settings.spoil_time_modifier", "asteroids"]);
+    }
+
+    #[test]
+    fn test_read_both_shapes() {
+        let v1_1 = r#"{"recipe_difficulty": "expensive", "technology_price_multiplier": 4.0}"#;
+        let v2_0 = r#"{"technology_price_multiplier": 4.0, "spoil_time_modifier": 0.5}"#;
+        let v1_1: crate::DifficultySettings = serde_json::from_str(v1_1).unwrap();
+        let v2_0: crate::DifficultySettings = serde_json::from_str(v2_0).unwrap();
+        assert!(v1_1.check_version(&VERSION_1_1).is_ok());
+        assert!(v2_0.check_version(&VERSION_2_0).is_ok());
+        assert!(v1_1.check_version(&VERSION_2_0).is_err());
+        assert!(v2_0.check_version(&VERSION_1_1).is_err());
+
+        let written = serde_json::to_value(&v2_0).unwrap();
+        assert_eq!(written, serde_json::json!({"technology_price_multiplier": 4.0, "spoil_time_modifier": 0.5}));
+    }
+
+    #[test]
+    fn test_space_age_autoplace_controls() {
+        let mut settings = MapGenSettings::default();
+        assert!(settings.check_version(&VERSION_1_1).is_ok());
+        settings
+            .autoplace_controls
+            .insert("vulcanus_coal".to_owned(), Default::default());
+        settings.no_enemies_mode = Some(true);
+
+        let errors = settings.check_version(&VERSION_2_0).unwrap_err();
+        let paths: Vec<_> = errors.0.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(paths, ["terrain_segmentation", "water"]);
+
+        settings.terrain_segmentation = None;
+        settings.water = None;
+        assert!(settings.check_version(&VERSION_2_0).is_ok());
+        let errors = settings.check_version(&VERSION_1_1).unwrap_err();
+        let paths: Vec<_> = errors.0.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(paths, ["autoplace_controls.vulcanus_coal", "no_enemies_mode"]);
+    }
+}
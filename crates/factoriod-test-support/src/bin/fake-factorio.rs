@@ -0,0 +1,400 @@
+//! A fake of the game's headless binary, installed as `bin/x64/factorio` by
+//! [`FakeInstall`](factoriod_test_support::FakeInstall). See the crate documentation for what it emulates.
+
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use factoriod_test_support::rcon::{self, Packet};
+use factoriod_test_support::{Behavior, FakeSave, Invocation, RunState, INVOCATIONS_FILE, STATE_FILE};
+
+/// A 1x1 PNG, the preview of every map.
+const PREVIEW_PNG: [u8; 67] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4, 0x89, 0x00, 0x00, 0x00, 0x0a, 0x49,
+    0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00, 0x01, 0x00, 0x00, 0x05, 0x00, 0x01, 0x0d, 0x0a, 0x2d, 0xb4, 0x00, 0x00,
+    0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+/// The signal that stopped the server, or 0.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// The options of the game the fake understands. The others are recorded but ignored.
+#[derive(Debug, Default)]
+struct Args {
+    version: bool,
+    create: Option<PathBuf>,
+    start_server: Option<PathBuf>,
+    load_scenario: Option<String>,
+    preview: Option<PathBuf>,
+    map_gen_settings: Option<PathBuf>,
+    map_settings: Option<PathBuf>,
+    seed: Option<u32>,
+    report_quantities: Vec<String>,
+    config: Option<PathBuf>,
+    rcon_bind: Option<String>,
+    rcon_port: Option<u16>,
+    rcon_password: Option<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("Option '{}' needs a value", arg));
+            match arg.as_str() {
+                "--version" => parsed.version = true,
+                "--create" => parsed.create = Some(value()?.into()),
+                "--start-server" => parsed.start_server = Some(value()?.into()),
+                "--start-server-load-scenario" => parsed.load_scenario = Some(value()?),
+                "--generate-map-preview" => parsed.preview = Some(value()?.into()),
+                "--map-gen-settings" => parsed.map_gen_settings = Some(value()?.into()),
+                "--map-settings" => parsed.map_settings = Some(value()?.into()),
+                "--map-gen-seed" => parsed.seed = Some(value()?.parse().map_err(|e| format!("bad seed: {}", e))?),
+                "--report-quantities" => parsed.report_quantities = value()?.split(',').map(str::to_owned).collect(),
+                "--config" | "-c" => parsed.config = Some(value()?.into()),
+                "--rcon-bind" => parsed.rcon_bind = Some(value()?),
+                "--rcon-port" => parsed.rcon_port = Some(value()?.parse().map_err(|e| format!("bad port: {}", e))?),
+                "--rcon-password" => parsed.rcon_password = Some(value()?),
+                "--mod-directory" | "--server-settings" | "--server-whitelist" | "--server-banlist"
+                | "--server-adminlist" | "--map-preview-size" | "--map-preview-scale" | "--port" | "--bind" => {
+                    value()?;
+                },
+                _ => {},
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Writes console lines like the game's, with the seconds since it started.
+struct Console {
+    started: Instant,
+}
+
+impl Console {
+    fn log(&self, level: &str, message: &str) {
+        let seconds = self.started.elapsed().as_secs_f64();
+        println!("{:>8.3} {} {}", seconds, level, message);
+        io::stdout().flush().ok();
+    }
+
+    fn info(&self, message: &str) {
+        self.log("Info", message);
+    }
+
+    fn error(&self, message: &str) {
+        self.log("Error", message);
+    }
+}
+
+/// The fake game, between parsing its arguments and exiting.
+struct Game {
+    factorio_dir: PathBuf,
+    behavior: Behavior,
+    args: Args,
+    console: Console,
+}
+
+impl Game {
+    /// Read a JSON settings file given to the game.
+    fn read_settings(&self, path: Option<&Path>) -> Result<Option<serde_json::Value>, String> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+
+        let contents = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Save `save` to `path`, counting the save, unless saves are skipped.
+    fn save(&self, mut save: FakeSave, path: &Path) -> Result<(), String> {
+        if self.behavior.skip_save {
+            return Ok(());
+        }
+
+        save.saves += 1;
+        self.console.info(&format!("AppManagerStates.cpp:2058: Saving to {} (blocking).", path.display()));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+
+        save.write(path).map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+        self.console.info("AppManagerStates.cpp:2058: Saving finished");
+        Ok(())
+    }
+
+    /// A new map from the arguments.
+    fn new_map(&self) -> Result<FakeSave, String> {
+        Ok(FakeSave {
+            seed: self.args.seed,
+            map_gen_settings: self.read_settings(self.args.map_gen_settings.as_deref())?,
+            map_settings: self.read_settings(self.args.map_settings.as_deref())?,
+            scenario: self.args.load_scenario.clone(),
+            saves: 0,
+        })
+    }
+
+    /// The directory the game writes to, from `write-data` in the `--config` file, otherwise the Factorio directory.
+    fn write_data(&self) -> PathBuf {
+        let write_data = self.args.config.as_ref().and_then(|config| {
+            let contents = std::fs::read_to_string(config).ok()?;
+            contents.lines().find_map(|line| line.strip_prefix("write-data=").map(PathBuf::from))
+        });
+
+        write_data.unwrap_or_else(|| self.factorio_dir.clone())
+    }
+
+    fn create(&self, path: &Path) -> Result<(), String> {
+        self.console.info(&format!("Main.cpp:1154: Creating new map {}", path.display()));
+        let map = self.new_map()?;
+        self.save(map, path)
+    }
+
+    fn generate_map_preview(&self, path: &Path) -> Result<(), String> {
+        self.new_map()?;
+        std::fs::create_dir_all(self.write_data()).map_err(|e| e.to_string())?;
+        self.console.info(&format!("Main.cpp:1346: Generating map preview to {}", path.display()));
+        std::fs::write(path, PREVIEW_PNG).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        // made up quantities, but stable for a seed and distinct between prototypes
+        let seed = u64::from(self.args.seed.unwrap_or_default());
+        for (i, name) in (1..).zip(&self.args.report_quantities) {
+            println!("{}: {}", name, (seed + 1) * i * 1000);
+        }
+
+        Ok(())
+    }
+
+    /// Run a server on `map` until it is stopped, then save it to `path`.
+    fn run_server(&self, map: FakeSave, path: &Path) -> Result<(), String> {
+        for signal in [libc::SIGTERM, libc::SIGINT] {
+            // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+            unsafe { libc::signal(signal, on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+        }
+
+        let quit = Arc::new(AtomicBool::new(false));
+        let rcon = self.serve_rcon(&quit)?;
+        self.console.info("ServerMultiplayerManager.cpp:797: updated state from CreatingGame to InGame");
+        self.write_state("InGame", rcon)?;
+
+        let started = Instant::now();
+        let run_for = self.behavior.run_for_ms.map(Duration::from_millis);
+        while SIGNAL.load(Ordering::SeqCst) == 0
+            && !quit.load(Ordering::SeqCst)
+            && run_for.is_none_or(|run_for| started.elapsed() < run_for)
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        match SIGNAL.load(Ordering::SeqCst) {
+            libc::SIGTERM => self.console.info("Received SIGTERM, shutting down"),
+            libc::SIGINT => self.console.info("Received SIGINT, shutting down"),
+            _ => self.console.info("Quitting multiplayer connection."),
+        }
+
+        self.save(map, path)?;
+        self.write_state("Exited", rcon)?;
+        self.console.info("Goodbye");
+        Ok(())
+    }
+
+    fn write_state(&self, state: &str, rcon: Option<std::net::SocketAddr>) -> Result<(), String> {
+        let state = RunState {
+            pid: std::process::id(),
+            state: state.to_owned(),
+            rcon,
+        };
+
+        let contents = serde_json::to_vec(&state).map_err(|e| e.to_string())?;
+        let path = self.factorio_dir.join(STATE_FILE);
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, contents)
+            .and_then(|()| std::fs::rename(&partial, &path))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Serve RCON in the background if it is configured, returning its address. Each connection is served on its own
+    /// thread, and `/quit` sets `quit`.
+    fn serve_rcon(&self, quit: &Arc<AtomicBool>) -> Result<Option<std::net::SocketAddr>, String> {
+        let Some(password) = self.args.rcon_password.clone() else {
+            return Ok(None);
+        };
+
+        let bind = match (&self.args.rcon_bind, self.args.rcon_port) {
+            (Some(bind), _) => bind.clone(),
+            (None, Some(port)) => format!("0.0.0.0:{}", port),
+            (None, None) => return Ok(None),
+        };
+
+        let listener = TcpListener::bind(&bind).map_err(|e| format!("Failed to bind RCON to {}: {}", bind, e))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        let message = format!("RemoteCommandProcessor.cpp:133: Starting RCON interface at IP ADDR:({{{}}})", addr);
+        self.console.info(&message);
+        let version = self.behavior.version.clone();
+        let quit = Arc::clone(quit);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let (password, version, quit) = (password.clone(), version.clone(), Arc::clone(&quit));
+                std::thread::spawn(move || serve_rcon_connection(stream, &password, &version, &quit));
+            }
+        });
+
+        Ok(Some(addr))
+    }
+
+    fn run(&self) -> Result<(), String> {
+        if let Some(path) = &self.args.create {
+            self.create(path)
+        } else if let Some(path) = &self.args.preview {
+            self.generate_map_preview(path)
+        } else if let Some(path) = &self.args.start_server {
+            self.console.info(&format!("Main.cpp:1175: Loading map {}", path.display()));
+            let map = FakeSave::read(path).map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+            self.run_server(map, path)
+        } else if let Some(scenario) = &self.args.load_scenario {
+            self.console.info(&format!("Main.cpp:1192: Loading scenario {}", scenario));
+            let path = self.write_data().join("saves/_autosave1.zip");
+            self.run_server(self.new_map()?, &path)
+        } else {
+            Err("No action given, the headless game needs --create, --start-server, or similar".to_owned())
+        }
+    }
+}
+
+/// Answer the RCON packets of `stream` until it is closed.
+fn serve_rcon_connection(mut stream: TcpStream, password: &str, version: &str, quit: &AtomicBool) {
+    let mut authenticated = false;
+    while let Ok(request) = Packet::read(&mut stream) {
+        let reply = match request.kind {
+            rcon::SERVERDATA_AUTH => {
+                authenticated = request.body == password;
+                Packet {
+                    id: if authenticated { request.id } else { -1 },
+                    kind: rcon::SERVERDATA_AUTH_RESPONSE,
+                    body: String::new(),
+                }
+            },
+            _ if !authenticated => return,
+            _ => {
+                let body = match request.body.as_str() {
+                    "/version" => version.to_owned(),
+                    "/players" | "/players online" => "Online players (0):".to_owned(),
+                    "/quit" => {
+                        quit.store(true, Ordering::SeqCst);
+                        String::new()
+                    },
+                    command if command.starts_with('/') => format!("Unknown command \"{}\".", &command[1..]),
+                    // anything else is chat, which has no output
+                    _ => String::new(),
+                };
+
+                Packet {
+                    id: request.id,
+                    kind: rcon::SERVERDATA_RESPONSE_VALUE,
+                    body,
+                }
+            },
+        };
+
+        if reply.write(&mut stream).is_err() {
+            return;
+        }
+    }
+}
+
+/// The Factorio directory of the fake, from its path in `bin/x64`.
+fn factorio_dir() -> io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    exe.ancestors()
+        .nth(3)
+        .map(Path::to_path_buf)
+        .ok_or_else(|| io::Error::other(format!("{} is not in bin/x64 of a Factorio directory", exe.display())))
+}
+
+/// Append this run to the invocations in `factorio_dir`.
+fn record_invocation(factorio_dir: &Path, args: &[String]) -> io::Result<()> {
+    let invocation = Invocation {
+        pid: std::process::id(),
+        args: args.to_vec(),
+    };
+
+    let mut line = serde_json::to_vec(&invocation)?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(factorio_dir.join(INVOCATIONS_FILE))?
+        .write_all(&line)
+}
+
+fn main() -> ExitCode {
+    let console = Console {
+        started: Instant::now(),
+    };
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let setup = factorio_dir().and_then(|dir| {
+        record_invocation(&dir, &args)?;
+        Ok((Behavior::load(&dir)?, dir))
+    });
+
+    let (behavior, factorio_dir) = match setup {
+        Ok(setup) => setup,
+        Err(e) => {
+            console.error(&format!("Main.cpp:1: fake setup failed: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let args = match Args::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            console.error(&format!("Main.cpp:1: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
+
+    if args.version {
+        println!("Version: {} (build 1, linux64, headless)", behavior.version);
+        return ExitCode::SUCCESS;
+    }
+
+    console.info(&format!("Factorio {} (build 1, linux64, headless)", behavior.version));
+    if let Some(signal) = behavior.signal {
+        // SAFETY: raise has no memory safety requirements
+        unsafe { libc::raise(signal) };
+    }
+
+    if let Some(code) = behavior.exit_code {
+        console.error(&format!("Main.cpp:1: fake failure, exiting with code {}", code));
+        return ExitCode::from(u8::try_from(code).unwrap_or(1));
+    }
+
+    let game = Game {
+        factorio_dir,
+        behavior,
+        args,
+        console,
+    };
+
+    match game.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            game.console.error(&e);
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,356 @@
+//! The *test-support* crate runs the daemon against a fake of the game, so that launching the game can be tested on CI
+//! without downloading it.
+//!
+//! The `fake-factorio` binary of this crate stands in for `bin/x64/factorio`. It emulates the parts of the game's CLI
+//! the daemon uses:
+//! - `--create` writes a save that records the map settings and seed it was created with, see [`FakeSave`].
+//! - `--start-server` and `--start-server-load-scenario` run until `SIGTERM`, `SIGINT`, or the `/quit` command, then
+//!   save and exit like the game.
+//! - `--rcon-port` or `--rcon-bind` with `--rcon-password` serve [RCON](rcon) with a few of the game's commands.
+//! - `--generate-map-preview` writes a PNG and prints the quantities of `--report-quantities`.
+//! - `--version` prints the version.
+//!
+//! It logs console lines like the game's, records each invocation in the Factorio directory, and can be made to fail
+//! with an exit code or a signal, see [`Behavior`].
+//!
+//! [`FakeInstall`] lays out temporary Factorio, state, configuration, and runtime directories with the fake installed.
+//! The integration tests of this crate find the fake with `env!("CARGO_BIN_EXE_fake-factorio")`.
+
+use std::io::{self, BufRead, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use factoriod::daemon::FactorioServer;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+pub mod rcon;
+
+/// The name of the file in the Factorio directory the fake reads its [`Behavior`] from.
+pub const BEHAVIOR_FILE: &str = "fake-factorio.json";
+
+/// The name of the file in the Factorio directory the fake appends an [`Invocation`] to each time it runs.
+pub const INVOCATIONS_FILE: &str = "fake-factorio-invocations.jsonl";
+
+/// The name of the file in the Factorio directory the fake writes its [`RunState`] to while it runs a server.
+pub const STATE_FILE: &str = "fake-factorio-state.json";
+
+/// The name of the entry in the saves written by the fake that holds the [`FakeSave`].
+pub const SAVE_INFO_FILE: &str = "fake-save.json";
+
+/// The game version the fake reports unless its [`Behavior`] says otherwise.
+pub const DEFAULT_VERSION: &str = "2.0.28";
+
+/// How the fake game behaves, read from [`BEHAVIOR_FILE`] each time it runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Behavior {
+    /// The version the fake reports, also written to `data/base/info.json` by [`FakeInstall::set_behavior`].
+    pub version: String,
+
+    /// Log an error and exit with this code before doing anything.
+    pub exit_code: Option<i32>,
+
+    /// Kill the fake with this signal, like `9` for `SIGKILL`, before doing anything.
+    pub signal: Option<i32>,
+
+    /// Exit successfully without writing or reporting saves, as if saving silently failed.
+    pub skip_save: bool,
+
+    /// Stop a server after this many milliseconds, as if it was quit, instead of running until it is stopped.
+    pub run_for_ms: Option<u64>,
+}
+
+impl Default for Behavior {
+    fn default() -> Self {
+        Self {
+            version: DEFAULT_VERSION.to_owned(),
+            exit_code: None,
+            signal: None,
+            skip_save: false,
+            run_for_ms: None,
+        }
+    }
+}
+
+impl Behavior {
+    /// Read the behavior from the Factorio directory `factorio_dir`, or the default behavior if there is none.
+    pub fn load(factorio_dir: &Path) -> io::Result<Self> {
+        match std::fs::read(factorio_dir.join(BEHAVIOR_FILE)) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(io::Error::from),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A run of the fake game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invocation {
+    pub pid: u32,
+
+    /// The arguments, without the binary.
+    pub args: Vec<String>,
+}
+
+impl Invocation {
+    /// The value of the option `name`, like `--create`, if it was given.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.args
+            .iter()
+            .position(|arg| arg == name)
+            .and_then(|i| self.args.get(i + 1))
+            .map(String::as_str)
+    }
+
+    /// Whether the option `name` was given.
+    pub fn has(&self, name: &str) -> bool {
+        self.args.iter().any(|arg| arg == name)
+    }
+}
+
+/// The state of a server run by the fake game, in [`STATE_FILE`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunState {
+    pub pid: u32,
+
+    /// Like the game's states: `InGame` while the server runs, then `Exited`.
+    pub state: String,
+
+    /// The address the RCON interface is bound to, if it is served.
+    pub rcon: Option<SocketAddr>,
+}
+
+/// What a save written by the fake game records, instead of a map.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FakeSave {
+    /// The value of `--map-gen-seed`.
+    pub seed: Option<u32>,
+
+    /// The contents of the `--map-gen-settings` file.
+    pub map_gen_settings: Option<serde_json::Value>,
+
+    /// The contents of the `--map-settings` file.
+    pub map_settings: Option<serde_json::Value>,
+
+    /// The scenario the map was created from, for `--start-server-load-scenario`.
+    pub scenario: Option<String>,
+
+    /// How many times the map was saved, including when it was created.
+    pub saves: u32,
+}
+
+impl FakeSave {
+    /// Read the save written by the fake game at `path`.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+        let name = archive
+            .file_names()
+            .find(|name| name.ends_with(SAVE_INFO_FILE))
+            .map(str::to_owned)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a fake save", path.display()))
+            })?;
+
+        let mut contents = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut contents)?;
+        serde_json::from_slice(&contents).map_err(io::Error::from)
+    }
+
+    /// Write the save to `path`, as a zip with the save in a directory named after the file, like the game's saves.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("save");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+        zip.start_file(format!("{}/{}", stem, SAVE_INFO_FILE), zip::write::SimpleFileOptions::default())?;
+        zip.write_all(&serde_json::to_vec_pretty(self)?)?;
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/// A temporary installation of the fake game, with the directories of a server, removed when dropped.
+///
+/// # Examples
+/// ```no_run
+/// use factoriod_test_support::{Behavior, FakeInstall};
+/// # let fake_factorio = "";
+/// let install = FakeInstall::new(fake_factorio).unwrap();
+/// install.set_behavior(&Behavior { exit_code: Some(3), ..Default::default() }).unwrap();
+/// let server = install.server();
+/// ```
+#[derive(Debug)]
+pub struct FakeInstall {
+    root: TempDir,
+}
+
+impl FakeInstall {
+    /// Install the `fake-factorio` binary at `binary` as the game in a new temporary directory, with empty state,
+    /// configuration, and runtime directories next to it.
+    pub fn new<P: AsRef<Path>>(binary: P) -> io::Result<Self> {
+        let install = Self {
+            root: tempfile::tempdir()?,
+        };
+
+        std::fs::create_dir_all(install.factorio_dir().join("bin/x64"))?;
+        std::fs::create_dir_all(install.factorio_dir().join("data/base"))?;
+        for dir in [install.saves_dir(), install.config_dir(), install.runtime_dir()] {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // copied by another process, so that no thread of the tests still has the binary open for writing when it
+        // runs, which fails with "text file busy"
+        let status = Command::new("install")
+            .arg("-m755")
+            .arg(binary.as_ref())
+            .arg(install.binary())
+            .status()?;
+
+        if !status.success() {
+            return Err(io::Error::other(format!("failed to install {}", binary.as_ref().display())));
+        }
+
+        install.set_behavior(&Behavior::default())?;
+        Ok(install)
+    }
+
+    /// The directory that holds the other directories.
+    pub fn root(&self) -> &Path {
+        self.root.path()
+    }
+
+    /// The Factorio directory, with the fake at `bin/x64/factorio`.
+    pub fn factorio_dir(&self) -> PathBuf {
+        self.root().join("factorio")
+    }
+
+    /// The path of the installed fake.
+    pub fn binary(&self) -> PathBuf {
+        self.factorio_dir().join("bin/x64/factorio")
+    }
+
+    /// The server's state directory.
+    pub fn state_dir(&self) -> PathBuf {
+        self.root().join("state")
+    }
+
+    /// The `saves` directory of the state directory.
+    pub fn saves_dir(&self) -> PathBuf {
+        self.state_dir().join("saves")
+    }
+
+    /// The server's configuration directory.
+    pub fn config_dir(&self) -> PathBuf {
+        self.root().join("config")
+    }
+
+    /// The server's runtime directory.
+    pub fn runtime_dir(&self) -> PathBuf {
+        self.root().join("run")
+    }
+
+    /// A server of the daemon on these directories.
+    pub fn server(&self) -> FactorioServer {
+        FactorioServer::try_new(self.factorio_dir())
+            .expect("the Factorio directory exists")
+            .with_config_dir(self.config_dir())
+            .with_state_dir(self.state_dir())
+            .with_runtime_dir(self.runtime_dir())
+    }
+
+    /// Make the fake behave as `behavior` from its next run.
+    pub fn set_behavior(&self, behavior: &Behavior) -> io::Result<()> {
+        let info = serde_json::json!({ "name": "base", "version": behavior.version });
+        std::fs::write(self.factorio_dir().join("data/base/info.json"), info.to_string())?;
+        std::fs::write(self.factorio_dir().join(BEHAVIOR_FILE), serde_json::to_vec_pretty(behavior)?)
+    }
+
+    /// Every run of the fake so far, oldest first.
+    pub fn invocations(&self) -> io::Result<Vec<Invocation>> {
+        let file = match std::fs::File::open(self.factorio_dir().join(INVOCATIONS_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        io::BufReader::new(file)
+            .lines()
+            .map(|line| serde_json::from_str(&line?).map_err(io::Error::from))
+            .collect()
+    }
+
+    /// Wait until the fake runs a server, returning its state.
+    ///
+    /// # Errors
+    /// With [`io::ErrorKind::TimedOut`] if no server is running after `timeout`.
+    pub fn wait_until_running(&self, timeout: Duration) -> io::Result<RunState> {
+        let deadline = Instant::now() + timeout;
+        let path = self.factorio_dir().join(STATE_FILE);
+        loop {
+            // the fake writes the state atomically, so a partial file is never read
+            let state = std::fs::read(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_slice::<RunState>(&contents).ok())
+                .filter(|state| state.state == "InGame");
+
+            if let Some(state) = state {
+                return Ok(state);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "the fake game did not start a server"));
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Send `signal`, like [`libc::SIGTERM`], to the process `pid`.
+pub fn kill(pid: u32, signal: i32) -> io::Result<()> {
+    let pid = libc::pid_t::try_from(pid).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // SAFETY: kill has no memory safety requirements
+    if unsafe { libc::kill(pid, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_save_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("my-map.zip");
+        let save = FakeSave {
+            seed: Some(1234),
+            map_gen_settings: Some(serde_json::json!({ "width": 64 })),
+            saves: 1,
+            ..Default::default()
+        };
+
+        save.write(&path).unwrap();
+        assert_eq!(FakeSave::read(&path).unwrap(), save);
+
+        std::fs::write(&path, "not a zip").unwrap();
+        assert!(FakeSave::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_invocation_value() {
+        let invocation = Invocation {
+            pid: 1,
+            args: ["--create", "saves/my-map.zip", "--map-gen-seed", "42"].map(str::to_owned).to_vec(),
+        };
+
+        assert_eq!(invocation.value("--create"), Some("saves/my-map.zip"));
+        assert_eq!(invocation.value("--map-gen-seed"), Some("42"));
+        assert_eq!(invocation.value("--start-server"), None);
+        assert!(invocation.has("--create"));
+    }
+}
@@ -0,0 +1,146 @@
+//! The [Source RCON protocol](https://developer.valvesoftware.com/wiki/Source_RCON_Protocol) the game serves with
+//! `--rcon-port` and `--rcon-password`, enough of it for the fake game to serve and for tests to send commands.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// The type of a packet that authenticates with the password in its body.
+pub const SERVERDATA_AUTH: i32 = 3;
+
+/// The type of the reply to [`SERVERDATA_AUTH`], with the id of the request, or `-1` if the password is wrong.
+pub const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+
+/// The type of a packet that runs the command in its body, like `/players`.
+pub const SERVERDATA_EXECCOMMAND: i32 = 2;
+
+/// The type of the reply to [`SERVERDATA_EXECCOMMAND`], with the output of the command in its body.
+pub const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+
+/// A single RCON packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    /// The id of the request, which its replies repeat.
+    pub id: i32,
+
+    /// The type of the packet, like [`SERVERDATA_EXECCOMMAND`].
+    pub kind: i32,
+    pub body: String,
+}
+
+impl Packet {
+    /// Read a packet from `reader`: its little-endian length, id, and type, then its NUL terminated body and a
+    /// trailing NUL.
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut int = [0; 4];
+        reader.read_exact(&mut int)?;
+        let size = i32::from_le_bytes(int);
+        let Some(body_size) = usize::try_from(size).ok().and_then(|size| size.checked_sub(10)) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid packet size {}", size)));
+        };
+
+        reader.read_exact(&mut int)?;
+        let id = i32::from_le_bytes(int);
+        reader.read_exact(&mut int)?;
+        let kind = i32::from_le_bytes(int);
+        let mut body = vec![0; body_size + 2];
+        reader.read_exact(&mut body)?;
+        body.truncate(body_size);
+        let body = String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self { id, kind, body })
+    }
+
+    /// Write the packet to `writer`, as [`read`](Self::read) reads it.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let size = i32::try_from(self.body.len() + 10)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the body is too long"))?;
+
+        let mut packet = Vec::with_capacity(self.body.len() + 14);
+        packet.extend(size.to_le_bytes());
+        packet.extend(self.id.to_le_bytes());
+        packet.extend(self.kind.to_le_bytes());
+        packet.extend(self.body.as_bytes());
+        packet.extend([0, 0]);
+        writer.write_all(&packet)
+    }
+}
+
+/// A connection to the RCON interface of a server.
+#[derive(Debug)]
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl RconClient {
+    /// Connect to the server at `addr` and authenticate with `password`.
+    ///
+    /// # Errors
+    /// If the server cannot be reached, or with [`io::ErrorKind::PermissionDenied`] if the password is wrong.
+    pub fn connect<A: ToSocketAddrs>(addr: A, password: &str) -> io::Result<Self> {
+        let mut client = Self {
+            stream: TcpStream::connect(addr)?,
+            next_id: 1,
+        };
+
+        let id = client.send(SERVERDATA_AUTH, password)?;
+        loop {
+            let reply = Packet::read(&mut client.stream)?;
+            match reply.kind {
+                SERVERDATA_AUTH_RESPONSE if reply.id == id => return Ok(client),
+                SERVERDATA_AUTH_RESPONSE => {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "the RCON password is wrong"))
+                },
+                // some servers send an empty value before the auth response
+                _ => continue,
+            }
+        }
+    }
+
+    /// Run `command` on the server, like `/players`, and return its output.
+    pub fn command(&mut self, command: &str) -> io::Result<String> {
+        let id = self.send(SERVERDATA_EXECCOMMAND, command)?;
+        let reply = Packet::read(&mut self.stream)?;
+        if reply.id != id || reply.kind != SERVERDATA_RESPONSE_VALUE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply {:?}", reply)));
+        }
+
+        Ok(reply.body)
+    }
+
+    /// Send a packet of `kind` with `body`, returning its id.
+    fn send(&mut self, kind: i32, body: &str) -> io::Result<i32> {
+        let id = self.next_id;
+        self.next_id += 1;
+        Packet {
+            id,
+            kind,
+            body: body.to_owned(),
+        }
+        .write(&mut self.stream)?;
+
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = Packet {
+            id: 42,
+            kind: SERVERDATA_EXECCOMMAND,
+            body: "/players online".to_owned(),
+        };
+
+        let mut bytes = Vec::new();
+        packet.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..4], &25i32.to_le_bytes());
+        assert_eq!(Packet::read(&mut bytes.as_slice()).unwrap(), packet);
+
+        let mut invalid = 3i32.to_le_bytes().to_vec();
+        invalid.extend([0; 3]);
+        assert_eq!(Packet::read(&mut invalid.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,286 @@
+//! The daemon against the fake game.
+
+use std::process::Command;
+use std::time::Duration;
+
+use factoriod::daemon::factorio_server::FactorioServerStartError;
+use factoriod::daemon::{MapPreviewOptions, NewSaveOptions, Overwrite};
+use factoriod::explore::SeedExploration;
+use factoriod::instances::{InstanceRoots, RCON_PASSWORD_FILE};
+use factoriod_config::{MapGenSettings, MapPreset};
+use factoriod_test_support::rcon::RconClient;
+use factoriod_test_support::{kill, Behavior, FakeInstall, FakeSave, DEFAULT_VERSION};
+
+const FAKE_FACTORIO: &str = env!("CARGO_BIN_EXE_fake-factorio");
+
+/// How long a test waits for the fake to start a server.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[test]
+fn test_new_save() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let settings = MapGenSettings {
+        width: 128,
+        ..MapGenSettings::for_version(&DEFAULT_VERSION.parse().unwrap())
+    };
+
+    let options = NewSaveOptions::new("my-map")
+        .with_preset(MapPreset::RailWorld)
+        .with_seed(1234)
+        .with_map_gen_settings(settings);
+
+    let save = install.server().new_save(&options).unwrap();
+    assert_eq!(save, install.saves_dir().join("my-map.zip"));
+
+    let fake_save = FakeSave::read(&save).unwrap();
+    assert_eq!(fake_save.seed, Some(1234));
+    assert_eq!(fake_save.map_gen_settings.unwrap()["width"], 128);
+    assert!(fake_save.map_settings.is_some(), "the preset's map settings are passed");
+
+    let invocations = install.invocations().unwrap();
+    assert_eq!(invocations.len(), 1);
+    assert_eq!(invocations[0].value("--map-gen-seed"), Some("1234"));
+    assert!(invocations[0].has("--map-gen-settings"));
+}
+
+#[test]
+fn test_new_save_exit_code() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    install
+        .set_behavior(&Behavior {
+            exit_code: Some(3),
+            ..Default::default()
+        })
+        .unwrap();
+
+    match install.server().new_save(&NewSaveOptions::new("my-map")).unwrap_err() {
+        FactorioServerStartError::GameExited { code, output, .. } => {
+            assert_eq!(code, 3);
+            assert!(output.last().unwrap().contains("fake failure"), "{:?}", output);
+        },
+        error => panic!("unexpected error: {:?}", error),
+    }
+
+    assert!(!install.saves_dir().join("my-map.zip").exists());
+}
+
+#[test]
+fn test_new_save_replace_keeps_save_on_failure() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let options = NewSaveOptions::new("my-map").with_seed(1).with_overwrite(Overwrite::Replace);
+    let save = install.server().new_save(&options).unwrap();
+
+    install
+        .set_behavior(&Behavior {
+            exit_code: Some(3),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let options = options.with_seed(2);
+    let error = install.server().new_save(&options).unwrap_err();
+    assert!(matches!(error, FactorioServerStartError::GameExited { code: 3, .. }), "{:?}", error);
+    assert_eq!(FakeSave::read(&save).unwrap().seed, Some(1));
+
+    install.set_behavior(&Behavior::default()).unwrap();
+    install.server().new_save(&options).unwrap();
+    assert_eq!(FakeSave::read(&save).unwrap().seed, Some(2));
+    assert_eq!(std::fs::read_dir(install.saves_dir()).unwrap().count(), 1, "the replaced save is removed");
+}
+
+#[test]
+fn test_new_save_without_saving() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    install
+        .set_behavior(&Behavior {
+            skip_save: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let error = install.server().new_save(&NewSaveOptions::new("my-map")).unwrap_err();
+    assert!(matches!(error, FactorioServerStartError::MissingSuccessMarker { .. }), "{:?}", error);
+}
+
+#[test]
+fn test_new_save_signal() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    install
+        .set_behavior(&Behavior {
+            signal: Some(libc::SIGKILL),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let error = install.server().new_save(&NewSaveOptions::new("my-map")).unwrap_err();
+    assert!(matches!(error, FactorioServerStartError::GameKilled { signal: libc::SIGKILL, .. }), "{:?}", error);
+}
+
+#[test]
+fn test_start_save_until_terminated() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let server = install.server();
+    let save = server.new_save(&NewSaveOptions::new("my-map")).unwrap();
+
+    std::thread::scope(|scope| {
+        let running = scope.spawn(|| server.start_save(&save));
+        let state = install.wait_until_running(TIMEOUT).unwrap();
+        kill(state.pid, libc::SIGTERM).unwrap();
+        running.join().unwrap().unwrap();
+    });
+
+    // the game saves when it is stopped
+    assert_eq!(FakeSave::read(&save).unwrap().saves, 2);
+    assert_eq!(install.invocations().unwrap()[1].value("--start-server"), save.to_str());
+}
+
+#[test]
+fn test_restart_continues_scenario() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    std::fs::create_dir_all(install.factorio_dir().join("data/base/scenarios/pvp")).unwrap();
+    let server = install.server();
+    server.select_scenario("base/pvp").unwrap();
+
+    for _ in 0..2 {
+        std::thread::scope(|scope| {
+            let running = scope.spawn(|| server.start());
+            let state = install.wait_until_running(TIMEOUT).unwrap();
+            kill(state.pid, libc::SIGTERM).unwrap();
+            running.join().unwrap().unwrap();
+        });
+    }
+
+    // the game saves the new map where the daemon looks for saves, so the restart continues it
+    let invocations = install.invocations().unwrap();
+    assert_eq!(invocations[0].value("--start-server-load-scenario"), Some("base/pvp"));
+    let save = install.saves_dir().join("_autosave1.zip");
+    assert_eq!(invocations[1].value("--start-server"), save.to_str());
+    assert!(!invocations[1].has("--start-server-load-scenario"));
+    assert_eq!(FakeSave::read(&save).unwrap().scenario.as_deref(), Some("base/pvp"));
+}
+
+#[test]
+fn test_start_save_killed() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let server = install.server();
+    let save = server.new_save(&NewSaveOptions::new("my-map")).unwrap();
+
+    std::thread::scope(|scope| {
+        let running = scope.spawn(|| server.start_save(&save));
+        let state = install.wait_until_running(TIMEOUT).unwrap();
+        kill(state.pid, libc::SIGKILL).unwrap();
+        let error = running.join().unwrap().unwrap_err();
+        assert!(matches!(error, FactorioServerStartError::GameKilled { signal: libc::SIGKILL, .. }), "{:?}", error);
+    });
+
+    assert_eq!(FakeSave::read(&save).unwrap().saves, 1);
+}
+
+#[test]
+fn test_start_missing_save() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let error = install.server().start_save(&install.saves_dir().join("missing.zip")).unwrap_err();
+    assert_eq!(error.exit_code(), Some(1), "{:?}", error);
+}
+
+#[test]
+fn test_rcon() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let save = install.server().new_save(&NewSaveOptions::new("my-map")).unwrap();
+    let mut game = Command::new(install.binary())
+        .arg("--start-server")
+        .arg(&save)
+        .args(["--rcon-bind", "127.0.0.1:0", "--rcon-password", "secret"])
+        .spawn()
+        .unwrap();
+
+    let state = install.wait_until_running(TIMEOUT).unwrap();
+    let rcon = state.rcon.unwrap();
+    assert!(RconClient::connect(rcon, "wrong").is_err());
+
+    let mut client = RconClient::connect(rcon, "secret").unwrap();
+    assert_eq!(client.command("/version").unwrap(), factoriod_test_support::DEFAULT_VERSION);
+    assert_eq!(client.command("/players online").unwrap(), "Online players (0):");
+    client.command("/quit").unwrap();
+    assert!(game.wait().unwrap().success());
+}
+
+#[test]
+fn test_instance() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let roots = InstanceRoots {
+        config_dir: install.root().join("etc"),
+        state_dir: install.root().join("lib"),
+        runtime_dir: install.root().join("run"),
+    };
+
+    let instance = roots.create("my-instance").unwrap();
+    let ports = instance.ports;
+    let server = install.server().with_instance(instance);
+    let save = server.new_save(&NewSaveOptions::new("my-map")).unwrap();
+
+    std::thread::scope(|scope| {
+        let running = scope.spawn(|| server.start_save(&save));
+        let state = install.wait_until_running(TIMEOUT).unwrap();
+        assert_eq!(state.rcon.unwrap().port(), ports.rcon_port);
+
+        let password = std::fs::read_to_string(install.state_dir().join(RCON_PASSWORD_FILE)).unwrap();
+        let mut client = RconClient::connect(("127.0.0.1", ports.rcon_port), &password).unwrap();
+        client.command("/quit").unwrap();
+        running.join().unwrap().unwrap();
+    });
+
+    let invocation = &install.invocations().unwrap()[1];
+    assert_eq!(invocation.value("--port"), Some(ports.port.to_string().as_str()));
+    let game_config = std::fs::read_to_string(invocation.value("--config").unwrap()).unwrap();
+    assert!(game_config.contains(&format!("write-data={}", install.state_dir().display())), "{}", game_config);
+}
+
+#[test]
+fn test_generate_map_preview() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let options = MapPreviewOptions {
+        seed: Some(1234),
+        ..Default::default()
+    };
+
+    let preview = install.server().generate_map_preview(&options).unwrap();
+    assert!(preview.is_file());
+
+    install
+        .set_behavior(&Behavior {
+            exit_code: Some(3),
+            ..Default::default()
+        })
+        .unwrap();
+
+    match install.server().generate_map_preview(&options).unwrap_err() {
+        FactorioServerStartError::GameExited { code, output, .. } => {
+            assert_eq!(code, 3);
+            assert!(output.iter().any(|line| line.contains("fake failure")), "{:?}", output);
+        },
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+fn test_explore_seeds() {
+    let install = FakeInstall::new(FAKE_FACTORIO).unwrap();
+    let exploration = SeedExploration {
+        preview: MapPreviewOptions::default(),
+        seeds: vec![1, 2, 3],
+        jobs: 2,
+        report_quantities: vec!["iron-ore".to_owned(), "coal".to_owned()],
+    };
+
+    let html = install.server().explore_seeds(&exploration).unwrap();
+    let dir = html.parent().unwrap();
+    let index: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("index.json")).unwrap()).unwrap();
+    let previews = index["previews"].as_array().unwrap();
+    assert_eq!(previews.len(), 3);
+    assert_eq!(previews[1]["seed"], 2);
+    assert_eq!(previews[1]["quantities"]["iron-ore"], 3000.0);
+    assert_eq!(previews[1]["quantities"]["coal"], 6000.0);
+    assert!(dir.join("2.png").is_file());
+    assert!(!dir.join(".write-data").exists());
+}
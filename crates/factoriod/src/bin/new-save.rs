@@ -1,17 +1,58 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
 use clap::Parser;
-use factoriod::daemon::FactorioServer;
+use factoriod::daemon::factorio_server::FactorioServerStartError;
+use factoriod::daemon::{FactorioServer, NewSaveOptions, Overwrite};
+use factoriod_config::MapPreset;
+use tracing::error;
 
 #[derive(Parser)]
 struct Args {
     /// The name of the save to create.
     name: String,
+
+    /// The map generation preset to start from, like `rail-world`. The map settings in the configuration directory
+    /// override it.
+    #[arg(long)]
+    preset: Option<MapPreset>,
+
+    /// The map seed, instead of a random seed or the seed of the map generation settings.
+    #[arg(long)]
+    seed: Option<u32>,
+
+    /// What to do if the save already exists.
+    #[arg(long, value_enum, default_value_t)]
+    overwrite: Overwrite,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    factoriod::setup_tracing();
-    let args = Args::parse();
+fn new_save(args: Args) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let factorio_dir = factoriod::get_factorio_directory(std::env::current_dir()?)?;
     let server = FactorioServer::try_new(factorio_dir)?;
-    server.new_save(&args.name)?;
-    Ok(())
+    let mut options = NewSaveOptions::new(args.name).with_overwrite(args.overwrite);
+    if let Some(preset) = args.preset {
+        options = options.with_preset(preset);
+    }
+
+    if let Some(seed) = args.seed {
+        options = options.with_seed(seed);
+    }
+
+    Ok(server.new_save(&options)?)
+}
+
+/// Exits with the game's exit code if it failed, otherwise with 1 on failure.
+fn main() -> ExitCode {
+    factoriod::setup_tracing();
+    match new_save(Args::parse()) {
+        Ok(save) => {
+            println!("{}", save.display());
+            ExitCode::SUCCESS
+        },
+        Err(e) => {
+            error!("{}", e);
+            let code = e.downcast_ref::<FactorioServerStartError>().and_then(FactorioServerStartError::exit_code);
+            ExitCode::from(code.and_then(|code| u8::try_from(code).ok()).filter(|&code| code != 0).unwrap_or(1))
+        },
+    }
 }
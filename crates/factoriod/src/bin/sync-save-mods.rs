@@ -0,0 +1,35 @@
+//! Makes the server's mods directory match the mods loaded in a save, so that a save from a modded single-player game
+//! can be loaded by the server. Missing mods are downloaded from the mod portal.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use factoriod::daemon::FactorioServer;
+
+#[derive(Parser)]
+struct Args {
+    /// The path to the save whose mods should be installed.
+    save: PathBuf,
+
+    /// Copy the save to the saves directory and start the server on it once the mods are installed.
+    #[arg(long)]
+    start: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    factoriod::setup_tracing();
+    let args = Args::parse();
+    let factorio_dir = factoriod::get_factorio_directory(std::env::current_dir()?)?;
+    let server = FactorioServer::try_new(factorio_dir)?;
+    let header = server.sync_mods_to_save(&args.save)?;
+    for m in &header.mods {
+        println!("{} {}", m.name, m.version);
+    }
+
+    if args.start {
+        let save = server.import_save(&args.save)?;
+        server.start_save(&save)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1028 @@
+//! Reading and checking the config files in the configuration directory.
+//!
+//! The `server-settings.json` in the configuration directory should not contain secrets. Instead, the secrets are read
+//! from [credentials](crate::credentials) and the complete settings are written to the runtime directory, readable only
+//! by the service's user, where the game reads them.
+//!
+//! Each config is merged from layers: the built-in defaults, `{name}.json`, then the drop-ins in `{name}.d/*.json`. See
+//! [`factoriod_config::Layered`]. The files may also be written in TOML or YAML, and are converted to the JSON the game
+//! reads. The source of each value in the effective file can be printed with
+//! `factoriod config explain`.
+//!
+//! A map can be created from one of the game's [presets](MapPreset), which is layered between the built-in defaults
+//! and the files in the configuration directory. When the version of the installed game is known, the map settings
+//! start from its defaults and the fields it does not accept are reported, see [`VersionCheck`].
+
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use factoriod_config::{
+    layer_paths, ConfigFormat, Diff, Difference, FormatError, GameVersion, LayerPathsError, Layered,
+    MapAndDifficultySettings, MapGenSettings, MapPreset, MigrationChange, ServerSettingsWithSecrets, Validate,
+    ValidationErrors, VersionCheck,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{debug, info, trace, warn};
+
+use crate::credentials;
+
+/// An error returned when a config file cannot be used.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read or written.
+    Io { path: PathBuf, source: std::io::Error },
+
+    /// The file cannot be parsed, or the result of merging it with its layers does not match its type.
+    Parse { path: PathBuf, source: FormatError },
+
+    /// The same file exists in more than one format.
+    Ambiguous { paths: Vec<PathBuf> },
+
+    /// The file parsed but violates constraints on its values.
+    Invalid { path: PathBuf, errors: ValidationErrors },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "failed to access {}: {}", path.display(), source),
+            ConfigError::Parse { path, source } => write!(f, "failed to parse {}: {}", path.display(), source),
+            ConfigError::Invalid { path, errors } => write!(f, "{} is invalid:\n{}", path.display(), errors),
+            ConfigError::Ambiguous { paths } => write!(
+                f,
+                "{} are the same config in different formats, remove all but one",
+                paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" and ")
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { errors, .. } => Some(errors),
+            ConfigError::Ambiguous { .. } => None,
+        }
+    }
+}
+
+/// A layered config file in the configuration directory. See [`factoriod_config::Layered`] for how the layers are
+/// merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigFile {
+    /// `server-settings.json`, passed to `--start-server`.
+    ServerSettings,
+
+    /// `map-gen-settings.json`, passed to `--create`.
+    MapGenSettings,
+
+    /// `map-settings.json`, passed to `--create`.
+    MapSettings,
+}
+
+impl ConfigFile {
+    /// The name of the config, like `server-settings`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigFile::ServerSettings => "server-settings",
+            ConfigFile::MapGenSettings => "map-gen-settings",
+            ConfigFile::MapSettings => "map-settings",
+        }
+    }
+
+    /// The name of the effective file the game reads, like `server-settings.json`.
+    pub fn file_name(self) -> String {
+        format!("{}.json", self.name())
+    }
+
+    fn defaults(self, version: Option<&GameVersion>) -> serde_json::Result<Layered> {
+        let mut value = match (self, version) {
+            (ConfigFile::ServerSettings, _) => serde_json::to_value(ServerSettingsWithSecrets::default())?,
+            (ConfigFile::MapGenSettings, version) => {
+                serde_json::to_value(version.map_or_else(MapGenSettings::default, MapGenSettings::for_version))?
+            },
+            (ConfigFile::MapSettings, version) => serde_json::to_value(
+                version.map_or_else(MapAndDifficultySettings::default, MapAndDifficultySettings::for_version),
+            )?,
+        };
+
+        if let Some(version) = version {
+            self.migrate(&mut value, version);
+        }
+
+        Layered::new(&value)
+    }
+
+    /// Migrate the fields of a layer of this config to the game `version`.
+    fn migrate(self, value: &mut serde_json::Value, version: &GameVersion) -> Vec<MigrationChange> {
+        match self {
+            ConfigFile::ServerSettings => Vec::new(),
+            ConfigFile::MapGenSettings => factoriod_config::migrate_map_gen_settings(value, version),
+            ConfigFile::MapSettings => factoriod_config::migrate_map_settings(value, version),
+        }
+    }
+
+    /// The settings of `preset` for this config, or [`None`] if presets do not apply to it.
+    fn preset(self, preset: MapPreset, version: Option<&GameVersion>) -> serde_json::Result<Option<serde_json::Value>> {
+        let value = match (self, version) {
+            (ConfigFile::ServerSettings, _) => return Ok(None),
+            (ConfigFile::MapGenSettings, _) => serde_json::to_value(preset.map_gen_settings()),
+            (ConfigFile::MapSettings, Some(version)) => serde_json::to_value(preset.map_settings_for(version)),
+            (ConfigFile::MapSettings, None) => serde_json::to_value(preset.map_settings()),
+        };
+
+        let mut value = value?;
+        if let Some(version) = version {
+            self.migrate(&mut value, version);
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Check that the effective document deserializes to the config's type, then that `version` of the game accepts
+    /// it, if given. Unknown autoplace controls and property expressions of the map generator are logged as warnings.
+    fn check(self, layered: &Layered, version: Option<&GameVersion>) -> serde_json::Result<CheckResult> {
+        fn check_version<T: DeserializeOwned + VersionCheck>(
+            layered: &Layered,
+            version: Option<&GameVersion>,
+        ) -> serde_json::Result<CheckResult> {
+            let value: T = layered.deserialize()?;
+            Ok(version.map_or(Ok(()), |version| value.check_version(version)))
+        }
+
+        match self {
+            ConfigFile::ServerSettings => layered.deserialize::<ServerSettingsWithSecrets>().map(|_| Ok(())),
+            ConfigFile::MapGenSettings => {
+                // names defined by mods are allowed, so unknown names are only warned about
+                for warning in layered.deserialize::<MapGenSettings>()?.warnings() {
+                    warn!("{}: {}", self.file_name(), warning);
+                }
+
+                check_version::<MapGenSettings>(layered, version)
+            },
+            ConfigFile::MapSettings => check_version::<MapAndDifficultySettings>(layered, version),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// The differences from `old` to `new`, both documents of this config. The secrets of the server settings are not
+    /// compared.
+    fn diff(self, old: &Layered, new: &Layered) -> serde_json::Result<Vec<Difference>> {
+        fn diff<T: DeserializeOwned + Diff>(old: &Layered, new: &Layered) -> serde_json::Result<Vec<Difference>> {
+            Ok(old.deserialize::<T>()?.diff(&new.deserialize()?))
+        }
+
+        match self {
+            ConfigFile::ServerSettings => diff::<ServerSettingsWithSecrets>(old, new),
+            ConfigFile::MapGenSettings => diff::<MapGenSettings>(old, new),
+            ConfigFile::MapSettings => diff::<MapAndDifficultySettings>(old, new),
+        }
+    }
+}
+
+/// The violations found by [`ConfigFile::check`] in a document of the right type.
+type CheckResult = Result<(), ValidationErrors>;
+
+/// How to build a layered config, beyond the files in the configuration directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayerOptions {
+    /// The map generation preset layered over the built-in defaults.
+    pub preset: Option<MapPreset>,
+
+    /// The version of the installed game. The map settings start from its defaults, and fields it does not accept are
+    /// errors.
+    pub version: Option<GameVersion>,
+}
+
+/// Read the layer at `path`, in the format of its extension, or JSON if it has none.
+fn read_layer(path: &Path) -> Result<serde_json::Value, ConfigError> {
+    let contents = std::fs::read(path).map_err(|source| ConfigError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    let format = ConfigFormat::from_path(path).unwrap_or(ConfigFormat::Json);
+    let mut layer = format.parse(&contents).map_err(|source| ConfigError::Parse {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    // a reference to the file's schema is for editors, not the game
+    if let Some(layer) = layer.as_object_mut() {
+        layer.remove("$schema");
+    }
+
+    Ok(layer)
+}
+
+/// Merge the built-in defaults for `file` with its layers in `config_dir`. Returns [`None`] if `config_dir` has no
+/// layers for it, in which case the game's own defaults apply.
+pub fn read_layered(config_dir: &Path, file: ConfigFile) -> Result<Option<Layered>, ConfigError> {
+    read_layered_with(config_dir, file, LayerOptions::default())
+}
+
+/// Like [`read_layered`], with the given [`LayerOptions`]. Returns [`None`] only if there is no preset for `file` and
+/// `config_dir` has no layers for it.
+pub fn read_layered_with(
+    config_dir: &Path,
+    file: ConfigFile,
+    options: LayerOptions,
+) -> Result<Option<Layered>, ConfigError> {
+    let paths = layer_paths(config_dir, file.name()).map_err(|e| match e {
+        LayerPathsError::Io { path, source } => ConfigError::Io { path, source },
+        LayerPathsError::Ambiguous(paths) => ConfigError::Ambiguous { paths },
+    })?;
+
+    let parse_error = |source: serde_json::Error| ConfigError::Parse {
+        path: config_dir.join(file.file_name()),
+        source: source.into(),
+    };
+
+    let version = options.version.as_ref();
+    let preset = match options.preset {
+        Some(preset) => file.preset(preset, version).map_err(parse_error)?.map(|value| (preset, value)),
+        None => None,
+    };
+
+    if paths.is_empty() && preset.is_none() {
+        return Ok(None);
+    }
+
+    let mut layered = file.defaults(version).map_err(parse_error)?;
+    if let Some((preset, value)) = preset {
+        trace!("merging preset {}", preset);
+        layered.push_changes(format!("preset {}", preset), value);
+    }
+
+    for path in paths {
+        let layer = read_layer(&path)?;
+        trace!("merging {}", path.display());
+        layered.push(path.display().to_string(), layer);
+    }
+
+    file.check(&layered, version)
+        .map_err(parse_error)?
+        .map_err(|errors| ConfigError::Invalid {
+            path: config_dir.join(file.file_name()),
+            errors,
+        })?;
+
+    Ok(Some(layered))
+}
+
+/// Add the secrets and username of the server settings from the credentials returned by `read`, each as its own layer.
+/// Credentials that are not set leave the settings unchanged, and the username is only used if none is configured.
+fn apply_credentials(layered: &mut Layered, read: impl Fn(&str) -> Option<String>) {
+    let username_set = layered.value()["username"].as_str().is_some_and(|username| !username.is_empty());
+    let fields = [
+        ("token", credentials::TOKEN),
+        ("password", credentials::PASSWORD),
+        ("game_password", credentials::GAME_PASSWORD),
+        ("username", credentials::USERNAME),
+    ];
+
+    for (field, name) in fields {
+        if field == "username" && username_set {
+            continue;
+        }
+
+        if let Some(value) = read(name) {
+            layered.push(format!("credential {}", name), serde_json::json!({ field: value }));
+        }
+    }
+}
+
+/// Merge the layers of the server settings in `config_dir`, then add secrets from credentials. Returns [`None`] if
+/// `config_dir` has no server settings.
+///
+/// Without server settings, the password and the game password credentials are added to the built-in defaults
+/// instead, since the server settings are their only use. The factorio.com username and token are also used to
+/// download mods, so they alone do not give the server settings, and a warning is logged that they are not added.
+pub fn compose_layered_server_settings(config_dir: &Path) -> Result<Option<Layered>, ConfigError> {
+    compose_layered_server_settings_with(config_dir, credentials::read_credential)
+}
+
+/// Like [`compose_layered_server_settings`], reading the credentials with `read`.
+fn compose_layered_server_settings_with(
+    config_dir: &Path,
+    read: impl Fn(&str) -> Option<String>,
+) -> Result<Option<Layered>, ConfigError> {
+    let mut layered = match read_layered(config_dir, ConfigFile::ServerSettings)? {
+        Some(layered) => layered,
+        None if [credentials::PASSWORD, credentials::GAME_PASSWORD].iter().any(|name| read(name).is_some()) => {
+            info!("{} has no server settings, the credentials are added to the defaults", config_dir.display());
+            ConfigFile::ServerSettings.defaults(None).map_err(|source| ConfigError::Parse {
+                path: config_dir.join(ConfigFile::ServerSettings.file_name()),
+                source: source.into(),
+            })?
+        },
+        None => {
+            if [credentials::USERNAME, credentials::TOKEN].iter().any(|name| read(name).is_some()) {
+                warn!(
+                    "{} has no server settings, the factorio.com credentials are not added to the game's settings",
+                    config_dir.display()
+                );
+            }
+
+            return Ok(None);
+        },
+    };
+
+    apply_credentials(&mut layered, read);
+    Ok(Some(layered))
+}
+
+/// Compose the server settings from their layers in `config_dir` and secrets from credentials. Returns [`None`] if
+/// `config_dir` has no server settings.
+pub fn compose_server_settings(config_dir: &Path) -> Result<Option<ServerSettingsWithSecrets>, ConfigError> {
+    let Some(layered) = compose_layered_server_settings(config_dir)? else {
+        return Ok(None);
+    };
+
+    layered.deserialize().map(Some).map_err(|source| ConfigError::Parse {
+        path: config_dir.join(ConfigFile::ServerSettings.file_name()),
+        source: source.into(),
+    })
+}
+
+/// The paths in the server settings whose values are secret.
+pub const SECRET_PATHS: &[&str] = &["token", "password", "game_password"];
+
+/// What secret values are shown as.
+const REDACTED: &str = "<redacted>";
+
+/// Whether `value` at `path` is a secret that is set. Unset secrets are empty and shown as they are.
+fn is_secret(path: &str, value: &serde_json::Value) -> bool {
+    SECRET_PATHS.contains(&path) && value.as_str().is_some_and(|s| !s.is_empty())
+}
+
+/// Describe each value of `layered` and where it came from, one line per value. Secret values are redacted.
+pub fn explain(layered: &Layered) -> Vec<String> {
+    layered
+        .explain()
+        .into_iter()
+        .map(|(path, value, source)| {
+            let value = if is_secret(&path, value) {
+                REDACTED.to_owned()
+            } else {
+                value.to_string()
+            };
+
+            format!("{} = {} ({})", path, value, source)
+        })
+        .collect()
+}
+
+/// `difference` with its secret values redacted, in its values and its description.
+fn redact_difference(difference: Difference) -> Difference {
+    let secret = |value: &Option<serde_json::Value>| {
+        value.as_ref().is_some_and(|value| is_secret(&difference.path, value))
+    };
+
+    if !secret(&difference.old) && !secret(&difference.new) {
+        return difference;
+    }
+
+    let redact = |value: Option<serde_json::Value>| {
+        value.map(|value| if is_secret(&difference.path, &value) { REDACTED.into() } else { value })
+    };
+
+    let (old, new) = (redact(difference.old), redact(difference.new));
+    Difference::new(difference.path, old, new, |value| match value.as_str() {
+        Some(REDACTED) => REDACTED.to_owned(),
+        _ => value.to_string(),
+    })
+}
+
+/// Check that the composed server settings, if any, are valid. All violations are reported at once.
+pub fn check_server_settings(config_dir: &Path) -> Result<(), ConfigError> {
+    let Some(settings) = compose_server_settings(config_dir)? else {
+        return Ok(());
+    };
+
+    validate_server_settings(config_dir, &settings)
+}
+
+fn validate_server_settings(config_dir: &Path, settings: &ServerSettingsWithSecrets) -> Result<(), ConfigError> {
+    settings.validate().map_err(|errors| ConfigError::Invalid {
+        path: config_dir.join("server-settings.json"),
+        errors,
+    })
+}
+
+/// Check that the effective map generation settings, if any, are valid, like starting points within a finite map. All
+/// violations are reported at once.
+pub fn check_map_gen_settings(config_dir: &Path, options: LayerOptions) -> Result<(), ConfigError> {
+    let file = ConfigFile::MapGenSettings;
+    let Some(layered) = read_layered_with(config_dir, file, options)? else {
+        return Ok(());
+    };
+
+    let path = config_dir.join(file.file_name());
+    let settings: MapGenSettings = layered.deserialize().map_err(|source| ConfigError::Parse {
+        path: path.clone(),
+        source: source.into(),
+    })?;
+
+    settings
+        .validate()
+        .map_err(|errors| ConfigError::Invalid { path, errors })
+}
+
+/// The name of the game config written to the state directory, see [`write_game_config`].
+pub const GAME_CONFIG_FILE: &str = "config.ini";
+
+/// Write a game config to `state_dir` that reads the data of the game in `factorio_dir` but writes to `state_dir`, so
+/// that the game keeps its saves, logs, and custom scenarios there instead of in the downloaded game. Returns its path.
+pub fn write_game_config(factorio_dir: &Path, state_dir: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(state_dir)?;
+    let path = state_dir.join(GAME_CONFIG_FILE);
+    let contents = format!(
+        "[path]\nread-data={}\nwrite-data={}\n",
+        factorio_dir.join("data").display(),
+        state_dir.display()
+    );
+
+    factoriod_config::write_atomic(&path, contents.as_bytes())?;
+    Ok(path)
+}
+
+/// Write `contents` to `path`, readable and writable only by the owner.
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+
+    // the mode only applies when the file is created
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents)
+}
+
+/// Compose and validate the server settings, then write them to `server-settings.json` in `runtime_dir` with `0600`
+/// permissions. Returns the path of the written file, or [`None`] if `config_dir` has no `server-settings.json`.
+#[tracing::instrument]
+pub fn write_server_settings(config_dir: &Path, runtime_dir: &Path) -> Result<Option<PathBuf>, ConfigError> {
+    let Some(settings) = compose_server_settings(config_dir)? else {
+        debug!("no server settings in {}", config_dir.display());
+        return Ok(None);
+    };
+
+    validate_server_settings(config_dir, &settings)?;
+
+    let path = runtime_dir.join("server-settings.json");
+    info!("Writing server settings to {}", path.display());
+    serde_json::to_vec_pretty(&settings)
+        .map_err(std::io::Error::from)
+        .and_then(|contents| write_private(&path, &contents))
+        .map_err(|source| ConfigError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+    Ok(Some(path))
+}
+
+/// Merge the layers of `file` in `config_dir` with the given [`LayerOptions`] and write the effective file to `dir`.
+/// Returns the path of the written file, or [`None`] if there is nothing to write. The server settings contain secrets
+/// and are written with [`write_server_settings`] instead.
+#[tracing::instrument]
+pub fn write_effective(
+    config_dir: &Path,
+    file: ConfigFile,
+    options: LayerOptions,
+    dir: &Path,
+) -> Result<Option<PathBuf>, ConfigError> {
+    let Some(layered) = read_layered_with(config_dir, file, options)? else {
+        debug!("no {} in {}", file.name(), config_dir.display());
+        return Ok(None);
+    };
+
+    let path = dir.join(file.file_name());
+    info!("Writing {} to {}", file.file_name(), path.display());
+    serde_json::to_vec_pretty(layered.value())
+        .map_err(std::io::Error::from)
+        .and_then(|contents| factoriod_config::write_atomic(&path, &contents))
+        .map_err(|source| ConfigError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+    Ok(Some(path))
+}
+
+/// Write `settings`, given in memory instead of layered from a configuration directory, as the effective `file` in
+/// `dir`. The settings are first checked against the game `version`, if known. Returns the path of the written file.
+#[tracing::instrument(skip(settings))]
+pub fn write_settings<T: Serialize + VersionCheck>(
+    file: ConfigFile,
+    settings: &T,
+    version: Option<&GameVersion>,
+    dir: &Path,
+) -> Result<PathBuf, ConfigError> {
+    let path = dir.join(file.file_name());
+    if let Some(version) = version {
+        settings
+            .check_version(version)
+            .map_err(|errors| ConfigError::Invalid {
+                path: path.clone(),
+                errors,
+            })?;
+    }
+
+    info!("Writing {} to {}", file.file_name(), path.display());
+    serde_json::to_vec_pretty(settings)
+        .map_err(std::io::Error::from)
+        .and_then(|contents| factoriod_config::write_atomic(&path, &contents))
+        .map_err(|source| ConfigError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+    Ok(path)
+}
+
+/// The differences of `file` from `other`, a single file of the config in any format, to the effective config in
+/// `config_dir`. Without `other`, the differences from the built-in defaults. Secret values are redacted.
+pub fn diff(config_dir: &Path, file: ConfigFile, other: Option<&Path>) -> Result<Vec<Difference>, ConfigError> {
+    let parse_error = |path: PathBuf| {
+        move |source: serde_json::Error| ConfigError::Parse {
+            path,
+            source: source.into(),
+        }
+    };
+
+    let defaults = file.defaults(None).map_err(parse_error(config_dir.join(file.file_name())))?;
+    let mut old = defaults.clone();
+    if let Some(other) = other {
+        old.push(other.display().to_string(), read_layer(other)?);
+    }
+
+    let new = read_layered(config_dir, file)?.unwrap_or(defaults);
+    let path = other.map_or_else(|| config_dir.join(file.file_name()), Path::to_owned);
+    let differences = file.diff(&old, &new).map_err(parse_error(path))?;
+    Ok(differences.into_iter().map(redact_difference).collect())
+}
+
+/// A config file changed by [`migrate`].
+#[derive(Debug)]
+pub struct MigratedFile {
+    /// The migrated file.
+    pub path: PathBuf,
+
+    /// The copy of the file before the migration, or [`None`] for a dry run.
+    pub backup: Option<PathBuf>,
+
+    /// What was changed.
+    pub changes: Vec<MigrationChange>,
+}
+
+/// The first of `{path}.bak`, `{path}.bak.1`, `{path}.bak.2`, ... that does not exist.
+pub(crate) fn backup_path(path: &Path) -> PathBuf {
+    std::iter::once(".bak".to_owned())
+        .chain((1..).map(|i| format!(".bak.{}", i)))
+        .map(|suffix| {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(suffix);
+            PathBuf::from(backup)
+        })
+        .find(|backup| !backup.exists())
+        .expect("there are unused backup paths")
+}
+
+/// Migrate every layer of the map settings in `config_dir` to the game version `to`, in place. Each changed file is
+/// first copied to a backup next to it, like `map-settings.json.bak`, and is rewritten in its own format. With
+/// `dry_run`, the changes are returned but no file is written.
+#[tracing::instrument]
+pub fn migrate(config_dir: &Path, to: &GameVersion, dry_run: bool) -> Result<Vec<MigratedFile>, ConfigError> {
+    let mut migrated = Vec::new();
+    for file in [ConfigFile::MapGenSettings, ConfigFile::MapSettings] {
+        let paths = layer_paths(config_dir, file.name()).map_err(|e| match e {
+            LayerPathsError::Io { path, source } => ConfigError::Io { path, source },
+            LayerPathsError::Ambiguous(paths) => ConfigError::Ambiguous { paths },
+        })?;
+
+        for path in paths {
+            let io_error = |source| ConfigError::Io {
+                path: path.clone(),
+                source,
+            };
+
+            let contents = std::fs::read(&path).map_err(io_error)?;
+            let format = ConfigFormat::from_path(&path).unwrap_or(ConfigFormat::Json);
+            let parse_error = |source| ConfigError::Parse {
+                path: path.clone(),
+                source,
+            };
+
+            let mut value = format.parse(&contents).map_err(parse_error)?;
+            let changes = file.migrate(&mut value, to);
+            if changes.is_empty() {
+                trace!("{} needs no migration", path.display());
+                continue;
+            }
+
+            let backup = if dry_run {
+                None
+            } else {
+                let contents = format.to_vec(&value).map_err(parse_error)?;
+                let backup = backup_path(&path);
+                info!("Migrating {}, backed up to {}", path.display(), backup.display());
+                std::fs::copy(&path, &backup).map_err(|source| ConfigError::Io {
+                    path: backup.clone(),
+                    source,
+                })?;
+
+                factoriod_config::write_atomic(&path, &contents).map_err(io_error)?;
+                Some(backup)
+            };
+
+            migrated.push(MigratedFile { path, backup, changes });
+        }
+    }
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use factoriod_config::ServerSettings;
+
+    use super::*;
+
+    #[test]
+    fn test_check_server_settings_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_server_settings(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_check_server_settings_parse_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("server-settings.json"), "{").unwrap();
+        assert!(matches!(check_server_settings(dir.path()), Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_check_server_settings_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = ServerSettings {
+            max_heartbeats_per_second: 1,
+            ..Default::default()
+        };
+
+        std::fs::write(dir.path().join("server-settings.json"), serde_json::to_string(&settings).unwrap()).unwrap();
+        let error = check_server_settings(dir.path()).unwrap_err();
+        let report = error.to_string();
+        assert!(report.contains("- username: must be set when visibility.public is true"), "{}", report);
+        assert!(report.contains("- max_heartbeats_per_second: must be between 6 and 240, got 1"), "{}", report);
+        assert!(report.contains("- token: "), "{}", report);
+    }
+
+    #[test]
+    fn test_check_map_gen_settings_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = r#"{"width": 64, "height": 64, "starting_points": [{"x": 100, "y": 0}]}"#;
+        std::fs::write(dir.path().join("map-gen-settings.json"), settings).unwrap();
+        let report = check_map_gen_settings(dir.path(), LayerOptions::default()).unwrap_err().to_string();
+        let expected = "- starting_points.0.x: must be between -32 and 32 for a width of 64, got 100";
+        assert!(report.contains(expected), "{}", report);
+
+        let options = LayerOptions {
+            preset: Some(MapPreset::RibbonWorld),
+            ..Default::default()
+        };
+
+        std::fs::remove_file(dir.path().join("map-gen-settings.json")).unwrap();
+        assert!(check_map_gen_settings(dir.path(), options).is_ok());
+    }
+
+    #[test]
+    fn test_apply_credentials() {
+        let mut layered = ConfigFile::ServerSettings.defaults(None).unwrap();
+        layered.push("server-settings.json", serde_json::json!({"password": "from-file"}));
+        let read = |name: &str| match name {
+            credentials::TOKEN => Some("token".to_owned()),
+            credentials::GAME_PASSWORD => Some("game".to_owned()),
+            credentials::USERNAME => Some("user".to_owned()),
+            _ => None,
+        };
+
+        apply_credentials(&mut layered, read);
+        let settings: ServerSettingsWithSecrets = layered.deserialize().unwrap();
+        assert_eq!(settings.token, "token");
+        assert_eq!(settings.password, "from-file");
+        assert_eq!(settings.game_password, "game");
+        assert_eq!(settings.server_settings.username, "user");
+        assert_eq!(layered.source("token"), Some("credential factorio-token"));
+
+        // a username in the settings is kept
+        let mut layered = ConfigFile::ServerSettings.defaults(None).unwrap();
+        layered.push("server-settings.json", serde_json::json!({"username": "configured"}));
+        apply_credentials(&mut layered, read);
+        assert_eq!(layered.value()["username"], "configured");
+    }
+
+    #[test]
+    fn test_compose_layered_server_settings_without_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(compose_layered_server_settings_with(dir.path(), |_| None).unwrap().is_none());
+
+        // the factorio.com credentials are also used for mods, so they alone do not give server settings
+        let factorio_com = |name: &str| (name == credentials::TOKEN).then(|| "token".to_owned());
+        assert!(compose_layered_server_settings_with(dir.path(), factorio_com).unwrap().is_none());
+
+        let game_password = |name: &str| match name {
+            credentials::TOKEN => Some("token".to_owned()),
+            credentials::GAME_PASSWORD => Some("game".to_owned()),
+            _ => None,
+        };
+
+        let layered = compose_layered_server_settings_with(dir.path(), game_password).unwrap().unwrap();
+        let settings: ServerSettingsWithSecrets = layered.deserialize().unwrap();
+        assert_eq!(settings.game_password, "game");
+        assert_eq!(settings.token, "token");
+        assert_eq!(settings.server_settings.name, ServerSettingsWithSecrets::default().server_settings.name);
+        assert_eq!(layered.source("game_password"), Some("credential factorio-game-password"));
+    }
+
+    #[test]
+    fn test_read_layered_drop_ins() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_layered(dir.path(), ConfigFile::ServerSettings).unwrap().is_none());
+
+        std::fs::create_dir(dir.path().join("server-settings.d")).unwrap();
+        let drop_in_dir = dir.path().join("server-settings.d");
+        std::fs::write(drop_in_dir.join("10-name.json"), r#"{"name": "foo", "max_players": 4}"#).unwrap();
+        std::fs::write(drop_in_dir.join("20-players.json"), r#"{"max_players": 8}"#).unwrap();
+        let layered = read_layered(dir.path(), ConfigFile::ServerSettings).unwrap().unwrap();
+        let settings: ServerSettingsWithSecrets = layered.deserialize().unwrap();
+        assert_eq!(settings.server_settings.name, "foo");
+        assert_eq!(settings.server_settings.max_players, 8);
+        assert!(layered.source("max_players").unwrap().ends_with("20-players.json"));
+        assert_eq!(layered.source("description"), Some("defaults"));
+    }
+
+    #[test]
+    fn test_read_layered_ignores_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = r#"{"$schema": "./map-gen-settings.schema.json", "seed": 1}"#;
+        std::fs::write(dir.path().join("map-gen-settings.json"), json).unwrap();
+        let layered = read_layered(dir.path(), ConfigFile::MapGenSettings).unwrap().unwrap();
+        assert!(layered.value().get("$schema").is_none());
+        assert_eq!(layered.value()["seed"], 1);
+    }
+
+    #[test]
+    fn test_read_layered_invalid_type() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("map-settings.json"), r#"{"pollution": {"enabled": "yes"}}"#).unwrap();
+        match read_layered(dir.path(), ConfigFile::MapSettings) {
+            Err(ConfigError::Parse { path, .. }) => assert_eq!(path, dir.path().join("map-settings.json")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_explain_redacts_secrets() {
+        let mut layered = ConfigFile::ServerSettings.defaults(None).unwrap();
+        layered.push("credential factorio-token", serde_json::json!({"token": "secret"}));
+        let lines = explain(&layered);
+        assert!(lines.contains(&"token = <redacted> (credential factorio-token)".to_owned()), "{:?}", lines);
+        assert!(lines.contains(&r#"password = "" (defaults)"#.to_owned()), "{:?}", lines);
+        assert!(!lines.iter().any(|line| line.contains("secret")), "{:?}", lines);
+    }
+
+    #[test]
+    fn test_read_layered_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = "name = \"foo\"\n[visibility]\npublic = false\n";
+        std::fs::write(dir.path().join("server-settings.toml"), toml).unwrap();
+        std::fs::create_dir(dir.path().join("server-settings.d")).unwrap();
+        std::fs::write(dir.path().join("server-settings.d/10-players.yaml"), "max_players: 8\n").unwrap();
+        let settings: ServerSettingsWithSecrets =
+            read_layered(dir.path(), ConfigFile::ServerSettings).unwrap().unwrap().deserialize().unwrap();
+
+        assert_eq!(settings.server_settings.name, "foo");
+        assert!(!settings.server_settings.visibility.public);
+        assert_eq!(settings.server_settings.max_players, 8);
+
+        std::fs::write(dir.path().join("server-settings.json"), "{}").unwrap();
+        let error = read_layered(dir.path(), ConfigFile::ServerSettings).unwrap_err();
+        assert!(matches!(error, ConfigError::Ambiguous { .. }), "{:?}", error);
+        assert!(error.to_string().contains("server-settings.json and "), "{}", error);
+    }
+
+    #[test]
+    fn test_write_effective() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let out_dir = tempfile::tempdir().unwrap();
+        let write = || {
+            write_effective(config_dir.path(), ConfigFile::MapGenSettings, LayerOptions::default(), out_dir.path())
+        };
+
+        assert!(write().unwrap().is_none());
+
+        std::fs::write(config_dir.path().join("map-gen-settings.json"), r#"{"seed": 1234}"#).unwrap();
+        let path = write().unwrap().unwrap();
+        let written: MapGenSettings = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(written.seed, Some(1234));
+    }
+
+    #[test]
+    fn test_write_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = MapAndDifficultySettings::default();
+        let version = GameVersion::new(2, 0, 28, 0);
+        let error = write_settings(ConfigFile::MapSettings, &settings, Some(&version), dir.path()).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { .. }), "{:?}", error);
+        assert!(!dir.path().join("map-settings.json").exists());
+
+        let path = write_settings(ConfigFile::MapSettings, &settings, None, dir.path()).unwrap();
+        let written: MapAndDifficultySettings = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(written.max_failed_behavior_count, 3);
+    }
+
+    #[test]
+    fn test_read_layered_with_preset() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = LayerOptions {
+            preset: Some(MapPreset::RibbonWorld),
+            version: None,
+        };
+
+        assert!(read_layered_with(dir.path(), ConfigFile::ServerSettings, options).unwrap().is_none());
+
+        let layered = read_layered_with(dir.path(), ConfigFile::MapGenSettings, options).unwrap().unwrap();
+        assert_eq!(layered.source("height"), Some("preset ribbon-world"));
+        assert_eq!(layered.source("width"), Some("defaults"));
+
+        std::fs::write(dir.path().join("map-gen-settings.json"), r#"{"height": 256}"#).unwrap();
+        let layered = read_layered_with(dir.path(), ConfigFile::MapGenSettings, options).unwrap().unwrap();
+        let settings: MapGenSettings = layered.deserialize().unwrap();
+        assert_eq!(settings.height, 256);
+        assert_eq!(settings.water, Some(0.25));
+        assert!(layered.source("height").unwrap().ends_with("map-gen-settings.json"));
+    }
+
+    #[test]
+    fn test_read_layered_for_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = LayerOptions {
+            preset: Some(MapPreset::Marathon),
+            version: Some(GameVersion::new(2, 0, 28, 0)),
+        };
+
+        let layered = read_layered_with(dir.path(), ConfigFile::MapSettings, options).unwrap().unwrap();
+        let settings: MapAndDifficultySettings = layered.deserialize().unwrap();
+        assert_eq!(settings.difficulty_settings.technology_price_multiplier, 4.0);
+        assert!(settings.difficulty_settings.recipe_difficulty.is_none());
+        assert_eq!(settings.difficulty_settings.spoil_time_modifier, Some(1.0));
+
+        std::fs::write(
+            dir.path().join("map-settings.json"),
+            r#"{"difficulty_settings": {"recipe_difficulty": "expensive"}}"#,
+        )
+        .unwrap();
+
+        let error = read_layered_with(dir.path(), ConfigFile::MapSettings, options).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { .. }), "{:?}", error);
+        assert!(error.to_string().contains("difficulty_settings.recipe_difficulty: was removed"), "{}", error);
+
+        let options = LayerOptions {
+            version: Some(GameVersion::new(1, 1, 110, 0)),
+            ..options
+        };
+
+        assert!(read_layered_with(dir.path(), ConfigFile::MapSettings, options).is_ok());
+    }
+
+    #[test]
+    fn test_diff() {
+        let config_dir = tempfile::tempdir().unwrap();
+        assert!(diff(config_dir.path(), ConfigFile::MapSettings, None).unwrap().is_empty());
+
+        let map_settings = r#"{"enemy_expansion": {"enabled": false}, "pollution": {"ageing": 2}}"#;
+        std::fs::write(config_dir.path().join("map-settings.json"), map_settings).unwrap();
+        let differences = diff(config_dir.path(), ConfigFile::MapSettings, None).unwrap();
+        let paths: Vec<_> = differences.iter().map(|difference| difference.path.as_str()).collect();
+        assert_eq!(paths, ["enemy_expansion.enabled", "pollution.ageing"]);
+
+        let other_dir = tempfile::tempdir().unwrap();
+        let other = other_dir.path().join("other.yaml");
+        std::fs::write(&other, "enemy_expansion:\n  enabled: false\n").unwrap();
+        let differences = diff(config_dir.path(), ConfigFile::MapSettings, Some(&other)).unwrap();
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].to_string(), "pollution.ageing: changed from 1.0 to 2.0");
+    }
+
+    #[test]
+    fn test_diff_redacts_secrets() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let settings = r#"{"token": "secret", "game_password": "hunter2", "max_players": 4}"#;
+        std::fs::write(config_dir.path().join("server-settings.json"), settings).unwrap();
+
+        let other_dir = tempfile::tempdir().unwrap();
+        let other = other_dir.path().join("other.json");
+        std::fs::write(&other, r#"{"game_password": "letmein"}"#).unwrap();
+
+        let differences = diff(config_dir.path(), ConfigFile::ServerSettings, Some(&other)).unwrap();
+        let lines: Vec<_> = differences.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "game_password: changed from <redacted> to <redacted>",
+                "max_players: changed from 0 to 4",
+                "token: changed from \"\" to <redacted>",
+            ]
+        );
+
+        assert_eq!(differences[0].old, Some(REDACTED.into()));
+        assert_eq!(differences[2].new, Some(REDACTED.into()));
+        let debug = format!("{:?}", differences);
+        assert!(!["secret", "hunter2", "letmein"].iter().any(|secret| debug.contains(secret)), "{}", debug);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("map-settings.toml");
+        let original = "[difficulty_settings]\nrecipe_difficulty = \"expensive\"\ntechnology_price_multiplier = 2.0\n";
+        std::fs::write(&path, original).unwrap();
+        std::fs::create_dir(dir.path().join("map-gen-settings.d")).unwrap();
+        std::fs::write(dir.path().join("map-gen-settings.d/seed.json"), r#"{"seed": 1}"#).unwrap();
+
+        let to = GameVersion::new(2, 0, 0, 0);
+        let migrated = migrate(dir.path(), &to, true).unwrap();
+        assert_eq!(migrated.len(), 1);
+        assert!(migrated[0].backup.is_none());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+
+        let migrated = migrate(dir.path(), &to, false).unwrap();
+        assert_eq!(migrated[0].path, path);
+        assert_eq!(migrated[0].backup, Some(dir.path().join("map-settings.toml.bak")));
+        assert_eq!(std::fs::read_to_string(dir.path().join("map-settings.toml.bak")).unwrap(), original);
+
+        let migrated = std::fs::read_to_string(&path).unwrap();
+        assert!(!migrated.contains("recipe_difficulty"), "{}", migrated);
+        assert!(migrated.contains("technology_price_multiplier = 2.0"), "{}", migrated);
+
+        // migrated layers pass the checks of the new version
+        let options = LayerOptions {
+            preset: None,
+            version: Some(to),
+        };
+
+        assert!(read_layered_with(dir.path(), ConfigFile::MapSettings, options).is_ok());
+        assert!(migrate(dir.path(), &to, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backup_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("map-settings.json");
+        assert_eq!(backup_path(&path), dir.path().join("map-settings.json.bak"));
+        std::fs::write(dir.path().join("map-settings.json.bak"), "").unwrap();
+        std::fs::write(dir.path().join("map-settings.json.bak.1"), "").unwrap();
+        assert_eq!(backup_path(&path), dir.path().join("map-settings.json.bak.2"));
+    }
+
+    #[test]
+    fn test_write_server_settings() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let runtime_dir = tempfile::tempdir().unwrap();
+        assert!(write_server_settings(config_dir.path(), runtime_dir.path()).unwrap().is_none());
+
+        let mut settings = ServerSettingsWithSecrets::default();
+        settings.server_settings.username = "foo".to_owned();
+        settings.token = "secret".to_owned();
+        std::fs::write(config_dir.path().join("server-settings.json"), serde_json::to_string(&settings).unwrap()).unwrap();
+
+        let path = write_server_settings(config_dir.path(), runtime_dir.path()).unwrap().unwrap();
+        assert_eq!(path, runtime_dir.path().join("server-settings.json"));
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let written: ServerSettingsWithSecrets = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written.token, "secret");
+    }
+
+    #[test]
+    fn test_write_private_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        std::fs::write(&path, "old contents").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
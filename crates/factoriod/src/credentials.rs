@@ -0,0 +1,109 @@
+//! Secrets used by *factoriod*, such as the factorio.com token and the game password.
+//!
+//! Each credential has a name like `factorio-token`. It is read from the file of the same name in the systemd
+//! credentials directory (`$CREDENTIALS_DIRECTORY`, see `LoadCredential=` in `systemd.exec(5)`) or, failing that, from
+//! the environment variable named after it in upper snake case, like `FACTORIO_TOKEN`.
+
+use std::path::Path;
+
+use factorio_http_api::Credentials;
+use tracing::{debug, trace};
+
+/// The name of the credential holding the factorio.com username.
+pub const USERNAME: &str = "factorio-username";
+
+/// The name of the credential holding the factorio.com authentication token.
+pub const TOKEN: &str = "factorio-token";
+
+/// The name of the credential holding the factorio.com password, which the game may use instead of the token.
+pub const PASSWORD: &str = "factorio-password";
+
+/// The name of the credential holding the password players need to join the game.
+pub const GAME_PASSWORD: &str = "factorio-game-password";
+
+/// The environment variable that holds the credential `name`.
+///
+/// # Examples
+/// ```
+/// use factoriod::credentials;
+/// assert_eq!(credentials::env_var_name("factorio-token"), "FACTORIO_TOKEN");
+/// ```
+pub fn env_var_name(name: &str) -> String {
+    name.replace('-', "_").to_uppercase()
+}
+
+/// Read the credential `name` from `credentials_dir`, falling back to `env`. Trailing newlines are removed and empty
+/// values are ignored.
+fn read_credential_from(
+    credentials_dir: Option<&Path>,
+    name: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let from_file = credentials_dir.and_then(|dir| {
+        let path = dir.join(name);
+        match std::fs::read_to_string(&path) {
+            Ok(value) => {
+                debug!("read credential {} from {}", name, path.display());
+                Some(value)
+            },
+            Err(e) => {
+                trace!("failed to read credential {} from {}: {}", name, path.display(), e);
+                None
+            },
+        }
+    });
+
+    from_file
+        .or_else(|| env(&env_var_name(name)))
+        .map(|value| value.trim_end_matches(['\r', '\n']).to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+/// Read the credential `name` from the systemd credentials directory or the environment.
+pub fn read_credential(name: &str) -> Option<String> {
+    let credentials_dir = std::env::var_os("CREDENTIALS_DIRECTORY");
+    read_credential_from(credentials_dir.as_ref().map(Path::new), name, |var| std::env::var(var).ok())
+}
+
+/// Read the factorio.com credentials, used to download mods and list games, if both the username and token are set.
+pub fn factorio_credentials() -> Option<Credentials> {
+    Some(Credentials {
+        username: read_credential(USERNAME)?,
+        token: read_credential(TOKEN)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn test_read_credential_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(TOKEN), "secret\n").unwrap();
+        assert_eq!(read_credential_from(Some(dir.path()), TOKEN, no_env), Some("secret".to_owned()));
+        assert_eq!(read_credential_from(Some(dir.path()), USERNAME, no_env), None);
+    }
+
+    #[test]
+    fn test_read_credential_from_env() {
+        let env = |var: &str| (var == "FACTORIO_TOKEN").then(|| "from-env".to_owned());
+        assert_eq!(read_credential_from(None, TOKEN, env), Some("from-env".to_owned()));
+
+        // the credentials directory takes precedence over the environment
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(TOKEN), "from-file").unwrap();
+        assert_eq!(read_credential_from(Some(dir.path()), TOKEN, env), Some("from-file".to_owned()));
+    }
+
+    #[test]
+    fn test_read_credential_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(TOKEN), "\n").unwrap();
+        assert_eq!(read_credential_from(Some(dir.path()), TOKEN, no_env), None);
+    }
+}
@@ -4,24 +4,44 @@ use std::error::Error;
 use std::fmt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use factoriod_config::{GameVersion, MapPreset, Validate, VersionCheck};
+use serde::Serialize;
 use systemd_directories::SystemdDirs;
-use tracing::{debug, trace, warn};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::config::{self, ConfigError, ConfigFile, LayerOptions};
+use crate::daemon::game_process;
+use crate::daemon::new_save::{InvalidSaveNameError, NewSaveOptions, Overwrite};
+use crate::explore::{self, ExplorationIndex, SeedExploration, SeedPreview};
+use crate::instances::{Instance, InstanceError, DEFAULT_PORT};
+use crate::listing;
+use crate::save::SaveHeader;
+use crate::scenarios::{self, Scenario};
+use crate::state::{DaemonState, StartFrom};
 
 pub type Result<T> = std::result::Result<T, FactorioServerStartError>;
 
+/// The line the game writes once it has written a new save.
+const NEW_SAVE_MARKER: &str = "Saving finished";
+
 /// The directories used by the Factorio server.
 #[derive(Debug)]
 struct FactorioServerDirs {
     /// The path to the Factorio directory. This is the directory that contains the Factorio binary and data.
     factorio_dir: PathBuf,
 
-    /// The path to the server's state directory. This directory may contain save files and other generated content.
+    /// The path to the server's state directory. This directory may contain save files, mods, and other generated
+    /// content.
     state_dir: PathBuf,
 
     /// The path to the server's configuration directory. This directory may contain JSON files that configure the
     /// server, map generation, and other settings. See [`crate::config`] for more information.
     config_dir: PathBuf,
+
+    /// The path to the server's runtime directory, if any. The server settings, including secrets, are written here.
+    runtime_dir: Option<PathBuf>,
 }
 
 /// A Factorio server instance. This struct manages the server's state and configuration directories, and provides
@@ -30,6 +50,10 @@ struct FactorioServerDirs {
 pub struct FactorioServer {
     /// The directories used by the Factorio server.
     dirs: FactorioServerDirs,
+
+    /// The instance the server runs as, if any, which gives it its own ports and game config. See
+    /// [`crate::instances`].
+    instance: Option<Instance>,
 }
 
 
@@ -39,6 +63,47 @@ pub enum FactorioServerStartError {
 
     /// No save was found in the saves directory.
     NoSaveFound(PathBuf),
+
+    /// A config file in the configuration directory cannot be used.
+    InvalidConfig(ConfigError),
+
+    /// The name of a new save is not a valid file name.
+    InvalidSaveName(InvalidSaveNameError),
+
+    /// A save with the name of a new save already exists, and overwriting it was not allowed.
+    SaveExists(PathBuf),
+
+    /// The options of the server's instance could not be prepared.
+    Instance(InstanceError),
+
+    /// The game exited with a non-zero exit code.
+    GameExited {
+        binary: PathBuf,
+        code: i32,
+
+        /// The last lines of the game's output.
+        output: Vec<String>,
+    },
+
+    /// The game was killed by a signal.
+    GameKilled {
+        binary: PathBuf,
+        signal: i32,
+
+        /// The last lines of the game's output.
+        output: Vec<String>,
+    },
+
+    /// The game exited successfully, but without writing the line that shows it did what it was run for.
+    MissingSuccessMarker {
+        binary: PathBuf,
+
+        /// The text the game writes on success, like `Saving finished`.
+        marker: String,
+
+        /// The last lines of the game's output.
+        output: Vec<String>,
+    },
     StartFailed {
         path: PathBuf,
         source: std::io::Error,
@@ -54,6 +119,24 @@ impl fmt::Display for FactorioServerStartError {
             FactorioServerStartError::NoSaveFound(path) => {
                 write!(f, "No save found in the saves directory at {}.", path.display())
             },
+            FactorioServerStartError::InvalidConfig(e) => write!(f, "{}", e),
+            FactorioServerStartError::InvalidSaveName(e) => write!(f, "{}", e),
+            FactorioServerStartError::SaveExists(path) => {
+                write!(f, "The save {} already exists.", path.display())
+            },
+            FactorioServerStartError::Instance(e) => write!(f, "{}", e),
+            FactorioServerStartError::GameExited { binary, code, output } => {
+                write!(f, "{} exited with code {}", binary.display(), code)?;
+                write_output(f, output)
+            },
+            FactorioServerStartError::GameKilled { binary, signal, output } => {
+                write!(f, "{} was killed by signal {}", binary.display(), signal)?;
+                write_output(f, output)
+            },
+            FactorioServerStartError::MissingSuccessMarker { binary, marker, output } => {
+                write!(f, "{} exited without writing {:?}", binary.display(), marker)?;
+                write_output(f, output)
+            },
             FactorioServerStartError::StartFailed { path, source } => write!(
                 f,
                 "Failed to start Factorio server from binary {}: {}",
@@ -68,11 +151,70 @@ impl Error for FactorioServerStartError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             FactorioServerStartError::StartFailed { source, .. } => Some(source),
+            FactorioServerStartError::InvalidConfig(e) => Some(e),
+            FactorioServerStartError::InvalidSaveName(e) => Some(e),
+            FactorioServerStartError::Instance(e) => Some(e),
             _ => None,
         }
     }
 }
 
+impl FactorioServerStartError {
+    /// The exit code of the game, if it exited with a non-zero code.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            FactorioServerStartError::GameExited { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Write the last lines of the game's `output` after an error message, if there are any.
+fn write_output(f: &mut fmt::Formatter<'_>, output: &[String]) -> fmt::Result {
+    if output.is_empty() {
+        return write!(f, " without output.");
+    }
+
+    write!(f, ", its last output was:")?;
+    for line in output {
+        write!(f, "\n    {}", line)?;
+    }
+
+    Ok(())
+}
+
+/// Options for [`FactorioServer::generate_map_preview`]. Options that are not set use the game's defaults.
+#[derive(Debug, Clone, Default)]
+pub struct MapPreviewOptions {
+    /// The map generation preset to start from, overridden by the map generation settings in the configuration
+    /// directory.
+    pub preset: Option<MapPreset>,
+
+    /// The map seed, or [`None`] for a random seed.
+    pub seed: Option<u32>,
+
+    /// The width and height of the image, in pixels.
+    pub size: Option<u32>,
+
+    /// How many tiles each pixel covers.
+    pub scale: Option<f64>,
+}
+
+impl MapPreviewOptions {
+    /// The file name of the preview, like `rail-world-1234.png`. A preview with a random seed is named after the time
+    /// it was generated instead.
+    fn file_name(&self) -> String {
+        let preset = self.preset.map_or("map", MapPreset::name);
+        match self.seed {
+            Some(seed) => format!("{}-{}.png", preset, seed),
+            None => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+                format!("{}-random-{}.png", preset, now)
+            },
+        }
+    }
+}
+
 impl FactorioServer {
     /// Tries to create a new Factorio server instance.
     ///
@@ -89,96 +231,489 @@ impl FactorioServer {
                     .map_err(|_| FactorioServerStartError::PathNotFound(factorio_dir.as_ref().to_path_buf()))?,
                 state_dir: dirs.state_dir().map(|p| p.to_path_buf()).unwrap_or(PathBuf::from("var/lib/factoriod")),
                 config_dir: dirs.config_dir().map(|p| p.to_path_buf()).unwrap_or(PathBuf::from("etc/factoriod")),
+                runtime_dir: dirs.runtime_dir().map(|p| p.to_path_buf()),
             },
+            instance: None,
         })
     }
 
-    /// Start the Factorio server.
+    /// Use `config_dir` as the server's configuration directory instead of the systemd configuration directory.
+    pub fn with_config_dir<P: AsRef<Path>>(mut self, config_dir: P) -> Self {
+        self.dirs.config_dir = config_dir.as_ref().to_owned();
+        self
+    }
+
+    /// Use `state_dir` as the server's state directory instead of the systemd state directory.
+    pub fn with_state_dir<P: AsRef<Path>>(mut self, state_dir: P) -> Self {
+        self.dirs.state_dir = state_dir.as_ref().to_owned();
+        self
+    }
+
+    /// Use `runtime_dir` as the server's runtime directory instead of the systemd runtime directory.
+    pub fn with_runtime_dir<P: AsRef<Path>>(mut self, runtime_dir: P) -> Self {
+        self.dirs.runtime_dir = Some(runtime_dir.as_ref().to_owned());
+        self
+    }
+
+    /// Run the server as `instance`, on its ports with its own game config.
+    pub fn with_instance(mut self, instance: Instance) -> Self {
+        self.instance = Some(instance);
+        self
+    }
+
+    /// Start the Factorio server on the scenario selected in the daemon state, until a save is created from it,
+    /// otherwise on the latest save in the saves directory. See [`crate::state`].
     #[tracing::instrument(level = "trace")]
     pub fn start(&self) -> Result<()> {
+        match crate::server_opts::start_from(&self.dirs.state_dir, &self.dirs.state_dir.join("saves")) {
+            StartFrom::Scenario(scenario) => self.start_scenario(&scenario),
+            StartFrom::LatestSave => {
+                let save = self.latest_save()?;
+                self.start_save(&save)
+            },
+        }
+    }
+
+    /// Start the Factorio server on the given save.
+    #[tracing::instrument(level = "trace")]
+    pub fn start_save(&self, save: &Path) -> Result<()> {
+        self.run_server(|command| {
+            command.arg("--start-server").arg(save);
+        })
+    }
+
+    /// Start the Factorio server on a new map from the scenario `id`, like `base/pvp`. See [`crate::scenarios`].
+    #[tracing::instrument(level = "trace")]
+    pub fn start_scenario(&self, id: &str) -> Result<()> {
+        self.run_server(|command| {
+            command.arg("--start-server-load-scenario").arg(id);
+        })
+    }
+
+    /// The scenarios the server can be started from, see [`scenarios::list_scenarios`].
+    pub fn scenarios(&self) -> Vec<Scenario> {
+        scenarios::list_scenarios(&self.dirs.factorio_dir, &self.dirs.state_dir)
+    }
+
+    /// Select the scenario `id` to start the server from on its next start, recording it in the daemon state.
+    pub fn select_scenario(&self, id: &str) -> std::result::Result<Scenario, Box<dyn Error>> {
+        let scenario = self
+            .scenarios()
+            .into_iter()
+            .find(|scenario| scenario.id() == id)
+            .ok_or_else(|| format!("no scenario {}, list them with `factoriod scenario list`", id))?;
+
+        let mut state = DaemonState::load(&self.dirs.state_dir)?;
+        state.select_scenario(&scenario);
+        state.save(&self.dirs.state_dir)?;
+        Ok(scenario)
+    }
+
+    /// Run the server with its configuration and mods, and the options added by `start` that choose what to play,
+    /// until it exits. Fails if the game does not exit successfully.
+    fn run_server(&self, start: impl FnOnce(&mut Command)) -> Result<()> {
         if !self.dirs.factorio_dir.exists() {
             return Err(FactorioServerStartError::PathNotFound(
                 self.dirs.factorio_dir.clone(),
             ));
         }
 
-        let binary = self
-            .dirs
-            .factorio_dir
-            .join("bin/x64/factorio")
-            .canonicalize()
-            .map_err(|_| FactorioServerStartError::PathNotFound(self.dirs.factorio_dir.clone()))?;
+        config::write_server_settings(&self.dirs.config_dir, self.effective_dir())
+            .map_err(FactorioServerStartError::InvalidConfig)?;
 
+        let binary = self.binary()?;
         let mut command = Command::new(&binary);
         self.add_server_options(&mut command);
-        self.add_save(&mut command)?;
+        self.add_mod_directory(&mut command);
+        start(&mut command);
+        // the game writes to the state directory, where the daemon finds the saves it creates, like those of scenarios,
+        // and where the game finds the custom scenarios
+        match &self.instance {
+            Some(instance) => {
+                command.args(
+                    instance
+                        .game_options(&self.dirs.factorio_dir, &self.dirs.state_dir)
+                        .map_err(FactorioServerStartError::Instance)?,
+                );
+            },
+            None => {
+                let game_config = config::write_game_config(&self.dirs.factorio_dir, &self.dirs.state_dir)
+                    .map_err(|source| FactorioServerStartError::StartFailed {
+                        path: self.dirs.state_dir.join(config::GAME_CONFIG_FILE),
+                        source,
+                    })?;
+
+                command.arg("--config").arg(game_config);
+            },
+        }
+
+        let (config_dir, state_dir) = (self.dirs.config_dir.clone(), self.dirs.state_dir.clone());
+        let port = self.instance.as_ref().map_or(DEFAULT_PORT, |instance| instance.ports.port);
+        std::thread::spawn(move || {
+            listing::verify_listing(&config_dir, &state_dir, port, listing::LISTING_ATTEMPTS, listing::LISTING_INTERVAL)
+        });
+
+        game_process::run_game(&mut command, &binary, None)
+    }
+
+    /// Copy a save into the saves directory, unless it is already there. A different save with the same name in the saves
+    /// directory is first moved to a backup next to it, like `my-map.zip.bak`. Returns the path of the save in the saves
+    /// directory.
+    #[tracing::instrument(level = "trace")]
+    pub fn import_save(&self, save: &Path) -> Result<PathBuf> {
+        let save = save
+            .canonicalize()
+            .map_err(|_| FactorioServerStartError::PathNotFound(save.to_path_buf()))?;
 
-        let mut child = command.spawn()
-            .map_err(|source| FactorioServerStartError::StartFailed {
-                path: binary.clone(),
+        let saves_dir = self.dirs.state_dir.join("saves");
+        std::fs::create_dir_all(&saves_dir).map_err(|source| FactorioServerStartError::StartFailed {
+            path: saves_dir.clone(),
+            source,
+        })?;
+
+        let saves_dir = saves_dir
+            .canonicalize()
+            .map_err(|_| FactorioServerStartError::PathNotFound(saves_dir.clone()))?;
+
+        if save.parent() == Some(saves_dir.as_path()) {
+            return Ok(save);
+        }
+
+        let file_name = save
+            .file_name()
+            .ok_or_else(|| FactorioServerStartError::PathNotFound(save.clone()))?;
+
+        let destination = saves_dir.join(file_name);
+        if destination.exists() {
+            let backup = config::backup_path(&destination);
+            info!("Moving {} to {}", destination.display(), backup.display());
+            std::fs::rename(&destination, &backup).map_err(|source| FactorioServerStartError::StartFailed {
+                path: destination.clone(),
                 source,
             })?;
+        }
 
-        // if waiting fails, we don't care because the process was never running
-        child.wait().ok();
-        Ok(())
+        info!("copying {} to {}", save.display(), destination.display());
+        std::fs::copy(&save, &destination).map_err(|source| FactorioServerStartError::StartFailed {
+            path: destination.clone(),
+            source,
+        })?;
+
+        Ok(destination)
     }
 
+    /// Make the server's mods directory match the mods loaded in the given save, downloading them as needed. The
+    /// `mods.json` in the configuration directory is updated to pin the save's mods.
     #[tracing::instrument(level = "trace")]
-    pub fn new_save(&self, name: &str) -> Result<()> {
-        let binary = self
-            .dirs
-            .factorio_dir
-            .join("bin/x64/factorio")
-            .canonicalize()
-            .map_err(|_| FactorioServerStartError::PathNotFound(self.dirs.factorio_dir.clone()))?;
+    pub fn sync_mods_to_save(&self, save: &Path) -> std::result::Result<SaveHeader, Box<dyn Error>> {
+        let config_dir = Some(self.dirs.config_dir.as_path()).filter(|dir| dir.is_dir());
+        crate::mods::sync_mods_to_save(save, config_dir, &self.dirs.state_dir.join("mods"), &self.dirs.factorio_dir)
+    }
 
-        let mut command = Command::new(&binary);
+    /// Create a new save as described by `options`, returning its path in the saves directory. The map settings are
+    /// those given in `options`, otherwise the map settings in the configuration directory layered over
+    /// `options.preset`.
+    ///
+    /// # Errors
+    /// If the name is not a valid file name, if the save exists and `options` do not allow overwriting it, if the map
+    /// settings are invalid, or if the game fails or does not report and create the save, see
+    /// [`FactorioServerStartError::GameExited`].
+    #[tracing::instrument(level = "trace", skip(options), fields(name = %options.name))]
+    pub fn new_save(&self, options: &NewSaveOptions) -> Result<PathBuf> {
+        let file_name = options.file_name().map_err(FactorioServerStartError::InvalidSaveName)?;
+        let binary = self.binary()?;
         let saves_dir = self.dirs.state_dir.join("saves");
         std::fs::create_dir_all(&saves_dir).map_err(|source| FactorioServerStartError::StartFailed {
             path: saves_dir.clone(),
             source,
         })?;
 
-        let save_file = self.dirs.state_dir.join("saves").join(if name.ends_with(".zip") {
-            name.into()
-        } else {
-            format!("{}.zip", name)
+        let save_file = saves_dir.join(file_name);
+        let mut command = Command::new(&binary);
+        command.arg("--create").arg(&save_file);
+        self.add_mod_directory(&mut command);
+        self.add_new_map_settings(&mut command, options)?;
+        if let Some(seed) = options.seed {
+            command.arg("--map-gen-seed").arg(seed.to_string());
+        }
+
+        let replaced = make_room_for_save(&save_file, options.overwrite)?;
+        info!("Creating {}", save_file.display());
+        let created = game_process::run_game(&mut command, &binary, Some(NEW_SAVE_MARKER)).and_then(|()| {
+            if save_file.is_file() {
+                Ok(())
+            } else {
+                warn!("{} exited without creating {}", binary.display(), save_file.display());
+                Err(FactorioServerStartError::PathNotFound(save_file.clone()))
+            }
         });
 
-        command.arg("--create").arg(save_file);
+        if let Some(replaced) = replaced {
+            finish_replacing_save(&save_file, &replaced, created.is_ok());
+        }
+
+        created.map(|()| save_file)
+    }
 
-        if self.dirs.config_dir.is_dir() {
-            if let Ok(map_gen_settings) = self.dirs.config_dir.join("map-gen-settings.json").canonicalize() {
-                if map_gen_settings.is_file() {
-                    command.arg("--map-gen-settings").arg(map_gen_settings);
-                } else {
-                    warn!("{} is not a file!", map_gen_settings.display());
-                }
-            } else {
-                trace!("map-gen-settings.json not found in {}", self.dirs.config_dir.display());
+    /// Add the map generation settings and map settings of a new map to `command`, writing them to the effective
+    /// directory.
+    fn add_new_map_settings(&self, command: &mut Command, options: &NewSaveOptions) -> Result<()> {
+        let version = self.installed_game_version();
+        let layer_options = LayerOptions {
+            preset: options.preset,
+            version,
+        };
+
+        let layered = self.dirs.config_dir.is_dir() || options.preset.is_some();
+        let map_gen_settings = match &options.map_gen_settings {
+            Some(settings) => {
+                let path = self.effective_dir().join(ConfigFile::MapGenSettings.file_name());
+                settings.validate().map_err(|errors| {
+                    FactorioServerStartError::InvalidConfig(ConfigError::Invalid { path, errors })
+                })?;
+
+                Some(self.write_settings(ConfigFile::MapGenSettings, settings, version.as_ref())?)
+            },
+            None if layered => self.write_map_gen_settings(layer_options)?,
+            None => None,
+        };
+
+        let map_settings = match &options.map_settings {
+            Some(settings) => Some(self.write_settings(ConfigFile::MapSettings, settings, version.as_ref())?),
+            None if layered => self.write_effective(ConfigFile::MapSettings, layer_options)?,
+            None => None,
+        };
+
+        if let Some(map_gen_settings) = map_gen_settings {
+            command.arg("--map-gen-settings").arg(map_gen_settings);
+        }
+
+        if let Some(map_settings) = map_settings {
+            command.arg("--map-settings").arg(map_settings);
+        }
+
+        Ok(())
+    }
+
+    /// Generate a preview image of a new map without creating a save or starting the server, to try out map
+    /// generation settings and seeds. The map generation settings in the configuration directory are used, layered
+    /// over `options.preset` if given. Returns the path of the PNG, in the `previews` directory of the state directory.
+    #[tracing::instrument(level = "trace")]
+    pub fn generate_map_preview(&self, options: &MapPreviewOptions) -> Result<PathBuf> {
+        let (binary, map_gen_settings) = self.prepare_map_preview(options)?;
+        let preview = self.previews_dir()?.join(options.file_name());
+        let mut command = self.map_preview_command(&binary, map_gen_settings.as_deref(), options, &preview);
+
+        info!("Generating a map preview at {}", preview.display());
+        game_process::run_game(&mut command, &binary, None)?;
+        if !preview.is_file() {
+            warn!("{} exited without writing a preview", binary.display());
+            return Err(FactorioServerStartError::PathNotFound(preview));
+        }
+
+        Ok(preview)
+    }
+
+    /// Generate a map preview for each seed of `exploration`, running at most `exploration.jobs` games at once, into a
+    /// new directory in the `previews` directory of the state directory. The seeds, their previews, and the
+    /// quantities of resources the game reports are indexed in `index.json` and `index.html` there. A seed whose
+    /// preview fails is recorded with its error. Returns the path of `index.html`.
+    #[tracing::instrument(level = "trace", skip(exploration))]
+    pub fn explore_seeds(&self, exploration: &SeedExploration) -> Result<PathBuf> {
+        let (binary, map_gen_settings) = self.prepare_map_preview(&exploration.preview)?;
+        let preset = exploration.preview.preset.map(MapPreset::name);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+        let dir = self.previews_dir()?.join(format!("{}-seeds-{}", preset.unwrap_or("map"), now));
+        let write_data_dir = dir.join(".write-data");
+        let io_error = |path: &Path| {
+            let path = path.to_owned();
+            move |source| FactorioServerStartError::StartFailed { path, source }
+        };
+
+        std::fs::create_dir_all(&dir).map_err(io_error(&dir))?;
+        info!("Generating previews of {} seeds in {}", exploration.seeds.len(), dir.display());
+        let previews = explore::run_bounded(exploration.seeds.clone(), exploration.jobs, |seed| {
+            let file_name = format!("{}.png", seed);
+            let path = dir.join(&file_name);
+            let options = MapPreviewOptions {
+                seed: Some(seed),
+                ..exploration.preview.clone()
+            };
+
+            let mut command = self.map_preview_command(&binary, map_gen_settings.as_deref(), &options, &path);
+            if !exploration.report_quantities.is_empty() {
+                command.arg("--report-quantities").arg(exploration.report_quantities.join(","));
             }
 
-            if let Ok(map_settings) = self.dirs.config_dir.join("map-settings.json").canonicalize() {
-                if map_settings.is_file() {
-                    command.arg("--map-settings").arg(map_settings);
-                } else {
-                    warn!("{} is not a file!", map_settings.display());
-                }
-            } else {
-                trace!("map-settings.json not found in {}", self.dirs.config_dir.display());
+            // the game locks its write data directory, so each instance needs its own
+            let write_data = write_data_dir.join(seed.to_string());
+            let started = Instant::now();
+            let output = self
+                .write_data_config(&write_data)
+                .and_then(|config| command.arg("--config").arg(config).output());
+
+            let mut preview = SeedPreview::new(seed, file_name, started.elapsed());
+            match output {
+                Ok(output) if output.status.success() && path.is_file() => {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    preview.quantities = explore::parse_quantities(&stdout, &exploration.report_quantities);
+                    info!("Generated the preview of seed {} in {:.1}s", seed, preview.seconds);
+                },
+                Ok(output) => preview.error = Some(format!("the game exited with {}", output.status)),
+                Err(e) => preview.error = Some(e.to_string()),
             }
+
+            if let Some(error) = &preview.error {
+                warn!("Failed to generate the preview of seed {}: {}", seed, error);
+            }
+
+            preview
+        });
+
+        std::fs::remove_dir_all(&write_data_dir).ok();
+        let index = ExplorationIndex {
+            preset: preset.map(str::to_owned),
+            previews,
+        };
+
+        explore::write_index(&dir, &index).map_err(io_error(&dir))
+    }
+
+    /// The game binary and the effective map generation settings for map previews with `options`.
+    fn prepare_map_preview(&self, options: &MapPreviewOptions) -> Result<(PathBuf, Option<PathBuf>)> {
+        let binary = self.binary()?;
+        if !self.dirs.config_dir.is_dir() && options.preset.is_none() {
+            return Ok((binary, None));
         }
 
-        let mut child = command.spawn()
-            .map_err(|source| FactorioServerStartError::StartFailed {
-                path: binary.clone(),
-                source,
-            })?;
+        let layer_options = LayerOptions {
+            preset: options.preset,
+            version: self.installed_game_version(),
+        };
 
-        // if waiting fails, we don't care because the process was never running
-        child.wait().ok();
-        Ok(())
+        Ok((binary, self.write_map_gen_settings(layer_options)?))
+    }
+
+    /// The command that generates a map preview at `preview` with `options`.
+    fn map_preview_command(
+        &self,
+        binary: &Path,
+        map_gen_settings: Option<&Path>,
+        options: &MapPreviewOptions,
+        preview: &Path,
+    ) -> Command {
+        let mut command = Command::new(binary);
+        command.arg("--generate-map-preview").arg(preview);
+        self.add_mod_directory(&mut command);
+
+        if let Some(map_gen_settings) = map_gen_settings {
+            command.arg("--map-gen-settings").arg(map_gen_settings);
+        }
+
+        if let Some(seed) = options.seed {
+            command.arg("--map-gen-seed").arg(seed.to_string());
+        }
+
+        if let Some(size) = options.size {
+            command.arg("--map-preview-size").arg(size.to_string());
+        }
+
+        if let Some(scale) = options.scale {
+            command.arg("--map-preview-scale").arg(scale.to_string());
+        }
+
+        command
+    }
+
+    /// The `previews` directory of the state directory, created if needed.
+    fn previews_dir(&self) -> Result<PathBuf> {
+        let previews_dir = self.dirs.state_dir.join("previews");
+        std::fs::create_dir_all(&previews_dir).map_err(|source| FactorioServerStartError::StartFailed {
+            path: previews_dir.clone(),
+            source,
+        })?;
+
+        Ok(previews_dir)
+    }
+
+    /// Write a game config to `write_data`, which reads the game's data as usual but writes to `write_data`, and
+    /// return its path.
+    fn write_data_config(&self, write_data: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(write_data)?;
+        let write_data = write_data.canonicalize()?;
+        let config = write_data.join("config.ini");
+        let contents = format!(
+            "[path]\nread-data={}\nwrite-data={}\n",
+            self.dirs.factorio_dir.join("data").display(),
+            write_data.display()
+        );
+
+        std::fs::write(&config, contents)?;
+        Ok(config)
+    }
+
+    /// The path of the game's binary.
+    fn binary(&self) -> Result<PathBuf> {
+        self.dirs
+            .factorio_dir
+            .join("bin/x64/factorio")
+            .canonicalize()
+            .map_err(|_| FactorioServerStartError::PathNotFound(self.dirs.factorio_dir.clone()))
+    }
+
+    /// Write the effective `file` to the effective directory, returning its path, or [`None`] if neither the
+    /// configuration directory nor `options` have one.
+    fn write_effective(&self, file: ConfigFile, options: LayerOptions) -> Result<Option<PathBuf>> {
+        let effective_dir = self.effective_dir();
+        std::fs::create_dir_all(effective_dir).map_err(|source| FactorioServerStartError::StartFailed {
+            path: effective_dir.to_owned(),
+            source,
+        })?;
+
+        config::write_effective(&self.dirs.config_dir, file, options, effective_dir)
+            .map_err(FactorioServerStartError::InvalidConfig)
+    }
+
+    /// Write `settings` given in memory as the effective `file`, like [`write_effective`](Self::write_effective).
+    fn write_settings<T: Serialize + VersionCheck>(
+        &self,
+        file: ConfigFile,
+        settings: &T,
+        version: Option<&GameVersion>,
+    ) -> Result<PathBuf> {
+        let effective_dir = self.effective_dir();
+        std::fs::create_dir_all(effective_dir).map_err(|source| FactorioServerStartError::StartFailed {
+            path: effective_dir.to_owned(),
+            source,
+        })?;
+
+        config::write_settings(file, settings, version, effective_dir).map_err(FactorioServerStartError::InvalidConfig)
+    }
+
+    /// Check the effective map generation settings, then write them like [`write_effective`](Self::write_effective),
+    /// so that the game is not run on settings it would reject.
+    fn write_map_gen_settings(&self, options: LayerOptions) -> Result<Option<PathBuf>> {
+        config::check_map_gen_settings(&self.dirs.config_dir, options)
+            .map_err(FactorioServerStartError::InvalidConfig)?;
+        self.write_effective(ConfigFile::MapGenSettings, options)
+    }
+
+    /// The version of the installed game, or [`None`] if it cannot be read, in which case the config files are not
+    /// checked against it.
+    fn installed_game_version(&self) -> Option<GameVersion> {
+        match crate::mods::installed_game_version(&self.dirs.factorio_dir) {
+            Ok(version) => Some(GameVersion::new(version.major, version.minor, version.patch, 0)),
+            Err(e) => {
+                warn!("Could not read the version of the game in {}: {}", self.dirs.factorio_dir.display(), e);
+                None
+            },
+        }
+    }
+
+    /// The directory the effective config files are written to, merged from the layers in the configuration directory
+    /// and converted to JSON. This is the runtime directory, if any, otherwise the state directory.
+    fn effective_dir(&self) -> &Path {
+        self.dirs.runtime_dir.as_deref().unwrap_or(&self.dirs.state_dir)
     }
 
     /// Add the server's configuration to the given command, if any.
@@ -194,14 +729,19 @@ impl FactorioServer {
             return;
         }
 
-        if let Ok(server_settings) = self.dirs.config_dir.join("server-settings.json").canonicalize() {
+        // the effective settings include secrets and layers, so they take precedence
+        let settings_dir = Some(self.effective_dir())
+            .filter(|dir| dir.join("server-settings.json").is_file())
+            .unwrap_or(&self.dirs.config_dir);
+
+        if let Ok(server_settings) = settings_dir.join("server-settings.json").canonicalize() {
             if server_settings.is_file() {
                 command.arg("--server-settings").arg(server_settings);
             } else {
                 warn!("{} is not a file!", server_settings.display());
             }
         } else {
-            trace!("server-settings.json not found in {}", self.dirs.config_dir.display());
+            trace!("server-settings.json not found in {}", settings_dir.display());
         }
 
         if let Ok(server_whitelist) = self.dirs.config_dir.join("server-whitelist.json").canonicalize() {
@@ -235,24 +775,232 @@ impl FactorioServer {
         }
     }
 
-    /// Add the save to the given command, if any.
-    fn add_save(&self, command: &mut Command) -> Result<&Self> {
+    /// Add the server's mods directory to the given command, if it exists. Otherwise the game uses its default mods
+    /// directory.
+    fn add_mod_directory(&self, command: &mut Command) {
+        if let Ok(mods_dir) = self.dirs.state_dir.join("mods").canonicalize() {
+            if mods_dir.is_dir() {
+                command.arg("--mod-directory").arg(mods_dir);
+            } else {
+                warn!("{} is not a directory!", mods_dir.display());
+            }
+        } else {
+            trace!("mods directory not found in {}", self.dirs.state_dir.display());
+        }
+    }
+
+    /// Get the latest save in the saves directory.
+    fn latest_save(&self) -> Result<PathBuf> {
         let save_dir = self.dirs.state_dir.join("saves");
         match self.dirs.state_dir.join("saves").canonicalize() {
             Ok(save_dir) if save_dir.is_dir() => {
                 let latest_save = crate::get_latest_save(&save_dir)?;
                 debug!("latest save: {}", latest_save.display());
-                command.arg("--start-server").arg(latest_save);
-                Ok(self)
+                Ok(latest_save)
             },
             Ok(save_dir) => {
                 warn!("{} is not a directory", save_dir.display());
-                return Err(FactorioServerStartError::PathNotFound(save_dir.clone()));
+                Err(FactorioServerStartError::PathNotFound(save_dir.clone()))
             }
             _ => {
                 warn!("saves directory does not exist at {}. A save must be created first before continuing.", save_dir.display());
-                return Err(FactorioServerStartError::PathNotFound(save_dir.clone()));
+                Err(FactorioServerStartError::PathNotFound(save_dir.clone()))
             }
         }
     }
 }
+
+/// Apply `overwrite` to an existing save at `save_file`, so that the game can create a new one there. A save that is
+/// being replaced is only moved aside, and its new path is returned so that [`finish_replacing_save`] can remove or
+/// restore it once the game is done.
+fn make_room_for_save(save_file: &Path, overwrite: Overwrite) -> Result<Option<PathBuf>> {
+    if !save_file.exists() {
+        return Ok(None);
+    }
+
+    let backup = match overwrite {
+        Overwrite::Error => return Err(FactorioServerStartError::SaveExists(save_file.to_owned())),
+        Overwrite::Replace | Overwrite::Backup => config::backup_path(save_file),
+    };
+
+    info!("Moving {} to {}", save_file.display(), backup.display());
+    std::fs::rename(save_file, &backup).map_err(|source| FactorioServerStartError::StartFailed {
+        path: save_file.to_owned(),
+        source,
+    })?;
+
+    Ok(Some(backup).filter(|_| overwrite == Overwrite::Replace))
+}
+
+/// Remove the save at `replaced` that was moved aside from `save_file` if the new save was `created`, otherwise move it
+/// back. Failures are only logged, since the outcome of creating the save is what gets reported.
+fn finish_replacing_save(save_file: &Path, replaced: &Path, created: bool) {
+    if created {
+        info!("Replaced {}", save_file.display());
+        if let Err(e) = std::fs::remove_file(replaced) {
+            warn!("Could not remove the replaced save {}: {}", replaced.display(), e);
+        }
+    } else {
+        info!("Restoring {} to {}", replaced.display(), save_file.display());
+        if let Err(e) = std::fs::rename(replaced, save_file) {
+            error!("Could not restore {} to {}: {}", replaced.display(), save_file.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the save given to `--create`, like the game.
+    const CREATE_SAVE: &str = r#"for arg; do [ "$prev" = --create ] && touch "$arg"; prev=$arg; done"#;
+
+    /// A server in `dir` whose game binary is the shell `script`.
+    fn fake_server(dir: &Path, script: &str) -> FactorioServer {
+        let factorio_dir = dir.join("factorio");
+        std::fs::create_dir_all(factorio_dir.join("bin/x64")).unwrap();
+        let source = dir.join("factorio.sh");
+        std::fs::write(&source, format!("#!/bin/sh\n{}\n", script)).unwrap();
+
+        // installed by another process, so that no thread of the tests still has the binary open for writing when
+        // it runs, which fails with "text file busy"
+        let status = Command::new("install")
+            .arg("-m755")
+            .arg(&source)
+            .arg(factorio_dir.join("bin/x64/factorio"))
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+        FactorioServer::try_new(&factorio_dir)
+            .unwrap()
+            .with_config_dir(dir.join("config"))
+            .with_state_dir(dir.join("state"))
+            .with_runtime_dir(dir.join("run"))
+    }
+
+    #[test]
+    fn test_new_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = format!("{}\necho '   0.123 Info AppManagerStates.cpp:2058: {}'", CREATE_SAVE, NEW_SAVE_MARKER);
+        let server = fake_server(dir.path(), &script);
+        let save = server.new_save(&NewSaveOptions::new("my-map").with_seed(1234)).unwrap();
+        assert_eq!(save, dir.path().join("state/saves/my-map.zip"));
+        assert!(save.is_file());
+    }
+
+    #[test]
+    fn test_new_save_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = fake_server(dir.path(), "echo 'Error: no space left'\necho 'Goodbye' >&2\nexit 3");
+        let error = server.new_save(&NewSaveOptions::new("my-map")).unwrap_err();
+        assert_eq!(error.exit_code(), Some(3));
+        match &error {
+            FactorioServerStartError::GameExited { code, output, .. } => {
+                assert_eq!(*code, 3);
+                assert!(output.contains(&"Error: no space left".to_owned()), "{:?}", output);
+                assert!(output.contains(&"Goodbye".to_owned()), "{:?}", output);
+            },
+            _ => panic!("unexpected error: {:?}", error),
+        }
+
+        assert!(error.to_string().contains("exited with code 3, its last output was:\n"), "{}", error);
+    }
+
+    #[test]
+    fn test_new_save_killed() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = fake_server(dir.path(), "kill -KILL $$");
+        let error = server.new_save(&NewSaveOptions::new("my-map")).unwrap_err();
+        assert!(matches!(error, FactorioServerStartError::GameKilled { signal: 9, .. }), "{:?}", error);
+        assert_eq!(error.exit_code(), None);
+    }
+
+    #[test]
+    fn test_new_save_missing_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = fake_server(dir.path(), CREATE_SAVE);
+        match server.new_save(&NewSaveOptions::new("my-map")).unwrap_err() {
+            FactorioServerStartError::MissingSuccessMarker { marker, .. } => assert_eq!(marker, NEW_SAVE_MARKER),
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_start_save_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = fake_server(dir.path(), "exit 2");
+        let error = server.start_save(&dir.path().join("my-map.zip")).unwrap_err();
+        assert_eq!(error.exit_code(), Some(2));
+        assert!(error.to_string().ends_with("exited with code 2 without output."), "{}", error);
+    }
+
+    #[test]
+    fn test_import_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = fake_server(dir.path(), "exit 0");
+        let save = dir.path().join("my-map.zip");
+        std::fs::write(&save, "new").unwrap();
+        let saves_dir = dir.path().join("state/saves");
+        std::fs::create_dir_all(&saves_dir).unwrap();
+        std::fs::write(saves_dir.join("my-map.zip"), "old").unwrap();
+
+        let imported = server.import_save(&save).unwrap();
+        assert_eq!(imported, saves_dir.canonicalize().unwrap().join("my-map.zip"));
+        assert_eq!(std::fs::read_to_string(&imported).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(saves_dir.join("my-map.zip.bak")).unwrap(), "old");
+
+        // a save already in the saves directory is not copied
+        assert_eq!(server.import_save(&imported).unwrap(), imported);
+        assert!(!saves_dir.join("my-map.zip.bak.1").exists());
+    }
+
+    #[test]
+    fn test_preview_file_name() {
+        let options = MapPreviewOptions {
+            preset: Some(MapPreset::RailWorld),
+            seed: Some(1234),
+            ..Default::default()
+        };
+
+        assert_eq!(options.file_name(), "rail-world-1234.png");
+        assert!(MapPreviewOptions::default().file_name().starts_with("map-random-"));
+    }
+
+    #[test]
+    fn test_make_room_for_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let save = dir.path().join("my-map.zip");
+        assert_eq!(make_room_for_save(&save, Overwrite::Error).unwrap(), None);
+
+        std::fs::write(&save, "old").unwrap();
+        assert!(matches!(
+            make_room_for_save(&save, Overwrite::Error),
+            Err(FactorioServerStartError::SaveExists(path)) if path == save
+        ));
+        assert!(save.is_file());
+
+        assert_eq!(make_room_for_save(&save, Overwrite::Backup).unwrap(), None);
+        assert!(!save.exists());
+        assert_eq!(std::fs::read_to_string(dir.path().join("my-map.zip.bak")).unwrap(), "old");
+
+        std::fs::write(&save, "newer").unwrap();
+        make_room_for_save(&save, Overwrite::Backup).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("my-map.zip.bak.1")).unwrap(), "newer");
+
+        // the replaced save is kept until the new one is created, and restored if creating it fails
+        std::fs::write(&save, "newest").unwrap();
+        let replaced = make_room_for_save(&save, Overwrite::Replace).unwrap().unwrap();
+        assert!(!save.exists());
+        finish_replacing_save(&save, &replaced, false);
+        assert_eq!(std::fs::read_to_string(&save).unwrap(), "newest");
+        assert!(!replaced.exists());
+
+        let replaced = make_room_for_save(&save, Overwrite::Replace).unwrap().unwrap();
+        std::fs::write(&save, "created").unwrap();
+        finish_replacing_save(&save, &replaced, true);
+        assert_eq!(std::fs::read_to_string(&save).unwrap(), "created");
+        assert!(!replaced.exists());
+        assert!(!dir.path().join("my-map.zip.bak.2").exists());
+    }
+}
@@ -0,0 +1,112 @@
+//! Running the game and checking how it exited.
+//!
+//! The game's output is forwarded as it is written, so it still reaches the journal, and its last lines are kept to
+//! explain a failure.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use super::factorio_server::{FactorioServerStartError, Result};
+
+/// How many of the last lines of the game's output are kept in errors.
+pub(crate) const OUTPUT_TAIL_LINES: usize = 20;
+
+/// The last lines of the game's output, and whether the success marker was among all of them.
+#[derive(Debug, Default)]
+struct Output {
+    tail: VecDeque<String>,
+    marker_seen: bool,
+}
+
+impl Output {
+    fn push(&mut self, line: String, marker: Option<&str>) {
+        self.marker_seen |= marker.is_some_and(|marker| line.contains(marker));
+        if self.tail.len() == OUTPUT_TAIL_LINES {
+            self.tail.pop_front();
+        }
+
+        self.tail.push_back(line);
+    }
+}
+
+/// Forward each line of `reader` to `writer`, recording it in `output`.
+fn forward(reader: impl Read, mut writer: impl Write, output: &Mutex<Output>, marker: Option<&str>) {
+    for line in BufReader::new(reader).split(b'\n').map_while(|line| line.ok()) {
+        // the game's output is only forwarded on a best effort basis, it is still recorded
+        writer.write_all(&line).and_then(|()| writer.write_all(b"\n")).ok();
+        let line = String::from_utf8_lossy(&line).trim_end().to_owned();
+        output.lock().expect("output lock poisoned").push(line, marker);
+    }
+}
+
+/// Run the game `binary` with `command` until it exits, forwarding its output.
+///
+/// # Errors
+/// If the game cannot be started, exits with a non-zero code or from a signal, or, given `success_marker`, exits
+/// without writing a line that contains it. The errors include the last lines of the game's output.
+pub(crate) fn run_game(command: &mut Command, binary: &Path, success_marker: Option<&str>) -> Result<()> {
+    let start_failed = |source| FactorioServerStartError::StartFailed {
+        path: binary.to_owned(),
+        source,
+    };
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(start_failed)?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let output = Mutex::new(Output::default());
+    std::thread::scope(|scope| {
+        scope.spawn(|| forward(stdout, std::io::stdout(), &output, success_marker));
+        forward(stderr, std::io::stderr(), &output, success_marker);
+    });
+
+    let status = child.wait().map_err(start_failed)?;
+    let output = output.into_inner().expect("output lock poisoned");
+    let binary = binary.to_owned();
+    let tail = Vec::from(output.tail);
+    match (status.code(), status.signal()) {
+        (Some(0), _) => match success_marker {
+            Some(marker) if !output.marker_seen => Err(FactorioServerStartError::MissingSuccessMarker {
+                binary,
+                marker: marker.to_owned(),
+                output: tail,
+            }),
+            _ => Ok(()),
+        },
+        (Some(code), _) => Err(FactorioServerStartError::GameExited {
+            binary,
+            code,
+            output: tail,
+        }),
+        (None, signal) => Err(FactorioServerStartError::GameKilled {
+            binary,
+            signal: signal.unwrap_or_default(),
+            output: tail,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_tail() {
+        let mut output = Output::default();
+        for i in 0..OUTPUT_TAIL_LINES + 5 {
+            output.push(format!("line {}", i), Some("line 2"));
+        }
+
+        assert!(output.marker_seen);
+        assert_eq!(output.tail.len(), OUTPUT_TAIL_LINES);
+        assert_eq!(output.tail.front().map(String::as_str), Some("line 5"));
+    }
+}
@@ -1,4 +1,8 @@
 //! The *daemon* crate manages a headless Factorio server.
 
 pub mod factorio_server;
-pub use factorio_server::FactorioServer;
+mod game_process;
+pub use factorio_server::{FactorioServer, MapPreviewOptions};
+
+pub mod new_save;
+pub use new_save::{InvalidSaveNameError, NewSaveOptions, Overwrite};
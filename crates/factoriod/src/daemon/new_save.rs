@@ -0,0 +1,132 @@
+//! Options for creating a new save with [`FactorioServer::new_save`](super::FactorioServer::new_save).
+
+use std::error::Error;
+use std::fmt;
+
+use factoriod_config::{MapAndDifficultySettings, MapGenSettings, MapPreset};
+
+/// What to do when a save with the same name already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Overwrite {
+    /// Fail without touching the existing save.
+    #[default]
+    Error,
+
+    /// Replace the existing save once the new save is created. If creating it fails, the existing save is kept.
+    Replace,
+
+    /// Move the existing save to a backup next to it, like `my-map.zip.bak`, then create the new save.
+    Backup,
+}
+
+/// An error returned for a save name that cannot be used as the file name of a save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSaveNameError(pub String);
+
+impl fmt::Display for InvalidSaveNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid save name {:?}, it must be a file name without path separators", self.0)
+    }
+}
+
+impl Error for InvalidSaveNameError {}
+
+/// How to create a new save. By default, the map settings are layered from the built-in defaults and the
+/// configuration directory.
+///
+/// # Examples
+/// ```
+/// use factoriod::daemon::{NewSaveOptions, Overwrite};
+/// use factoriod_config::MapPreset;
+/// let options = NewSaveOptions::new("my-map")
+///     .with_preset(MapPreset::RailWorld)
+///     .with_seed(1234)
+///     .with_overwrite(Overwrite::Backup);
+///
+/// assert_eq!(options.file_name().unwrap(), "my-map.zip");
+/// assert!(NewSaveOptions::new("../my-map").file_name().is_err());
+/// ```
+#[derive(Debug, Default)]
+pub struct NewSaveOptions {
+    pub(super) name: String,
+    pub(super) preset: Option<MapPreset>,
+    pub(super) seed: Option<u32>,
+    pub(super) map_gen_settings: Option<MapGenSettings>,
+    pub(super) map_settings: Option<MapAndDifficultySettings>,
+    pub(super) overwrite: Overwrite,
+}
+
+impl NewSaveOptions {
+    /// Options for the save `name`, with or without its `.zip` extension.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Layer the map settings of `preset` between the built-in defaults and the configuration directory.
+    pub fn with_preset(mut self, preset: MapPreset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Use `seed` for the map, overriding the seed of the map generation settings.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Use `settings` instead of the map generation settings of the configuration directory and the preset.
+    pub fn with_map_gen_settings(mut self, settings: MapGenSettings) -> Self {
+        self.map_gen_settings = Some(settings);
+        self
+    }
+
+    /// Use `settings` instead of the map settings of the configuration directory and the preset.
+    pub fn with_map_settings(mut self, settings: MapAndDifficultySettings) -> Self {
+        self.map_settings = Some(settings);
+        self
+    }
+
+    /// What to do if the save already exists. Defaults to [`Overwrite::Error`].
+    pub fn with_overwrite(mut self, overwrite: Overwrite) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// The file name of the save in the saves directory, like `my-map.zip`.
+    ///
+    /// # Errors
+    /// If the name is empty, is `.` or `..`, or contains a path separator or NUL.
+    pub fn file_name(&self) -> Result<String, InvalidSaveNameError> {
+        let stem = self.name.strip_suffix(".zip").unwrap_or(&self.name);
+        let invalid = stem.is_empty() || stem == "." || stem == ".." || stem.contains(['/', '\\', '\0']);
+
+        if invalid {
+            return Err(InvalidSaveNameError(self.name.clone()));
+        }
+
+        Ok(format!("{}.zip", stem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(NewSaveOptions::new("my-map").file_name(), Ok("my-map.zip".to_owned()));
+        assert_eq!(NewSaveOptions::new("my-map.zip").file_name(), Ok("my-map.zip".to_owned()));
+        assert_eq!(NewSaveOptions::new("my map (2)").file_name(), Ok("my map (2).zip".to_owned()));
+        for name in ["", ".zip", "..", "a/b", "a\\b", "/etc/passwd", "nul\0"] {
+            assert_eq!(
+                NewSaveOptions::new(name).file_name(),
+                Err(InvalidSaveNameError(name.to_owned())),
+                "{:?}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Exploration of map seeds: previews of a new map are generated for many seeds in parallel, with the quantities of
+//! resources the game reports for each, and indexed in a JSON file and an HTML page to browse them.
+//!
+//! See [`FactorioServer::explore_seeds`](crate::daemon::FactorioServer::explore_seeds).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::daemon::MapPreviewOptions;
+
+/// The prototypes whose quantities are reported by default: the resources of the base game.
+pub const DEFAULT_REPORT_QUANTITIES: [&str; 6] =
+    ["iron-ore", "copper-ore", "coal", "stone", "uranium-ore", "crude-oil"];
+
+/// The most seeds a list of seeds may have, so that a typo like `1-1000000` does not start a million games.
+pub const MAX_SEEDS: usize = 10_000;
+
+/// A list of distinct seeds in ascending order, parsed from comma-separated seeds and inclusive ranges, like `1-10,42`.
+///
+/// # Examples
+/// ```
+/// use factoriod::explore::Seeds;
+/// let seeds: Seeds = "42,1-3,2".parse().unwrap();
+/// assert_eq!(seeds.0, [1, 2, 3, 42]);
+/// assert!("3-1".parse::<Seeds>().is_err());
+/// assert!("1-100000".parse::<Seeds>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seeds(pub Vec<u32>);
+
+/// An error returned when parsing an invalid list of seeds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseSeedsError {
+    /// The list is not made of seeds and ranges.
+    Invalid(String),
+
+    /// The list has more than [`MAX_SEEDS`] seeds.
+    TooMany(String),
+}
+
+impl fmt::Display for ParseSeedsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseSeedsError::Invalid(seeds) => {
+                write!(f, "invalid seeds {}, expected seeds and ranges like 1-10,42", seeds)
+            },
+            ParseSeedsError::TooMany(seeds) => write!(f, "seeds {} are more than {} seeds", seeds, MAX_SEEDS),
+        }
+    }
+}
+
+impl Error for ParseSeedsError {}
+
+impl FromStr for Seeds {
+    type Err = ParseSeedsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseSeedsError::Invalid(s.to_owned());
+        let mut seeds = BTreeSet::new();
+        for part in s.split(',').map(str::trim) {
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let start: u32 = start.trim().parse().map_err(|_| error())?;
+                    let end: u32 = end.trim().parse().map_err(|_| error())?;
+                    if start > end {
+                        return Err(error());
+                    }
+
+                    // checked before expanding the range, which may have billions of seeds
+                    if usize::try_from(end - start).map_or(true, |count| count >= MAX_SEEDS) {
+                        return Err(ParseSeedsError::TooMany(s.to_owned()));
+                    }
+
+                    seeds.extend(start..=end);
+                },
+                None => {
+                    seeds.insert(part.parse().map_err(|_| error())?);
+                },
+            }
+
+            if seeds.len() > MAX_SEEDS {
+                return Err(ParseSeedsError::TooMany(s.to_owned()));
+            }
+        }
+
+        Ok(Seeds(seeds.into_iter().collect()))
+    }
+}
+
+/// What to explore with [`FactorioServer::explore_seeds`](crate::daemon::FactorioServer::explore_seeds).
+#[derive(Debug, Clone)]
+pub struct SeedExploration {
+    /// The options of every preview. The seed is ignored.
+    pub preview: MapPreviewOptions,
+
+    /// The seeds to generate previews for.
+    pub seeds: Vec<u32>,
+
+    /// How many previews are generated at once.
+    pub jobs: usize,
+
+    /// The prototypes whose quantities in the preview area are reported, like `iron-ore`.
+    pub report_quantities: Vec<String>,
+}
+
+/// The preview of a single seed.
+#[derive(Debug, Clone, Serialize)]
+pub struct SeedPreview {
+    pub seed: u32,
+
+    /// The file name of the preview, in the same directory as the index.
+    pub preview: String,
+
+    /// How long the game took to generate the preview, in seconds.
+    pub seconds: f64,
+
+    /// The approximate quantities of the reported prototypes in the preview area.
+    pub quantities: BTreeMap<String, f64>,
+
+    /// Why the preview could not be generated, if it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SeedPreview {
+    pub fn new(seed: u32, preview: String, duration: Duration) -> Self {
+        Self {
+            seed,
+            preview,
+            seconds: duration.as_secs_f64(),
+            quantities: BTreeMap::new(),
+            error: None,
+        }
+    }
+}
+
+/// The index of an exploration, written to `index.json` next to the previews.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplorationIndex {
+    /// The preset the map generation settings were layered over, if any.
+    pub preset: Option<String>,
+    pub previews: Vec<SeedPreview>,
+}
+
+/// Find the quantity of each of `names` in the output of the game, from lines like `iron-ore: 123456`, which may start
+/// with the game's timestamp. Names that are not reported are left out.
+///
+/// # Examples
+/// ```
+/// use factoriod::explore::parse_quantities;
+/// let output = "   1.234 Generating map preview\n   2.345 iron-ore: 1234.5\ncoal: 42\n";
+/// let quantities = parse_quantities(output, &["iron-ore".to_owned(), "coal".to_owned(), "stone".to_owned()]);
+/// assert_eq!(quantities.len(), 2);
+/// assert_eq!(quantities["iron-ore"], 1234.5);
+/// assert_eq!(quantities["coal"], 42.0);
+/// ```
+pub fn parse_quantities(output: &str, names: &[String]) -> BTreeMap<String, f64> {
+    let mut quantities = BTreeMap::new();
+    for line in output.lines() {
+        // the name is the first word after the timestamp, so a line that only mentions it is not a quantity
+        let rest = line.trim_start().trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let rest = rest.trim_start();
+        for name in names {
+            let Some(rest) = rest.strip_prefix(name.as_str()).and_then(|rest| rest.strip_prefix(':')) else {
+                continue;
+            };
+
+            let rest = rest.trim_start();
+            let number: String = rest.chars().take_while(|c| c.is_ascii_digit() || matches!(c, '.' | ',')).collect();
+            if let Ok(quantity) = number.replace(',', "").parse() {
+                quantities.insert(name.clone(), quantity);
+            }
+        }
+    }
+
+    quantities
+}
+
+/// Call `f` on each of `items` on at most `jobs` threads, returning the results in the order of `items`.
+pub fn run_bounded<T, R, F>(items: Vec<T>, jobs: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let count = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(Vec::with_capacity(count));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, count.max(1)) {
+            scope.spawn(|| loop {
+                // the lock is released before the item is processed, so workers run concurrently
+                let next = queue.lock().expect("queue lock poisoned").next();
+                let Some((i, item)) = next else {
+                    break;
+                };
+
+                let result = f(item);
+                results.lock().expect("results lock poisoned").push((i, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().expect("results lock poisoned");
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Render `index` as an HTML page with a card for each preview.
+fn index_html(index: &ExplorationIndex) -> String {
+    let title = format!("Seeds of {}", index.preset.as_deref().unwrap_or("the map generation settings"));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ font-family: sans-serif; display: flex; flex-wrap: wrap; gap: 1em; }}\n\
+         figure {{ margin: 0; width: 320px; }}\n\
+         img {{ width: 100%; }}\n\
+         </style>\n</head>\n<body>\n",
+        escape_html(&title)
+    );
+
+    for preview in &index.previews {
+        let mut caption = format!("<b>{}</b>", preview.seed);
+        match &preview.error {
+            Some(error) => caption.push_str(&format!("<br>{}", escape_html(error))),
+            None => {
+                for (name, quantity) in &preview.quantities {
+                    caption.push_str(&format!("<br>{}: {}", escape_html(name), quantity));
+                }
+            },
+        }
+
+        let file = escape_html(&preview.preview);
+        html.push_str(&format!(
+            "<figure>\n<a href=\"{0}\"><img src=\"{0}\" loading=\"lazy\"></a>\n\
+             <figcaption>{1}</figcaption>\n</figure>\n",
+            file, caption
+        ));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Write `index` to `index.json` and `index.html` in `dir`, returning the path of the HTML page.
+pub fn write_index(dir: &Path, index: &ExplorationIndex) -> std::io::Result<PathBuf> {
+    let json = serde_json::to_vec_pretty(index).map_err(std::io::Error::from)?;
+    factoriod_config::write_atomic(dir.join("index.json"), &json)?;
+    let html = dir.join("index.html");
+    factoriod_config::write_atomic(&html, index_html(index).as_bytes())?;
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeds() {
+        assert_eq!("7".parse(), Ok(Seeds(vec![7])));
+        assert_eq!(" 1 - 2 , 5".parse(), Ok(Seeds(vec![1, 2, 5])));
+        assert!("".parse::<Seeds>().is_err());
+        assert!("a-b".parse::<Seeds>().is_err());
+        assert!("-1".parse::<Seeds>().is_err());
+        assert_eq!("3,1-3,3".parse(), Ok(Seeds(vec![1, 2, 3])));
+
+        let max = u32::try_from(MAX_SEEDS).unwrap();
+        assert_eq!(format!("1-{}", max).parse::<Seeds>().unwrap().0.len(), MAX_SEEDS);
+        assert!(matches!(format!("0-{}", max).parse::<Seeds>(), Err(ParseSeedsError::TooMany(_))));
+        assert!(matches!("0-4294967295".parse::<Seeds>(), Err(ParseSeedsError::TooMany(_))));
+        assert!(matches!(format!("1-{},0", max).parse::<Seeds>(), Err(ParseSeedsError::TooMany(_))));
+    }
+
+    #[test]
+    fn test_parse_quantities() {
+        let names = ["iron-ore".to_owned(), "coal".to_owned()];
+        let output = "0.5 Checking iron-ore: 7 prototypes\n1.5 iron-ore:12,345\ncoal 42\n  2 coal: 1.5\n";
+        let quantities = parse_quantities(output, &names);
+        assert_eq!(quantities.len(), 2);
+        assert_eq!(quantities["iron-ore"], 12345.0);
+        assert_eq!(quantities["coal"], 1.5);
+    }
+
+    #[test]
+    fn test_run_bounded() {
+        let running = std::sync::atomic::AtomicUsize::new(0);
+        let max = std::sync::atomic::AtomicUsize::new(0);
+        let results = run_bounded((0..20).collect(), 3, |i: u32| {
+            use std::sync::atomic::Ordering;
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            i * 2
+        });
+
+        assert_eq!(results, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(max.into_inner() <= 3);
+        assert!(run_bounded(Vec::<u32>::new(), 3, |i| i).is_empty());
+    }
+
+    #[test]
+    fn test_write_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut preview = SeedPreview::new(42, "42.png".to_owned(), Duration::from_millis(1500));
+        preview.quantities.insert("iron-ore".to_owned(), 1000.0);
+        let failed = SeedPreview {
+            error: Some("exited with <signal>".to_owned()),
+            ..SeedPreview::new(43, "43.png".to_owned(), Duration::ZERO)
+        };
+
+        let index = ExplorationIndex {
+            preset: Some("rail-world".to_owned()),
+            previews: vec![preview, failed],
+        };
+
+        let html = std::fs::read_to_string(write_index(dir.path(), &index).unwrap()).unwrap();
+        assert!(html.contains("<img src=\"42.png\""), "{}", html);
+        assert!(html.contains("iron-ore: 1000"), "{}", html);
+        assert!(html.contains("exited with &lt;signal&gt;"), "{}", html);
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("index.json")).unwrap()).unwrap();
+        assert_eq!(json["previews"][0]["seconds"], 1.5);
+        assert!(json["previews"][0].get("error").is_none());
+    }
+}
@@ -0,0 +1,409 @@
+//! Named server instances, so that a host can run several worlds with the `factoriod@.service` template unit.
+//!
+//! Each instance has its own directories under those of the service, like `/etc/factoriod/instances/{name}/` for its
+//! configuration and `/var/lib/factoriod/instances/{name}/` for its saves and mods, while every instance shares the
+//! game downloaded to `/var/cache/factoriod/`. The configuration directory of an instance has an `instance.json` with
+//! the ports allocated to it when it was created, so that instances never collide.
+//!
+//! Since instances share the game, each runs it with its own game config, which reads the game's data but writes to the
+//! instance's state directory. Otherwise, the game would lock the same write data directory for every instance.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::Read;
+use std::net::{TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::GAME_CONFIG_FILE;
+
+/// The name of the directory of instances in each of the service's directories.
+pub const INSTANCES_DIR: &str = "instances";
+
+/// The name of the file in the configuration directory of an instance with its ports.
+pub const INSTANCE_FILE: &str = "instance.json";
+
+/// The name of the file in the state directory of an instance with its RCON password.
+pub const RCON_PASSWORD_FILE: &str = "rcon-password";
+
+/// The game's default port, used by the server of the `factoriod.service` unit.
+pub const DEFAULT_PORT: u16 = 34197;
+
+/// The conventional RCON port, left to the server of the `factoriod.service` unit.
+pub const DEFAULT_RCON_PORT: u16 = 27015;
+
+/// An error returned when an instance cannot be created or read.
+#[derive(Debug)]
+pub enum InstanceError {
+    /// The name cannot be used for the directories and the unit of an instance.
+    InvalidName(String),
+
+    /// An instance with the name already exists.
+    Exists(String),
+
+    /// There is no instance with the name.
+    NotFound(String),
+
+    /// Every port above the defaults is taken.
+    NoFreePort,
+
+    /// A file of an instance could not be read or written.
+    Io { path: PathBuf, source: std::io::Error },
+
+    /// The `instance.json` of an instance is not valid.
+    Parse { path: PathBuf, source: serde_json::Error },
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::InvalidName(name) => write!(
+                f,
+                "invalid instance name {:?}, expected letters, digits, '-' and '_', starting with a letter or digit",
+                name
+            ),
+            InstanceError::Exists(name) => write!(f, "the instance {} already exists", name),
+            InstanceError::NotFound(name) => {
+                write!(f, "no instance {}, create it with `factoriod instance create {}`", name, name)
+            },
+            InstanceError::NoFreePort => write!(f, "no free port is left for a new instance"),
+            InstanceError::Io { path, source } => write!(f, "failed to access {}: {}", path.display(), source),
+            InstanceError::Parse { path, source } => write!(f, "failed to parse {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for InstanceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InstanceError::Io { source, .. } => Some(source),
+            InstanceError::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Check that `name` can be used for an instance: letters, digits, `-` and `_`, starting with a letter or digit, and
+/// at most 64 characters. These are safe in paths and in the name of a systemd unit without escaping.
+///
+/// # Examples
+/// ```
+/// use factoriod::instances::validate_name;
+/// assert!(validate_name("my-world_2").is_ok());
+/// assert!(validate_name("../etc").is_err());
+/// assert!(validate_name("-rf").is_err());
+/// ```
+pub fn validate_name(name: &str) -> Result<(), InstanceError> {
+    let valid = name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(InstanceError::InvalidName(name.to_owned()))
+    }
+}
+
+/// The ports of an instance, in its `instance.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstancePorts {
+    /// The UDP port players connect to, passed with `--port`.
+    pub port: u16,
+
+    /// The TCP port of the RCON interface, passed with `--rcon-port`.
+    pub rcon_port: u16,
+}
+
+/// A named server instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    pub name: String,
+    pub ports: InstancePorts,
+}
+
+impl Instance {
+    /// Read the instance `name` from its configuration directory `config_dir`.
+    pub fn load(name: &str, config_dir: &Path) -> Result<Self, InstanceError> {
+        validate_name(name)?;
+        let path = config_dir.join(INSTANCE_FILE);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(InstanceError::NotFound(name.to_owned())),
+            Err(source) => return Err(InstanceError::Io { path, source }),
+        };
+
+        let ports = serde_json::from_slice(&contents).map_err(|source| InstanceError::Parse { path, source })?;
+        Ok(Self {
+            name: name.to_owned(),
+            ports,
+        })
+    }
+
+    /// The RCON password of this instance, generated in `state_dir` on first use.
+    pub fn rcon_password(&self, state_dir: &Path) -> Result<String, InstanceError> {
+        std::fs::create_dir_all(state_dir).map_err(io_error(state_dir))?;
+        let password_path = state_dir.join(RCON_PASSWORD_FILE);
+        match std::fs::read_to_string(&password_path) {
+            Ok(password) => Ok(password.trim().to_owned()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("Generating an RCON password for instance {} in {}", self.name, password_path.display());
+                let password = random_password().map_err(io_error(Path::new("/dev/urandom")))?;
+                crate::config::write_private(&password_path, password.as_bytes()).map_err(io_error(&password_path))?;
+                Ok(password)
+            },
+            Err(source) => Err(InstanceError::Io {
+                path: password_path,
+                source,
+            }),
+        }
+    }
+
+    /// The options of the game for this instance: its ports, its RCON password, and a game config that writes to
+    /// `state_dir` and reads the data of the game in `factorio_dir`. The password and the config are created in
+    /// `state_dir` as needed.
+    pub fn game_options(&self, factorio_dir: &Path, state_dir: &Path) -> Result<Vec<OsString>, InstanceError> {
+        let password = self.rcon_password(state_dir)?;
+        let game_config = crate::config::write_game_config(factorio_dir, state_dir)
+            .map_err(io_error(&state_dir.join(GAME_CONFIG_FILE)))?;
+
+        Ok(vec![
+            "--port".into(),
+            self.ports.port.to_string().into(),
+            "--rcon-port".into(),
+            self.ports.rcon_port.to_string().into(),
+            "--rcon-password".into(),
+            password.into(),
+            "--config".into(),
+            game_config.into(),
+        ])
+    }
+}
+
+/// Map an IO error on `path` to an [`InstanceError`].
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> InstanceError {
+    let path = path.to_owned();
+    move |source| InstanceError::Io { path, source }
+}
+
+/// 32 random hex digits.
+fn random_password() -> std::io::Result<String> {
+    let mut bytes = [0; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// The directories of the service, under which each instance has its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceRoots {
+    /// The service's configuration directory, like `/etc/factoriod`.
+    pub config_dir: PathBuf,
+
+    /// The service's state directory, like `/var/lib/factoriod`.
+    pub state_dir: PathBuf,
+
+    /// The service's runtime directory, like `/run/factoriod`.
+    pub runtime_dir: PathBuf,
+}
+
+impl Default for InstanceRoots {
+    fn default() -> Self {
+        Self {
+            config_dir: PathBuf::from("/etc/factoriod"),
+            state_dir: PathBuf::from("/var/lib/factoriod"),
+            runtime_dir: PathBuf::from("/run/factoriod"),
+        }
+    }
+}
+
+impl InstanceRoots {
+    /// The configuration directory of the instance `name`.
+    pub fn config_dir(&self, name: &str) -> PathBuf {
+        self.config_dir.join(INSTANCES_DIR).join(name)
+    }
+
+    /// The state directory of the instance `name`.
+    pub fn state_dir(&self, name: &str) -> PathBuf {
+        self.state_dir.join(INSTANCES_DIR).join(name)
+    }
+
+    /// The runtime directory of the instance `name`.
+    pub fn runtime_dir(&self, name: &str) -> PathBuf {
+        self.runtime_dir.join(INSTANCES_DIR).join(name)
+    }
+
+    /// Read the instance `name`.
+    pub fn load(&self, name: &str) -> Result<Instance, InstanceError> {
+        validate_name(name)?;
+        Instance::load(name, &self.config_dir(name))
+    }
+
+    /// Every instance, sorted by name. Directories without a valid `instance.json` are skipped.
+    pub fn list(&self) -> Result<Vec<Instance>, InstanceError> {
+        let dir = self.config_dir.join(INSTANCES_DIR);
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => return Err(InstanceError::Io { path: dir, source }),
+        };
+
+        let mut instances: Vec<_> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|name| match self.load(&name) {
+                Ok(instance) => Some(instance),
+                Err(e) => {
+                    tracing::warn!("skipping {}: {}", dir.join(&name).display(), e);
+                    None
+                },
+            })
+            .collect();
+
+        instances.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(instances)
+    }
+
+    /// Create the instance `name` with ports that no other instance uses and that are free on this host, writing its
+    /// `instance.json`. The state and runtime directories are left to systemd, which creates them for the unit.
+    pub fn create(&self, name: &str) -> Result<Instance, InstanceError> {
+        validate_name(name)?;
+        let config_dir = self.config_dir(name);
+        if config_dir.join(INSTANCE_FILE).exists() {
+            return Err(InstanceError::Exists(name.to_owned()));
+        }
+
+        let taken = self.list()?;
+        let port = first_free(DEFAULT_PORT, |port| {
+            taken.iter().any(|instance| instance.ports.port == port) || UdpSocket::bind(("0.0.0.0", port)).is_err()
+        })
+        .ok_or(InstanceError::NoFreePort)?;
+
+        let rcon_port = first_free(DEFAULT_RCON_PORT, |port| {
+            taken.iter().any(|instance| instance.ports.rcon_port == port)
+                || TcpListener::bind(("0.0.0.0", port)).is_err()
+        })
+        .ok_or(InstanceError::NoFreePort)?;
+
+        let instance = Instance {
+            name: name.to_owned(),
+            ports: InstancePorts { port, rcon_port },
+        };
+
+        let path = config_dir.join(INSTANCE_FILE);
+        std::fs::create_dir_all(&config_dir)
+            .and_then(|()| serde_json::to_vec_pretty(&instance.ports).map_err(std::io::Error::from))
+            .and_then(|contents| factoriod_config::write_atomic(&path, &contents))
+            .map_err(|source| InstanceError::Io { path, source })?;
+
+        Ok(instance)
+    }
+}
+
+/// The first port after `default` that is not `taken`. The default itself is left to the `factoriod.service` unit.
+fn first_free(default: u16, taken: impl Fn(u16) -> bool) -> Option<u16> {
+    (default.checked_add(1)?..=u16::MAX).find(|&port| !taken(port))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn roots(dir: &Path) -> InstanceRoots {
+        InstanceRoots {
+            config_dir: dir.join("etc"),
+            state_dir: dir.join("lib"),
+            runtime_dir: dir.join("run"),
+        }
+    }
+
+    #[test]
+    fn test_validate_name() {
+        for name in ["a", "my-world", "World_2", "0"] {
+            assert!(validate_name(name).is_ok(), "{}", name);
+        }
+
+        for name in ["", "-a", "_a", "a/b", "..", "a b", "a.b", "é", &"a".repeat(65)] {
+            assert!(matches!(validate_name(name), Err(InstanceError::InvalidName(_))), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_first_free() {
+        assert_eq!(first_free(10, |_| false), Some(11));
+        assert_eq!(first_free(10, |port| port < 13), Some(13));
+        assert_eq!(first_free(u16::MAX - 1, |_| true), None);
+        assert_eq!(first_free(u16::MAX, |_| false), None);
+    }
+
+    #[test]
+    fn test_create_and_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = roots(dir.path());
+        assert!(roots.list().unwrap().is_empty());
+
+        let a = roots.create("a").unwrap();
+        let b = roots.create("b").unwrap();
+        assert!(a.ports.port > DEFAULT_PORT && a.ports.rcon_port > DEFAULT_RCON_PORT);
+        assert_ne!(a.ports.port, b.ports.port);
+        assert_ne!(a.ports.rcon_port, b.ports.rcon_port);
+        assert!(matches!(roots.create("a"), Err(InstanceError::Exists(_))));
+        assert!(matches!(roots.create("../a"), Err(InstanceError::InvalidName(_))));
+
+        // a directory without an instance.json is not an instance
+        std::fs::create_dir_all(roots.config_dir("c")).unwrap();
+        assert_eq!(roots.list().unwrap(), [a.clone(), b]);
+        assert_eq!(roots.load("a").unwrap(), a);
+        assert!(matches!(roots.load("c"), Err(InstanceError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_game_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = roots(dir.path());
+        let instance = roots.create("a").unwrap();
+        let state_dir = roots.state_dir("a");
+        let factorio_dir = dir.path().join("factorio");
+        let options = instance.game_options(&factorio_dir, &state_dir).unwrap();
+        let password = instance.rcon_password(&state_dir).unwrap();
+        assert_eq!(
+            options,
+            [
+                "--port".into(),
+                instance.ports.port.to_string().into(),
+                "--rcon-port".into(),
+                instance.ports.rcon_port.to_string().into(),
+                "--rcon-password".into(),
+                OsString::from(&password),
+                "--config".into(),
+                state_dir.join(GAME_CONFIG_FILE).into_os_string(),
+            ]
+        );
+
+        let game_config = std::fs::read_to_string(state_dir.join(GAME_CONFIG_FILE)).unwrap();
+        assert!(game_config.contains(&format!("write-data={}\n", state_dir.display())), "{}", game_config);
+
+        // the password is kept across starts
+        assert_eq!(instance.game_options(&factorio_dir, &state_dir).unwrap(), options);
+    }
+
+    #[test]
+    fn test_rcon_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = roots(dir.path());
+        let instance = roots.create("a").unwrap();
+        let state_dir = roots.state_dir("a");
+        let password = instance.rcon_password(&state_dir).unwrap();
+        assert_eq!(password.len(), 32);
+
+        let path = state_dir.join(RCON_PASSWORD_FILE);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), password);
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // the password is kept across starts
+        assert_eq!(instance.rcon_password(&state_dir).unwrap(), password);
+    }
+}
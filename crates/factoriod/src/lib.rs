@@ -4,8 +4,17 @@ pub use utils::*;
 use factorio_http_api::download;
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter, FmtSubscriber};
 
+pub mod config;
+pub mod credentials;
 pub mod daemon;
+pub mod explore;
+pub mod instances;
+pub mod listing;
+pub mod mods;
+pub mod save;
+pub mod scenarios;
 mod server_opts;
+pub mod state;
 mod utils;
 
 /// Set up tracing for the application. This will log all traces to the console. It additionall sets the log level for
@@ -0,0 +1,354 @@
+//! Verifying that a public server appears in the public game list.
+//!
+//! The game registers public servers with the matchmaking server shortly after the save is loaded. Registration can
+//! fail silently, for example when the factorio.com credentials in `server-settings.json` are wrong, so the listing is
+//! checked from the outside with the [matchmaking API](factorio_http_api::matchmaking).
+//!
+//! The result of the last check is kept in `listing.json` in the state directory, see [`ListingReport`].
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use factorio_http_api::matchmaking::{self, Game};
+use factorio_http_api::Credentials;
+use factoriod_config::Visibility;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::config::ConfigFile;
+
+/// The parts of `server-settings.json` that determine how a server is listed.
+#[derive(Deserialize, Debug, Default)]
+pub struct ListingSettings {
+    /// The name of the game in the game list.
+    pub name: String,
+
+    /// Whether the game is published to the game list.
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+/// Read the listing settings from the server settings in `config_dir`. Returns [`None`] if there are none.
+pub fn read_listing_settings<P: AsRef<Path>>(config_dir: P) -> Result<Option<ListingSettings>, Box<dyn Error>> {
+    match crate::config::read_layered(config_dir.as_ref(), ConfigFile::ServerSettings)? {
+        Some(layered) => Ok(Some(layered.deserialize()?)),
+        None => Ok(None),
+    }
+}
+
+/// The name of the file in the state directory with the result of the last check.
+pub const LISTING_FILE: &str = "listing.json";
+
+/// How many times to check the public game list for the server after it starts.
+pub const LISTING_ATTEMPTS: u32 = 10;
+
+/// How long to wait between checks of the public game list.
+pub const LISTING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The games in `games` named `name` whose address has the port `port`. Other servers can use the same name, but not
+/// the same name on the same port.
+pub fn matching_games<'a>(games: &'a [Game], name: &str, port: u16) -> Vec<&'a Game> {
+    games
+        .iter()
+        .filter(|game| game.name == name)
+        .filter(|game| {
+            let game_port = game.host_address.as_deref().and_then(|address| address.rsplit_once(':'));
+            game_port.and_then(|(_, game_port)| game_port.parse().ok()) == Some(port)
+        })
+        .collect()
+}
+
+/// Check the game list for a game named `name` on `port` every `interval`, up to `attempts` times. Returns the matching
+/// games once at least one is listed, or an empty list if none was found.
+#[tracing::instrument(skip(credentials))]
+pub fn wait_for_listing(
+    credentials: &Credentials,
+    name: &str,
+    port: u16,
+    attempts: u32,
+    interval: Duration,
+) -> Result<Vec<Game>, Box<dyn Error>> {
+    for attempt in 1..=attempts {
+        std::thread::sleep(interval);
+        let games = matchmaking::get_games(credentials)?;
+        let matching = matching_games(&games, name, port);
+        if !matching.is_empty() {
+            return Ok(matching.into_iter().cloned().collect());
+        }
+
+        debug!("{} is not listed yet (attempt {} of {})", name, attempt, attempts);
+    }
+
+    Ok(Vec::new())
+}
+
+/// The result of checking the game list for the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ListingStatus {
+    /// The game list is being checked.
+    Checking,
+
+    /// The server is not public, so the game list is not checked.
+    NotPublic,
+
+    /// There are no factorio.com credentials to read the game list with.
+    NoCredentials,
+
+    /// The server is in the game list.
+    Listed { games: Vec<ListedGame> },
+
+    /// The server did not appear in the game list.
+    NotListed,
+
+    /// The game list or the server settings could not be read.
+    Failed { error: String },
+}
+
+/// A game of the server in the game list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListedGame {
+    pub game_id: u64,
+    pub host_address: Option<String>,
+}
+
+impl fmt::Display for ListingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListingStatus::Checking => write!(f, "checking the public game list"),
+            ListingStatus::NotPublic => write!(f, "not public, the game list is not checked"),
+            ListingStatus::NoCredentials => write!(f, "no factorio.com credentials, the game list is not checked"),
+            ListingStatus::Listed { games } => {
+                write!(f, "listed")?;
+                for game in games {
+                    let address = game.host_address.as_deref().unwrap_or("an unknown address");
+                    write!(f, "\n    game {} at {}", game.game_id, address)?;
+                }
+
+                Ok(())
+            },
+            ListingStatus::NotListed => write!(f, "not in the public game list"),
+            ListingStatus::Failed { error } => write!(f, "failed to check the public game list: {}", error),
+        }
+    }
+}
+
+/// The result of the last check of the game list, in `listing.json` in the state directory.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListingReport {
+    /// The name of the server, if its settings could be read.
+    pub name: Option<String>,
+
+    /// The port the server was expected on.
+    pub port: u16,
+
+    /// When the status was written, in seconds since the Unix epoch.
+    pub updated_at: u64,
+
+    #[serde(flatten)]
+    pub status: ListingStatus,
+}
+
+impl ListingReport {
+    fn new(name: Option<&str>, port: u16, status: ListingStatus) -> Self {
+        Self {
+            name: name.map(str::to_owned),
+            port,
+            updated_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs()),
+            status,
+        }
+    }
+
+    /// Read the last report from `state_dir`, or [`None`] if the game list was never checked.
+    pub fn load(state_dir: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        match std::fs::read(state_dir.join(LISTING_FILE)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the report to `state_dir`. Failures are logged, since they must not stop the server.
+    fn save(&self, state_dir: &Path) {
+        let path = state_dir.join(LISTING_FILE);
+        let written = serde_json::to_vec_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| factoriod_config::write_atomic(&path, &contents));
+
+        if let Err(e) = written {
+            warn!("failed to write {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Verify that the server configured in `config_dir` appears in the game list on `port`, logging the result and
+/// writing it to `listing.json` in `state_dir`. Servers that are not public, or without factorio.com credentials, are
+/// not checked.
+#[tracing::instrument]
+pub fn verify_listing(
+    config_dir: &Path,
+    state_dir: &Path,
+    port: u16,
+    attempts: u32,
+    interval: Duration,
+) -> ListingStatus {
+    let (name, status) = check_listing(config_dir, port, attempts, interval, |name| {
+        ListingReport::new(Some(name), port, ListingStatus::Checking).save(state_dir);
+    });
+
+    ListingReport::new(name.as_deref(), port, status.clone()).save(state_dir);
+    status
+}
+
+/// Check the game list as [`verify_listing`] does, calling `checking` before waiting for the server named in its
+/// settings. Returns that name, if the settings could be read, and the result.
+fn check_listing(
+    config_dir: &Path,
+    port: u16,
+    attempts: u32,
+    interval: Duration,
+    checking: impl FnOnce(&str),
+) -> (Option<String>, ListingStatus) {
+    let settings = match read_listing_settings(config_dir) {
+        Ok(Some(settings)) if settings.visibility.public => settings,
+        Ok(settings) => {
+            debug!("server is not public, the game list will not be checked");
+            return (settings.map(|settings| settings.name), ListingStatus::NotPublic);
+        },
+        Err(e) => {
+            warn!("failed to read server settings, the game list will not be checked: {}", e);
+            return (None, ListingStatus::Failed { error: e.to_string() });
+        },
+    };
+
+    let Some(credentials) = crate::credentials::factorio_credentials() else {
+        debug!("no factorio.com credentials, the game list will not be checked");
+        return (Some(settings.name), ListingStatus::NoCredentials);
+    };
+
+    checking(&settings.name);
+    let status = match wait_for_listing(&credentials, &settings.name, port, attempts, interval) {
+        Ok(games) if games.is_empty() => {
+            warn!("{} does not appear in the public game list on port {}", settings.name, port);
+            ListingStatus::NotListed
+        },
+        Ok(games) => {
+            for game in &games {
+                info!(
+                    "{} is listed as game {} at {}",
+                    game.name,
+                    game.game_id,
+                    game.host_address.as_deref().unwrap_or("an unknown address")
+                );
+            }
+
+            ListingStatus::Listed {
+                games: games
+                    .into_iter()
+                    .map(|game| ListedGame {
+                        game_id: game.game_id,
+                        host_address: game.host_address,
+                    })
+                    .collect(),
+            }
+        },
+        Err(e) => {
+            warn!("failed to check the public game list: {}", e);
+            ListingStatus::Failed { error: e.to_string() }
+        },
+    };
+
+    (Some(settings.name), status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(game_id: u64, name: &str, host_address: Option<&str>) -> Game {
+        serde_json::from_value(serde_json::json!({
+            "application_version": {
+                "build_mode": "headless",
+                "build_version": 60000,
+                "game_version": "1.1.110",
+                "platform": "linux64"
+            },
+            "game_id": game_id,
+            "name": name,
+            "host_address": host_address,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_matching_games() {
+        let games = vec![
+            game(1, "foo", Some("203.0.113.1:34197")),
+            game(2, "bar", Some("203.0.113.1:34197")),
+            game(3, "foo", Some("[2001:db8::1]:34197")),
+            game(4, "foo", Some("198.51.100.7:34198")),
+            game(5, "foo", None),
+        ];
+
+        let matching = matching_games(&games, "foo", 34197);
+        assert_eq!(matching.iter().map(|game| game.game_id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(matching_games(&games, "foo", 34198)[0].game_id, 4);
+        assert!(matching_games(&games, "baz", 34197).is_empty());
+    }
+
+    #[test]
+    fn test_read_listing_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_listing_settings(dir.path()).unwrap().is_none());
+
+        std::fs::write(
+            dir.path().join("server-settings.json"),
+            r#"{"name": "foo", "visibility": {"public": false, "lan": true}, "max_players": 4}"#,
+        )
+        .unwrap();
+
+        let settings = read_listing_settings(dir.path()).unwrap().unwrap();
+        assert_eq!(settings.name, "foo");
+        assert!(!settings.visibility.public);
+    }
+
+    #[test]
+    fn test_verify_listing_not_public() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let state_dir = tempfile::tempdir().unwrap();
+        assert!(ListingReport::load(state_dir.path()).unwrap().is_none());
+
+        let settings = r#"{"name": "foo", "visibility": {"public": false, "lan": true}}"#;
+        std::fs::write(config_dir.path().join("server-settings.json"), settings).unwrap();
+        let status = verify_listing(config_dir.path(), state_dir.path(), 34197, 1, Duration::ZERO);
+        assert_eq!(status, ListingStatus::NotPublic);
+
+        let report = ListingReport::load(state_dir.path()).unwrap().unwrap();
+        assert_eq!(report.status, ListingStatus::NotPublic);
+        assert_eq!(report.name.as_deref(), Some("foo"));
+        assert_eq!(report.port, 34197);
+    }
+
+    #[test]
+    fn test_listing_report_json() {
+        let report = ListingReport {
+            name: Some("foo".to_owned()),
+            port: 34197,
+            updated_at: 1,
+            status: ListingStatus::Listed {
+                games: vec![ListedGame {
+                    game_id: 42,
+                    host_address: Some("203.0.113.1:34197".to_owned()),
+                }],
+            },
+        };
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "listed");
+        assert_eq!(json["games"][0]["game_id"], 42);
+        assert_eq!(serde_json::from_value::<ListingReport>(json).unwrap(), report);
+        assert_eq!(report.status.to_string(), "listed\n    game 42 at 203.0.113.1:34197");
+    }
+}
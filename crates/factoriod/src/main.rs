@@ -2,32 +2,80 @@
 //!
 //! # Features
 //! 1. A file containing the factorio executable's command line options, that can either be sorued with sh or bash, or
-//!     included with the `EnvironmentFile=` option in the systemd unit file.
-//! 2. The latest save file in the state directory is found and used as the server's save file.
+//!    included with the `EnvironmentFile=` option in the systemd unit file.
+//! 2. The latest save file in the state directory is found and used as the server's save file, unless a scenario was
+//!    selected with `factoriod scenario select` and no save has been created from it yet.
 //! 3. The game binaries are downloaded and extracted to the cache directory.
+//! 4. The mods declared in the configuration directory are downloaded to the state directory.
+//! 5. The server settings are combined with secrets from credentials, validated, and written to the runtime directory.
+//!    The server is not started if they are invalid.
+//! 6. Once the server has started, `factoriod listing verify` checks that a public server appears in the public game
+//!    list, and writes the result to the state directory for `factoriod listing status`.
+//!
+//! Subcommands edit the configuration directory instead, such as the server's whitelist, adminlist and banlist. Others
+//! explain where each value of a layered config file came from and how it differs from the defaults, or migrate the
+//! map settings to another version of the game. `factoriod preview` renders an image of a new map to try out map
+//! generation settings and seeds before creating a save, and `factoriod explore` renders many seeds at once.
+//!
+//! With `--instance {name}`, factoriod works on a named instance created with `factoriod instance create` instead, for
+//! the `factoriod@.service` template unit. See [`factoriod::instances`].
 
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use clap::{Parser, Subcommand};
+use factoriod::config::ConfigFile;
+use factoriod::daemon::{FactorioServer, MapPreviewOptions};
+use factoriod::explore::{SeedExploration, Seeds, DEFAULT_REPORT_QUANTITIES};
+use factoriod::instances::{Instance, InstanceRoots, DEFAULT_PORT};
+use factoriod::listing::{self, ListingReport, LISTING_ATTEMPTS, LISTING_INTERVAL};
+use factoriod::state::DaemonState;
 use factoriod::ServerOpts;
+use factoriod_config::{Ban, Banlist, GameVersion, MapPreset, PlayerList};
 use factorio_http_api::download::{self, Build, Distro};
 use systemd_directories::SystemdDirs;
-use tracing::{info, trace};
+use tracing::{error, info, trace};
 
-/// Writes the options for the factoriod systemd service to the `factorio.opts.env` file in the cache directory.
-fn write_opts_env(systemd_dirs: &SystemdDirs) -> Result<(), Box<dyn std::error::Error>> {
-    let opts_env = systemd_dirs.cache_dir()
-        .ok_or("cache dir not found")?
-        .join("factorio.opts.env");
+/// Writes the options for the factoriod systemd service to the `factorio.opts.env` file in the cache directory. The
+/// options of an instance are written to its runtime directory instead, readable only by its owner, since they include
+/// its RCON password. Either way, the game writes to the state directory, see
+/// [`factoriod::config::write_game_config`].
+fn write_opts_env(systemd_dirs: &SystemdDirs, instance: Option<&Instance>) -> Result<(), Box<dyn std::error::Error>> {
+    let cache_dir = systemd_dirs.cache_dir().ok_or("cache dir not found")?;
+    let state_dir = systemd_dirs.state_dir().ok_or("state dir not found")?;
+    let server_opts = ServerOpts::new(systemd_dirs.config_dir(), Some(state_dir))
+        .with_runtime_dir(systemd_dirs.runtime_dir());
+
+    let factorio_dir = cache_dir.join("factorio");
+    match instance {
+        Some(instance) => {
+            let runtime_dir = systemd_dirs.runtime_dir().ok_or("runtime dir not found")?;
+            let server_opts = server_opts.with_args(instance.game_options(&factorio_dir, state_dir)?);
+            let opts_env = runtime_dir.join("factorio.opts.env");
+            info!("Writing server options to {}", opts_env.display());
+            factoriod::config::write_private(&opts_env, server_opts.to_env().as_encoded_bytes())?;
+        },
+        None => {
+            let game_config = factoriod::config::write_game_config(&factorio_dir, state_dir)?;
+            let server_opts = server_opts.with_args([OsString::from("--config"), game_config.into_os_string()]);
+            let opts_env = cache_dir.join("factorio.opts.env");
+            info!("Writing server options to {}", opts_env.display());
+            std::fs::write(&opts_env, server_opts.to_env().as_encoded_bytes())?;
+        },
+    }
 
-    let server_opts = ServerOpts::new(systemd_dirs.config_dir(), systemd_dirs.state_dir());
-    info!("Writing server options to {}", opts_env.display());
-    std::fs::write(&opts_env, server_opts.to_env().as_encoded_bytes())?;
     Ok(())
 }
 
-/// Downloads and extracts the latest headless Factorio server binary to the cache directory.
+/// Downloads and extracts the latest headless Factorio server binary to the cache directory. Instances share the cache
+/// directory, so it is locked until the game is extracted.
 fn acquire_binaries(systemd_dirs: &SystemdDirs) -> Result<(), Box<dyn std::error::Error>> {
     let download_directory = systemd_dirs.cache_dir().ok_or("cache dir not found")?;
+    let lock = std::fs::File::create(download_directory.join(".download.lock"))?;
+    lock.lock()?;
     let scan_tar_xz_paths = || -> Result<Vec<PathBuf>, std::io::Error> {
         Ok(download_directory
             .read_dir()?
@@ -47,10 +95,7 @@ fn acquire_binaries(systemd_dirs: &SystemdDirs) -> Result<(), Box<dyn std::error
                     .and_then(|ext| ext.to_str())
                     == Some("tar")
             })
-            .map(|path| {
-                trace!("Found tar.xz archive: {}", path.display());
-                path
-            })
+            .inspect(|path| trace!("Found tar.xz archive: {}", path.display()))
             .collect())
     };
 
@@ -58,7 +103,7 @@ fn acquire_binaries(systemd_dirs: &SystemdDirs) -> Result<(), Box<dyn std::error
     if tar_xz_paths.is_empty() {
         info!("No compressed binaries found in {}, downloading the latest.", download_directory.display());
         let latest_stable_headless_version = download::latest_stable_headless_version()?;
-        download::download_to(&latest_stable_headless_version, Build::Headless, Distro::Linux64, &download_directory)?;
+        download::download_to(&latest_stable_headless_version, Build::Headless, Distro::Linux64, download_directory)?;
         tar_xz_paths = scan_tar_xz_paths()?;
     }
 
@@ -71,10 +116,623 @@ fn acquire_binaries(systemd_dirs: &SystemdDirs) -> Result<(), Box<dyn std::error
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Synchronizes the `mods` directory in the state directory with the mods declared in the configuration directory.
+fn sync_mods(systemd_dirs: &SystemdDirs) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(config_dir) = systemd_dirs.config_dir() else {
+        info!("No configuration directory, mods will not be managed.");
+        return Ok(());
+    };
+
+    let state_dir = systemd_dirs.state_dir().ok_or("state dir not found")?;
+    let factorio_dir = systemd_dirs.cache_dir().ok_or("cache dir not found")?.join("factorio");
+    let mods_dir = state_dir.join("mods");
+    factoriod::mods::sync_mods(config_dir, &mods_dir, &factorio_dir)?;
+    factoriod::mods::sync_mod_settings(config_dir, &mods_dir, &factorio_dir)
+}
+
+#[derive(Parser)]
+#[command(about)]
+struct Args {
+    /// The configuration directory of the subcommands. Defaults to `$CONFIGURATION_DIRECTORY`, then `/etc/factoriod`,
+    /// or to the instance's with `--instance`. Preparing the server always uses the unit's.
+    #[arg(long, global = true)]
+    config_dir: Option<PathBuf>,
+
+    /// Work on the named instance instead of the default server, like the `factoriod@{name}.service` unit.
+    #[arg(long, global = true)]
+    instance: Option<String>,
+
+    /// Edit the configuration instead of preparing the server to start.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Edit the players allowed to join the server, in `server-whitelist.json`.
+    Whitelist {
+        #[command(subcommand)]
+        action: PlayerListAction,
+    },
+
+    /// Edit the server's admins, in `server-adminlist.json`.
+    Adminlist {
+        #[command(subcommand)]
+        action: PlayerListAction,
+    },
+
+    /// Edit the players banned from the server, in `server-banlist.json`.
+    Banlist {
+        #[command(subcommand)]
+        action: BanlistAction,
+    },
+
+    /// Inspect or migrate the configuration.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// List the scenarios the server can be started from, or select one for its next start.
+    Scenario {
+        #[command(subcommand)]
+        action: ScenarioAction,
+    },
+
+    /// Generate a preview image of a new map from the map generation settings, without creating a save. The path of the
+    /// PNG is printed.
+    Preview {
+        /// The map generation preset to start from, like `rail-world`.
+        #[arg(long)]
+        preset: Option<MapPreset>,
+
+        /// The map seed. Defaults to a random seed.
+        #[arg(long)]
+        seed: Option<u32>,
+
+        /// The width and height of the image, in pixels.
+        #[arg(long)]
+        size: Option<u32>,
+
+        /// How many tiles each pixel covers.
+        #[arg(long)]
+        scale: Option<f64>,
+    },
+
+    /// Generate map previews for many seeds in parallel, indexed with the quantities of resources in each in
+    /// `index.html` and `index.json`. The path of `index.html` is printed.
+    Explore {
+        /// The seeds, as a comma-separated list of seeds and inclusive ranges, like `1-10,42`.
+        seeds: Seeds,
+
+        /// The map generation preset to start from, like `rail-world`.
+        #[arg(long)]
+        preset: Option<MapPreset>,
+
+        /// The width and height of each image, in pixels.
+        #[arg(long)]
+        size: Option<u32>,
+
+        /// How many tiles each pixel covers.
+        #[arg(long)]
+        scale: Option<f64>,
+
+        /// How many previews to generate at once. Defaults to the number of CPUs.
+        #[arg(long)]
+        jobs: Option<usize>,
+
+        /// The prototypes whose quantities are reported, like `iron-ore`. Defaults to the resources of the base game.
+        #[arg(long, value_delimiter = ',')]
+        report_quantities: Option<Vec<String>>,
+    },
+
+    /// Check that the server appears in the public game list, or print the result of the last check.
+    Listing {
+        #[command(subcommand)]
+        action: ListingAction,
+    },
+
+    /// List or create the named server instances, which run side by side with their own ports, configuration and saves.
+    Instance {
+        #[command(subcommand)]
+        action: InstanceAction,
+    },
+
+    /// Print the JSON Schema of a config file, or write all of them to a directory.
+    Schema {
+        /// The config file, like `server-settings` or `map-gen-settings`.
+        #[arg(required_unless_present = "out_dir")]
+        name: Option<String>,
+
+        /// Write every schema to `{name}.schema.json` in this directory.
+        #[arg(long, conflicts_with = "name")]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print each value of the effective config file and the layer it came from. Secrets are redacted.
+    Explain { file: ConfigFile },
+
+    /// Print the fields of the effective config file that differ from the built-in defaults, or from another file.
+    /// Secrets are not compared.
+    Diff {
+        file: ConfigFile,
+
+        /// A config file to compare with instead of the defaults, like the settings of another server. It may be
+        /// partial, and is merged over the defaults.
+        other: Option<PathBuf>,
+    },
+
+    /// Rewrite the map settings for another version of the game, dropping obsolete fields and renaming others. Each
+    /// changed file is backed up next to it first.
+    Migrate {
+        /// The version of the game to migrate to, like `2.0`.
+        #[arg(long)]
+        to: GameVersion,
+
+        /// Print the changes without writing any file.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScenarioAction {
+    /// Print the scenarios of the game, the mods, and the `scenarios` directory of the state directory.
+    List,
+
+    /// Start the server from a new map of the scenario, like `base/pvp`, when it next starts. Once the game saves the
+    /// map, restarts continue the save.
+    Select { id: String },
+
+    /// Forget the selected scenario, so that the server starts the latest save.
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ListingAction {
+    /// Wait for a public server to appear in the game list, and write the result to `listing.json` in the state
+    /// directory. The service runs this when the server starts.
+    Verify {
+        /// Check in a new process and return at once, like systemd's `ExecStartPost=` needs.
+        #[arg(long)]
+        detach: bool,
+    },
+
+    /// Print the result of the last check.
+    Status,
+}
+
+#[derive(Subcommand)]
+enum InstanceAction {
+    /// Print each instance with its ports.
+    List,
+
+    /// Create an instance on ports no other instance uses, with directories under `instances/{name}`. Start it with
+    /// `systemctl start factoriod@{name}`.
+    Create { name: String },
+}
+
+#[derive(Subcommand)]
+enum PlayerListAction {
+    /// Add players to the list.
+    Add {
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Remove players from the list.
+    Remove {
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Print the players in the list.
+    List,
+}
+
+#[derive(Subcommand)]
+enum BanlistAction {
+    /// Ban a player, replacing any existing ban.
+    Add {
+        username: String,
+
+        /// Why the player is banned.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// The address of the player.
+        #[arg(long)]
+        address: Option<String>,
+    },
+
+    /// Unban players.
+    Remove {
+        #[arg(required = true)]
+        usernames: Vec<String>,
+    },
+
+    /// Print the banned players.
+    List,
+}
+
+/// Applies `action` to the player list at `path`.
+fn edit_player_list(path: &Path, action: PlayerListAction) -> Result<(), Box<dyn std::error::Error>> {
+    let mut list = PlayerList::load(path)?;
+    match action {
+        PlayerListAction::Add { names } => {
+            for name in names {
+                if !list.add(&name) {
+                    info!("{} is already in {}", name, path.display());
+                }
+            }
+
+            list.save(path)?;
+        },
+        PlayerListAction::Remove { names } => {
+            for name in names {
+                if !list.remove(&name) {
+                    info!("{} is not in {}", name, path.display());
+                }
+            }
+
+            list.save(path)?;
+        },
+        PlayerListAction::List => {
+            for player in &list.players {
+                println!("{}", player);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Applies `action` to the banlist at `path`.
+fn edit_banlist(path: &Path, action: BanlistAction) -> Result<(), Box<dyn std::error::Error>> {
+    let mut banlist = Banlist::load(path)?;
+    match action {
+        BanlistAction::Add {
+            username,
+            reason,
+            address,
+        } => {
+            if !banlist.add(Ban::new(username.clone(), reason, address)) {
+                info!("{} was already banned, replacing the ban", username);
+            }
+
+            banlist.save(path)?;
+        },
+        BanlistAction::Remove { usernames } => {
+            for username in usernames {
+                if !banlist.remove(&username) {
+                    info!("{} is not banned", username);
+                }
+            }
+
+            banlist.save(path)?;
+        },
+        BanlistAction::List => {
+            for ban in &banlist.bans {
+                let details = [ban.reason(), ban.address()].into_iter().flatten().collect::<Vec<_>>();
+                if details.is_empty() {
+                    println!("{}", ban.username());
+                } else {
+                    println!("{} ({})", ban.username(), details.join(", "));
+                }
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Applies `action` to the configuration in `config_dir`.
+fn config(config_dir: &Path, action: ConfigAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ConfigAction::Explain { file } => {
+            let layered = match file {
+                ConfigFile::ServerSettings => factoriod::config::compose_layered_server_settings(config_dir)?,
+                file => factoriod::config::read_layered(config_dir, file)?,
+            };
+
+            let Some(layered) = layered else {
+                info!("{} has no {}, the game's defaults are used", config_dir.display(), file.file_name());
+                return Ok(());
+            };
+
+            for line in factoriod::config::explain(&layered) {
+                println!("{}", line);
+            }
+        },
+        ConfigAction::Diff { file, other } => {
+            let differences = factoriod::config::diff(config_dir, file, other.as_deref())?;
+            if differences.is_empty() {
+                info!("{} has no differences", file.file_name());
+            }
+
+            for difference in differences {
+                println!("{}", difference);
+            }
+        },
+        ConfigAction::Migrate { to, dry_run } => {
+            let migrated = factoriod::config::migrate(config_dir, &to, dry_run)?;
+            if migrated.is_empty() {
+                info!("the map settings in {} need no migration", config_dir.display());
+            }
+
+            for file in migrated {
+                match file.backup {
+                    Some(backup) => println!("{} (backed up to {}):", file.path.display(), backup.display()),
+                    None => println!("{}:", file.path.display()),
+                }
+
+                for change in file.changes {
+                    println!("  - {}", change);
+                }
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Prints the schema of the config file `name`, or writes every schema to `out_dir`.
+fn schema(name: Option<String>, out_dir: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(name) = name {
+        let schema = factoriod_config::config_schema_json(&name).ok_or_else(|| {
+            format!("no schema for {}, expected one of: {}", name, factoriod_config::SCHEMA_NAMES.join(", "))
+        })?;
+
+        print!("{}", schema);
+        return Ok(());
+    }
+
+    let out_dir = out_dir.ok_or("either a name or --out-dir is required")?;
+    std::fs::create_dir_all(&out_dir)?;
+    for name in factoriod_config::SCHEMA_NAMES {
+        let path = out_dir.join(format!("{}.schema.json", name));
+        info!("Writing {}", path.display());
+        let schema = factoriod_config::config_schema_json(name).ok_or("missing schema")?;
+        std::fs::write(path, schema)?;
+    }
+
+    Ok(())
+}
+
+/// The directories of the server the subcommands work on: the service's, or an instance's with `--instance`.
+struct Dirs {
+    config_dir: PathBuf,
+    state_dir: PathBuf,
+    cache_dir: Option<PathBuf>,
+
+    /// The port the server listens on.
+    port: u16,
+}
+
+impl Dirs {
+    /// The directories of `instance` under `roots`, or of the service. `config_dir` overrides the configuration
+    /// directory of either.
+    fn new(
+        systemd_dirs: &SystemdDirs,
+        roots: &InstanceRoots,
+        config_dir: Option<PathBuf>,
+        instance: Option<&Instance>,
+    ) -> Self {
+        let cache_dir = systemd_dirs.cache_dir().map(Path::to_path_buf);
+        match instance {
+            Some(instance) => Dirs {
+                config_dir: config_dir.unwrap_or_else(|| roots.config_dir(&instance.name)),
+                state_dir: roots.state_dir(&instance.name),
+                cache_dir,
+                port: instance.ports.port,
+            },
+            None => Dirs {
+                config_dir: config_dir
+                    .or_else(|| systemd_dirs.config_dir().map(Path::to_path_buf))
+                    .unwrap_or_else(|| PathBuf::from("/etc/factoriod")),
+                state_dir: systemd_dirs
+                    .state_dir()
+                    .map_or_else(|| roots.state_dir.clone(), Path::to_path_buf),
+                cache_dir,
+                port: DEFAULT_PORT,
+            },
+        }
+    }
+}
+
+/// The server for the game in the cache directory, with the configuration and state in `dirs`.
+fn server(dirs: &Dirs) -> Result<FactorioServer, Box<dyn std::error::Error>> {
+    let factorio_dir = dirs.cache_dir.as_deref().ok_or("cache dir not found")?.join("factorio");
+    Ok(FactorioServer::try_new(factorio_dir)?
+        .with_config_dir(&dirs.config_dir)
+        .with_state_dir(&dirs.state_dir))
+}
+
+/// Applies `action` to the public game list check of the server.
+fn listing(dirs: &Dirs, action: ListingAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ListingAction::Verify { detach: true } => {
+            let args = std::env::args_os().skip(1).filter(|arg| arg != "--detach");
+            let child = std::process::Command::new(std::env::current_exe()?)
+                .args(args)
+                .stdin(Stdio::null())
+                .spawn()?;
+
+            info!("checking the public game list in process {}", child.id());
+        },
+        ListingAction::Verify { detach: false } => {
+            let (attempts, interval) = (LISTING_ATTEMPTS, LISTING_INTERVAL);
+            let status = listing::verify_listing(&dirs.config_dir, &dirs.state_dir, dirs.port, attempts, interval);
+            println!("{}", status);
+        },
+        ListingAction::Status => {
+            let Some(report) = ListingReport::load(&dirs.state_dir)? else {
+                info!("the public game list has not been checked yet");
+                return Ok(());
+            };
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+            println!(
+                "{} on port {}, {} seconds ago: {}",
+                report.name.as_deref().unwrap_or("the server"),
+                report.port,
+                now.saturating_sub(report.updated_at),
+                report.status
+            );
+        },
+    }
+
+    Ok(())
+}
+
+/// Applies `action` to the instances under `roots`.
+fn instances(roots: &InstanceRoots, action: InstanceAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        InstanceAction::List => {
+            for instance in roots.list()? {
+                println!("{} (port {}, RCON port {})", instance.name, instance.ports.port, instance.ports.rcon_port);
+            }
+        },
+        InstanceAction::Create { name } => {
+            let instance = roots.create(&name)?;
+            info!("created {}, configure it in {}", instance.name, roots.config_dir(&instance.name).display());
+            println!("{} (port {}, RCON port {})", instance.name, instance.ports.port, instance.ports.rcon_port);
+        },
+    }
+
+    Ok(())
+}
+
+/// Applies `action` to the scenarios of the server.
+fn scenario(dirs: &Dirs, action: ScenarioAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ScenarioAction::List => {
+            for scenario in server(dirs)?.scenarios() {
+                println!("{}", scenario);
+            }
+        },
+        ScenarioAction::Select { id } => {
+            let scenario = server(dirs)?.select_scenario(&id)?;
+            info!("the server will start from a new map of {} when it next starts", scenario);
+        },
+        ScenarioAction::Clear => {
+            let state_dir = &dirs.state_dir;
+            let mut state = DaemonState::load(state_dir)?;
+            if state.scenario.take().is_none() {
+                info!("no scenario is selected");
+            }
+
+            state.save(state_dir)?;
+        },
+    }
+
+    Ok(())
+}
+
+/// Generates a map preview with `options` and prints its path.
+fn preview(dirs: &Dirs, options: MapPreviewOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let preview = server(dirs)?.generate_map_preview(&options)?;
+    println!("{}", preview.display());
+    Ok(())
+}
+
+/// Generates map previews for each seed of `exploration` and prints the path of the index.
+fn explore(dirs: &Dirs, exploration: SeedExploration) -> Result<(), Box<dyn std::error::Error>> {
+    let index = server(dirs)?.explore_seeds(&exploration)?;
+    println!("{}", index.display());
+    Ok(())
+}
+
+/// Prepares the server to start: checks the config, acquires the game, syncs mods, and writes the server options.
+/// The systemd directories of an instance's unit are its own, except for the shared cache directory.
+fn prepare(systemd_dirs: &SystemdDirs, instance: Option<&Instance>) -> Result<(), Box<dyn std::error::Error>> {
+    match (systemd_dirs.config_dir(), systemd_dirs.runtime_dir()) {
+        (Some(config_dir), Some(runtime_dir)) => {
+            factoriod::config::write_server_settings(config_dir, runtime_dir)?;
+        },
+        (Some(config_dir), None) => {
+            info!("No runtime directory, secrets will not be added to the server settings.");
+            factoriod::config::check_server_settings(config_dir)?;
+        },
+        _ => {},
+    }
+
+    acquire_binaries(systemd_dirs)?;
+    sync_mods(systemd_dirs)?;
+    write_opts_env(systemd_dirs, instance)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
     factoriod::setup_tracing();
+    let args = Args::parse();
     let systemd_dirs = SystemdDirs::new();
-    write_opts_env(&systemd_dirs)?;
-    acquire_binaries(&systemd_dirs)?;
-    Ok(())
+    let roots = InstanceRoots::default();
+    let instance = match args.instance.as_deref().map(|name| roots.load(name)).transpose() {
+        Ok(instance) => instance,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        },
+    };
+
+    if args.command.is_none() && args.config_dir.is_some() {
+        error!("--config-dir only applies to the subcommands, the server is prepared from $CONFIGURATION_DIRECTORY");
+        return ExitCode::FAILURE;
+    }
+
+    let dirs = Dirs::new(&systemd_dirs, &roots, args.config_dir, instance.as_ref());
+    let config_dir = &dirs.config_dir;
+    let result = match args.command {
+        None => prepare(&systemd_dirs, instance.as_ref()),
+        Some(Command::Whitelist { action }) => edit_player_list(&config_dir.join("server-whitelist.json"), action),
+        Some(Command::Adminlist { action }) => edit_player_list(&config_dir.join("server-adminlist.json"), action),
+        Some(Command::Banlist { action }) => edit_banlist(&config_dir.join("server-banlist.json"), action),
+        Some(Command::Config { action }) => config(config_dir, action),
+        Some(Command::Scenario { action }) => scenario(&dirs, action),
+        Some(Command::Preview {
+            preset,
+            seed,
+            size,
+            scale,
+        }) => preview(&dirs, MapPreviewOptions { preset, seed, size, scale }),
+        Some(Command::Explore {
+            seeds,
+            preset,
+            size,
+            scale,
+            jobs,
+            report_quantities,
+        }) => {
+            let exploration = SeedExploration {
+                preview: MapPreviewOptions {
+                    preset,
+                    seed: None,
+                    size,
+                    scale,
+                },
+                seeds: seeds.0,
+                jobs: jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, NonZeroUsize::get)),
+                report_quantities: report_quantities
+                    .unwrap_or_else(|| DEFAULT_REPORT_QUANTITIES.map(str::to_owned).to_vec()),
+            };
+
+            explore(&dirs, exploration)
+        },
+        Some(Command::Listing { action }) => listing(&dirs, action),
+        Some(Command::Instance { action }) => instances(&roots, action),
+        Some(Command::Schema { name, out_dir }) => schema(name, out_dir),
+    };
+
+    // errors are formatted with Display rather than Debug so that reports like invalid settings are readable
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
 }
@@ -0,0 +1,821 @@
+//! Management of the mods directory.
+//!
+//! The mods the server should run are declared in `mods.json` in the configuration directory (see
+//! [`factoriod_config::DeclaredMods`]). Synchronizing the mods directory:
+//! 1. resolves the declared mods and their dependencies against the mod portal for the installed game version;
+//! 1. downloads releases that are missing or corrupt;
+//! 1. removes zips that are no longer needed; and
+//! 1. writes `mod-list.json` with the enabled flag of every mod.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use factorio_http_api::mod_portal::resolve::{self, Resolution, Resolver};
+use factorio_http_api::mod_portal::{self, Credentials, Dependency, Mod, ModVersion, Release};
+use factoriod_config::{DeclaredMod, DeclaredMods, GameVersion, ModList, ModListEntry, ModSettings};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::save::{self, SaveHeader, SaveMod};
+
+/// A mod release that should be present in the mods directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManagedMod {
+    /// The name of the mod.
+    pub name: String,
+
+    /// The release of the mod.
+    pub release: Release,
+
+    /// Whether the game should load the mod.
+    pub enabled: bool,
+}
+
+/// The changes needed to make a mods directory match a set of [`ManagedMod`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModsPlan {
+    /// Releases that are missing from the mods directory, or whose zip does not match the release's hash.
+    pub download: Vec<Release>,
+
+    /// Zips in the mods directory that do not belong to any managed mod.
+    pub remove: Vec<PathBuf>,
+
+    /// The contents of `mod-list.json`.
+    pub mod_list: ModList,
+}
+
+/// Get the version of the game installed in `factorio_dir`, as found in the base mod's `info.json`.
+pub fn installed_game_version<P: AsRef<Path>>(factorio_dir: P) -> Result<ModVersion, Box<dyn Error>> {
+    #[derive(Deserialize)]
+    struct BaseInfo {
+        version: ModVersion,
+    }
+
+    let info_json = factorio_dir.as_ref().join("data/base/info.json");
+    let info: BaseInfo = serde_json::from_reader(std::fs::File::open(&info_json)?)?;
+    Ok(info.version)
+}
+
+/// Read `mods.json` from the configuration directory. Returns [`None`] if the file does not exist, in which case the
+/// mods directory is not managed.
+pub fn read_declared_mods<P: AsRef<Path>>(config_dir: P) -> Result<Option<DeclaredMods>, Box<dyn Error>> {
+    let path = config_dir.as_ref().join("mods.json");
+    if !path.is_file() {
+        debug!("{} not found, mods will not be managed", path.display());
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_reader(std::fs::File::open(&path)?)?))
+}
+
+/// Transform the declared mods into dependencies for the resolver. A declared mod `"foo"` with version `">= 1.0"`
+/// becomes the dependency `"foo >= 1.0"`.
+fn declared_dependency(name: &str, version: Option<&str>) -> Result<Dependency, Box<dyn Error>> {
+    let dependency: Dependency = match version {
+        Some(version) => format!("{} {}", name, version).parse()?,
+        None => Dependency::required(name),
+    };
+
+    Ok(dependency)
+}
+
+/// Fetch `names` and every mod their releases for `game_version` require, directly or transitively, from the mod
+/// portal. Builtin mods are skipped.
+pub fn fetch_mods<I, S>(names: I, game_version: &ModVersion) -> Result<Vec<Mod>, Box<dyn Error>>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut queue = names.into_iter().map(Into::into).collect::<Vec<String>>();
+    let mut seen = HashSet::new();
+    let mut mods = Vec::new();
+    while let Some(name) = queue.pop() {
+        if resolve::is_builtin(&name) || !seen.insert(name.clone()) {
+            continue;
+        }
+
+        debug!("fetching {} from the mod portal", name);
+        let full_mod = mod_portal::full_mod(&name)?;
+        queue.extend(
+            full_mod
+                .releases
+                .iter()
+                .filter(|release| release.info_json.factorio_version.is_loadable_by(game_version))
+                .flat_map(|release| &release.info_json.dependencies)
+                .filter(|dependency| dependency.kind.is_required())
+                .map(|dependency| dependency.name.clone()),
+        );
+
+        mods.push(full_mod);
+    }
+
+    Ok(mods)
+}
+
+/// Pick the releases of the declared mods for the `game_version`, using the portal information in `mods`.
+///
+/// Enabled mods are resolved together with their dependencies. Disabled mods are not loaded by the game, so their
+/// dependencies are ignored and the newest release matching their version requirement is used.
+pub fn managed_mods(
+    declared: &DeclaredMods,
+    mods: &[Mod],
+    game_version: &ModVersion,
+) -> Result<(Vec<ManagedMod>, BTreeSet<String>), Box<dyn Error>> {
+    let requested = declared
+        .mods
+        .iter()
+        .filter(|m| m.enabled)
+        .map(|m| declared_dependency(&m.name, m.version.as_deref()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let Resolution { mods: resolved, builtins } = Resolver::new(mods, *game_version).resolve(&requested)?;
+    let mut managed = resolved
+        .into_iter()
+        .map(|(name, release)| ManagedMod {
+            name,
+            release,
+            enabled: true,
+        })
+        .collect::<Vec<_>>();
+
+    for disabled in declared.mods.iter().filter(|m| !m.enabled) {
+        if resolve::is_builtin(&disabled.name) {
+            continue;
+        }
+
+        if managed.iter().any(|m| m.name == disabled.name) {
+            warn!("{} is disabled in mods.json but required by another mod, it will be enabled", disabled.name);
+            continue;
+        }
+
+        let dependency = declared_dependency(&disabled.name, disabled.version.as_deref())?;
+        let release = mods
+            .iter()
+            .find(|m| m.name == disabled.name)
+            .and_then(|m| {
+                m.releases
+                    .iter()
+                    .filter(|release| release.info_json.factorio_version.is_loadable_by(game_version))
+                    .filter(|release| dependency.matches(&release.version))
+                    .max_by_key(|release| release.version)
+            })
+            .ok_or_else(|| format!("no release of {} matches \"{}\"", disabled.name, dependency))?;
+
+        managed.push(ManagedMod {
+            name: disabled.name.clone(),
+            release: release.clone(),
+            enabled: false,
+        });
+    }
+
+    managed.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((managed, builtins))
+}
+
+/// Create the `mod-list.json` contents for the `managed` mods. The base mod is always enabled. Since Factorio 2.0,
+/// the other builtin mods are listed as well and are enabled only when they are in `builtins`.
+pub fn mod_list(managed: &[ManagedMod], builtins: &BTreeSet<String>, game_version: &ModVersion) -> ModList {
+    let mut entries = vec![ModListEntry::new("base", true)];
+    if game_version.major >= 2 {
+        entries.extend(
+            resolve::BUILTIN_MODS
+                .iter()
+                .filter(|name| !matches!(**name, "base" | "core"))
+                .map(|name| ModListEntry::new(*name, builtins.contains(*name))),
+        );
+    }
+
+    entries.extend(managed.iter().map(|m| ModListEntry::new(m.name.clone(), m.enabled)));
+    ModList { mods: entries }
+}
+
+/// Get all zip files in the mods directory, keyed by file name.
+fn existing_zips(mods_dir: &Path) -> std::io::Result<BTreeMap<String, PathBuf>> {
+    if !mods_dir.is_dir() {
+        return Ok(BTreeMap::new());
+    }
+
+    Ok(mods_dir
+        .read_dir()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|ft| ft.is_file()).unwrap_or(false))
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map(|ext| ext == "zip").unwrap_or(false))
+        .filter_map(|path| Some((path.file_name()?.to_str()?.to_owned(), path)))
+        .collect())
+}
+
+/// Plan the changes needed to make `mods_dir` contain exactly the `managed` mods and the given `mod_list`.
+pub fn plan<P: AsRef<Path>>(mods_dir: P, managed: &[ManagedMod], mod_list: ModList) -> std::io::Result<ModsPlan> {
+    let mut existing = existing_zips(mods_dir.as_ref())?;
+    let mut download = Vec::new();
+    for m in managed {
+        match existing.remove(&m.release.file_name) {
+            Some(path) if mod_portal::sha1_file(&path)?.eq_ignore_ascii_case(&m.release.sha1) => {
+                debug!("{} is up to date", path.display());
+            },
+            Some(path) => {
+                warn!("{} does not match its SHA1 hash and will be downloaded again", path.display());
+                download.push(m.release.clone());
+            },
+            None => download.push(m.release.clone()),
+        }
+    }
+
+    Ok(ModsPlan {
+        download,
+        remove: existing.into_values().collect(),
+        mod_list,
+    })
+}
+
+/// Write `mod-list.json` to the mods directory.
+pub fn write_mod_list<P: AsRef<Path>>(mods_dir: P, mod_list: &ModList) -> Result<(), Box<dyn Error>> {
+    let path = mods_dir.as_ref().join("mod-list.json");
+    info!("Writing {}", path.display());
+    factoriod_config::write_atomic(&path, serde_json::to_string_pretty(mod_list)?.as_bytes())?;
+    Ok(())
+}
+
+/// Apply a plan to the mods directory. Downloads require `credentials`.
+pub fn apply<P: AsRef<Path>>(
+    mods_dir: P,
+    plan: &ModsPlan,
+    credentials: Option<&Credentials>,
+) -> Result<(), Box<dyn Error>> {
+    let mods_dir = mods_dir.as_ref();
+    std::fs::create_dir_all(mods_dir)?;
+
+    if !plan.download.is_empty() {
+        let credentials = credentials.ok_or_else(|| {
+            format!(
+                "{} mods must be downloaded but the {} and {} credentials are not set",
+                plan.download.len(),
+                crate::credentials::USERNAME,
+                crate::credentials::TOKEN
+            )
+        })?;
+
+        for release in &plan.download {
+            info!("Downloading {}", release.file_name);
+            mod_portal::download_release_to(release, credentials, mods_dir)?;
+        }
+    }
+
+    for path in &plan.remove {
+        info!("Removing {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+
+    write_mod_list(mods_dir, &plan.mod_list)
+}
+
+/// Synchronize `mods_dir` with the mods declared in `config_dir`, for the game installed in `factorio_dir`. If the
+/// configuration directory does not declare any mods, the mods directory is left untouched.
+///
+/// If the mod portal cannot be reached, or the mods cannot be downloaded, like when the credentials are missing, the
+/// mods directory is kept as it is with a warning, provided it has a `mod-list.json` from an earlier synchronization.
+/// Errors in `mods.json` itself, like an invalid version requirement, and mods that cannot be resolved are always
+/// returned.
+#[tracing::instrument]
+pub fn sync_mods(config_dir: &Path, mods_dir: &Path, factorio_dir: &Path) -> Result<(), Box<dyn Error>> {
+    sync_mods_with(
+        config_dir,
+        mods_dir,
+        factorio_dir,
+        |declared, game_version| fetch_mods(declared.mods.iter().map(|m| m.name.clone()), game_version),
+        crate::credentials::factorio_credentials().as_ref(),
+    )
+}
+
+/// [`sync_mods`], with the mods fetched from the portal by `fetch` and downloaded with `credentials`.
+fn sync_mods_with(
+    config_dir: &Path,
+    mods_dir: &Path,
+    factorio_dir: &Path,
+    fetch: impl FnOnce(&DeclaredMods, &ModVersion) -> Result<Vec<Mod>, Box<dyn Error>>,
+    credentials: Option<&Credentials>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(declared) = read_declared_mods(config_dir)? else {
+        return Ok(());
+    };
+
+    // invalid requirements are reported before the portal is asked about them
+    for declared_mod in &declared.mods {
+        declared_dependency(&declared_mod.name, declared_mod.version.as_deref())?;
+    }
+
+    let game_version = installed_game_version(factorio_dir)?;
+    let keep_existing = |e: Box<dyn Error>| {
+        if mods_dir.join("mod-list.json").is_file() {
+            warn!("failed to synchronize the mods, keeping the mods in {}: {}", mods_dir.display(), e);
+            Ok(())
+        } else {
+            Err(e)
+        }
+    };
+
+    info!("Resolving {} declared mods for factorio {}", declared.mods.len(), game_version);
+    let mods = match fetch(&declared, &game_version) {
+        Ok(mods) => mods,
+        Err(e) => return keep_existing(e),
+    };
+
+    let (managed, builtins) = managed_mods(&declared, &mods, &game_version)?;
+    let plan = plan(mods_dir, &managed, mod_list(&managed, &builtins, &game_version))?;
+    apply(mods_dir, &plan, credentials).or_else(keep_existing)
+}
+
+/// Write `mod-settings.dat` in the mods directory from the settings declared in `mod-settings.json` in the configuration
+/// directory. Declared settings overwrite those already in `mod-settings.dat`, and other settings are kept. Nothing is
+/// written if `mod-settings.json` does not exist.
+#[tracing::instrument]
+pub fn sync_mod_settings(config_dir: &Path, mods_dir: &Path, factorio_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let declared_path = config_dir.join("mod-settings.json");
+    if !declared_path.exists() {
+        debug!("{} does not exist, mod settings will not be managed", declared_path.display());
+        return Ok(());
+    }
+
+    let declared: ModSettings = serde_json::from_reader(std::fs::File::open(&declared_path)?)?;
+    let path = mods_dir.join("mod-settings.dat");
+    let (version, mut settings) = match std::fs::File::open(&path) {
+        Ok(mut file) => ModSettings::read_from(&mut file)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let game_version = installed_game_version(factorio_dir)?;
+            let version = GameVersion::new(game_version.major, game_version.minor, game_version.patch, 0);
+            (version, ModSettings::default())
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    settings.merge(declared);
+    info!("Writing {}", path.display());
+    std::fs::create_dir_all(mods_dir)?;
+    let mut bytes = Vec::new();
+    settings.write_to(&mut bytes, &version)?;
+    factoriod_config::write_atomic(&path, &bytes)?;
+    Ok(())
+}
+
+/// Pick the releases matching exactly the mods loaded in a save, using the portal information in `mods`. Builtin mods
+/// are returned separately.
+pub fn save_managed_mods(
+    save_mods: &[SaveMod],
+    mods: &[Mod],
+) -> Result<(Vec<ManagedMod>, BTreeSet<String>), Box<dyn Error>> {
+    let mut managed = Vec::new();
+    let mut builtins = BTreeSet::new();
+    for save_mod in save_mods {
+        if resolve::is_builtin(&save_mod.name) {
+            builtins.insert(save_mod.name.clone());
+            continue;
+        }
+
+        let release = mods
+            .iter()
+            .find(|m| m.name == save_mod.name)
+            .and_then(|m| m.release(&save_mod.version))
+            .ok_or_else(|| format!("{} {} is not available on the mod portal", save_mod.name, save_mod.version))?;
+
+        managed.push(ManagedMod {
+            name: save_mod.name.clone(),
+            release: release.clone(),
+            enabled: true,
+        });
+    }
+
+    managed.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((managed, builtins))
+}
+
+/// The declared mods that pin every mod of a save to its exact version.
+fn pinned_declared_mods(save_mods: &[SaveMod]) -> DeclaredMods {
+    DeclaredMods {
+        mods: save_mods
+            .iter()
+            .filter(|m| !matches!(m.name.as_str(), "base" | "core"))
+            .map(|m| DeclaredMod {
+                name: m.name.clone(),
+                version: Some(format!("= {}", m.version)),
+                enabled: true,
+            })
+            .collect(),
+    }
+}
+
+/// Make `mods_dir` contain exactly the mods loaded in the save at `save`, versions included, for the game installed in
+/// `factorio_dir`. Missing mods are downloaded from the mod portal.
+///
+/// If `config_dir` is given, its `mods.json` is rewritten to pin the save's mods so that the next synchronization with
+/// [`sync_mods`] keeps them. An existing `mods.json` is first copied to a backup next to it, like `mods.json.bak`.
+#[tracing::instrument]
+pub fn sync_mods_to_save(
+    save: &Path,
+    config_dir: Option<&Path>,
+    mods_dir: &Path,
+    factorio_dir: &Path,
+) -> Result<SaveHeader, Box<dyn Error>> {
+    let header = save::read_header(save)?;
+    let game_version = installed_game_version(factorio_dir)?;
+    if (header.game_version.major, header.game_version.minor) != (game_version.major, game_version.minor) {
+        warn!("{} was saved with factorio {} but {} is installed", save.display(), header.game_version, game_version);
+    }
+
+    info!("{} uses {} mods", save.display(), header.mods.len());
+    let mods = header
+        .mods
+        .iter()
+        .filter(|m| !resolve::is_builtin(&m.name))
+        .map(|m| {
+            debug!("fetching {} from the mod portal", m.name);
+            mod_portal::full_mod(&m.name)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (managed, builtins) = save_managed_mods(&header.mods, &mods)?;
+    let plan = plan(mods_dir, &managed, mod_list(&managed, &builtins, &game_version))?;
+    apply(mods_dir, &plan, crate::credentials::factorio_credentials().as_ref())?;
+
+    if let Some(config_dir) = config_dir {
+        let path = config_dir.join("mods.json");
+        info!("Pinning the mods of {} in {}", save.display(), path.display());
+        pin_mods(&path, &header.mods)?;
+    }
+
+    Ok(header)
+}
+
+/// Rewrite the `mods.json` at `path` to pin `save_mods`, backing up the existing file first.
+fn pin_mods(path: &Path, save_mods: &[SaveMod]) -> Result<(), Box<dyn Error>> {
+    if path.exists() {
+        let backup = crate::config::backup_path(path);
+        info!("Backing up {} to {}", path.display(), backup.display());
+        std::fs::copy(path, &backup)?;
+    }
+
+    let contents = serde_json::to_string_pretty(&pinned_declared_mods(save_mods))?;
+    factoriod_config::write_atomic(path, contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use factorio_http_api::mod_portal::InfoJson;
+    use factoriod_config::PropertyTree;
+
+    use super::*;
+
+    const GAME: ModVersion = ModVersion::new(1, 1, 110);
+
+    fn release(name: &str, version: &str, dependencies: &[&str], contents: &[u8]) -> Release {
+        let mut hasher = sha1_smol::Sha1::new();
+        hasher.update(contents);
+        Release {
+            download_url: format!("/download/{}/{}", name, version),
+            file_name: format!("{}_{}.zip", name, version),
+            info_json: InfoJson {
+                factorio_version: ModVersion::new(1, 1, 0),
+                dependencies: dependencies.iter().map(|d| d.parse().unwrap()).collect(),
+            },
+            released_at: "2024-01-01T00:00:00.000000Z".to_owned(),
+            version: version.parse().unwrap(),
+            sha1: hasher.digest().to_string(),
+        }
+    }
+
+    fn portal_mod(name: &str, releases: Vec<Release>) -> Mod {
+        Mod {
+            name: name.to_owned(),
+            title: name.to_owned(),
+            owner: "someone".to_owned(),
+            summary: String::new(),
+            downloads_count: 0,
+            releases,
+        }
+    }
+
+    fn declared_mod(name: &str, version: Option<&str>, enabled: bool) -> DeclaredMod {
+        DeclaredMod {
+            name: name.to_owned(),
+            version: version.map(str::to_owned),
+            enabled,
+        }
+    }
+
+    #[test]
+    fn test_installed_game_version() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(installed_game_version(dir.path()).is_err());
+
+        std::fs::create_dir_all(dir.path().join("data/base")).unwrap();
+        std::fs::write(
+            dir.path().join("data/base/info.json"),
+            r#"{"name": "base", "version": "1.1.110", "title": "Base mod"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(installed_game_version(dir.path()).unwrap(), GAME);
+    }
+
+    #[test]
+    fn test_read_declared_mods() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_declared_mods(dir.path()).unwrap(), None);
+
+        std::fs::write(dir.path().join("mods.json"), r#"{"mods": [{"name": "foo"}]}"#).unwrap();
+        let declared = read_declared_mods(dir.path()).unwrap().unwrap();
+        assert_eq!(declared.mods, vec![declared_mod("foo", None, true)]);
+
+        std::fs::write(dir.path().join("mods.json"), "not json").unwrap();
+        assert!(read_declared_mods(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_managed_mods() {
+        let mods = [
+            portal_mod("foo", vec![release("foo", "1.0.0", &["bar >= 2.0", "? quality"], b"foo")]),
+            portal_mod("bar", vec![release("bar", "1.0.0", &[], b"bar1"), release("bar", "2.0.0", &[], b"bar2")]),
+            portal_mod("baz", vec![release("baz", "0.1.0", &[], b"baz1"), release("baz", "0.2.0", &[], b"baz2")]),
+        ];
+
+        let declared = DeclaredMods {
+            mods: vec![declared_mod("foo", None, true), declared_mod("baz", Some("< 0.2"), false)],
+        };
+
+        let (managed, builtins) = managed_mods(&declared, &mods, &GAME).unwrap();
+        let summary = managed
+            .iter()
+            .map(|m| (m.name.as_str(), m.release.version.to_string(), m.enabled))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("bar", "2.0.0".to_owned(), true),
+                ("baz", "0.1.0".to_owned(), false),
+                ("foo", "1.0.0".to_owned(), true),
+            ]
+        );
+
+        assert_eq!(builtins, BTreeSet::from(["base".to_owned()]));
+    }
+
+    #[test]
+    fn test_managed_mods_errors() {
+        let mods = [portal_mod("foo", vec![release("foo", "1.0.0", &[], b"foo")])];
+        let declared = DeclaredMods {
+            mods: vec![declared_mod("foo", Some(">= 2.0"), true)],
+        };
+
+        assert!(managed_mods(&declared, &mods, &GAME).is_err());
+
+        let declared = DeclaredMods {
+            mods: vec![declared_mod("foo", Some("not a requirement"), true)],
+        };
+
+        assert!(managed_mods(&declared, &mods, &GAME).is_err());
+    }
+
+    #[test]
+    fn test_mod_list() {
+        let managed = vec![
+            ManagedMod {
+                name: "foo".to_owned(),
+                release: release("foo", "1.0.0", &[], b"foo"),
+                enabled: true,
+            },
+            ManagedMod {
+                name: "bar".to_owned(),
+                release: release("bar", "1.0.0", &[], b"bar"),
+                enabled: false,
+            },
+        ];
+
+        let builtins = BTreeSet::from(["base".to_owned(), "quality".to_owned()]);
+        let entries = |mod_list: ModList| {
+            mod_list
+                .mods
+                .into_iter()
+                .map(|entry| (entry.name, entry.enabled))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            entries(mod_list(&managed, &builtins, &GAME)),
+            vec![("base".to_owned(), true), ("foo".to_owned(), true), ("bar".to_owned(), false)]
+        );
+
+        assert_eq!(
+            entries(mod_list(&managed, &builtins, &ModVersion::new(2, 0, 7))),
+            vec![
+                ("base".to_owned(), true),
+                ("elevated-rails".to_owned(), false),
+                ("quality".to_owned(), true),
+                ("space-age".to_owned(), false),
+                ("foo".to_owned(), true),
+                ("bar".to_owned(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_and_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let up_to_date = release("foo", "1.0.0", &[], b"foo");
+        let corrupt = release("bar", "1.0.0", &[], b"bar");
+        let missing = release("baz", "1.0.0", &[], b"baz");
+        std::fs::write(dir.path().join(&up_to_date.file_name), b"foo").unwrap();
+        std::fs::write(dir.path().join(&corrupt.file_name), b"corrupt").unwrap();
+        std::fs::write(dir.path().join("stale_0.1.0.zip"), b"stale").unwrap();
+        std::fs::write(dir.path().join("mod-settings.dat"), b"settings").unwrap();
+
+        let managed = [up_to_date, corrupt.clone(), missing.clone()]
+            .into_iter()
+            .map(|release| ManagedMod {
+                name: release.file_name.split('_').next().unwrap().to_owned(),
+                release,
+                enabled: true,
+            })
+            .collect::<Vec<_>>();
+
+        let expected_mod_list = mod_list(&managed, &BTreeSet::new(), &GAME);
+        let plan = plan(dir.path(), &managed, expected_mod_list.clone()).unwrap();
+        assert_eq!(plan.download, vec![corrupt, missing]);
+        assert_eq!(plan.remove, vec![dir.path().join("stale_0.1.0.zip")]);
+        assert_eq!(plan.mod_list, expected_mod_list);
+
+        // without downloads, applying the plan does not need credentials
+        let plan = ModsPlan {
+            download: Vec::new(),
+            ..plan
+        };
+
+        apply(dir.path(), &plan, None).unwrap();
+        assert!(!dir.path().join("stale_0.1.0.zip").exists());
+        assert!(dir.path().join("mod-settings.dat").exists());
+        let written: ModList =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("mod-list.json")).unwrap()).unwrap();
+        assert_eq!(written, plan.mod_list);
+    }
+
+    #[test]
+    fn test_apply_requires_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan = ModsPlan {
+            download: vec![release("foo", "1.0.0", &[], b"foo")],
+            remove: Vec::new(),
+            mod_list: ModList::default(),
+        };
+
+        assert!(apply(dir.path(), &plan, None).is_err());
+    }
+
+    fn save_mod(name: &str, version: &str) -> SaveMod {
+        SaveMod {
+            name: name.to_owned(),
+            version: version.parse().unwrap(),
+            crc: 0,
+        }
+    }
+
+    #[test]
+    fn test_save_managed_mods() {
+        let mods = [
+            portal_mod("foo", vec![release("foo", "1.0.0", &[], b"foo1"), release("foo", "1.1.0", &[], b"foo2")]),
+            portal_mod("bar", vec![release("bar", "2.0.0", &[], b"bar")]),
+        ];
+
+        let save_mods = [
+            save_mod("base", "2.0.7"),
+            save_mod("space-age", "2.0.7"),
+            save_mod("foo", "1.0.0"),
+            save_mod("bar", "2.0.0"),
+        ];
+
+        let (managed, builtins) = save_managed_mods(&save_mods, &mods).unwrap();
+        let summary = managed
+            .iter()
+            .map(|m| (m.name.as_str(), m.release.version.to_string()))
+            .collect::<Vec<_>>();
+
+        // the save's version is used even though a newer release exists
+        assert_eq!(summary, vec![("bar", "2.0.0".to_owned()), ("foo", "1.0.0".to_owned())]);
+        assert_eq!(builtins, BTreeSet::from(["base".to_owned(), "space-age".to_owned()]));
+
+        let error = save_managed_mods(&[save_mod("foo", "0.9.0")], &mods).unwrap_err();
+        assert_eq!(error.to_string(), "foo 0.9.0 is not available on the mod portal");
+    }
+
+    #[test]
+    fn test_pinned_declared_mods() {
+        let declared =
+            pinned_declared_mods(&[save_mod("base", "2.0.7"), save_mod("quality", "2.0.7"), save_mod("foo", "1.0.0")]);
+
+        assert_eq!(
+            declared.mods,
+            vec![
+                declared_mod("quality", Some("= 2.0.7"), true),
+                declared_mod("foo", Some("= 1.0.0"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pin_mods() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mods.json");
+        pin_mods(&path, &[save_mod("foo", "1.0.0")]).unwrap();
+        assert!(!dir.path().join("mods.json.bak").exists());
+
+        let pinned = std::fs::read_to_string(&path).unwrap();
+        pin_mods(&path, &[save_mod("bar", "2.0.0")]).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("mods.json.bak")).unwrap(), pinned);
+        let declared = read_declared_mods(dir.path()).unwrap().unwrap();
+        assert_eq!(declared.mods, vec![declared_mod("bar", Some("= 2.0.0"), true)]);
+    }
+
+    #[test]
+    fn test_sync_mods_without_declared_mods() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let mods_dir = tempfile::tempdir().unwrap();
+        std::fs::write(mods_dir.path().join("foo_1.0.0.zip"), b"foo").unwrap();
+        sync_mods(config_dir.path(), mods_dir.path(), Path::new("/does/not/exist")).unwrap();
+        assert!(mods_dir.path().join("foo_1.0.0.zip").exists());
+    }
+
+    #[test]
+    fn test_sync_mods_keeps_existing_mods() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let mods_dir = tempfile::tempdir().unwrap();
+        let factorio_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(factorio_dir.path().join("data/base")).unwrap();
+        std::fs::write(factorio_dir.path().join("data/base/info.json"), r#"{"version": "1.1.110"}"#).unwrap();
+        std::fs::write(config_dir.path().join("mods.json"), r#"{"mods": [{"name": "foo"}]}"#).unwrap();
+        let sync = |fetched: Result<Vec<Mod>, Box<dyn Error>>| {
+            sync_mods_with(config_dir.path(), mods_dir.path(), factorio_dir.path(), |_, _| fetched, None)
+        };
+
+        let unreachable = || Err("the mod portal is unreachable".into());
+        let foo = || Ok(vec![portal_mod("foo", vec![release("foo", "2.0.0", &[], b"foo2")])]);
+
+        // an unreachable portal fails a first synchronization
+        assert!(sync(unreachable()).is_err());
+
+        // but keeps the mods of an earlier one, as do missing credentials for a download
+        std::fs::write(mods_dir.path().join("foo_1.0.0.zip"), b"foo").unwrap();
+        std::fs::write(mods_dir.path().join("mod-list.json"), r#"{"mods": []}"#).unwrap();
+        sync(unreachable()).unwrap();
+        sync(foo()).unwrap();
+        assert!(mods_dir.path().join("foo_1.0.0.zip").exists());
+
+        // mods that cannot be resolved are never ignored
+        assert!(sync(Ok(Vec::new())).is_err());
+
+        // nor is an invalid mods.json, which is checked before fetching anything
+        let fetch_fails = |_: &DeclaredMods, _: &ModVersion| -> Result<Vec<Mod>, Box<dyn Error>> {
+            panic!("an invalid mods.json is not fetched")
+        };
+
+        let invalid = r#"{"mods": [{"name": "foo", "version": ">= one"}]}"#;
+        std::fs::write(config_dir.path().join("mods.json"), invalid).unwrap();
+        assert!(sync_mods_with(config_dir.path(), mods_dir.path(), factorio_dir.path(), fetch_fails, None).is_err());
+        std::fs::write(config_dir.path().join("mods.json"), "not json").unwrap();
+        assert!(sync_mods_with(config_dir.path(), mods_dir.path(), factorio_dir.path(), fetch_fails, None).is_err());
+    }
+
+    #[test]
+    fn test_sync_mod_settings() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let mods_dir = tempfile::tempdir().unwrap();
+        let factorio_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(factorio_dir.path().join("data/base")).unwrap();
+        std::fs::write(factorio_dir.path().join("data/base/info.json"), r#"{"version": "1.1.110"}"#).unwrap();
+
+        // without mod-settings.json, nothing is written
+        sync_mod_settings(config_dir.path(), mods_dir.path(), factorio_dir.path()).unwrap();
+        assert!(!mods_dir.path().join("mod-settings.dat").exists());
+
+        std::fs::write(config_dir.path().join("mod-settings.json"), r#"{"startup": {"foo": true}}"#).unwrap();
+        sync_mod_settings(config_dir.path(), mods_dir.path(), factorio_dir.path()).unwrap();
+        let mut file = std::fs::File::open(mods_dir.path().join("mod-settings.dat")).unwrap();
+        let (version, mut settings) = ModSettings::read_from(&mut file).unwrap();
+        assert_eq!(version, GameVersion::new(1, 1, 110, 0));
+        assert_eq!(settings.startup["foo"], PropertyTree::Bool(true));
+
+        // settings changed by the game are kept, and the version of the existing file is reused
+        settings.runtime_global.insert("bar".to_owned(), PropertyTree::Number(1.0));
+        settings.startup.insert("foo".to_owned(), PropertyTree::Bool(false));
+        let mut bytes = Vec::new();
+        settings.write_to(&mut bytes, &GameVersion::new(1, 1, 110, 60000)).unwrap();
+        std::fs::write(mods_dir.path().join("mod-settings.dat"), bytes).unwrap();
+
+        sync_mod_settings(config_dir.path(), mods_dir.path(), factorio_dir.path()).unwrap();
+        let mut file = std::fs::File::open(mods_dir.path().join("mod-settings.dat")).unwrap();
+        let (version, settings) = ModSettings::read_from(&mut file).unwrap();
+        assert_eq!(version, GameVersion::new(1, 1, 110, 60000));
+        assert_eq!(settings.startup["foo"], PropertyTree::Bool(true));
+        assert_eq!(settings.runtime_global["bar"], PropertyTree::Number(1.0));
+    }
+}
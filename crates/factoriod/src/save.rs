@@ -0,0 +1,465 @@
+//! Reading the header of a save file.
+//!
+//! A save is a zip containing a directory named after the save. The header of the map, stored at the start of
+//! `level-init.dat` (or of `level.dat0` or `level.dat` in older saves), records the game version the save was made with
+//! and the mods that were loaded. The level file may be zlib-compressed.
+//!
+//! The header is laid out as follows, with all integers little-endian:
+//! ```text
+//! u16 x4   game version (major, minor, patch, build)
+//! u8       unknown, since 0.17
+//! string   campaign
+//! string   level name
+//! string   base mod
+//! u8       difficulty
+//! bool     finished
+//! bool     player won
+//! string   next level
+//! bool     can continue
+//! bool     finished but continuing
+//! bool     saving replay
+//! bool     allow non-admin debug options, since 0.16
+//! u8 x3    loaded from version (major, minor, patch)
+//! u16      loaded from build
+//! u8       allowed commands
+//! count    number of mods
+//! mod*     mods: string name, optim u16 x3 version, u32 CRC
+//! ```
+//!
+//! Strings are a `count` of bytes followed by UTF-8 data. A `count` is a single byte, or `0xFF` followed by a `u32`.
+//! An `optim u16` is a single byte, or `0xFF` followed by a `u16`.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use factorio_http_api::mod_portal::ModVersion;
+pub use factoriod_config::GameVersion;
+
+/// A mod that was loaded when a save was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveMod {
+    /// The name of the mod.
+    pub name: String,
+
+    /// The version of the mod.
+    pub version: ModVersion,
+
+    /// The CRC of the mod's files, used by the game to detect modified mods.
+    pub crc: u32,
+}
+
+/// The header of a save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveHeader {
+    /// The version of the game the save was made with.
+    pub game_version: GameVersion,
+
+    /// The name of the campaign, empty for freeplay.
+    pub campaign: String,
+
+    /// The name of the level, like `freeplay`.
+    pub level_name: String,
+
+    /// The mod providing the level, usually `base`.
+    pub base_mod: String,
+
+    /// The mods loaded when the save was made, including builtin mods like `base`.
+    pub mods: Vec<SaveMod>,
+}
+
+/// An error returned when reading a save header fails.
+#[derive(Debug)]
+pub enum SaveHeaderError {
+    /// The save could not be read.
+    Io(std::io::Error),
+
+    /// The save is not a valid zip.
+    Zip(zip::result::ZipError),
+
+    /// The save does not contain a level file.
+    MissingLevel(PathBuf),
+
+    /// The header ended before all fields were read.
+    UnexpectedEof,
+
+    /// A string in the header is not valid UTF-8.
+    InvalidUtf8,
+
+    /// The save was made with a version of the game whose header layout is not supported.
+    UnsupportedVersion(GameVersion),
+}
+
+impl fmt::Display for SaveHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveHeaderError::Io(e) => write!(f, "failed to read save: {}", e),
+            SaveHeaderError::Zip(e) => write!(f, "save is not a valid zip: {}", e),
+            SaveHeaderError::MissingLevel(path) => {
+                write!(f, "save {} does not contain a level file", path.display())
+            },
+            SaveHeaderError::UnexpectedEof => write!(f, "save header is truncated"),
+            SaveHeaderError::InvalidUtf8 => write!(f, "save header contains a string that is not valid UTF-8"),
+            SaveHeaderError::UnsupportedVersion(version) => {
+                write!(f, "saves from factorio {} are not supported", version)
+            },
+        }
+    }
+}
+
+impl Error for SaveHeaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveHeaderError::Io(e) => Some(e),
+            SaveHeaderError::Zip(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveHeaderError {
+    fn from(e: std::io::Error) -> Self {
+        SaveHeaderError::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for SaveHeaderError {
+    fn from(e: zip::result::ZipError) -> Self {
+        SaveHeaderError::Zip(e)
+    }
+}
+
+/// A cursor over the bytes of a header.
+struct HeaderReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> HeaderReader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], SaveHeaderError> {
+        if self.data.len() < n {
+            return Err(SaveHeaderError::UnexpectedEof);
+        }
+
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveHeaderError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveHeaderError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveHeaderError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn optim_u16(&mut self) -> Result<u16, SaveHeaderError> {
+        match self.u8()? {
+            0xFF => self.u16(),
+            value => Ok(value.into()),
+        }
+    }
+
+    fn count(&mut self) -> Result<u32, SaveHeaderError> {
+        match self.u8()? {
+            0xFF => self.u32(),
+            value => Ok(value.into()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SaveHeaderError> {
+        let len = self.count()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SaveHeaderError::InvalidUtf8)
+    }
+}
+
+/// Parse a save header from the uncompressed contents of a level file.
+pub fn parse_header(data: &[u8]) -> Result<SaveHeader, SaveHeaderError> {
+    let mut reader = HeaderReader { data };
+    let game_version = GameVersion {
+        major: reader.u16()?,
+        minor: reader.u16()?,
+        patch: reader.u16()?,
+        build: reader.u16()?,
+    };
+
+    if game_version < GameVersion::new(0, 17, 0, 0) {
+        return Err(SaveHeaderError::UnsupportedVersion(game_version));
+    }
+
+    reader.u8()?;
+    let campaign = reader.string()?;
+    let level_name = reader.string()?;
+    let base_mod = reader.string()?;
+
+    // difficulty, finished, player won
+    reader.bytes(3)?;
+
+    // next level
+    reader.string()?;
+
+    // can continue, finished but continuing, saving replay, allow non-admin debug options
+    reader.bytes(4)?;
+
+    // loaded from version and build, allowed commands
+    reader.bytes(3)?;
+    reader.u16()?;
+    reader.u8()?;
+
+    let mod_count = reader.count()?;
+    let mods = (0..mod_count)
+        .map(|_| {
+            let name = reader.string()?;
+            let version = ModVersion::new(reader.optim_u16()?, reader.optim_u16()?, reader.optim_u16()?);
+            let crc = reader.u32()?;
+            Ok(SaveMod { name, version, crc })
+        })
+        .collect::<Result<Vec<_>, SaveHeaderError>>()?;
+
+    Ok(SaveHeader {
+        game_version,
+        campaign,
+        level_name,
+        base_mod,
+        mods,
+    })
+}
+
+/// Whether `data` starts with a zlib header.
+fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0F == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Read the header of the save at `path`.
+///
+/// # Examples
+/// ```no_run
+/// let header = factoriod::save::read_header("/var/lib/factoriod/saves/my-save.zip").unwrap();
+/// for m in header.mods {
+///     println!("{} {}", m.name, m.version);
+/// }
+/// ```
+pub fn read_header<P: AsRef<Path>>(path: P) -> Result<SaveHeader, SaveHeaderError> {
+    let path = path.as_ref();
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+
+    // the header is at the start of the first of these files that exists
+    let level_name = ["level-init.dat", "level.dat0", "level.dat"]
+        .iter()
+        .find_map(|level| {
+            archive
+                .file_names()
+                .find(|name| name.rsplit('/').next() == Some(*level))
+                .map(str::to_owned)
+        })
+        .ok_or_else(|| SaveHeaderError::MissingLevel(path.to_path_buf()))?;
+
+    let mut data = Vec::new();
+    archive.by_name(&level_name)?.read_to_end(&mut data)?;
+    if is_zlib(&data) {
+        let mut inflated = Vec::new();
+        flate2::read::ZlibDecoder::new(data.as_slice()).read_to_end(&mut inflated)?;
+        data = inflated;
+    }
+
+    parse_header(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// Write a header in the layout documented on this module.
+    fn header_bytes(game_version: [u16; 4], mods: &[(&str, [u16; 3])]) -> Vec<u8> {
+        fn string(data: &mut Vec<u8>, s: &str) {
+            data.push(s.len() as u8);
+            data.extend_from_slice(s.as_bytes());
+        }
+
+        fn optim_u16(data: &mut Vec<u8>, value: u16) {
+            if value < 0xFF {
+                data.push(value as u8);
+            } else {
+                data.push(0xFF);
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let mut data = Vec::new();
+        for part in game_version {
+            data.extend_from_slice(&part.to_le_bytes());
+        }
+
+        data.push(0);
+        string(&mut data, "");
+        string(&mut data, "freeplay");
+        string(&mut data, "base");
+        data.extend_from_slice(&[0, 0, 0]);
+        string(&mut data, "");
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&[1, 1, 0]);
+        data.extend_from_slice(&1234u16.to_le_bytes());
+        data.push(1);
+        data.push(mods.len() as u8);
+        for (name, version) in mods {
+            string(&mut data, name);
+            for part in version {
+                optim_u16(&mut data, *part);
+            }
+
+            data.extend_from_slice(&42u32.to_le_bytes());
+        }
+
+        data
+    }
+
+    /// Write a save zip at `path` containing `level_file` with the given contents, zlib-compressed if `compress`.
+    fn write_save(path: &Path, level_file: &str, data: &[u8], compress: bool) {
+        let data = if compress {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            data.to_vec()
+        };
+
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("my-save/control.lua", options).unwrap();
+        zip.write_all(b"-- control").unwrap();
+        zip.start_file(format!("my-save/{}", level_file), options).unwrap();
+        zip.write_all(&data).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let data = header_bytes([1, 1, 110, 60000], &[("base", [1, 1, 110]), ("foo", [0, 6, 300])]);
+        let header = parse_header(&data).unwrap();
+        assert_eq!(
+            header.game_version,
+            GameVersion {
+                major: 1,
+                minor: 1,
+                patch: 110,
+                build: 60000,
+            }
+        );
+
+        assert_eq!(header.campaign, "");
+        assert_eq!(header.level_name, "freeplay");
+        assert_eq!(header.base_mod, "base");
+        assert_eq!(
+            header.mods,
+            vec![
+                SaveMod {
+                    name: "base".to_owned(),
+                    version: ModVersion::new(1, 1, 110),
+                    crc: 42,
+                },
+                SaveMod {
+                    name: "foo".to_owned(),
+                    version: ModVersion::new(0, 6, 300),
+                    crc: 42,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_header_truncated() {
+        let data = header_bytes([1, 1, 110, 60000], &[("base", [1, 1, 110])]);
+        for len in 0..data.len() {
+            assert!(
+                matches!(parse_header(&data[..len]), Err(SaveHeaderError::UnexpectedEof)),
+                "truncated at {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_header_unsupported() {
+        let data = header_bytes([0, 16, 51, 0], &[]);
+        assert!(matches!(parse_header(&data), Err(SaveHeaderError::UnsupportedVersion(_))));
+    }
+
+    /// Headers written by the game, to check the layout against the real thing rather than against [`header_bytes`].
+    #[test]
+    #[ignore = "no level files written by the game are checked in yet, see fixtures/level-dat/README.md"]
+    fn test_game_headers() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/level-dat");
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "dat"))
+            .collect();
+
+        paths.sort();
+        assert!(!paths.is_empty(), "no level files in {}", dir.display());
+        for path in paths {
+            let mut data = std::fs::read(&path).unwrap();
+            if is_zlib(&data) {
+                let mut inflated = Vec::new();
+                flate2::read::ZlibDecoder::new(data.as_slice()).read_to_end(&mut inflated).unwrap();
+                data = inflated;
+            }
+
+            let header = parse_header(&data).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            let version: GameVersion = path.file_stem().unwrap().to_str().unwrap().parse().unwrap();
+            let parsed = header.game_version;
+            assert_eq!(
+                (parsed.major, parsed.minor, parsed.patch),
+                (version.major, version.minor, version.patch),
+                "{}",
+                path.display()
+            );
+            assert!(header.mods.iter().any(|m| m.name == "base"), "{}: {:?}", path.display(), header.mods);
+        }
+    }
+
+    #[test]
+    fn test_is_zlib() {
+        assert!(is_zlib(&[0x78, 0x9C]));
+        assert!(is_zlib(&[0x78, 0x01]));
+        assert!(!is_zlib(&[0x01, 0x00]));
+        assert!(!is_zlib(&[0x78]));
+    }
+
+    #[test]
+    fn test_read_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = header_bytes([1, 1, 110, 60000], &[("base", [1, 1, 110]), ("foo", [1, 0, 0])]);
+        for (level_file, compress) in [("level-init.dat", true), ("level.dat0", true), ("level.dat", false)] {
+            let path = dir.path().join(format!("{}.zip", level_file));
+            write_save(&path, level_file, &data, compress);
+            let header = read_header(&path).unwrap();
+            assert_eq!(header.mods.len(), 2, "{}", level_file);
+            assert_eq!(header.mods[1].name, "foo", "{}", level_file);
+        }
+    }
+
+    #[test]
+    fn test_read_header_missing_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("save.zip");
+        write_save(&path, "script.dat", b"", false);
+        assert!(matches!(read_header(&path), Err(SaveHeaderError::MissingLevel(_))));
+
+        std::fs::write(&path, b"not a zip").unwrap();
+        assert!(matches!(read_header(&path), Err(SaveHeaderError::Zip(_))));
+    }
+}
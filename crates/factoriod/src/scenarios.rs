@@ -0,0 +1,198 @@
+//! Scenarios the server can be started from with `--start-server-load-scenario`.
+//!
+//! Scenarios are found in three places:
+//! 1. The game's data, like `base/freeplay` and `base/pvp`, in `data/{mod}/scenarios/` of the Factorio directory.
+//! 2. Mods in the `mods` directory of the state directory, like `my-mod/teams`, in `scenarios/` of the mod.
+//! 3. Custom scenarios in the `scenarios` directory of the state directory, named without a mod, like `my-scenario`.
+//!    The game finds these since it writes to the state directory, see [`crate::config::write_game_config`].
+
+use std::fmt;
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+/// A scenario the server can be started from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Scenario {
+    /// The mod, including the game's `base`, that provides the scenario, or [`None`] for a custom scenario.
+    pub mod_name: Option<String>,
+
+    /// The name of the scenario's directory.
+    pub name: String,
+}
+
+impl Scenario {
+    /// The argument of `--start-server-load-scenario`, like `base/pvp`, or just the name for a custom scenario.
+    ///
+    /// # Examples
+    /// ```
+    /// use factoriod::scenarios::Scenario;
+    /// let pvp = Scenario { mod_name: Some("base".to_owned()), name: "pvp".to_owned() };
+    /// assert_eq!(pvp.id(), "base/pvp");
+    /// let custom = Scenario { mod_name: None, name: "teams".to_owned() };
+    /// assert_eq!(custom.id(), "teams");
+    /// ```
+    pub fn id(&self) -> String {
+        match &self.mod_name {
+            Some(mod_name) => format!("{}/{}", mod_name, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+/// The names of the subdirectories of `dir`, or none if it cannot be read.
+fn subdirectories(dir: &Path) -> Vec<String> {
+    let Ok(entries) = dir.read_dir() else {
+        debug!("{} cannot be read, it has no scenarios", dir.display());
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// The name of a mod from the name of its zip or directory, like `my-mod` for `my-mod_1.2.3`.
+fn mod_name(file_stem: &str) -> &str {
+    match file_stem.rsplit_once('_') {
+        Some((name, version)) if version.split('.').all(|part| part.parse::<u32>().is_ok()) => name,
+        _ => file_stem,
+    }
+}
+
+/// The scenarios in the mod zip at `path`, from its entries like `my-mod_1.2.3/scenarios/teams/control.lua`.
+fn zip_scenarios(path: &Path) -> Vec<Scenario> {
+    let archive = std::fs::File::open(path)
+        .map_err(zip::result::ZipError::from)
+        .and_then(zip::ZipArchive::new);
+
+    let archive = match archive {
+        Ok(archive) => archive,
+        Err(e) => {
+            warn!("failed to read the scenarios of {}: {}", path.display(), e);
+            return Vec::new();
+        },
+    };
+
+    let mut scenarios: Vec<_> = archive
+        .file_names()
+        .filter_map(|entry| {
+            let mut parts = entry.split('/');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(root), Some("scenarios"), Some(name), Some(_)) if !name.is_empty() => Some(Scenario {
+                    mod_name: Some(mod_name(root).to_owned()),
+                    name: name.to_owned(),
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+
+    scenarios.sort();
+    scenarios.dedup();
+    scenarios
+}
+
+/// Every scenario of the game in `factorio_dir`, the mods in `state_dir`, and the custom scenarios in `state_dir`,
+/// sorted by mod and name.
+pub fn list_scenarios(factorio_dir: &Path, state_dir: &Path) -> Vec<Scenario> {
+    fn in_dir(mod_name: Option<&str>, dir: &Path) -> Vec<Scenario> {
+        subdirectories(dir)
+            .into_iter()
+            .map(|name| Scenario {
+                mod_name: mod_name.map(str::to_owned),
+                name,
+            })
+            .collect()
+    }
+
+    let mut scenarios = Vec::new();
+    let data_dir = factorio_dir.join("data");
+    for data_mod in subdirectories(&data_dir) {
+        scenarios.extend(in_dir(Some(&data_mod), &data_dir.join(&data_mod).join("scenarios")));
+    }
+
+    let mods_dir = state_dir.join("mods");
+    if let Ok(entries) = mods_dir.read_dir() {
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            // the version of an unzipped mod is not an extension
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            if path.is_dir() {
+                scenarios.extend(in_dir(Some(mod_name(file_name)), &path.join("scenarios")));
+            } else if path.extension().is_some_and(|ext| ext == "zip") {
+                scenarios.extend(zip_scenarios(&path));
+            }
+        }
+    }
+
+    scenarios.extend(in_dir(None, &state_dir.join("scenarios")));
+    scenarios.sort();
+    scenarios.dedup();
+    scenarios
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn scenario(mod_name: Option<&str>, name: &str) -> Scenario {
+        Scenario {
+            mod_name: mod_name.map(str::to_owned),
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_mod_name() {
+        assert_eq!(mod_name("my-mod_1.2.3"), "my-mod");
+        assert_eq!(mod_name("my_mod_0.1.0"), "my_mod");
+        assert_eq!(mod_name("my_mod"), "my_mod");
+    }
+
+    #[test]
+    fn test_list_scenarios() {
+        let factorio_dir = tempfile::tempdir().unwrap();
+        let state_dir = tempfile::tempdir().unwrap();
+        for dir in ["data/base/scenarios/freeplay", "data/base/scenarios/pvp", "data/core/graphics"] {
+            std::fs::create_dir_all(factorio_dir.path().join(dir)).unwrap();
+        }
+
+        for dir in ["scenarios/teams", "mods/unzipped_0.1.0/scenarios/arena"] {
+            std::fs::create_dir_all(state_dir.path().join(dir)).unwrap();
+        }
+
+        let file = std::fs::File::create(state_dir.path().join("mods/pvp-mod_1.0.0.zip")).unwrap();
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default();
+        for file in ["info.json", "scenarios/ctf/control.lua", "scenarios/ctf/locale/en/ctf.cfg"] {
+            zip.start_file(format!("pvp-mod_1.0.0/{}", file), options).unwrap();
+            zip.write_all(b"").unwrap();
+        }
+
+        zip.finish().unwrap();
+
+        assert_eq!(
+            list_scenarios(factorio_dir.path(), state_dir.path()),
+            [
+                scenario(None, "teams"),
+                scenario(Some("base"), "freeplay"),
+                scenario(Some("base"), "pvp"),
+                scenario(Some("pvp-mod"), "ctf"),
+                scenario(Some("unzipped"), "arena"),
+            ]
+        );
+    }
+}
@@ -7,6 +7,7 @@ use std::process::{Command, CommandArgs};
 
 use tracing::{debug, info, warn};
 
+use crate::state::{DaemonState, StartFrom};
 use crate::utils;
 
 /// Add a `file` option to the command if the file exists and is a file. The option will be prefixed with `flags`.
@@ -28,16 +29,31 @@ fn args_to_os_strings(args: CommandArgs) -> Vec<OsString> {
 
 /// Add the server options to the command. If the configuration directory does not exist, the options will not be added.
 fn add_server_options(command: &mut Command, config_dir: &Path) {
-    add_file_opt(command, &["--server-settings"], config_dir.join("server-settings.json"));
+    add_server_options_with_settings(command, config_dir, config_dir);
+}
+
+/// Add the server options to the command, using the `server-settings.json` in `settings_dir` and the other files in
+/// `config_dir`.
+fn add_server_options_with_settings(command: &mut Command, config_dir: &Path, settings_dir: &Path) {
+    add_file_opt(command, &["--server-settings"], settings_dir.join("server-settings.json"));
     add_file_opt(command, &["--use-server-whitelist", "--server-whitelist"], config_dir.join("server-whitelist.json"));
     add_file_opt(command, &["--server-banlist"], config_dir.join("server-banlist.json"));
     add_file_opt(command, &["--server-adminlist"], config_dir.join("server-adminlist.json"));
 }
 
-/// Add the save options to the command. If the state directory does not exist, `--start-server-load-latest` will be
-/// used instead.
+/// Add the save options to the command. The scenario selected in the daemon state is loaded until a save is created
+/// from it, otherwise the latest save is started. If there is no save, `--start-server-load-latest` will be used
+/// instead.
 fn add_save_options(command: &mut Command, state_dir: &Path) {
     let save_dir = state_dir.join("saves");
+    match start_from(state_dir, &save_dir) {
+        StartFrom::Scenario(scenario) => {
+            command.arg("--start-server-load-scenario").arg(scenario);
+            return;
+        },
+        StartFrom::LatestSave => {},
+    }
+
     let args: Vec<OsString> = utils::get_latest_save(save_dir)
         .map(|save| vec![OsString::from("--start-server"), save.into_os_string()])
         .unwrap_or_else(|_| vec![OsString::from("--start-server-load-latest")]);
@@ -45,6 +61,40 @@ fn add_save_options(command: &mut Command, state_dir: &Path) {
     command.args(args);
 }
 
+/// What the server starts from according to the daemon state in `state_dir`, recording a save created from the
+/// selected scenario. If the state cannot be read, the latest save is started.
+pub(crate) fn start_from(state_dir: &Path, save_dir: &Path) -> StartFrom {
+    let mut state = match DaemonState::load(state_dir) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("{}, starting the latest save", e);
+            return StartFrom::LatestSave;
+        },
+    };
+
+    let (start_from, changed) = state.start_from(save_dir);
+    if changed {
+        if let Err(e) = state.save(state_dir) {
+            warn!("{}", e);
+        }
+    }
+
+    start_from
+}
+
+/// Add the mod options to the command. If the state directory does not contain a `mods` directory, the game's default
+/// mods directory will be used.
+fn add_mod_options(command: &mut Command, state_dir: &Path) {
+    let mods_dir = state_dir.join("mods");
+    match mods_dir.canonicalize() {
+        Ok(mods_dir) if mods_dir.is_dir() => {
+            command.arg("--mod-directory").arg(mods_dir);
+        },
+        Ok(mods_dir) => warn!("{} is not a directory!", mods_dir.display()),
+        Err(e) => debug!("failed to canonicalize {}: {}", mods_dir.display(), e),
+    }
+}
+
 /// Invokes the `adder` function with the given `dir` if it is a directory. Trace events will be emitted if `dir` is
 /// [`None`], not a directory, or does not exist.
 fn add_opts<P: AsRef<Path>>(command: &mut Command, word: &str, dir: &Option<P>, adder: impl Fn(&mut Command, &Path)) {
@@ -66,8 +116,15 @@ pub struct ServerOpts {
     /// The path to the configuration directory containing files like `server-settings.json`.
     config_dir: Option<PathBuf>,
 
-    /// The path to the state directory containing directories like `saves`.
+    /// The path to the state directory containing directories like `saves` and `mods`.
     state_dir: Option<PathBuf>,
+
+    /// The path to the runtime directory. If it contains a `server-settings.json`, that file is used instead of the one
+    /// in the configuration directory, since it includes secrets.
+    runtime_dir: Option<PathBuf>,
+
+    /// Options added after the others, like the ports of an instance. See [`crate::instances`].
+    args: Vec<OsString>,
 }
 
 impl ServerOpts {
@@ -76,9 +133,23 @@ impl ServerOpts {
         ServerOpts {
             config_dir: config_dir.map(|p| p.as_ref().to_owned()),
             state_dir: state_dir.map(|p| p.as_ref().to_owned()),
+            runtime_dir: None,
+            args: Vec::new(),
         }
     }
 
+    /// Use the `server-settings.json` in `runtime_dir`, if it exists.
+    pub fn with_runtime_dir<P: AsRef<Path>>(mut self, runtime_dir: Option<P>) -> ServerOpts {
+        self.runtime_dir = runtime_dir.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Add `args` after the other options.
+    pub fn with_args<I: IntoIterator<Item = S>, S: Into<OsString>>(mut self, args: I) -> ServerOpts {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
     /// Transform the options into the `FACTORIO_OPTS` environment variable.
     pub fn to_env(&self) -> OsString {
         let mut env = OsString::from("FACTORIO_OPTS=");
@@ -92,8 +163,16 @@ impl ServerOpts {
     /// Get the value of the environment variable.
     fn get_opts(&self) -> Vec<OsString> {
         let mut command = Command::new("factorio");
-        add_opts(&mut command, "config", &self.config_dir, add_server_options);
+        match self.runtime_dir.as_ref().filter(|dir| dir.join("server-settings.json").is_file()) {
+            Some(runtime_dir) => add_opts(&mut command, "config", &self.config_dir, |command, config_dir| {
+                add_server_options_with_settings(command, config_dir, runtime_dir)
+            }),
+            None => add_opts(&mut command, "config", &self.config_dir, add_server_options),
+        }
+
         add_opts(&mut command, "state", &self.state_dir, add_save_options);
+        add_opts(&mut command, "state", &self.state_dir, add_mod_options);
+        command.args(&self.args);
         args_to_os_strings(command.get_args())
     }
 }
@@ -110,7 +189,7 @@ mod tests {
         let mut command = Command::new("factorio");
         let file = Path::new("/path/to/file");
         add_file_opt(&mut command, &["--server-settings"], file);
-        assert_eq!(command.get_args().into_iter().len(), 0);
+        assert_eq!(command.get_args().len(), 0);
         Ok(())
     }
 
@@ -128,7 +207,7 @@ mod tests {
         let mut command = Command::new("bin");
         let args = command.arg("a").arg("b").arg("c").get_args();
         let os_strings = args_to_os_strings(args);
-        assert_eq!(os_strings, vec!["a", "b", "c"].iter().map(OsString::from).collect::<Vec<_>>());
+        assert_eq!(os_strings, ["a", "b", "c"].iter().map(OsString::from).collect::<Vec<_>>());
     }
 
     struct TempServerOptionsDir {
@@ -181,6 +260,7 @@ mod tests {
     struct TempSaveOptionsDir {
         temp_dir: TempDir,
         latest_save: PathBuf,
+        mods_dir: PathBuf,
     }
 
     fn create_temp_save_options_dir() -> TempSaveOptionsDir {
@@ -189,9 +269,12 @@ mod tests {
         std::fs::create_dir(&save_dir).unwrap();
         let latest_save = save_dir.join("latest_save.zip");
         std::fs::File::create(&latest_save).unwrap();
+        let mods_dir = temp_dir.path().join("mods");
+        std::fs::create_dir(&mods_dir).unwrap();
         TempSaveOptionsDir {
             temp_dir,
             latest_save,
+            mods_dir,
         }
     }
 
@@ -199,6 +282,11 @@ mod tests {
         assert!(actual.contains(format!("--start-server {}", temp_save_options_dir.latest_save.display()).as_str()));
     }
 
+    fn assert_mod_options(actual: &str, temp_save_options_dir: &TempSaveOptionsDir) {
+        let mods_dir = temp_save_options_dir.mods_dir.canonicalize().unwrap();
+        assert!(actual.contains(format!("--mod-directory {}", mods_dir.display()).as_str()));
+    }
+
     #[test]
     fn test_add_save_options() {
         let mut command = Command::new("factorio");
@@ -216,11 +304,49 @@ mod tests {
         let save_dir = temp_dir.path().join("saves");
         std::fs::create_dir(&save_dir).unwrap();
 
-        add_save_options(&mut command, &temp_dir.path());
+        add_save_options(&mut command, temp_dir.path());
         let actual = args_to_os_strings(command.get_args()).join(OsString::from(" ").as_os_str());
         assert_eq!(actual, "--start-server-load-latest");
     }
 
+    #[test]
+    fn test_add_save_options_scenario() {
+        let temp_dir = create_temp_save_options_dir();
+        let before = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        let latest_save = std::fs::File::options().write(true).open(&temp_dir.latest_save).unwrap();
+        latest_save.set_modified(before).unwrap();
+
+        let mut state = DaemonState::default();
+        state.select_scenario(&crate::scenarios::Scenario {
+            mod_name: Some("base".to_owned()),
+            name: "pvp".to_owned(),
+        });
+
+        state.save(temp_dir.temp_dir.path()).unwrap();
+        let mut command = Command::new("factorio");
+        add_save_options(&mut command, temp_dir.temp_dir.path());
+        let actual = args_to_os_strings(command.get_args()).join(OsString::from(" ").as_os_str());
+        assert_eq!(actual, "--start-server-load-scenario base/pvp");
+    }
+
+    #[test]
+    fn test_add_mod_options() {
+        let mut command = Command::new("factorio");
+        let temp_dir = create_temp_save_options_dir();
+        add_mod_options(&mut command, temp_dir.temp_dir.path());
+        let actual = args_to_os_strings(command.get_args()).join(OsString::from(" ").as_os_str());
+        let actual = actual.to_string_lossy();
+        assert_mod_options(&actual, &temp_dir);
+    }
+
+    #[test]
+    fn test_add_mod_options_no_mods_dir() {
+        let mut command = Command::new("factorio");
+        let temp_dir = tempfile::tempdir().unwrap();
+        add_mod_options(&mut command, temp_dir.path());
+        assert_eq!(command.get_args().len(), 0);
+    }
+
     #[test]
     fn test_server_opts_to_env_none() {
         let server_opts = ServerOpts::new::<PathBuf, PathBuf>(None, None);
@@ -251,5 +377,41 @@ mod tests {
         assert!(actual.ends_with("'"));
         assert_server_options(&actual, &temp_server_options_dir);
         assert_save_options(&actual, &temp_save_options_dir);
+        assert_mod_options(&actual, &temp_save_options_dir);
+    }
+
+    #[test]
+    fn test_server_opts_runtime_server_settings() {
+        let temp_server_options_dir = create_temp_server_options_dir();
+        let runtime_dir = tempfile::tempdir().unwrap();
+        let server_opts = ServerOpts::new(Some(temp_server_options_dir.temp_dir.path()), None::<&Path>)
+            .with_runtime_dir(Some(runtime_dir.path()));
+
+        // without settings in the runtime directory, the configuration directory's are used
+        assert!(server_opts.to_env().to_string_lossy().contains(
+            format!("--server-settings {}", temp_server_options_dir.server_settings.display()).as_str()
+        ));
+
+        let runtime_settings = runtime_dir.path().join("server-settings.json");
+        std::fs::File::create(&runtime_settings).unwrap();
+        let actual = server_opts.to_env();
+        let actual = actual.to_string_lossy();
+        assert!(actual.contains(format!("--server-settings {}", runtime_settings.display()).as_str()));
+        assert_eq!(actual.matches("--server-settings").count(), 1);
+        assert!(actual.contains(
+            format!("--server-adminlist {}", temp_server_options_dir.server_adminlist.display()).as_str()
+        ));
+    }
+
+    #[test]
+    fn test_server_opts_with_args() {
+        let temp_save_options_dir = create_temp_save_options_dir();
+        let server_opts = ServerOpts::new(None::<&Path>, Some(temp_save_options_dir.temp_dir.path()))
+            .with_args(["--port", "34198"]);
+
+        let actual = server_opts.to_env();
+        let actual = actual.to_string_lossy();
+        assert!(actual.ends_with(" --port 34198'"), "{}", actual);
+        assert_save_options(&actual, &temp_save_options_dir);
     }
 }
@@ -0,0 +1,190 @@
+//! The daemon's own state, kept in `daemon-state.json` in the state directory across restarts.
+//!
+//! When a scenario is selected, the server is started from it with `--start-server-load-scenario` until the game saves
+//! the new map. From then on, restarts continue that save, like any other, instead of creating the scenario again.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::scenarios::Scenario;
+
+/// The name of the state file in the state directory.
+pub const STATE_FILE_NAME: &str = "daemon-state.json";
+
+/// An error returned when the daemon state cannot be read or written.
+#[derive(Debug)]
+pub enum StateError {
+    /// The state file could not be read or written.
+    Io { path: PathBuf, source: std::io::Error },
+
+    /// The state file is not valid JSON of the daemon state.
+    Parse { path: PathBuf, source: serde_json::Error },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Io { path, source } => write!(f, "failed to access {}: {}", path.display(), source),
+            StateError::Parse { path, source } => write!(f, "failed to parse {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for StateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StateError::Io { source, .. } => Some(source),
+            StateError::Parse { source, .. } => Some(source),
+        }
+    }
+}
+
+/// A scenario selected to start the server from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectedScenario {
+    /// The argument of `--start-server-load-scenario`, see [`Scenario::id`].
+    pub id: String,
+
+    /// When the scenario was selected, in milliseconds since the Unix epoch. Saves modified since were created from it.
+    pub selected_at: u64,
+
+    /// The first save created from the scenario, once the game has written one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save: Option<PathBuf>,
+}
+
+/// What the server starts from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartFrom {
+    /// The latest save in the saves directory.
+    LatestSave,
+
+    /// A new map from the scenario with this id.
+    Scenario(String),
+}
+
+/// The state of the daemon.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonState {
+    /// The scenario selected to start the server from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<SelectedScenario>,
+}
+
+impl DaemonState {
+    /// Read the state from `state_dir`, or the default state if there is no state file.
+    pub fn load(state_dir: &Path) -> Result<Self, StateError> {
+        let path = state_dir.join(STATE_FILE_NAME);
+        match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|source| StateError::Parse { path, source }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(StateError::Io { path, source }),
+        }
+    }
+
+    /// Write the state to `state_dir`.
+    pub fn save(&self, state_dir: &Path) -> Result<(), StateError> {
+        let path = state_dir.join(STATE_FILE_NAME);
+        serde_json::to_vec_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| factoriod_config::write_atomic(&path, &contents))
+            .map_err(|source| StateError::Io { path, source })
+    }
+
+    /// Select `scenario` to start the server from, replacing any previous selection.
+    pub fn select_scenario(&mut self, scenario: &Scenario) {
+        let selected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis().try_into().unwrap_or(u64::MAX));
+
+        self.scenario = Some(SelectedScenario {
+            id: scenario.id(),
+            selected_at,
+            save: None,
+        });
+    }
+
+    /// What the server starts from, given the saves in `saves_dir`. The selected scenario is started until a save
+    /// modified since it was selected appears, which is then recorded as the scenario's save. Returns whether the
+    /// state changed and should be saved.
+    pub fn start_from(&mut self, saves_dir: &Path) -> (StartFrom, bool) {
+        let Some(selected) = self.scenario.as_mut().filter(|selected| selected.save.is_none()) else {
+            return (StartFrom::LatestSave, false);
+        };
+
+        let selected_at = UNIX_EPOCH + Duration::from_millis(selected.selected_at);
+        let created = crate::get_latest_save(saves_dir).ok().filter(|save| {
+            save.metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= selected_at)
+        });
+
+        match created {
+            Some(save) => {
+                info!("{} was created from scenario {}, continuing it", save.display(), selected.id);
+                selected.save = Some(save);
+                (StartFrom::LatestSave, true)
+            },
+            None => (StartFrom::Scenario(selected.id.clone()), false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(DaemonState::load(dir.path()).unwrap(), DaemonState::default());
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(STATE_FILE_NAME), "{").unwrap();
+        assert!(matches!(DaemonState::load(dir.path()), Err(StateError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_scenario_until_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let saves_dir = dir.path().join("saves");
+        std::fs::create_dir(&saves_dir).unwrap();
+
+        let mut state = DaemonState::default();
+        assert_eq!(state.start_from(&saves_dir), (StartFrom::LatestSave, false));
+
+        let pvp = Scenario {
+            mod_name: Some("base".to_owned()),
+            name: "pvp".to_owned(),
+        };
+
+        state.select_scenario(&pvp);
+        state.save(dir.path()).unwrap();
+        let mut state = DaemonState::load(dir.path()).unwrap();
+        assert_eq!(state.start_from(&saves_dir), (StartFrom::Scenario("base/pvp".to_owned()), false));
+
+        // a save from before the selection is not the scenario's
+        let old = saves_dir.join("old.zip");
+        std::fs::write(&old, "").unwrap();
+        let before = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options().write(true).open(&old).unwrap().set_modified(before).unwrap();
+        assert_eq!(state.start_from(&saves_dir), (StartFrom::Scenario("base/pvp".to_owned()), false));
+
+        // file times may lag the clock slightly, so the new save is clearly after the selection
+        let created = saves_dir.join("_autosave1.zip");
+        std::fs::write(&created, "").unwrap();
+        let after = SystemTime::now() + Duration::from_secs(60);
+        std::fs::File::options().write(true).open(&created).unwrap().set_modified(after).unwrap();
+        assert_eq!(state.start_from(&saves_dir), (StartFrom::LatestSave, true));
+        assert_eq!(state.scenario.as_ref().unwrap().save.as_deref(), Some(created.canonicalize().unwrap().as_path()));
+        assert_eq!(state.start_from(&saves_dir), (StartFrom::LatestSave, false));
+    }
+}
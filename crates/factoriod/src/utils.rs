@@ -51,7 +51,7 @@ pub fn get_saves<P: AsRef<Path>>(save_dir: P) -> Result<Vec<PathBuf>> {
     let mut saves = get_zips(save_dir)?
         .collect::<Vec<_>>();
 
-    saves.sort_by_cached_key(|path| mtime_or_default(&path));
+    saves.sort_by_cached_key(|path| mtime_or_default(path));
     saves.reverse();
 
     Ok(saves)
@@ -72,7 +72,7 @@ pub fn get_saves<P: AsRef<Path>>(save_dir: P) -> Result<Vec<PathBuf>> {
 /// ```
 pub fn get_latest_save<P: AsRef<Path>>(save_dir: P) -> Result<PathBuf> {
     let latest_save = get_zips(&save_dir)?
-        .max_by_key(|path| mtime_or_default(&path));
+        .max_by_key(|path| mtime_or_default(path));
 
     latest_save.ok_or_else(|| FactorioServerStartError::NoSaveFound(save_dir.as_ref().into()))
 }
@@ -1,3 +1,9 @@
 #!/bin/bash
-source /var/cache/factoriod/factorio.opts.env
+# the options of an instance, like factoriod@.service passes, are in its runtime directory
+if [ -n "$1" ]; then
+    source "/run/factoriod/instances/$1/factorio.opts.env"
+else
+    source /var/cache/factoriod/factorio.opts.env
+fi
+
 exec /var/cache/factoriod/factorio/bin/x64/factorio ${FACTORIO_OPTS}